@@ -0,0 +1,226 @@
+//! Audit trail of every invocation, recorded to the system log similar to what sudo does, and
+//! on Linux to the kernel audit log as well. Where the systemd journal runs, the record goes to
+//! it instead of the system log, with its parts as fields of their own.
+
+use std::{
+    fmt::Write,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    env::EnvTrait, error::Rejection, output::Json, RET_AUTH_FAILED, RET_ENV_ERROR,
+    RET_INVOKER_DENIED, RET_OWNER_CONFIG, RET_OWNER_EXEC, RET_OWNER_PARENT, RET_OWNER_TARGET,
+    RET_PERM_CONFIG, RET_PERM_EXEC, RET_PERM_PARENT, RET_PERM_TARGET,
+};
+
+/// Rejections that count towards locking the invoker out, see [crate::lockout].
+const FAILURES: &[u8] = &[
+    RET_OWNER_EXEC,
+    RET_PERM_EXEC,
+    RET_OWNER_PARENT,
+    RET_PERM_PARENT,
+    RET_OWNER_TARGET,
+    RET_PERM_TARGET,
+    RET_OWNER_CONFIG,
+    RET_PERM_CONFIG,
+    RET_INVOKER_DENIED,
+    RET_AUTH_FAILED,
+];
+
+/// What is known about the invocation so far. Fields are filled in as `main` resolves them,
+/// and the records are written through `E`.
+pub(crate) struct Audit<E: EnvTrait> {
+    /// Whether records are written at all, can be turned off by the target configuration.
+    pub(crate) enabled: bool,
+    pub(crate) uid: u32,
+    pub(crate) cwd: Option<PathBuf>,
+    pub(crate) target: Option<PathBuf>,
+    /// The user the target runs as.
+    pub(crate) target_uid: Option<u32>,
+    pub(crate) args: Vec<String>,
+    /// Hash-chained log every record is appended to as well, see [crate::auditlog].
+    pub(crate) log_file: Option<PathBuf>,
+    /// State file the runs of the target are counted in, see [crate::stats].
+    pub(crate) stats_file: Option<PathBuf>,
+    env: PhantomData<E>,
+}
+
+impl<E: EnvTrait> Audit<E> {
+    pub(crate) fn new(uid: u32, args: Vec<String>) -> Audit<E> {
+        Audit {
+            enabled: true,
+            uid,
+            cwd: None,
+            target: None,
+            target_uid: None,
+            args,
+            log_file: None,
+            stats_file: None,
+            env: PhantomData,
+        }
+    }
+
+    /// Print and record a rejected invocation, and turn its exit code into the wrapper's result.
+    pub(crate) fn reject(&self, rejection: Rejection) -> ExitCode {
+        let exit = crate::exit::own(rejection.code);
+        rejection.print(exit);
+        let result = format!("rejected (exit code {})", exit);
+        // nothing more can be done about a log that failed, as the run is rejected either way
+        let _ = self.append(&result);
+        self.record(&result, Some(false));
+        self.count(false);
+        if FAILURES.contains(&rejection.code) {
+            if let Some(secs) = E::count_failure(self.uid) {
+                // logged even if the target turned off its audit records
+                let result = format!("locked out for {} seconds", secs);
+                E::syslog(
+                    &format!("UID={} ; RESULT={}", self.uid, result),
+                    &[
+                        ("RUN_SUID_REAL_UID", self.uid.to_string()),
+                        ("RESULT", result),
+                    ],
+                );
+            }
+        }
+        exit.into()
+    }
+
+    /// Record an invocation that passed every check, unless it could not be appended to the
+    /// audit log, which rejects it instead.
+    pub(crate) fn accept(&self, dry_run: bool) -> Result<(), Rejection> {
+        let result = if dry_run {
+            "accepted (dry run)"
+        } else {
+            "accepted"
+        };
+        // first, so that a run missing from the log is not recorded as accepted anywhere
+        if let Err(err) = self.append(result) {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to append to the audit log {:?}: {}",
+                    self.log_file.as_deref().unwrap_or_else(|| Path::new("")),
+                    err
+                ),
+            ));
+        }
+        self.record(result, Some(true).filter(|_| !dry_run));
+        // a dry run skips authentication, so it can not vouch for the invoker
+        if !dry_run {
+            E::reset_failures(self.uid);
+            self.count(true);
+        }
+        Ok(())
+    }
+
+    /// Count the invocation in the statistics of the target, if they are kept. A failure only
+    /// loses the count, so it does not keep the target from running.
+    fn count(&self, ok: bool) {
+        if let Some(path) = &self.stats_file {
+            if let Err(err) = crate::stats::count(path, self.uid, ok) {
+                crate::output::warn(format_args!(
+                    "Unable to count the run in {:?}: {}",
+                    path, err
+                ));
+            }
+        }
+    }
+
+    /// Append the invocation with its result to the audit log, if there is one.
+    fn append(&self, result: &str) -> Result<(), std::io::Error> {
+        let path = match &self.log_file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        crate::auditlog::append(
+            path,
+            vec![
+                ("time", Json::from(time)),
+                ("uid", Json::from(self.uid)),
+                ("target", Json::from(self.target.as_deref())),
+                ("target_uid", Json::from(self.target_uid)),
+                ("argv", Json::array(self.args.iter().map(String::as_str))),
+                ("result", Json::from(result)),
+            ],
+        )
+    }
+
+    /// Record the invocation with its result, and in the kernel audit log whether the target
+    /// is run, unless `ran` is `None` as for a dry run.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn record(&self, result: &str, ran: Option<bool>) {
+        let tty = E::tty_name();
+        // kept for compliance even if the target turned off its records in the system log
+        #[cfg(target_os = "linux")]
+        if let Some(ran) = ran {
+            let mut command = match &self.target {
+                Some(target) => target.to_string_lossy().into_owned(),
+                None => String::from("?"),
+            };
+            for a in self.args.iter() {
+                command.push(' ');
+                command.push_str(a);
+            }
+            let cwd = match &self.cwd {
+                Some(cwd) => cwd.to_string_lossy().into_owned(),
+                None => String::from("?"),
+            };
+            E::audit_command(&crate::auditd::user_cmd_message(
+                &cwd,
+                &command,
+                self.target_uid,
+                tty.as_deref(),
+                ran,
+            ));
+        }
+        if !self.enabled {
+            return;
+        }
+        let mut fields = vec![("RUN_SUID_REAL_UID", self.uid.to_string())];
+        let mut out = String::new();
+        write!(out, "UID={} ; TTY=", self.uid).unwrap();
+        match &tty {
+            Some(tty) => {
+                out.push_str(tty);
+                fields.push(("RUN_SUID_TTY", tty.clone()));
+            }
+            None => out.push_str("unknown"),
+        }
+        out.push_str(" ; PWD=");
+        match &self.cwd {
+            Some(cwd) => {
+                write!(out, "{:?}", cwd).unwrap();
+                fields.push(("RUN_SUID_PWD", cwd.to_string_lossy().into_owned()));
+            }
+            None => out.push_str("unknown"),
+        }
+        out.push_str(" ; TARGET=");
+        match &self.target {
+            Some(target) => {
+                write!(out, "{:?}", target).unwrap();
+                fields.push(("RUN_SUID_TARGET", target.to_string_lossy().into_owned()));
+            }
+            None => out.push_str("unknown"),
+        }
+        if let Some(uid) = self.target_uid {
+            fields.push(("RUN_SUID_TARGET_UID", uid.to_string()));
+        }
+        out.push_str(" ; ARGS=");
+        let start = out.len();
+        for (i, a) in self.args.iter().enumerate() {
+            if i != 0 {
+                out.push(' ');
+            }
+            write!(out, "{:?}", a).unwrap();
+        }
+        fields.push(("RUN_SUID_ARGS", out[start..].to_owned()));
+        write!(out, " ; RESULT={}", result).unwrap();
+        fields.push(("RESULT", result.to_owned()));
+        E::syslog(&out, &fields);
+    }
+}
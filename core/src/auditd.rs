@@ -0,0 +1,93 @@
+//! Records of the kernel audit subsystem, sent over its netlink socket to be logged by auditd
+//! as `USER_CMD` the way sudo does.
+//!
+//! The kernel adds the pid, the ids, the login uid and the audit session of the sender itself.
+
+use std::fmt::Write;
+
+/// `AUDIT_USER_CMD` of `linux/audit.h`.
+const AUDIT_USER_CMD: u16 = 1123;
+/// `MAX_AUDIT_MESSAGE_LENGTH` of `linux/audit.h`, longer messages are cut off.
+const MAX_MESSAGE: usize = 8970;
+
+/// Append a field value the way libaudit does: quoted, or in hexadecimal if it holds a quote,
+/// a space, or anything but printable ASCII.
+fn push_value(out: &mut String, value: &str) {
+    if value
+        .bytes()
+        .any(|b| b == b'"' || !(0x21..=0x7e).contains(&b))
+    {
+        for b in value.bytes() {
+            write!(out, "{:02X}", b).unwrap();
+        }
+    } else {
+        write!(out, "\"{}\"", value).unwrap();
+    }
+}
+
+/// The text of a `USER_CMD` record of running `command` as `uid` in `cwd`.
+pub(crate) fn user_cmd_message(
+    cwd: &str,
+    command: &str,
+    uid: Option<u32>,
+    terminal: Option<&str>,
+    success: bool,
+) -> String {
+    let mut out = String::from("cwd=");
+    push_value(&mut out, cwd);
+    out.push_str(" cmd=");
+    push_value(&mut out, command);
+    if let Some(uid) = uid {
+        write!(out, " id={}", uid).unwrap();
+    }
+    let terminal = terminal.map(|t| t.strip_prefix("/dev/").unwrap_or(t));
+    write!(out, " terminal={}", terminal.unwrap_or("?")).unwrap();
+    out.push_str(if success {
+        " res=success"
+    } else {
+        " res=failed"
+    });
+    out
+}
+
+/// Send a `USER_CMD` record, which requires `CAP_AUDIT_WRITE`. Failures are ignored as they
+/// are for the system log, such as when the kernel does not audit at all.
+pub(crate) fn send_user_cmd(message: &str) {
+    let mut end = message.len().min(MAX_MESSAGE - 1);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    let payload = &message.as_bytes()[..end];
+    // `nlmsghdr` followed by the message, terminated and padded to four bytes
+    let len = 16 + payload.len() + 1;
+    let mut buf = Vec::with_capacity((len + 3) & !3);
+    buf.extend_from_slice(&(len as u32).to_ne_bytes());
+    buf.extend_from_slice(&AUDIT_USER_CMD.to_ne_bytes());
+    buf.extend_from_slice(&(libc::NLM_F_REQUEST as u16).to_ne_bytes());
+    buf.extend_from_slice(&1u32.to_ne_bytes());
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    buf.extend_from_slice(payload);
+    buf.resize((len + 3) & !3, 0);
+
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_AUDIT,
+        );
+        if fd < 0 {
+            return;
+        }
+        let mut kernel: libc::sockaddr_nl = std::mem::zeroed();
+        kernel.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        libc::sendto(
+            fd,
+            buf.as_ptr().cast(),
+            buf.len(),
+            0,
+            (&kernel as *const libc::sockaddr_nl).cast(),
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        );
+        libc::close(fd);
+    }
+}
@@ -0,0 +1,76 @@
+//! A local audit trail of JSON lines chained by their digests, each record holding the SHA-256
+//! of the line before it, so that a record removed, altered, or cut off breaks the chain.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::Path,
+};
+
+use crate::output::Json;
+
+/// Digest the first record is chained to.
+const GENESIS: [u8; 32] = [0; 32];
+/// Records are never this long, a longer last line is taken as tampering.
+const MAX_RECORD: u64 = 1 << 20;
+
+/// Append a record of `fields` to the log at `path`, which is created if missing and must be
+/// owned by root and writable by no one else.
+pub(crate) fn append(path: &Path, fields: Vec<(&str, Json)>) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() || meta.uid() != 0 || meta.mode() & 0o022 != 0 {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "it must be a file owned by root and writable by no one else",
+        ));
+    }
+    // held until the file is closed, so that two records never chain to the same one
+    crate::sys::flock(&file, false)?;
+    let prev = match last_line(&mut file)? {
+        Some(line) => crate::sha256::digest(line.as_slice())?,
+        None => GENESIS,
+    };
+    let mut record = vec![("prev", Json::from(crate::sha256::to_hex(&prev)))];
+    record.extend(fields);
+    let mut line = Json::object(record).to_string();
+    line.push('\n');
+    // a single write, which `O_APPEND` puts at the end as a whole
+    file.write_all(line.as_bytes())
+}
+
+/// The last line of the log without its newline, `None` if the log is empty.
+fn last_line(file: &mut File) -> Result<Option<Vec<u8>>, Error> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut size = 4096;
+    loop {
+        let start = len.saturating_sub(size);
+        let mut tail = Vec::new();
+        file.seek(SeekFrom::Start(start))?;
+        (&*file).take(len - start).read_to_end(&mut tail)?;
+        if tail.last() == Some(&b'\n') {
+            tail.pop();
+        }
+        match tail.iter().rposition(|b| *b == b'\n') {
+            Some(at) => return Ok(Some(tail.split_off(at + 1))),
+            None if start == 0 => return Ok(Some(tail)),
+            None if size >= MAX_RECORD => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "its last record is too long",
+                ))
+            }
+            None => size *= 2,
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Authentication of the invoking user against the shadow file, and timestamps that let a
+//! recent authentication stand in for a new one, as `sudo` does.
+
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// What a timestamp is bound to, the session and terminal of the invoker.
+pub(crate) fn session_key(tty: Option<&str>) -> String {
+    let sid = crate::sys::getsid();
+    format!("{} {}\n", sid, tty.unwrap_or("-"))
+}
+
+/// Create a directory of the wrapper's state, or check that it is only accessible by the
+/// wrapper's owner.
+pub(crate) fn private_dir(dir: &Path, what: &str) -> Result<(), String> {
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("Unable to create the {} {:?}: {}", what, dir, e))?;
+    let metadata = std::fs::symlink_metadata(dir)
+        .map_err(|e| format!("Unable to check the {} {:?}: {}", what, dir, e))?;
+    if !metadata.is_dir() || metadata.uid() != crate::sys::geteuid() || metadata.mode() & 0o077 != 0
+    {
+        return Err(format!(
+            "The {} must be accessible by only the owner of the wrapper: {:?}",
+            what, dir
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the invoker authenticated within `grace` in the same session.
+pub(crate) fn is_fresh(dir: &Path, uid: u32, key: &str, grace: Duration) -> Result<bool, String> {
+    private_dir(dir, "timestamp directory")?;
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(dir.join(uid.to_string()));
+    let mut file = match file {
+        Ok(file) => file,
+        Err(_) => return Ok(false),
+    };
+    let metadata = match file.metadata() {
+        Ok(metadata) if metadata.is_file() && metadata.uid() == crate::sys::geteuid() => metadata,
+        _ => return Ok(false),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let age = now - metadata.mtime();
+    if age < 0 || age as u64 >= grace.as_secs() {
+        return Ok(false);
+    }
+    let mut content = String::new();
+    Ok(file.read_to_string(&mut content).is_ok() && content == key)
+}
+
+/// Remember that the invoker authenticated now.
+pub(crate) fn touch(dir: &Path, uid: u32, key: &str) -> Result<(), String> {
+    let path = dir.join(uid.to_string());
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(&path)
+        .and_then(|mut file| {
+            file.write_all(key.as_bytes())?;
+            // the file may have been left behind with another mode
+            crate::sys::fchmod(&file, 0o600)
+        })
+        .map_err(|e| format!("Unable to write the timestamp {:?}: {}", path, e))
+}
+
+/// Check a password against the hash of the user in the shadow file.
+#[cfg(all(feature = "shadow", target_os = "linux"))]
+pub(crate) fn check_shadow(user: &str, password: &str) -> Result<bool, String> {
+    use std::ffi::CString;
+
+    let name = CString::new(user).map_err(|e| e.to_string())?;
+    let password = CString::new(password).map_err(|e| e.to_string())?;
+    let hash = match crate::sys::shadow_hash(&name) {
+        Some(hash) => hash,
+        None => return Err(format!("No shadow entry for the user {}.", user)),
+    };
+    // a locked account or one without a password can not be authenticated with one
+    if !hash.to_bytes().starts_with(b"$") {
+        return Ok(false);
+    }
+    let computed = match crate::sys::crypt_with(&password, &hash) {
+        Some(computed) => computed,
+        None => return Ok(false),
+    };
+    let computed = computed.to_bytes();
+    let expected = hash.to_bytes();
+    // compared in full, so that the time taken does not tell how much matched
+    let differs = computed
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    Ok(computed.len() == expected.len() && differs == 0)
+}
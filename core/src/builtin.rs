@@ -0,0 +1,59 @@
+//! A policy baked into the wrapper from environment variables at build time, for deployments
+//! that may not be reconfigured without rebuilding.
+//!
+//! - `RUN_SUID_TARGET_PATH`: absolute path of the only target run, instead of the sibling.
+//! - `RUN_SUID_ALLOWED_UIDS`: comma separated uids of the only users allowed to invoke it.
+//! - `RUN_SUID_TARGET_SHA256`: SHA-256 digest the target must have, in hexadecimal.
+//!
+//! Once any of them is set, configuration files and allowlists next to the target are refused
+//! instead of read, and `--target` can not select another target.
+
+use crate::config::{Config, Invoker};
+
+pub(crate) const TARGET_PATH: Option<&str> = option_env!("RUN_SUID_TARGET_PATH");
+const ALLOWED_UIDS: Option<&str> = option_env!("RUN_SUID_ALLOWED_UIDS");
+const TARGET_SHA256: Option<&str> = option_env!("RUN_SUID_TARGET_SHA256");
+
+/// Whether a policy is built in, leaving nothing to configure at run time.
+pub(crate) const ENABLED: bool =
+    TARGET_PATH.is_some() || ALLOWED_UIDS.is_some() || TARGET_SHA256.is_some();
+
+/// The built in policy as a configuration, with everything it does not set at its default.
+pub(crate) fn config() -> Result<Config, String> {
+    let mut config = Config::default();
+    if let Some(path) = TARGET_PATH {
+        if !path.starts_with('/') {
+            return Err(format!(
+                "RUN_SUID_TARGET_PATH must be an absolute path: {:?}",
+                path
+            ));
+        }
+    }
+    if let Some(uids) = ALLOWED_UIDS {
+        let mut allowed = Vec::new();
+        for uid in uids.split(',').map(str::trim) {
+            match uid.parse() {
+                Ok(uid) => allowed.push(Invoker::Uid(uid)),
+                Err(_) => {
+                    return Err(format!(
+                        "RUN_SUID_ALLOWED_UIDS must only list uids: {:?}",
+                        uid
+                    ))
+                }
+            }
+        }
+        config.allowed_invokers = Some(allowed);
+    }
+    if let Some(hex) = TARGET_SHA256 {
+        match crate::sha256::parse_hex(hex) {
+            Some(digest) => config.sha256 = Some(digest),
+            None => {
+                return Err(format!(
+                    "RUN_SUID_TARGET_SHA256 must be 64 hexadecimal digits: {:?}",
+                    hex
+                ))
+            }
+        }
+    }
+    Ok(config)
+}
@@ -0,0 +1,102 @@
+//! Transient cgroup v2 groups that confine a proxied child process and all of its descendants.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::{fs::DirBuilderExt, io::AsRawFd, prelude::OpenOptionsExt},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::config;
+
+/// A cgroup created for a single run, removed again when dropped.
+pub(crate) struct Cgroup {
+    dir: PathBuf,
+    procs: File,
+}
+
+impl Cgroup {
+    /// Create the cgroup `name` beneath the configured parent and set its limits.
+    pub(crate) fn create(cfg: &config::Cgroup, name: &str) -> Result<Cgroup, String> {
+        let controllers = [
+            ("memory", cfg.memory_max.is_some()),
+            ("cpu", cfg.cpu_max.is_some()),
+            ("pids", cfg.pids_max.is_some()),
+        ];
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(&cfg.parent)
+            .map_err(|e| format!("Unable to create the cgroup {:?}: {}", cfg.parent, e))?;
+        for (controller, _) in controllers.iter().filter(|(_, used)| *used) {
+            write(
+                &cfg.parent.join("cgroup.subtree_control"),
+                &format!("+{}", controller),
+            )?;
+        }
+
+        let dir = cfg.parent.join(name);
+        std::fs::DirBuilder::new()
+            .mode(0o755)
+            .create(&dir)
+            .map_err(|e| format!("Unable to create the cgroup {:?}: {}", dir, e))?;
+        let procs = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(dir.join("cgroup.procs"));
+        // from here on the directory is removed again by the drop
+        let cgroup = match procs {
+            Ok(procs) => Cgroup { dir, procs },
+            Err(e) => {
+                let _ = std::fs::remove_dir(&dir);
+                return Err(format!("Unable to open the cgroup {:?}: {}", dir, e));
+            }
+        };
+        for (file, value) in [
+            ("memory.max", &cfg.memory_max),
+            ("cpu.max", &cfg.cpu_max),
+            ("pids.max", &cfg.pids_max),
+        ] {
+            if let Some(value) = value {
+                write(&cgroup.dir.join(file), value)?;
+            }
+        }
+        Ok(cgroup)
+    }
+
+    /// Descriptor of `cgroup.procs`, to which the child writes `0` to move itself.
+    pub(crate) fn procs_fd(&self) -> libc::c_int {
+        self.procs.as_raw_fd()
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // descendants left behind by the target keep the cgroup busy
+        let _ = write(&self.dir.join("cgroup.kill"), "1");
+        for _ in 0..50 {
+            if std::fs::remove_dir(&self.dir).is_ok() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        crate::output::warn(format_args!("Unable to remove the cgroup {:?}", self.dir));
+    }
+}
+
+/// Move the calling process into the cgroup, from a `pre_exec` hook.
+pub(crate) fn enter(procs: libc::c_int) -> Result<(), &'static [u8]> {
+    if !crate::sys::write_once(procs, b"0") {
+        return Err(b"Unable to move the target into its cgroup.\n");
+    }
+    Ok(())
+}
+
+fn write(path: &Path, value: &str) -> Result<(), String> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(value.as_bytes()))
+        .map_err(|e| format!("Unable to write {:?} to {:?}: {}", value, path, e))
+}
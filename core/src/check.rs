@@ -0,0 +1,751 @@
+//! The checks of a wrapper and its sibling target that running the target with the privileges
+//! of its owner depends on.
+//!
+//! Every component is opened without following symbolic links, and the target is opened
+//! beneath the checked directory, so that the checked files are the ones that are used.
+
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{env::EnvTrait, error::Rejection, exit::*, nix, Env};
+
+/// A wrapper that passed the checks of its file, its directory, and the ancestors of it.
+#[derive(Debug)]
+pub struct Wrapper {
+    pub exe: PathBuf,
+    /// File name of the wrapper.
+    pub name: String,
+    pub parent: PathBuf,
+    /// The user owning the wrapper and its directory.
+    pub owner: u32,
+    /// Number of hard links to the wrapper.
+    pub links: u64,
+    /// The opened directory, which the target is opened beneath.
+    pub(crate) dir: File,
+}
+
+/// A target that passed the checks of its file, opened to be executed.
+#[derive(Debug)]
+pub struct Target {
+    pub path: PathBuf,
+    pub owner: u32,
+    /// Number of hard links to the target.
+    pub links: u64,
+    /// The opened target, executed through its descriptor where supported.
+    pub file: File,
+}
+
+impl Wrapper {
+    /// The path of the target the wrapper runs when invoked as `name`.
+    pub fn sibling(&self, name: &str) -> PathBuf {
+        Env::sibling_target(&self.parent, name)
+    }
+}
+
+/// Check the absolute path `exe` of a wrapper that runs as `owner`, and open its directory.
+///
+/// Both the wrapper and its directory must be owned by `owner` and writable by no one else,
+/// the wrapper must be setuid, and every ancestor must be owned by root or `owner` and
+/// writable by only its owner.
+pub fn wrapper(exe: &Path, owner: u32) -> Result<Wrapper, Rejection> {
+    wrapper_in::<Env>(exe, owner)
+}
+
+/// [wrapper] within the environment `E`.
+pub(crate) fn wrapper_in<E: EnvTrait>(exe: &Path, owner: u32) -> Result<Wrapper, Rejection> {
+    let name = match exe.file_name().map(OsStr::to_str) {
+        Some(Some(name)) => name,
+        Some(None) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to read the name of the executable: {:?}", exe),
+            ));
+        }
+        None => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to find the name of the executable: {:?}", exe),
+            ));
+        }
+    };
+    let parent = match exe.parent() {
+        Some(a) => a,
+        None => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to find the parent directory of the executable: {}",
+                    exe.display()
+                ),
+            ));
+        }
+    };
+    let (par_uid, dir) = match E::open_dir(parent) {
+        Ok((par_uid, m, true, dir)) if m.is_dir() => (par_uid, dir),
+        Ok((_, _, true, _)) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("The parent directory must be a ... directory: {:?}", parent),
+            ));
+        }
+        Ok((_, m, false, _)) => {
+            let message = format!(
+                "The parent directory permissions must be writable by only the owning user: {:?}",
+                parent
+            );
+            return Err(Rejection::new(RET_PERM_PARENT, message)
+                .path(parent)
+                .mismatch(expected_mode(true), actual_mode(m.mode)));
+        }
+        Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+            return Err(Rejection::new(
+                RET_PERM_PARENT,
+                format!(
+                    "The path of the executable must not contain symbolic links: {:?}",
+                    exe
+                ),
+            )
+            .path(exe));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to find the owner of the parent directory: {}", err),
+            ));
+        }
+    };
+
+    let (exe_uid, links) = match E::open_target(&dir, OsStr::new(name)) {
+        Ok((exe_uid, m, true, _)) => (exe_uid, m.nlink),
+        Ok((_, meta, false, _)) if !meta.is_file() => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("The executable must be a ... file: {:?}", exe),
+            ));
+        }
+        Ok((_, m, false, _)) => {
+            let message = format!("The executable permissions must include the SUID bit as well as be writable by only the owning user: {:?}", exe);
+            return Err(Rejection::new(RET_PERM_EXEC, message)
+                .path(exe)
+                .mismatch(expected_mode(false), actual_mode(m.mode)));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to find the owner of the executable: {}", err),
+            ));
+        }
+    };
+
+    if owner != exe_uid {
+        return Err(
+            Rejection::new(RET_OWNER_EXEC, "You are not the owner of this executable.")
+                .path(exe)
+                .mismatch(format!("uid {}", owner), format!("uid {}", exe_uid)),
+        );
+    }
+
+    if owner != par_uid {
+        return Err(Rejection::new(
+            RET_OWNER_PARENT,
+            "The the owner of the parent directory is not the same as the executable.",
+        )
+        .path(parent)
+        .mismatch(format!("uid {}", owner), format!("uid {}", par_uid)));
+    }
+    match E::insecure_ancestor(parent, owner) {
+        Ok(None) => {}
+        Ok(Some((dir, uid, mode))) => {
+            let code = if uid != 0 && uid != owner {
+                RET_OWNER_PARENT
+            } else {
+                RET_PERM_PARENT
+            };
+            let message = format!("Every ancestor directory must be owned by root or the owner of the executable and writable by only its owner: {:?} (owner {}, mode {:o})", dir, uid, mode & 0o7777);
+            return Err(Rejection::new(code, message).path(&dir).mismatch(
+                format!("uid 0 or {}, not writable by group or others", owner),
+                format!("uid {}, mode {:04o}", uid, mode & 0o7777),
+            ));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to check the ancestors of the parent directory: {}",
+                    err
+                ),
+            ));
+        }
+    }
+
+    Ok(Wrapper {
+        exe: exe.to_path_buf(),
+        name: name.to_string(),
+        parent: parent.to_path_buf(),
+        owner,
+        links,
+        dir,
+    })
+}
+
+/// Check and open the target that `wrapper` runs when invoked as `name`.
+///
+/// The target must be a setuid file writable by no one but its owner, which must be the owner
+/// of the wrapper unless the wrapper is owned by root.
+pub fn target(wrapper: &Wrapper, name: &str) -> Result<Target, Rejection> {
+    target_in::<Env>(wrapper, name)
+}
+
+/// [target] within the environment `E`.
+pub(crate) fn target_in<E: EnvTrait>(wrapper: &Wrapper, name: &str) -> Result<Target, Rejection> {
+    let target = E::sibling_target(&wrapper.parent, name);
+    open_target_in::<E>(wrapper.owner, &wrapper.dir, target)
+}
+
+/// Check and open the target at the absolute `path` instead of a sibling of `wrapper`.
+///
+/// Its directory and every ancestor must pass the same checks as those of the wrapper, except
+/// that the directory may also be owned by root.
+pub fn target_at(wrapper: &Wrapper, path: &Path) -> Result<Target, Rejection> {
+    target_at_in::<Env>(wrapper, path)
+}
+
+/// [target_at] within the environment `E`.
+pub(crate) fn target_at_in<E: EnvTrait>(
+    wrapper: &Wrapper,
+    path: &Path,
+) -> Result<Target, Rejection> {
+    let parent = match path.parent() {
+        Some(parent) if path.is_absolute() => parent,
+        _ => {
+            return Err(Rejection::new(
+                RET_CONFIG,
+                format!("The path of the target must be absolute: {:?}", path),
+            ));
+        }
+    };
+    let dir = match E::open_dir(parent) {
+        Ok((uid, m, true, dir)) if m.is_dir() => {
+            if uid != wrapper.owner && uid != 0 {
+                return Err(Rejection::new(
+                    RET_OWNER_PARENT,
+                    "The owner of the directory of the target is neither root nor the owner of the executable.",
+                )
+                .path(parent)
+                .mismatch(format!("uid 0 or {}", wrapper.owner), format!("uid {}", uid)));
+            }
+            dir
+        }
+        Ok((_, m, _, _)) => {
+            let message = format!(
+                "The directory of the target must be a directory writable by only the owning user: {:?}",
+                parent
+            );
+            return Err(Rejection::new(RET_PERM_PARENT, message)
+                .path(parent)
+                .mismatch(expected_mode(true), actual_mode(m.mode)));
+        }
+        Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+            return Err(Rejection::new(
+                RET_PERM_PARENT,
+                format!(
+                    "The path of the target must not contain symbolic links: {:?}",
+                    path
+                ),
+            )
+            .path(path));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to open the directory of the target {:?}: {}",
+                    parent, err
+                ),
+            ));
+        }
+    };
+    match E::insecure_ancestor(parent, wrapper.owner) {
+        Ok(None) => {}
+        Ok(Some((dir, uid, mode))) => {
+            let code = if uid != 0 && uid != wrapper.owner {
+                RET_OWNER_PARENT
+            } else {
+                RET_PERM_PARENT
+            };
+            let message = format!("Every ancestor directory of the target must be owned by root or the owner of the executable and writable by only its owner: {:?} (owner {}, mode {:o})", dir, uid, mode & 0o7777);
+            return Err(Rejection::new(code, message).path(&dir).mismatch(
+                format!(
+                    "uid 0 or {}, not writable by group or others",
+                    wrapper.owner
+                ),
+                format!("uid {}, mode {:04o}", uid, mode & 0o7777),
+            ));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to check the ancestors of the directory of the target: {}",
+                    err
+                ),
+            ));
+        }
+    }
+    open_target_in::<E>(wrapper.owner, &dir, path.to_path_buf())
+}
+
+/// The directory of targets that `wrapper` dispatches to when invoked as `name`, which is only
+/// used if there is no target of that name.
+pub fn dispatch_dir(wrapper: &Wrapper, name: &str) -> Option<PathBuf> {
+    dispatch_dir_in::<Env>(wrapper, name)
+}
+
+/// [dispatch_dir] within the environment `E`.
+pub(crate) fn dispatch_dir_in<E: EnvTrait>(wrapper: &Wrapper, name: &str) -> Option<PathBuf> {
+    let target = E::sibling_target(&wrapper.parent, name);
+    match E::open_target(&wrapper.dir, target.file_name().unwrap_or_default()) {
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        _ => return None,
+    }
+    let dir = E::target_dir(&target);
+    match E::open_dir(&dir) {
+        Ok((_, m, _, _)) if m.is_dir() => Some(dir),
+        _ => None,
+    }
+}
+
+/// Check and open the target named `name` in the directory `dir` of targets of `wrapper`.
+///
+/// The name must be a bare file name not starting with a dot, and the directory must pass the
+/// same checks as the directory of the wrapper.
+pub fn member(wrapper: &Wrapper, dir: &Path, name: &str) -> Result<Target, Rejection> {
+    member_in::<Env>(wrapper, dir, name)
+}
+
+/// [member] within the environment `E`.
+pub(crate) fn member_in<E: EnvTrait>(
+    wrapper: &Wrapper,
+    dir: &Path,
+    name: &str,
+) -> Result<Target, Rejection> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\0']) {
+        return Err(Rejection::new(
+            RET_GENERIC_ERROR,
+            format!("Not the name of a target in {:?}: {:?}", dir, name),
+        ));
+    }
+    let opened = match E::open_dir(dir) {
+        Ok((uid, m, true, opened)) if m.is_dir() => {
+            if uid != wrapper.owner {
+                return Err(Rejection::new(
+                    RET_OWNER_PARENT,
+                    "The owner of the directory of targets is not the same as the executable.",
+                )
+                .path(dir)
+                .mismatch(format!("uid {}", wrapper.owner), format!("uid {}", uid)));
+            }
+            opened
+        }
+        Ok((_, m, _, _)) => {
+            let message = format!(
+                "The directory of targets must be a directory writable by only the owning user: {:?}",
+                dir
+            );
+            return Err(Rejection::new(RET_PERM_PARENT, message)
+                .path(dir)
+                .mismatch(expected_mode(true), actual_mode(m.mode)));
+        }
+        Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+            return Err(Rejection::new(
+                RET_PERM_PARENT,
+                format!(
+                    "The directory of targets must not be a symbolic link: {:?}",
+                    dir
+                ),
+            )
+            .path(dir));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to open the directory of targets {:?}: {}", dir, err),
+            ));
+        }
+    };
+    open_target_in::<E>(wrapper.owner, &opened, dir.join(name))
+}
+
+/// Check and open `target` beneath the opened directory `dir`, for a wrapper owned by `owner`.
+fn open_target_in<E: EnvTrait>(
+    owner: u32,
+    dir: &File,
+    target: PathBuf,
+) -> Result<Target, Rejection> {
+    let tar_name = target.file_name().unwrap_or_default();
+    let (tar_uid, links, file) = match E::open_target(dir, tar_name) {
+        Ok((exe_uid, m, true, file)) => (exe_uid, m.nlink, file),
+        Ok((_, m, false, _)) if m.is_symlink() => {
+            return Err(Rejection::new(
+                RET_PERM_TARGET,
+                format!(
+                    "The target executable must not be a symbolic link: {:?}",
+                    target
+                ),
+            )
+            .path(&target));
+        }
+        Ok((_, m, false, _)) if !m.is_file() => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!("The target executable must be a file: {:?}", target),
+            )
+            .path(&target));
+        }
+        Ok((_, m, false, _)) => {
+            let message = format!("The target executable permissions must include the SUID bit as well as be writable by only the owning user: {:?}", target);
+            return Err(Rejection::new(RET_PERM_TARGET, message)
+                .path(&target)
+                .mismatch(expected_mode(false), actual_mode(m.mode)));
+        }
+        // without `O_PATH` opening a symbolic link fails instead
+        Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+            return Err(Rejection::new(
+                RET_PERM_TARGET,
+                format!(
+                    "The target executable must not be a symbolic link: {:?}",
+                    target
+                ),
+            )
+            .path(&target));
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(Rejection::new(
+                RET_NO_TARGET,
+                format!(
+                    "Unable to find the owner of the target executable {:?}: {}",
+                    target, err
+                ),
+            )
+            .path(&target));
+        }
+        Err(err) => {
+            return Err(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to find the owner of the target executable {:?}: {}",
+                    target, err
+                ),
+            ));
+        }
+    };
+    if owner != 0 && owner != tar_uid {
+        return Err(Rejection::new(
+            RET_OWNER_TARGET,
+            "The the owner of the target executable is not the same as the executable.",
+        )
+        .path(&target)
+        .mismatch(format!("uid {}", owner), format!("uid {}", tar_uid)));
+    }
+    Ok(Target {
+        path: target,
+        owner: tar_uid,
+        links,
+        file,
+    })
+}
+
+/// The mode a checked file or directory must have, see [nix::PERM_FILE_MASK].
+pub(crate) fn expected_mode(dir: bool) -> String {
+    let (expected, mask) = match dir {
+        true => (nix::PERM_DIR_EXPECTED, nix::PERM_DIR_MASK),
+        false => (nix::PERM_FILE_EXPECTED, nix::PERM_FILE_MASK),
+    };
+    format!("mode {:04o} within the bits {:04o}", expected, mask)
+}
+
+pub(crate) fn actual_mode(mode: u32) -> String {
+    format!("mode {:04o}", mode & 0o7777)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Entry, MockEnv, State};
+
+    const OWNER: u32 = 1000;
+
+    /// A healthy installation of `/opt/app/bin/tool` owned by [OWNER].
+    fn installed() -> State {
+        let mut state = State {
+            euid: OWNER,
+            uid: 1001,
+            ..State::default()
+        };
+        for (path, entry) in [
+            ("/opt/app/bin", Entry::dir(OWNER, 0o500)),
+            ("/opt/app/bin/tool", Entry::file(OWNER, 0o4511)),
+            ("/opt/app/bin/tool.run-suid", Entry::file(OWNER, 0o4500)),
+        ] {
+            state.files.insert(PathBuf::from(path), entry);
+        }
+        state
+    }
+
+    fn with(path: &str, entry: Entry) -> State {
+        let mut state = installed();
+        state.files.insert(PathBuf::from(path), entry);
+        state
+    }
+
+    fn check(state: State) -> Result<(Wrapper, Target), Rejection> {
+        let owner = state.euid;
+        MockEnv::set(state);
+        let wrapper = wrapper_in::<MockEnv>(Path::new("/opt/app/bin/tool"), owner)?;
+        let target = target_in::<MockEnv>(&wrapper, "tool")?;
+        Ok((wrapper, target))
+    }
+
+    fn rejected(state: State) -> Rejection {
+        match check(state) {
+            Ok(_) => panic!("the installation was accepted"),
+            Err(rejection) => rejection,
+        }
+    }
+
+    #[test]
+    fn accepts_a_healthy_installation() {
+        let (wrapper, target) = check(installed()).unwrap();
+        assert_eq!(wrapper.owner, OWNER);
+        assert_eq!(wrapper.parent, Path::new("/opt/app/bin"));
+        assert_eq!(target.path, Path::new("/opt/app/bin/tool.run-suid"));
+        assert_eq!(target.owner, OWNER);
+    }
+
+    #[test]
+    fn counts_hard_links() {
+        let state = with("/opt/app/bin/tool", Entry::file(OWNER, 0o4511).links(2));
+        let (wrapper, target) = check(state).unwrap();
+        assert_eq!((wrapper.links, target.links), (2, 1));
+    }
+
+    #[test]
+    fn rejects_a_wrapper_of_another_owner() {
+        let rejection = rejected(with("/opt/app/bin/tool", Entry::file(1002, 0o4511)));
+        assert_eq!(rejection.code, RET_OWNER_EXEC);
+        assert_eq!(
+            rejection.path.as_deref(),
+            Some(Path::new("/opt/app/bin/tool"))
+        );
+        let mismatch = ("uid 1000".to_string(), "uid 1002".to_string());
+        assert_eq!(rejection.mismatch, Some(mismatch));
+    }
+
+    #[test]
+    fn rejects_a_wrapper_without_setuid() {
+        let rejection = rejected(with("/opt/app/bin/tool", Entry::file(OWNER, 0o511)));
+        assert_eq!(rejection.code, RET_PERM_EXEC);
+        assert_eq!(rejection.mismatch.unwrap().1, "mode 0511");
+    }
+
+    #[test]
+    fn rejects_a_writable_wrapper() {
+        let rejection = rejected(with("/opt/app/bin/tool", Entry::file(OWNER, 0o4531)));
+        assert_eq!(rejection.code, RET_PERM_EXEC);
+    }
+
+    #[test]
+    fn rejects_a_writable_directory() {
+        let rejection = rejected(with("/opt/app/bin", Entry::dir(OWNER, 0o520)));
+        assert_eq!(rejection.code, RET_PERM_PARENT);
+        assert_eq!(rejection.path.as_deref(), Some(Path::new("/opt/app/bin")));
+    }
+
+    #[test]
+    fn rejects_a_directory_of_another_owner() {
+        let rejection = rejected(with("/opt/app/bin", Entry::dir(1002, 0o500)));
+        assert_eq!(rejection.code, RET_OWNER_PARENT);
+    }
+
+    #[test]
+    fn rejects_a_symbolic_link_in_the_path() {
+        let rejection = rejected(with("/opt/app/bin", Entry::symlink(OWNER)));
+        assert_eq!(rejection.code, RET_PERM_PARENT);
+        assert_eq!(
+            rejection.path.as_deref(),
+            Some(Path::new("/opt/app/bin/tool"))
+        );
+    }
+
+    #[test]
+    fn rejects_an_ancestor_of_another_owner() {
+        let rejection = rejected(with("/opt/app", Entry::dir(1002, 0o755)));
+        assert_eq!(rejection.code, RET_OWNER_PARENT);
+        assert_eq!(rejection.path.as_deref(), Some(Path::new("/opt/app")));
+    }
+
+    #[test]
+    fn rejects_a_writable_ancestor() {
+        let rejection = rejected(with("/opt", Entry::dir(0, 0o777)));
+        assert_eq!(rejection.code, RET_PERM_PARENT);
+        assert_eq!(rejection.path.as_deref(), Some(Path::new("/opt")));
+    }
+
+    #[test]
+    fn accepts_an_ancestor_of_the_owner() {
+        assert!(check(with("/opt/app", Entry::dir(OWNER, 0o755))).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_missing_target() {
+        let mut state = installed();
+        state.files.remove(Path::new("/opt/app/bin/tool.run-suid"));
+        let rejection = rejected(state);
+        assert_eq!(rejection.code, RET_NO_TARGET);
+        let target = Path::new("/opt/app/bin/tool.run-suid");
+        assert_eq!(rejection.path.as_deref(), Some(target));
+    }
+
+    #[test]
+    fn rejects_a_target_that_is_a_symbolic_link() {
+        let state = with("/opt/app/bin/tool.run-suid", Entry::symlink(OWNER));
+        assert_eq!(rejected(state).code, RET_PERM_TARGET);
+    }
+
+    #[test]
+    fn rejects_a_target_that_is_a_directory() {
+        let state = with("/opt/app/bin/tool.run-suid", Entry::dir(OWNER, 0o500));
+        assert_eq!(rejected(state).code, RET_ENV_ERROR);
+    }
+
+    #[test]
+    fn rejects_a_target_without_setuid() {
+        let state = with("/opt/app/bin/tool.run-suid", Entry::file(OWNER, 0o500));
+        assert_eq!(rejected(state).code, RET_PERM_TARGET);
+    }
+
+    #[test]
+    fn rejects_a_target_of_another_owner() {
+        let state = with("/opt/app/bin/tool.run-suid", Entry::file(1002, 0o4500));
+        assert_eq!(rejected(state).code, RET_OWNER_TARGET);
+    }
+
+    #[test]
+    fn runs_a_target_of_any_owner_as_root() {
+        let mut state = installed();
+        state.euid = 0;
+        for (path, entry) in [
+            ("/opt/app/bin", Entry::dir(0, 0o500)),
+            ("/opt/app/bin/tool", Entry::file(0, 0o4511)),
+            ("/opt/app/bin/tool.run-suid", Entry::file(1002, 0o4500)),
+        ] {
+            state.files.insert(PathBuf::from(path), entry);
+        }
+        let (_, target) = check(state).unwrap();
+        assert_eq!(target.owner, 1002);
+    }
+
+    #[test]
+    fn reports_scripted_errors() {
+        let mut state = installed();
+        let target = PathBuf::from("/opt/app/bin/tool.run-suid");
+        state.errors.insert(target, libc::EACCES);
+        let rejection = rejected(state);
+        assert_eq!(rejection.code, RET_ENV_ERROR);
+        assert!(rejection.message.contains("Permission denied"));
+    }
+
+    #[test]
+    fn keeps_the_extension_of_the_target() {
+        let mut state = installed();
+        let target = PathBuf::from("/opt/app/bin/tool.run-suid.sh");
+        state
+            .files
+            .insert(target.clone(), Entry::file(OWNER, 0o4500));
+        MockEnv::set(state);
+        let wrapper = wrapper_in::<MockEnv>(Path::new("/opt/app/bin/tool"), OWNER).unwrap();
+        assert_eq!(
+            target_in::<MockEnv>(&wrapper, "tool.sh").unwrap().path,
+            target
+        );
+    }
+
+    /// The installation with its target replaced by a directory of targets holding `backup`.
+    fn dispatching() -> State {
+        let mut state = installed();
+        state.files.remove(Path::new("/opt/app/bin/tool.run-suid"));
+        for (path, entry) in [
+            ("/opt/app/bin/tool.run-suid.d", Entry::dir(OWNER, 0o500)),
+            (
+                "/opt/app/bin/tool.run-suid.d/backup",
+                Entry::file(OWNER, 0o4500),
+            ),
+        ] {
+            state.files.insert(PathBuf::from(path), entry);
+        }
+        state
+    }
+
+    fn dispatch(state: State, name: &str) -> Result<Target, Rejection> {
+        MockEnv::set(state);
+        let wrapper = wrapper_in::<MockEnv>(Path::new("/opt/app/bin/tool"), OWNER)?;
+        let dir = dispatch_dir_in::<MockEnv>(&wrapper, "tool").expect("no directory of targets");
+        member_in::<MockEnv>(&wrapper, &dir, name)
+    }
+
+    #[test]
+    fn dispatches_to_a_target_in_the_directory() {
+        let target = dispatch(dispatching(), "backup").unwrap();
+        assert_eq!(
+            target.path,
+            Path::new("/opt/app/bin/tool.run-suid.d/backup")
+        );
+        MockEnv::set(installed());
+        let wrapper = wrapper_in::<MockEnv>(Path::new("/opt/app/bin/tool"), OWNER).unwrap();
+        assert_eq!(dispatch_dir_in::<MockEnv>(&wrapper, "tool"), None);
+    }
+
+    #[test]
+    fn rejects_a_dispatched_name_that_is_not_bare() {
+        for name in ["", "..", ".hidden", "../tool.run-suid", "sub/backup"] {
+            let rejection = dispatch(dispatching(), name).unwrap_err();
+            assert_eq!(rejection.code, RET_GENERIC_ERROR, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_a_writable_directory_of_targets() {
+        let mut state = dispatching();
+        let dir = PathBuf::from("/opt/app/bin/tool.run-suid.d");
+        state.files.insert(dir.clone(), Entry::dir(OWNER, 0o770));
+        let rejection = dispatch(state, "backup").unwrap_err();
+        assert_eq!(rejection.code, RET_PERM_PARENT);
+        assert_eq!(rejection.path, Some(dir));
+    }
+
+    #[test]
+    fn checks_the_directory_of_a_target_at_a_fixed_path() {
+        let mut state = installed();
+        for (path, entry) in [
+            ("/usr/libexec/app", Entry::dir(0, 0o755)),
+            ("/usr/libexec/app/helper", Entry::file(OWNER, 0o4500)),
+        ] {
+            state.files.insert(PathBuf::from(path), entry);
+        }
+        MockEnv::set(state.clone());
+        let wrapper = wrapper_in::<MockEnv>(Path::new("/opt/app/bin/tool"), OWNER).unwrap();
+        let path = Path::new("/usr/libexec/app/helper");
+        assert_eq!(target_at_in::<MockEnv>(&wrapper, path).unwrap().path, path);
+
+        let dir = PathBuf::from("/usr/libexec/app");
+        state.files.insert(dir.clone(), Entry::dir(1002, 0o755));
+        MockEnv::set(state);
+        let rejection = target_at_in::<MockEnv>(&wrapper, path).unwrap_err();
+        assert_eq!(rejection.code, RET_OWNER_PARENT);
+        assert_eq!(rejection.path, Some(dir));
+    }
+}
@@ -0,0 +1,1656 @@
+//! Per-target configuration, read from an optional `<target>.toml` file next to the target executable.
+//!
+//! Unknown keys are rejected so that a typo can never silently weaken the policy.
+//!
+//! Sections `[group.<name or gid>]` and `[user.<name or uid>]` replace a few settings for the
+//! invokers they match, see [Override].
+
+use std::{fmt, io::Read, path::PathBuf, time::Duration};
+
+use crate::toml::{self, Table, Value};
+
+/// Upper bound on the size of a configuration file.
+const MAX_CONFIG_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Config {
+    /// Real users allowed to invoke the target. `None` allows everyone.
+    pub(crate) allowed_invokers: Option<Vec<Invoker>>,
+    /// Names of environment variables copied from the caller's environment.
+    pub(crate) preserve_env: Vec<String>,
+    /// Names of environment variables the caller may set with `--env`.
+    pub(crate) allowed_env: Vec<String>,
+    /// Pass `TERM`, `LANG`, `LC_*` and `TZ` through if their values look safe, on unless turned off.
+    pub(crate) pass_locale: Option<bool>,
+    /// File of `NAME=VALUE` lines set in the target's environment.
+    pub(crate) env_file: Option<PathBuf>,
+    /// Arguments passed to the target when the caller supplies none.
+    pub(crate) default_args: Vec<String>,
+    /// Arguments always passed to the target before those of the caller or the default ones.
+    pub(crate) prepend_args: Vec<String>,
+    /// What the arguments the caller gives the target must look like.
+    pub(crate) args: Args,
+    /// Overrides for invokers in a group given by gid or name, in the order of the names.
+    pub(crate) group_overrides: Vec<(Invoker, Override)>,
+    /// Overrides for invokers given by uid or name, applied after those of their groups.
+    pub(crate) user_overrides: Vec<(Invoker, Override)>,
+    /// `argv[0]` of the target, instead of its path.
+    pub(crate) arg0: Option<String>,
+    /// Names the caller may give the target as `argv[0]` with `--arg0`.
+    pub(crate) allowed_arg0: Vec<String>,
+    /// Working directory of the target, instead of the caller's current directory.
+    pub(crate) working_dir: Option<PathBuf>,
+    /// `PATH` of the target, instead of the filtered default.
+    pub(crate) path: Option<String>,
+    /// File mode creation mask of the target, such as `"027"`.
+    pub(crate) umask: Option<u32>,
+    /// Niceness of the target, from -20 to 19.
+    pub(crate) nice: Option<i32>,
+    /// Scheduling policy of the target, only supported on Linux.
+    pub(crate) scheduler: Option<Scheduler>,
+    /// Numbers of the only CPUs the target may run on, only supported on Linux.
+    pub(crate) cpus: Option<Vec<usize>>,
+    /// OOM score adjustment of the target, from -1000 to 1000, only supported on Linux.
+    pub(crate) oom_score_adj: Option<i32>,
+    /// SELinux context the target is executed in, only supported on Linux.
+    pub(crate) selinux_context: Option<String>,
+    /// AppArmor profile the target is executed in, only supported on Linux.
+    pub(crate) apparmor_profile: Option<String>,
+    /// Run the target unconfined if its AppArmor profile can not be changed to.
+    pub(crate) apparmor_optional: bool,
+    /// Resource limits of the target, both soft and hard, `None` being unlimited.
+    pub(crate) rlimits: Vec<(Rlimit, Option<u64>)>,
+    /// Transient cgroup a proxied target is confined to, only supported on Linux.
+    pub(crate) cgroup: Option<Cgroup>,
+    /// Directory the target is confined to as its root, the target must reside beneath it.
+    pub(crate) chroot: Option<PathBuf>,
+    /// Keep the target from gaining privileges through `exec`, by default only if it is not root.
+    pub(crate) no_new_privs: Option<bool>,
+    /// File the standard output of the target is written to.
+    pub(crate) stdout_file: Option<PathBuf>,
+    /// File the standard error of the target is written to.
+    pub(crate) stderr_file: Option<PathBuf>,
+    /// Append to the output files instead of truncating them.
+    pub(crate) append_output: bool,
+    /// Directory every terminal session of the target is recorded into.
+    pub(crate) record_dir: Option<PathBuf>,
+    /// Record the terminal sessions of the target in utmp, wtmp and lastlog, only supported on
+    /// Linux with glibc.
+    pub(crate) utmp: bool,
+    /// Give the target its own empty `/tmp` and `/var/tmp`, only supported on Linux.
+    pub(crate) private_tmp: bool,
+    /// Run the target in a transient systemd scope of its own, only supported on Linux.
+    pub(crate) scope: bool,
+    /// Names of the only capabilities the target may hold.
+    pub(crate) caps: Option<Vec<String>>,
+    /// Syscall filter installed right before the target is executed.
+    pub(crate) seccomp: Option<Seccomp>,
+    /// Filesystem sandbox applied right before the target is executed.
+    pub(crate) landlock: Option<Landlock>,
+    /// Record invocations in the system log, on unless turned off.
+    pub(crate) syslog: Option<bool>,
+    /// File owned by root every invocation is appended to, as a hash-chained JSON line.
+    pub(crate) audit_log: Option<PathBuf>,
+    /// Count the runs of the target in a state file next to it, only when run by root.
+    pub(crate) keep_stats: bool,
+    /// PAM authentication and session handling.
+    pub(crate) pam: Option<Pam>,
+    /// Authentication of the invoking user before each run, unless done recently.
+    pub(crate) auth: Option<Auth>,
+    /// polkit action the invoking user must be authorized for, needs the `polkit` feature.
+    pub(crate) polkit_action: Option<String>,
+    /// Expected SHA-256 digest of the target executable.
+    pub(crate) sha256: Option<[u8; 32]>,
+    /// Untrusted kinds of filesystems, such as `nfs` or `fuse`, the target may still reside on.
+    pub(crate) allowed_filesystems: Vec<String>,
+    /// Run even if the wrapper or target has more than one hard link.
+    pub(crate) allow_hard_links: bool,
+    /// Key that must have signed the target, see [crate::minisign].
+    pub(crate) minisign_key: Option<crate::minisign::PublicKey>,
+    /// Terminate the target when a proxying wrapper dies, on unless turned off.
+    pub(crate) kill_with_wrapper: Option<bool>,
+    /// Start a proxied target in a new session instead of only a new process group.
+    pub(crate) new_session: bool,
+    /// Refuse to run unless stdin of the wrapper is a terminal.
+    pub(crate) require_tty: bool,
+    /// Most instances of the target that may run at once.
+    pub(crate) max_instances: Option<u32>,
+    /// Directory of the lock files that limit the instances, see [crate::instances].
+    pub(crate) lock_dir: Option<PathBuf>,
+    /// Ask the invoker to confirm each run on the terminal.
+    pub(crate) confirm: bool,
+    /// Send `SIGKILL` to a proxied target this long after `SIGTERM` was forwarded to it.
+    pub(crate) kill_after: Option<Duration>,
+    /// Longest time a proxied target may run before it is sent `timeout_signal`.
+    pub(crate) timeout: Option<Duration>,
+    /// Signal sent when `timeout` passes, `SIGTERM` unless set.
+    pub(crate) timeout_signal: Option<i32>,
+    /// How the exit status of the target and the wrapper's own exit codes are reported.
+    pub(crate) exit: Exit,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Args {
+    /// Patterns one of which every argument must match, any argument is allowed if `None`.
+    pub(crate) allow: Option<Vec<String>>,
+    /// Patterns no argument may match, whether allowed or not.
+    pub(crate) deny: Vec<String>,
+    /// Patterns one of which every argument starting with `-` must match, before the end of the
+    /// options, any option is allowed if `None`.
+    pub(crate) options: Option<Vec<String>>,
+    /// Whether a `--` is inserted where the options of the invoker end, unless it gave one.
+    pub(crate) end_options: bool,
+    /// The most arguments the invoker may give.
+    pub(crate) max_count: Option<usize>,
+    /// The most bytes the arguments of the invoker may take, counting a terminating NUL each.
+    pub(crate) max_bytes: Option<usize>,
+}
+
+/// Settings replacing those of the configuration for the invokers an override section matches.
+///
+/// The base configuration is overridden by every matching group section in the order of their
+/// names, and then by the section of the user, so that the most specific one wins. Resource
+/// limits are replaced one resource at a time, and `args` are merged as [Args::merge] does, so
+/// that an override never drops a restriction it does not name. Every other setting is
+/// replaced as a whole.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Override {
+    pub(crate) preserve_env: Option<Vec<String>>,
+    pub(crate) allowed_env: Option<Vec<String>>,
+    pub(crate) args: Option<Args>,
+    pub(crate) rlimits: Vec<(Rlimit, Option<u64>)>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Exit {
+    /// Exit codes of the target and what the wrapper exits with instead.
+    pub(crate) remap: Vec<(u8, u8)>,
+    /// First code of the band the wrapper's own exit codes are moved into, see [crate::exit].
+    pub(crate) band: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AuthMethod {
+    /// The `auth` stack of the PAM service, needs the `pam` feature.
+    Pam,
+    /// The password of the invoker in the shadow file, needs the `shadow` feature.
+    Shadow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Auth {
+    pub(crate) method: AuthMethod,
+    /// How long a successful authentication is remembered for the same terminal session.
+    pub(crate) grace: Option<Duration>,
+    /// Directory owned by the owner of the wrapper where authentications are remembered.
+    pub(crate) timestamp_dir: PathBuf,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Auth {
+            method: AuthMethod::Pam,
+            grace: None,
+            timestamp_dir: PathBuf::from(run_dir!("run-suid/ts")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Pam {
+    /// Name of the PAM service, selecting the file in `/etc/pam.d`.
+    pub(crate) service: String,
+    /// Require the invoking user to authenticate.
+    pub(crate) authenticate: bool,
+    /// Open a session for the target user around its execution.
+    pub(crate) session: bool,
+}
+
+impl Default for Pam {
+    fn default() -> Self {
+        Pam {
+            service: "run-suid".to_string(),
+            authenticate: false,
+            session: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Cgroup {
+    /// Directory of the cgroup v2 hierarchy beneath which the cgroup of each run is created.
+    pub(crate) parent: PathBuf,
+    /// Written to `memory.max`, such as `"512M"`.
+    pub(crate) memory_max: Option<String>,
+    /// Written to `cpu.max`, such as `"50000 100000"`.
+    pub(crate) cpu_max: Option<String>,
+    /// Written to `pids.max`.
+    pub(crate) pids_max: Option<String>,
+}
+
+impl Default for Cgroup {
+    fn default() -> Self {
+        Cgroup {
+            parent: PathBuf::from("/sys/fs/cgroup/run-suid"),
+            memory_max: None,
+            cpu_max: None,
+            pids_max: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Landlock {
+    /// Paths beneath which the target may read and execute.
+    pub(crate) read_only: Vec<PathBuf>,
+    /// Paths beneath which the target has full access.
+    pub(crate) read_write: Vec<PathBuf>,
+    /// Refuse to run the target if the kernel does not support Landlock.
+    pub(crate) required: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Seccomp {
+    /// Names of the syscalls the target may use.
+    pub(crate) allow: Vec<String>,
+    /// What happens when the target uses any other syscall.
+    pub(crate) action: SeccompAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SeccompAction {
+    /// Kill the whole process.
+    Kill,
+    /// Fail the syscall with the given error number.
+    Errno(u16),
+}
+
+/// Scheduling policies that can only make the target yield to other work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scheduler {
+    Other,
+    Batch,
+    Idle,
+}
+
+/// Resources that can be limited with `setrlimit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rlimit {
+    As,
+    Core,
+    Cpu,
+    Data,
+    Fsize,
+    Memlock,
+    Nofile,
+    Nproc,
+    Stack,
+}
+
+impl Rlimit {
+    fn parse(key: &str, value: Value) -> Result<Vec<(Rlimit, Option<u64>)>, ConfigError> {
+        let mut limits = Vec::new();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            let resource = match k.as_str() {
+                "as" => Rlimit::As,
+                "core" => Rlimit::Core,
+                "cpu" => Rlimit::Cpu,
+                "data" => Rlimit::Data,
+                "fsize" => Rlimit::Fsize,
+                "memlock" => Rlimit::Memlock,
+                "nofile" => Rlimit::Nofile,
+                "nproc" => Rlimit::Nproc,
+                "stack" => Rlimit::Stack,
+                _ => return Err(unknown_key(&full)),
+            };
+            let limit = match v {
+                Value::Integer(n) if n >= 0 => Some(n as u64),
+                Value::String(s) if s == "unlimited" => None,
+                v => return Err(mismatch(&full, "a limit or \"unlimited\"", &v)),
+            };
+            limits.push((resource, limit));
+        }
+        Ok(limits)
+    }
+}
+
+/// An entry of an invoker allowlist, or the group of an override by gid or name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Invoker {
+    Uid(u32),
+    Name(String),
+}
+
+impl fmt::Display for Invoker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invoker::Uid(id) => write!(f, "{}", id),
+            Invoker::Name(n) => f.write_str(n),
+        }
+    }
+}
+
+impl Invoker {
+    /// Check whether the entry matches the real user, whose name may be unknown.
+    pub(crate) fn matches(&self, uid: u32, name: Option<&str>) -> bool {
+        match self {
+            Invoker::Uid(id) => *id == uid,
+            Invoker::Name(n) => Some(n.as_str()) == name,
+        }
+    }
+}
+
+/// Parse an allowlist file with one uid or user name per line. `#` starts a comment.
+pub(crate) fn parse_allow_list(input: &str) -> Result<Vec<Invoker>, ConfigError> {
+    let mut list = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        match invoker(line) {
+            Some(inv) => list.push(inv),
+            None => {
+                return Err(ConfigError(format!(
+                    "line {}: invalid user {:?}",
+                    i + 1,
+                    line
+                )))
+            }
+        }
+    }
+    Ok(list)
+}
+
+/// Read an environment file with one `NAME=VALUE` per line. Lines starting with `#` are
+/// comments. Values are taken literally and never echoed, as the file may hold secrets.
+pub(crate) fn read_env_file(file: impl Read) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut input = String::new();
+    file.take(MAX_CONFIG_SIZE + 1).read_to_string(&mut input)?;
+    if input.len() as u64 > MAX_CONFIG_SIZE {
+        return Err(ConfigError(format!(
+            "the file is larger than {} bytes",
+            MAX_CONFIG_SIZE
+        )));
+    }
+    let mut vars = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((name, value)) if is_env_name(name) && !is_env_denied(name) => {
+                vars.push((name.to_owned(), value.to_owned()))
+            }
+            Some(_) => return Err(ConfigError(format!("line {}: name not allowed", i + 1))),
+            None => return Err(ConfigError(format!("line {}: expected NAME=VALUE", i + 1))),
+        }
+    }
+    Ok(vars)
+}
+
+fn invoker(s: &str) -> Option<Invoker> {
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse().ok().map(Invoker::Uid);
+    }
+    if s.bytes()
+        .any(|b| b.is_ascii_whitespace() || b == b':' || b == b'/')
+    {
+        return None;
+    }
+    Some(Invoker::Name(s.to_owned()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<toml::ParseError> for ConfigError {
+    fn from(e: toml::ParseError) -> Self {
+        ConfigError(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError(e.to_string())
+    }
+}
+
+impl Config {
+    /// Read and validate a configuration from an already opened and checked file.
+    pub(crate) fn read(file: impl Read) -> Result<Config, ConfigError> {
+        let mut input = String::new();
+        file.take(MAX_CONFIG_SIZE + 1).read_to_string(&mut input)?;
+        if input.len() as u64 > MAX_CONFIG_SIZE {
+            return Err(ConfigError(format!(
+                "the file is larger than {} bytes",
+                MAX_CONFIG_SIZE
+            )));
+        }
+        Config::parse(&input)
+    }
+
+    /// Parse and validate a configuration.
+    pub(crate) fn parse(input: &str) -> Result<Config, ConfigError> {
+        let table = toml::parse(input)?;
+        let mut config = Config::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "allowed_invokers" => {
+                    config.allowed_invokers = Some(invoker_array(&key, value)?);
+                }
+                "preserve_env" => config.preserve_env = env_names(&key, value, "preserved")?,
+                "allowed_env" => config.allowed_env = env_names(&key, value, "set")?,
+                "default_args" => config.default_args = string_array(&key, value)?,
+                "prepend_args" => config.prepend_args = string_array(&key, value)?,
+                "arg0" => {
+                    let name = string(&key, value)?;
+                    if !is_arg0(&name) {
+                        return Err(ConfigError(format!("arg0 must not be empty: {:?}", name)));
+                    }
+                    config.arg0 = Some(name);
+                }
+                "allowed_arg0" => {
+                    let names = string_array(&key, value)?;
+                    if let Some(name) = names.iter().find(|n| !is_arg0(n)) {
+                        return Err(ConfigError(format!(
+                            "allowed_arg0 must not list an empty name: {:?}",
+                            name
+                        )));
+                    }
+                    config.allowed_arg0 = names;
+                }
+                "env_file" => {
+                    let file = PathBuf::from(string(&key, value)?);
+                    if !file.is_absolute() {
+                        return Err(ConfigError(format!(
+                            "env_file must be an absolute path: {:?}",
+                            file
+                        )));
+                    }
+                    config.env_file = Some(file);
+                }
+                "max_instances" => match value {
+                    Value::Integer(n) if (1..=1024).contains(&n) => {
+                        config.max_instances = Some(n as u32)
+                    }
+                    v => return Err(mismatch(&key, "a number from 1 to 1024", &v)),
+                },
+                "chroot" | "record_dir" | "lock_dir" => {
+                    let dir = PathBuf::from(string(&key, value)?);
+                    if !dir.is_absolute() {
+                        return Err(ConfigError(format!(
+                            "{} must be an absolute path: {:?}",
+                            key, dir
+                        )));
+                    }
+                    match key.as_str() {
+                        "chroot" => config.chroot = Some(dir),
+                        "record_dir" => config.record_dir = Some(dir),
+                        _ => config.lock_dir = Some(dir),
+                    }
+                }
+                "stdout_file" | "stderr_file" | "audit_log" => {
+                    let file = PathBuf::from(string(&key, value)?);
+                    if !file.is_absolute() {
+                        return Err(ConfigError(format!(
+                            "{} must be an absolute path: {:?}",
+                            key, file
+                        )));
+                    }
+                    match key.as_str() {
+                        "stdout_file" => config.stdout_file = Some(file),
+                        "stderr_file" => config.stderr_file = Some(file),
+                        _ => config.audit_log = Some(file),
+                    }
+                }
+                "append_output" => config.append_output = boolean(&key, value)?,
+                "working_dir" => {
+                    let dir = PathBuf::from(string(&key, value)?);
+                    if !dir.is_absolute() {
+                        return Err(ConfigError(format!(
+                            "working_dir must be an absolute path: {:?}",
+                            dir
+                        )));
+                    }
+                    config.working_dir = Some(dir);
+                }
+                "path" => {
+                    let path = string(&key, value)?;
+                    if path.is_empty() {
+                        return Err(ConfigError("path must not be empty".to_string()));
+                    }
+                    // an empty or relative entry would search the working directory of the caller
+                    if let Some(dir) = path.split(':').find(|d| !d.starts_with('/')) {
+                        return Err(ConfigError(format!(
+                            "path must only list absolute directories: {:?}",
+                            dir
+                        )));
+                    }
+                    config.path = Some(path);
+                }
+                "umask" => {
+                    let mask = string(&key, value)?;
+                    match parse_umask(&mask) {
+                        Some(mask) => config.umask = Some(mask),
+                        None => {
+                            return Err(ConfigError(format!(
+                                "umask must be octal digits such as \"027\": {:?}",
+                                mask
+                            )))
+                        }
+                    }
+                }
+                "nice" => match value {
+                    Value::Integer(n) if (-20..=19).contains(&n) => config.nice = Some(n as i32),
+                    v => return Err(mismatch(&key, "a niceness from -20 to 19", &v)),
+                },
+                "scheduler" => {
+                    let name = string(&key, value)?;
+                    config.scheduler = Some(match name.as_str() {
+                        "other" => Scheduler::Other,
+                        "batch" => Scheduler::Batch,
+                        "idle" => Scheduler::Idle,
+                        _ => {
+                            return Err(ConfigError(format!(
+                                "scheduler must be \"other\", \"batch\" or \"idle\": {:?}",
+                                name
+                            )))
+                        }
+                    });
+                }
+                "cpus" => {
+                    let list = string(&key, value)?;
+                    match parse_cpus(&list) {
+                        Some(cpus) => config.cpus = Some(cpus),
+                        None => {
+                            return Err(ConfigError(format!(
+                                "cpus must be a list of CPUs such as \"0-3,6\": {:?}",
+                                list
+                            )))
+                        }
+                    }
+                }
+                "oom_score_adj" => match value {
+                    Value::Integer(n) if (-1000..=1000).contains(&n) => {
+                        config.oom_score_adj = Some(n as i32)
+                    }
+                    v => return Err(mismatch(&key, "an adjustment from -1000 to 1000", &v)),
+                },
+                "selinux_context" => {
+                    let context = string(&key, value)?;
+                    if !is_selinux_context(&context) {
+                        return Err(ConfigError(format!(
+                            "selinux_context must be a context such as \"system_u:system_r:foo_t:s0\": {:?}",
+                            context
+                        )));
+                    }
+                    config.selinux_context = Some(context);
+                }
+                "apparmor_profile" => {
+                    let profile = string(&key, value)?;
+                    if profile.is_empty() || profile.contains(['\0', '\n']) {
+                        return Err(ConfigError(format!(
+                            "apparmor_profile must name a profile: {:?}",
+                            profile
+                        )));
+                    }
+                    config.apparmor_profile = Some(profile);
+                }
+                "apparmor_optional" => config.apparmor_optional = boolean(&key, value)?,
+                "utmp" => config.utmp = boolean(&key, value)?,
+                "scope" => config.scope = boolean(&key, value)?,
+                "keep_stats" => config.keep_stats = boolean(&key, value)?,
+                "rlimits" => config.rlimits = Rlimit::parse(&key, value)?,
+                "syslog" => config.syslog = Some(boolean(&key, value)?),
+                "pass_locale" => config.pass_locale = Some(boolean(&key, value)?),
+                "kill_with_wrapper" => config.kill_with_wrapper = Some(boolean(&key, value)?),
+                "new_session" => config.new_session = boolean(&key, value)?,
+                "require_tty" => config.require_tty = boolean(&key, value)?,
+                "confirm" => config.confirm = boolean(&key, value)?,
+                "private_tmp" => config.private_tmp = boolean(&key, value)?,
+                "no_new_privs" => config.no_new_privs = Some(boolean(&key, value)?),
+                "kill_after" => config.kill_after = Some(seconds(&key, value)?),
+                "timeout" => config.timeout = Some(seconds(&key, value)?),
+                "timeout_signal" => {
+                    let name = string(&key, value)?;
+                    match signal_number(&name) {
+                        Some(signal) => config.timeout_signal = Some(signal),
+                        None => {
+                            return Err(ConfigError(format!(
+                                "{} must name a signal such as \"TERM\": {:?}",
+                                key, name
+                            )))
+                        }
+                    }
+                }
+                "caps" => config.caps = Some(string_array(&key, value)?),
+                "seccomp" => config.seccomp = Some(Seccomp::parse(&key, value)?),
+                "landlock" => config.landlock = Some(Landlock::parse(&key, value)?),
+                "cgroup" => config.cgroup = Some(Cgroup::parse(&key, value)?),
+                "pam" => config.pam = Some(Pam::parse(&key, value)?),
+                "polkit_action" => {
+                    let action = string(&key, value)?;
+                    if !is_polkit_action(&action) {
+                        return Err(ConfigError(format!(
+                            "polkit_action must be an action id such as \"org.example.foo.run\": {:?}",
+                            action
+                        )));
+                    }
+                    config.polkit_action = Some(action);
+                }
+                "auth" => config.auth = Some(Auth::parse(&key, value)?),
+                "exit" => config.exit = Exit::parse(&key, value)?,
+                "args" => config.args = Args::parse(&key, value)?,
+                "user" => config.user_overrides = Override::parse_all(&key, value)?,
+                "group" => config.group_overrides = Override::parse_all(&key, value)?,
+                "allow_hard_links" => config.allow_hard_links = boolean(&key, value)?,
+                "allowed_filesystems" => config.allowed_filesystems = string_array(&key, value)?,
+                "minisign_key" => {
+                    let key = string(&key, value)?;
+                    match crate::minisign::PublicKey::parse(&key) {
+                        Some(k) => config.minisign_key = Some(k),
+                        None => {
+                            return Err(ConfigError(format!(
+                                "minisign_key is not a minisign public key: {:?}",
+                                key
+                            )))
+                        }
+                    }
+                }
+                "sha256" => {
+                    let hex = string(&key, value)?;
+                    match crate::sha256::parse_hex(&hex) {
+                        Some(digest) => config.sha256 = Some(digest),
+                        None => {
+                            return Err(ConfigError(format!(
+                                "sha256 must be 64 hexadecimal digits: {:?}",
+                                hex
+                            )))
+                        }
+                    }
+                }
+                _ => return Err(unknown_key(&key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Apply the overrides for the invoker `uid` named `name`, a member of `groups` given by
+    /// id and name, and get the sections that were applied.
+    pub(crate) fn apply_overrides(
+        &mut self,
+        uid: u32,
+        name: Option<&str>,
+        groups: &[(u32, Option<String>)],
+    ) -> Vec<String> {
+        let group_overrides = std::mem::take(&mut self.group_overrides);
+        let user_overrides = std::mem::take(&mut self.user_overrides);
+        let matching = group_overrides
+            .into_iter()
+            .filter(|(g, _)| groups.iter().any(|(gid, n)| g.matches(*gid, n.as_deref())))
+            .map(|(g, o)| (format!("group.{}", g), o))
+            .chain(
+                user_overrides
+                    .into_iter()
+                    .filter(|(u, _)| u.matches(uid, name))
+                    .map(|(u, o)| (format!("user.{}", u), o)),
+            );
+        let mut applied = Vec::new();
+        for (section, o) in matching {
+            if let Some(names) = o.preserve_env {
+                self.preserve_env = names;
+            }
+            if let Some(names) = o.allowed_env {
+                self.allowed_env = names;
+            }
+            if let Some(args) = o.args {
+                self.args.merge(args);
+            }
+            for (resource, limit) in o.rlimits {
+                self.rlimits.retain(|(r, _)| *r != resource);
+                self.rlimits.push((resource, limit));
+            }
+            applied.push(section);
+        }
+        applied
+    }
+}
+
+impl Override {
+    /// Parse the sections of a `user` or `group` table, keyed by id or name.
+    fn parse_all(key: &str, value: Value) -> Result<Vec<(Invoker, Override)>, ConfigError> {
+        let mut all = Vec::new();
+        for (who, v) in table(key, value)? {
+            let full = format!("{}.{}", key, who);
+            match invoker(&who) {
+                Some(who) => all.push((who, Override::parse(&full, v)?)),
+                None => return Err(ConfigError(format!("{} does not name a {}", full, key))),
+            }
+        }
+        Ok(all)
+    }
+
+    fn parse(key: &str, value: Value) -> Result<Override, ConfigError> {
+        let mut o = Override::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "preserve_env" => o.preserve_env = Some(env_names(&full, v, "preserved")?),
+                "allowed_env" => o.allowed_env = Some(env_names(&full, v, "set")?),
+                "args" => o.args = Some(Args::parse(&full, v)?),
+                "rlimits" => o.rlimits = Rlimit::parse(&full, v)?,
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(o)
+    }
+}
+
+impl Auth {
+    fn parse(key: &str, value: Value) -> Result<Auth, ConfigError> {
+        let mut auth = Auth::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "method" => {
+                    auth.method = match string(&full, v)?.as_str() {
+                        "pam" => AuthMethod::Pam,
+                        "shadow" => AuthMethod::Shadow,
+                        m => {
+                            return Err(ConfigError(format!(
+                                "{} must be \"pam\" or \"shadow\": {:?}",
+                                full, m
+                            )))
+                        }
+                    }
+                }
+                "grace" => auth.grace = Some(seconds(&full, v)?),
+                "timestamp_dir" => {
+                    auth.timestamp_dir = PathBuf::from(string(&full, v)?);
+                    if !auth.timestamp_dir.is_absolute() {
+                        return Err(ConfigError(format!(
+                            "{} must be an absolute path: {:?}",
+                            full, auth.timestamp_dir
+                        )));
+                    }
+                }
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(auth)
+    }
+}
+
+impl Args {
+    fn parse(key: &str, value: Value) -> Result<Args, ConfigError> {
+        let mut args = Args::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "allow" => args.allow = Some(patterns(&full, v)?),
+                "deny" => args.deny = patterns(&full, v)?,
+                "options" => args.options = Some(patterns(&full, v)?),
+                "end_options" => args.end_options = boolean(&full, v)?,
+                "max_count" | "max_bytes" => {
+                    let max = match v {
+                        Value::Integer(n) if n >= 0 => Some(n as usize),
+                        v => return Err(mismatch(&full, "a number of at least 0", &v)),
+                    };
+                    match k.as_str() {
+                        "max_count" => args.max_count = max,
+                        _ => args.max_bytes = max,
+                    }
+                }
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(args)
+    }
+
+    /// Apply the `args` of an override. Its denied patterns are added to these and it can only
+    /// turn `end_options` on, while the allowed patterns and limits replace these only where it
+    /// sets them.
+    fn merge(&mut self, o: Args) {
+        if o.allow.is_some() {
+            self.allow = o.allow;
+        }
+        self.deny.extend(o.deny);
+        if o.options.is_some() {
+            self.options = o.options;
+        }
+        self.end_options |= o.end_options;
+        if o.max_count.is_some() {
+            self.max_count = o.max_count;
+        }
+        if o.max_bytes.is_some() {
+            self.max_bytes = o.max_bytes;
+        }
+    }
+
+    /// What the arguments exceed, how much of it they take and the most allowed.
+    pub(crate) fn exceeded(&self, args: &[String]) -> Option<(&'static str, usize, usize)> {
+        if let Some(max) = self.max_count.filter(|max| args.len() > *max) {
+            return Some(("arguments", args.len(), max));
+        }
+        let bytes = args.iter().map(|arg| arg.len() + 1).sum::<usize>();
+        match self.max_bytes {
+            Some(max) if bytes > max => Some(("bytes of arguments", bytes, max)),
+            _ => None,
+        }
+    }
+
+    /// The first argument that is not allowed, and why.
+    pub(crate) fn refused<'a>(&'a self, args: &'a [String]) -> Option<(&'a str, Refusal<'a>)> {
+        let options_end = self.options_end(args);
+        args.iter().enumerate().find_map(|(i, arg)| {
+            if let Some(pattern) = self.deny.iter().find(|p| crate::glob::matches(p, arg)) {
+                return Some((arg.as_str(), Refusal::Denied(pattern)));
+            }
+            if let Some(allow) = &self.allow {
+                if !allow.iter().any(|p| crate::glob::matches(p, arg)) {
+                    return Some((arg.as_str(), Refusal::NotAllowed));
+                }
+            }
+            match &self.options {
+                Some(options)
+                    if i < options_end
+                        && arg.starts_with('-')
+                        && !options.iter().any(|p| crate::glob::matches(p, arg)) =>
+                {
+                    Some((arg.as_str(), Refusal::Option))
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Insert a `--` where the options of the invoker end, if configured to.
+    pub(crate) fn end_options(&self, args: &mut Vec<String>) {
+        if !self.end_options || args.is_empty() {
+            return;
+        }
+        let end = self.options_end(args);
+        if args.get(end).map(String::as_str) != Some("--") {
+            args.insert(end, "--".to_string());
+        }
+    }
+
+    /// The index of the first argument that is no longer an option: the first `--`, which
+    /// is only an option itself unless `end_options`, or else the first argument not
+    /// starting with `-` if `end_options`.
+    fn options_end(&self, args: &[String]) -> usize {
+        for (i, arg) in args.iter().enumerate() {
+            if arg == "--" {
+                return if self.end_options { i } else { i + 1 };
+            }
+            if self.end_options && !arg.starts_with('-') {
+                return i;
+            }
+        }
+        args.len()
+    }
+}
+
+/// Why an argument is not allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Refusal<'a> {
+    /// It matches the denied pattern.
+    Denied(&'a str),
+    /// It matches no allowed pattern.
+    NotAllowed,
+    /// It is an option matching no allowed option.
+    Option,
+}
+
+impl Exit {
+    fn parse(key: &str, value: Value) -> Result<Exit, ConfigError> {
+        let mut exit = Exit::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "remap" => {
+                    for (from, to) in table(&full, v)? {
+                        let entry = format!("{}.{}", full, from);
+                        let from = match from.parse::<u8>() {
+                            Ok(code) => code,
+                            Err(_) => {
+                                return Err(ConfigError(format!(
+                                    "{} must be keyed by exit codes from 0 to 255",
+                                    full
+                                )))
+                            }
+                        };
+                        match to {
+                            Value::Integer(n) if (0..=255).contains(&n) => {
+                                exit.remap.push((from, n as u8))
+                            }
+                            v => return Err(mismatch(&entry, "an exit code from 0 to 255", &v)),
+                        }
+                    }
+                }
+                // the band may not reach the codes of a shell that is unable to run a command
+                "band" => match v {
+                    Value::Integer(n) if (64..=94).contains(&n) || (128..=224).contains(&n) => {
+                        exit.band = Some(n as u8)
+                    }
+                    v => return Err(mismatch(&full, "a number from 64 to 94 or 128 to 224", &v)),
+                },
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(exit)
+    }
+}
+
+impl Cgroup {
+    fn parse(key: &str, value: Value) -> Result<Cgroup, ConfigError> {
+        let mut cgroup = Cgroup::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "parent" => {
+                    cgroup.parent = PathBuf::from(string(&full, v)?);
+                    if !cgroup.parent.is_absolute() {
+                        return Err(ConfigError(format!("{} must be an absolute path", full)));
+                    }
+                }
+                "memory_max" => cgroup.memory_max = Some(cgroup_value(&full, v, true)?),
+                "pids_max" => cgroup.pids_max = Some(cgroup_value(&full, v, false)?),
+                "cpu_max" => {
+                    let max = string(&full, v)?;
+                    let mut parts = max.split(' ');
+                    let quota = parts.next().unwrap_or_default();
+                    let valid = (quota == "max" || is_digits(quota))
+                        && parts.next().map_or(true, is_digits)
+                        && parts.next().is_none();
+                    if !valid {
+                        return Err(ConfigError(format!(
+                            "{} must be a quota and period such as \"50000 100000\": {:?}",
+                            full, max
+                        )));
+                    }
+                    cgroup.cpu_max = Some(max);
+                }
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(cgroup)
+    }
+}
+
+/// A limit of a cgroup, either a number, optionally with a size suffix, or `"max"`.
+fn cgroup_value(key: &str, value: Value, size: bool) -> Result<String, ConfigError> {
+    match value {
+        Value::Integer(n) if n >= 0 => Ok(n.to_string()),
+        Value::String(s) if s == "max" => Ok(s),
+        Value::String(s)
+            if size && is_digits(s.strip_suffix(['K', 'M', 'G', 'T']).unwrap_or(&s)) =>
+        {
+            Ok(s)
+        }
+        v => Err(mismatch(
+            key,
+            if size {
+                "a size such as \"512M\" or \"max\""
+            } else {
+                "a number or \"max\""
+            },
+            &v,
+        )),
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl Landlock {
+    fn parse(key: &str, value: Value) -> Result<Landlock, ConfigError> {
+        let mut landlock = Landlock::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "read_only" => landlock.read_only = absolute_paths(&full, v)?,
+                "read_write" => landlock.read_write = absolute_paths(&full, v)?,
+                "required" => landlock.required = boolean(&full, v)?,
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(landlock)
+    }
+}
+
+impl Pam {
+    fn parse(key: &str, value: Value) -> Result<Pam, ConfigError> {
+        let mut pam = Pam::default();
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "service" => {
+                    pam.service = string(&full, v)?;
+                    if pam.service.is_empty() || pam.service.contains('/') {
+                        return Err(ConfigError(format!(
+                            "{} must be a plain service name",
+                            full
+                        )));
+                    }
+                }
+                "authenticate" => pam.authenticate = boolean(&full, v)?,
+                "session" => pam.session = boolean(&full, v)?,
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        Ok(pam)
+    }
+}
+
+impl Seccomp {
+    fn parse(key: &str, value: Value) -> Result<Seccomp, ConfigError> {
+        let mut allow = None;
+        let mut action = None;
+        let mut errno = None;
+        for (k, v) in table(key, value)? {
+            let full = format!("{}.{}", key, k);
+            match k.as_str() {
+                "allow" => allow = Some(string_array(&full, v)?),
+                "action" => action = Some(string(&full, v)?),
+                "errno" => match v {
+                    Value::Integer(i) if (1..4096).contains(&i) => errno = Some(i as u16),
+                    Value::Integer(i) => {
+                        return Err(ConfigError(format!("{} is not a valid errno: {}", full, i)))
+                    }
+                    v => return Err(mismatch(&full, "an integer", &v)),
+                },
+                _ => return Err(unknown_key(&full)),
+            }
+        }
+        let action = match (action.as_deref(), errno) {
+            (None | Some("kill"), None) => SeccompAction::Kill,
+            (Some("errno"), errno) => SeccompAction::Errno(errno.unwrap_or(1)),
+            (None | Some("kill"), Some(_)) => {
+                return Err(ConfigError(format!(
+                    "{}.errno requires {}.action = \"errno\"",
+                    key, key
+                )))
+            }
+            (Some(other), _) => {
+                return Err(ConfigError(format!(
+                    "{}.action must be \"kill\" or \"errno\", found {:?}",
+                    key, other
+                )))
+            }
+        };
+        match allow {
+            Some(allow) => Ok(Seccomp { allow, action }),
+            None => Err(ConfigError(format!("{}.allow is required", key))),
+        }
+    }
+}
+
+/// Parse a positive number of seconds given on the command line.
+pub fn parse_seconds(s: &str) -> Option<Duration> {
+    match s.parse::<u64>() {
+        Ok(n) if n > 0 => Some(Duration::from_secs(n)),
+        _ => None,
+    }
+}
+
+/// Parse an octal file mode creation mask such as `027`.
+pub fn parse_umask(s: &str) -> Option<u32> {
+    if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+        return None;
+    }
+    u32::from_str_radix(s, 8).ok().filter(|m| *m <= 0o777)
+}
+
+/// Highest number of CPUs an affinity mask can hold.
+const MAX_CPUS: usize = 1024;
+
+/// Parse a list of CPUs such as `0-3,6` into sorted CPU numbers.
+pub fn parse_cpus(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in s.split(',') {
+        let (first, last): (usize, usize) = match part.split_once('-') {
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            None => {
+                let cpu = part.parse().ok()?;
+                (cpu, cpu)
+            }
+        };
+        if first > last || last >= MAX_CPUS {
+            return None;
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Some(cpus)
+}
+
+/// Look up a signal that may end a target by name, with or without the `SIG` prefix.
+#[cfg(unix)]
+pub(crate) fn signal_number(name: &str) -> Option<i32> {
+    let signal = match name.strip_prefix("SIG").unwrap_or(name) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "ALRM" => libc::SIGALRM,
+        "TERM" => libc::SIGTERM,
+        _ => return None,
+    };
+    Some(signal)
+}
+
+/// A target on Windows can not be sent a signal.
+#[cfg(windows)]
+pub(crate) fn signal_number(_name: &str) -> Option<i32> {
+    None
+}
+
+/// Whether `context` looks like an SELinux context of a user, role, type, and optional level.
+fn is_selinux_context(context: &str) -> bool {
+    let parts = context.split(':').collect::<Vec<_>>();
+    parts.len() >= 3
+        && parts[..3].iter().all(|p| !p.is_empty())
+        && !context.bytes().any(|b| b.is_ascii_whitespace() || b == 0)
+}
+
+/// Whether `action` is a polkit action id, made of lowercase letters, digits, `.` and `-`.
+fn is_polkit_action(action: &str) -> bool {
+    !action.is_empty()
+        && action
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+}
+
+/// Whether a name can be passed as `argv[0]`.
+pub fn is_arg0(name: &str) -> bool {
+    !name.is_empty() && !name.contains('\0')
+}
+
+/// Check that a name is usable as an environment variable name.
+pub fn is_env_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    matches!(bytes.next(), Some(b'A'..=b'Z' | b'a'..=b'z' | b'_'))
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Variables that influence how the target is loaded or interpreted, and can never be passed through.
+static ENV_DENYLIST: &[&str] = &[
+    "BASH_ENV",
+    "BASHOPTS",
+    "CDPATH",
+    "ENV",
+    "GCONV_PATH",
+    "GETCONF_DIR",
+    "GLOBIGNORE",
+    "HOSTALIASES",
+    "IFS",
+    "JAVA_TOOL_OPTIONS",
+    "LISTEN_FDNAMES",
+    "LISTEN_FDS",
+    "LISTEN_PID",
+    "LOCALDOMAIN",
+    "LOCPATH",
+    "MALLOC_TRACE",
+    "NIS_PATH",
+    "NLSPATH",
+    "NODE_OPTIONS",
+    "NODE_PATH",
+    "PATH",
+    "PERL5LIB",
+    "PERL5OPT",
+    "PERLLIB",
+    "PS4",
+    "PYTHONHOME",
+    "PYTHONINSPECT",
+    "PYTHONPATH",
+    "PYTHONSTARTUP",
+    "RES_OPTIONS",
+    "RESOLV_HOST_CONF",
+    "RUBYLIB",
+    "RUBYOPT",
+    "SHELLOPTS",
+    "TMPDIR",
+    "TZDIR",
+];
+
+/// Prefixes of variables that can never be passed through.
+static ENV_DENY_PREFIXES: &[&str] = &["BASH_FUNC_", "DYLD_", "LD_", "RUN_SUID_"];
+
+/// Check whether a variable describes the caller's terminal or locale.
+pub(crate) fn is_locale_env(name: &str) -> bool {
+    matches!(name, "TERM" | "LANG" | "TZ") || (name.starts_with("LC_") && is_env_name(name))
+}
+
+/// Check a terminal or locale value against a conservative allowlist, so that it can only name
+/// an entry of the system's databases and never a file of the caller's choosing.
+pub(crate) fn is_safe_locale_value(name: &str, value: &str) -> bool {
+    let allowed = |b: u8| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'+' => true,
+        b'@' | b'=' | b',' => name != "TERM",
+        b':' | b'/' => name == "TZ",
+        _ => false,
+    };
+    !value.is_empty()
+        && value.len() <= 128
+        && value.bytes().all(allowed)
+        && !value.contains("..")
+        && !value.trim_start_matches(':').starts_with('/')
+}
+
+/// Check whether a variable is on the hard-coded denylist.
+pub fn is_env_denied(name: &str) -> bool {
+    ENV_DENYLIST.contains(&name) || ENV_DENY_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+fn unknown_key(key: &str) -> ConfigError {
+    ConfigError(format!("unknown key {:?}", key))
+}
+
+fn mismatch(key: &str, expected: &str, value: &Value) -> ConfigError {
+    ConfigError(format!(
+        "{} must be {}, found {}",
+        key,
+        expected,
+        value.type_name()
+    ))
+}
+
+fn string(key: &str, value: Value) -> Result<String, ConfigError> {
+    match value {
+        Value::String(s) => Ok(s),
+        v => Err(mismatch(key, "a string", &v)),
+    }
+}
+
+fn boolean(key: &str, value: Value) -> Result<bool, ConfigError> {
+    match value {
+        Value::Boolean(b) => Ok(b),
+        v => Err(mismatch(key, "a boolean", &v)),
+    }
+}
+
+fn seconds(key: &str, value: Value) -> Result<Duration, ConfigError> {
+    match value {
+        Value::Integer(i) if i > 0 => Ok(Duration::from_secs(i as u64)),
+        v => Err(mismatch(key, "a positive number of seconds", &v)),
+    }
+}
+
+fn env_names(key: &str, value: Value, verb: &str) -> Result<Vec<String>, ConfigError> {
+    let names = string_array(key, value)?;
+    for name in names.iter() {
+        if !is_env_name(name) {
+            return Err(ConfigError(format!(
+                "{:?} is not a valid environment variable name",
+                name
+            )));
+        }
+        if is_env_denied(name) {
+            return Err(ConfigError(format!("{:?} can never be {}", name, verb)));
+        }
+    }
+    Ok(names)
+}
+
+fn patterns(key: &str, value: Value) -> Result<Vec<String>, ConfigError> {
+    let patterns = string_array(key, value)?;
+    for pattern in patterns.iter() {
+        if let Err(e) = crate::glob::validate(pattern) {
+            return Err(ConfigError(format!(
+                "{} has an invalid pattern {:?}, {}",
+                key, pattern, e
+            )));
+        }
+    }
+    Ok(patterns)
+}
+
+fn absolute_paths(key: &str, value: Value) -> Result<Vec<PathBuf>, ConfigError> {
+    let paths = string_array(key, value)?
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    match paths.iter().find(|p| !p.is_absolute()) {
+        Some(p) => Err(ConfigError(format!(
+            "{} must only contain absolute paths: {:?}",
+            key, p
+        ))),
+        None => Ok(paths),
+    }
+}
+
+fn string_array(key: &str, value: Value) -> Result<Vec<String>, ConfigError> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s),
+                v => Err(mismatch(key, "an array of strings", &v)),
+            })
+            .collect(),
+        v => Err(mismatch(key, "an array of strings", &v)),
+    }
+}
+
+fn invoker_array(key: &str, value: Value) -> Result<Vec<Invoker>, ConfigError> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|v| match v {
+                Value::Integer(i) if (0..=u32::MAX as i64).contains(&i) => {
+                    Ok(Invoker::Uid(i as u32))
+                }
+                Value::String(s) => invoker(&s).ok_or_else(|| {
+                    ConfigError(format!("{} contains an invalid user: {:?}", key, s))
+                }),
+                v => Err(mismatch(key, "an array of uids and user names", &v)),
+            })
+            .collect(),
+        v => Err(mismatch(key, "an array of uids and user names", &v)),
+    }
+}
+
+fn table(key: &str, value: Value) -> Result<Table, ConfigError> {
+    match value {
+        Value::Table(t) => Ok(t),
+        v => Err(mismatch(key, "a table", &v)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(input: &str) -> String {
+        match Config::parse(input) {
+            Ok(config) => panic!("{:?} was accepted as {:?}", input, config),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_a_configuration() {
+        let config = Config::parse(
+            "allowed_invokers = [\"alice\", 1002]\n\
+             allowed_env = [\"TZ\"]\n\
+             nice = 5\n\
+             umask = \"027\"\n\
+             cpus = \"0-1,4\"\n\
+             path = \"/usr/bin:/bin\"\n\
+             [args]\n\
+             deny = [\"--force*\"]\n\
+             max_count = 3\n\
+             [rlimits]\n\
+             nofile = 64\n\
+             core = \"unlimited\"\n\
+             [seccomp]\n\
+             allow = [\"read\"]\n\
+             action = \"errno\"\n\
+             errno = 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.allowed_invokers,
+            Some(vec![Invoker::Name("alice".into()), Invoker::Uid(1002)])
+        );
+        assert_eq!(config.allowed_env, ["TZ"]);
+        assert_eq!(config.nice, Some(5));
+        assert_eq!(config.umask, Some(0o027));
+        assert_eq!(config.cpus, Some(vec![0, 1, 4]));
+        assert_eq!(config.path.as_deref(), Some("/usr/bin:/bin"));
+        assert_eq!(config.args.deny, ["--force*"]);
+        assert_eq!(config.args.max_count, Some(3));
+        assert_eq!(
+            config.rlimits,
+            [(Rlimit::Core, None), (Rlimit::Nofile, Some(64))]
+        );
+        assert_eq!(
+            config.seccomp.map(|s| s.action),
+            Some(SeccompAction::Errno(1))
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for (input, key) in [
+            ("alowed_invokers = []", "alowed_invokers"),
+            ("[rlimits]\nfoo = 1", "rlimits.foo"),
+            ("[args]\nfoo = []", "args.foo"),
+            ("[exit]\nfoo = 1", "exit.foo"),
+            ("[seccomp]\nallow = []\nfoo = 1", "seccomp.foo"),
+            ("[landlock]\nfoo = []", "landlock.foo"),
+            ("[cgroup]\nfoo = 1", "cgroup.foo"),
+            ("[pam]\nfoo = true", "pam.foo"),
+            ("[auth]\nfoo = 1", "auth.foo"),
+            ("[user.alice]\nfoo = 1", "user.alice.foo"),
+            ("[group.wheel.args]\nfoo = []", "group.wheel.args.foo"),
+            ("[user.alice.rlimits]\nfoo = 1", "user.alice.rlimits.foo"),
+        ] {
+            assert_eq!(rejected(input), format!("unknown key {:?}", key));
+        }
+    }
+
+    #[test]
+    fn rejects_type_mismatches() {
+        for (input, message) in [
+            (
+                "nice = \"5\"",
+                "nice must be a niceness from -20 to 19, found string",
+            ),
+            ("syslog = 1", "syslog must be a boolean, found integer"),
+            ("umask = 27", "umask must be a string, found integer"),
+            (
+                "allowed_env = \"TZ\"",
+                "allowed_env must be an array of strings, found string",
+            ),
+            (
+                "default_args = [1]",
+                "default_args must be an array of strings, found integer",
+            ),
+            ("args = []", "args must be a table, found array"),
+            (
+                "[args]\nmax_count = -1",
+                "args.max_count must be a number of at least 0, found integer",
+            ),
+            (
+                "[rlimits]\nnofile = \"many\"",
+                "rlimits.nofile must be a limit or \"unlimited\", found string",
+            ),
+            (
+                "timeout = 0",
+                "timeout must be a positive number of seconds, found integer",
+            ),
+            (
+                "allowed_invokers = [true]",
+                "allowed_invokers must be an array of uids and user names, found boolean",
+            ),
+        ] {
+            assert_eq!(rejected(input), message);
+        }
+        assert!(rejected("allowed_invokers = [\"a b\"]").contains("invalid user"));
+        assert!(
+            rejected("allowed_env = [\"1X\"]").contains("not a valid environment variable name")
+        );
+        assert!(rejected("[args]\ndeny = [\"[a\"]").contains("invalid pattern"));
+    }
+
+    #[test]
+    fn checks_ranges() {
+        for (key, low, high) in [
+            ("nice", -20, 19),
+            ("oom_score_adj", -1000, 1000),
+            ("max_instances", 1, 1024),
+        ] {
+            for n in [low, high] {
+                assert!(
+                    Config::parse(&format!("{} = {}", key, n)).is_ok(),
+                    "{} {}",
+                    key,
+                    n
+                );
+            }
+            for n in [low - 1, high + 1] {
+                assert!(
+                    Config::parse(&format!("{} = {}", key, n)).is_err(),
+                    "{} {}",
+                    key,
+                    n
+                );
+            }
+        }
+        for mask in ["0", "027", "777", "0777"] {
+            assert!(
+                Config::parse(&format!("umask = \"{}\"", mask)).is_ok(),
+                "{}",
+                mask
+            );
+        }
+        for mask in ["", "8", "1000", "00000", "+27", "-1", "0x7", " 27"] {
+            assert!(rejected(&format!("umask = \"{}\"", mask)).starts_with("umask must be"));
+        }
+        assert!(Config::parse("[exit]\nband = 64").is_ok());
+        assert!(Config::parse("[exit]\nband = 100").is_err());
+        assert!(Config::parse("[exit.remap]\n256 = 1").is_err());
+        assert!(Config::parse("[seccomp]\nallow = []\naction = \"errno\"\nerrno = 4096").is_err());
+        assert!(Config::parse("[seccomp]\nallow = []\nerrno = 1").is_err());
+    }
+
+    #[test]
+    fn requires_absolute_paths() {
+        for key in [
+            "env_file",
+            "chroot",
+            "record_dir",
+            "lock_dir",
+            "stdout_file",
+            "stderr_file",
+            "audit_log",
+            "working_dir",
+        ] {
+            assert!(
+                Config::parse(&format!("{} = \"/srv/x\"", key)).is_ok(),
+                "{}",
+                key
+            );
+            for path in ["srv/x", "./x", ""] {
+                let err = rejected(&format!("{} = \"{}\"", key, path));
+                assert!(err.contains("must be an absolute path"), "{}: {}", key, err);
+            }
+        }
+        assert!(rejected("[auth]\ntimestamp_dir = \"ts\"").contains("absolute path"));
+        assert!(rejected("[cgroup]\nparent = \"x\"").contains("absolute path"));
+        assert!(rejected("[landlock]\nread_only = [\"/usr\", \"lib\"]").contains("absolute paths"));
+    }
+
+    #[test]
+    fn checks_path_entries() {
+        assert!(Config::parse("path = \"/usr/local/bin:/usr/bin\"").is_ok());
+        assert_eq!(rejected("path = \"\""), "path must not be empty");
+        for (path, entry) in [
+            ("/usr/bin:", ""),
+            (":/usr/bin", ""),
+            ("/usr/bin::/bin", ""),
+            ("/usr/bin:.", "."),
+            ("bin:/usr/bin", "bin"),
+        ] {
+            assert_eq!(
+                rejected(&format!("path = \"{}\"", path)),
+                format!("path must only list absolute directories: {:?}", entry)
+            );
+        }
+    }
+
+    #[test]
+    fn merges_the_args_of_an_override() {
+        let config = Config::parse(
+            "[args]\n\
+             deny = [\"--force\"]\n\
+             options = [\"-v\"]\n\
+             end_options = true\n\
+             max_count = 4\n\
+             max_bytes = 100\n\
+             [user.alice.args]\n\
+             allow = [\"status\", \"-v\"]\n\
+             deny = [\"--all\"]\n\
+             max_count = 2\n",
+        )
+        .unwrap();
+        let mut other = config.clone();
+        assert!(other.apply_overrides(1002, Some("bob"), &[]).is_empty());
+        assert_eq!(other.args, config.args);
+
+        let mut alice = config;
+        assert_eq!(
+            alice.apply_overrides(1001, Some("alice"), &[]),
+            ["user.alice"]
+        );
+        assert_eq!(
+            alice.args,
+            Args {
+                allow: Some(vec!["status".into(), "-v".into()]),
+                deny: vec!["--force".into(), "--all".into()],
+                options: Some(vec!["-v".into()]),
+                end_options: true,
+                max_count: Some(2),
+                max_bytes: Some(100),
+            }
+        );
+        // allowed by the override, but still denied by the base
+        let args = ["--force".to_string()];
+        assert_eq!(
+            alice.args.refused(&args).map(|(arg, _)| arg),
+            Some("--force")
+        );
+    }
+
+    #[test]
+    fn parses_cpus() {
+        assert_eq!(parse_cpus("0"), Some(vec![0]));
+        assert_eq!(parse_cpus("0-3,6"), Some(vec![0, 1, 2, 3, 6]));
+        assert_eq!(parse_cpus("6,2-3,3"), Some(vec![2, 3, 6]));
+        assert_eq!(parse_cpus("1023"), Some(vec![1023]));
+        for list in ["", "1024", "3-1", "0-", "-1", "a", "0,,1", "0-1-2", " 1"] {
+            assert_eq!(parse_cpus(list), None, "{:?}", list);
+        }
+    }
+
+    #[test]
+    fn parses_allow_lists() {
+        let list = parse_allow_list("# operators\n\nalice\n  1002  # bob\nbuild-bot\n").unwrap();
+        assert_eq!(
+            list,
+            [
+                Invoker::Name("alice".into()),
+                Invoker::Uid(1002),
+                Invoker::Name("build-bot".into())
+            ]
+        );
+        assert!(list[1].matches(1002, None));
+        assert!(!list[0].matches(1001, None));
+        assert_eq!(parse_allow_list("").unwrap(), []);
+        for (input, message) in [
+            ("alice\nbob smith\n", "line 2: invalid user \"bob smith\""),
+            ("a:b", "line 1: invalid user \"a:b\""),
+            ("../x", "line 1: invalid user \"../x\""),
+            ("99999999999", "line 1: invalid user \"99999999999\""),
+        ] {
+            assert_eq!(parse_allow_list(input).unwrap_err().to_string(), message);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! The little of the D-Bus protocol the wrapper needs to call services on the system bus,
+//! spoken directly on the socket of the bus instead of linking against a D-Bus library.
+
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+};
+
+const SYSTEM_BUS: &str = "/var/run/dbus/system_bus_socket";
+/// Replies and signals are never this large, anything longer is taken as garbage.
+const MAX_MESSAGE: usize = 1 << 20;
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// A connection to the system bus.
+pub(crate) struct Bus {
+    stream: UnixStream,
+    serial: u32,
+    /// Signals received while waiting for a reply.
+    signals: VecDeque<Message>,
+}
+
+impl Bus {
+    /// Connect to the system bus as the effective user of the wrapper.
+    pub(crate) fn system() -> Result<Bus, Error> {
+        let mut bus = Bus {
+            stream: UnixStream::connect(SYSTEM_BUS)?,
+            serial: 0,
+            signals: VecDeque::new(),
+        };
+        bus.authenticate()?;
+        bus.call(
+            ("org.freedesktop.DBus", "/org/freedesktop/DBus"),
+            ("org.freedesktop.DBus", "Hello"),
+            "",
+            &[],
+        )?;
+        Ok(bus)
+    }
+
+    /// Authenticate as the effective user, which the bus checks against the credentials of
+    /// the socket.
+    fn authenticate(&mut self) -> Result<(), Error> {
+        let uid = crate::sys::geteuid().to_string();
+        let mut line = b"\0AUTH EXTERNAL ".to_vec();
+        for b in uid.bytes() {
+            line.extend_from_slice(format!("{:02x}", b).as_bytes());
+        }
+        line.extend_from_slice(b"\r\n");
+        self.stream.write_all(&line)?;
+        // read byte by byte, as nothing past the line may be consumed
+        let mut answer = Vec::new();
+        let mut b = [0];
+        while !answer.ends_with(b"\r\n") {
+            if answer.len() > 512 {
+                return Err(invalid("the answer of the bus is too long"));
+            }
+            self.stream.read_exact(&mut b)?;
+            answer.push(b[0]);
+        }
+        if !answer.starts_with(b"OK ") {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "the bus refused the authentication",
+            ));
+        }
+        self.stream.write_all(b"BEGIN\r\n")
+    }
+
+    /// Call a method and wait for its reply, keeping the signals sent in the meantime.
+    pub(crate) fn call(
+        &mut self,
+        (destination, path): (&str, &str),
+        (interface, member): (&str, &str),
+        signature: &str,
+        body: &[u8],
+    ) -> Result<Message, Error> {
+        self.serial += 1;
+        let serial = self.serial;
+        let mut message = Writer(vec![b'l', METHOD_CALL, 0, 1]);
+        message.u32(body.len() as u32);
+        message.u32(serial);
+        let fields = message.array(8);
+        for (code, kind, value) in [
+            (FIELD_PATH, "o", path),
+            (FIELD_INTERFACE, "s", interface),
+            (FIELD_MEMBER, "s", member),
+            (FIELD_DESTINATION, "s", destination),
+        ] {
+            message.align(8);
+            message.0.push(code);
+            message.signature(kind);
+            message.string(value);
+        }
+        if !signature.is_empty() {
+            message.align(8);
+            message.0.push(FIELD_SIGNATURE);
+            message.signature("g");
+            message.signature(signature);
+        }
+        message.end_array(fields);
+        message.align(8);
+        message.0.extend_from_slice(body);
+        self.stream.write_all(&message.0)?;
+
+        loop {
+            let reply = Message::read(&mut self.stream)?;
+            if reply.kind == SIGNAL {
+                self.signals.push_back(reply);
+                continue;
+            }
+            if reply.reply_serial != Some(serial) {
+                continue;
+            }
+            match reply.kind {
+                METHOD_RETURN => return Ok(reply),
+                ERROR => {
+                    let text = reply.body().string();
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "{}: {}",
+                            reply.error.as_deref().unwrap_or("error"),
+                            text.unwrap_or_default()
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Wait for the next signal `member` of `interface`, which must have been subscribed to.
+    pub(crate) fn signal(&mut self, interface: &str, member: &str) -> Result<Message, Error> {
+        loop {
+            let signal = match self.signals.pop_front() {
+                Some(signal) => signal,
+                None => Message::read(&mut self.stream)?,
+            };
+            if signal.kind == SIGNAL
+                && signal.interface.as_deref() == Some(interface)
+                && signal.member.as_deref() == Some(member)
+            {
+                return Ok(signal);
+            }
+        }
+    }
+}
+
+/// A message received from the bus, with the header fields the calls look at.
+pub(crate) struct Message {
+    kind: u8,
+    little: bool,
+    reply_serial: Option<u32>,
+    error: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Message {
+    fn read(stream: &mut UnixStream) -> Result<Message, Error> {
+        let mut fixed = [0; 16];
+        stream.read_exact(&mut fixed)?;
+        let little = match fixed[0] {
+            b'l' => true,
+            b'B' => false,
+            _ => return Err(invalid("the bus sent a message of unknown byte order")),
+        };
+        let mut header = Reader::new(&fixed, little);
+        header.pos = 4;
+        let body_len = header.u32().unwrap_or_default() as usize;
+        header.pos = 12;
+        let fields_len = header.u32().unwrap_or_default() as usize;
+        if body_len > MAX_MESSAGE || fields_len > MAX_MESSAGE {
+            return Err(invalid("the bus sent a message that is too long"));
+        }
+        let header_len = (16 + fields_len + 7) & !7;
+        let mut buf = fixed.to_vec();
+        buf.resize(header_len + body_len, 0);
+        stream.read_exact(&mut buf[16..])?;
+
+        let mut message = Message {
+            kind: fixed[1],
+            little,
+            reply_serial: None,
+            error: None,
+            interface: None,
+            member: None,
+            body: buf[header_len..].to_vec(),
+        };
+        let mut fields = Reader::new(&buf[..16 + fields_len], little);
+        fields.pos = 16;
+        while fields.pos < 16 + fields_len {
+            fields.align(8);
+            let code = fields.byte();
+            let value = match (fields.signature(), code) {
+                (Some("u"), Some(FIELD_REPLY_SERIAL)) => {
+                    message.reply_serial = fields.u32();
+                    Some(())
+                }
+                (Some("u"), _) => fields.u32().map(drop),
+                (Some("s"), Some(code @ (FIELD_ERROR_NAME | FIELD_INTERFACE | FIELD_MEMBER))) => {
+                    let value = fields.string().map(str::to_owned);
+                    let set = value.is_some();
+                    match code {
+                        FIELD_ERROR_NAME => message.error = value,
+                        FIELD_INTERFACE => message.interface = value,
+                        _ => message.member = value,
+                    }
+                    Some(()).filter(|_| set)
+                }
+                (Some("s" | "o"), _) => fields.string().map(drop),
+                (Some("g"), _) => fields.signature().map(drop),
+                _ => None,
+            };
+            if value.is_none() {
+                return Err(invalid("the bus sent a malformed header"));
+            }
+        }
+        Ok(message)
+    }
+
+    /// The arguments of the message.
+    pub(crate) fn body(&self) -> Reader<'_> {
+        Reader::new(&self.body, self.little)
+    }
+}
+
+/// Marshalling in little endian, aligned relative to the start of the buffer, which must be
+/// where the message or its body starts.
+pub(crate) struct Writer(pub(crate) Vec<u8>);
+
+impl Writer {
+    pub(crate) fn align(&mut self, n: usize) {
+        while self.0.len() % n != 0 {
+            self.0.push(0);
+        }
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[cfg(feature = "polkit")]
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.align(8);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+    }
+
+    pub(crate) fn signature(&mut self, value: &str) {
+        self.0.push(value.len() as u8);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+    }
+
+    /// Start an array of elements aligned to `n`, returning where its length and elements are.
+    pub(crate) fn array(&mut self, n: usize) -> (usize, usize) {
+        self.u32(0);
+        let at = self.0.len() - 4;
+        self.align(n);
+        (at, self.0.len())
+    }
+
+    pub(crate) fn end_array(&mut self, (at, start): (usize, usize)) {
+        let len = (self.0.len() - start) as u32;
+        self.0[at..at + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+/// Unmarshalling of a received message, `None` once it ends early.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    little: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], little: bool) -> Reader<'a> {
+        Reader {
+            buf,
+            pos: 0,
+            little,
+        }
+    }
+
+    fn align(&mut self, n: usize) {
+        self.pos = (self.pos + n - 1) / n * n;
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.align(4);
+        let bytes = self.buf.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(match self.little {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn text(&mut self, len: usize) -> Option<&'a str> {
+        let bytes = self.buf.get(self.pos..self.pos + len)?;
+        self.pos += len + 1;
+        std::str::from_utf8(bytes).ok()
+    }
+
+    /// A string or an object path.
+    pub(crate) fn string(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        self.text(len)
+    }
+
+    fn signature(&mut self) -> Option<&'a str> {
+        let len = self.byte()? as usize;
+        self.text(len)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_owned())
+}
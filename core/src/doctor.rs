@@ -0,0 +1,279 @@
+//! Diagnosis of an installed wrapper, printing what every check expects of the files and the
+//! commands that would fix them.
+
+use std::{ffi::OsStr, fs::Metadata, os::unix::fs::MetadataExt, path::Path};
+
+use crate::{
+    env::EnvTrait,
+    nix::{
+        PERM_ANCESTOR_MASK, PERM_DIR_EXPECTED, PERM_DIR_MASK, PERM_FILE_EXPECTED, PERM_FILE_MASK,
+        PERM_POLICY_EXPECTED, PERM_POLICY_MASK,
+    },
+    Env,
+};
+
+/// Who may own a checked file.
+#[derive(Clone, Copy)]
+enum Owner {
+    /// The owner of the wrapper.
+    Wrapper,
+    /// Root or the owner of the wrapper.
+    RootOrWrapper,
+    /// Anyone, as a wrapper owned by root runs its target as the target's owner.
+    Any,
+}
+
+/// What a checked path must be.
+struct Expect {
+    dir: bool,
+    owner: Owner,
+    mask: u32,
+    mode: u32,
+    optional: bool,
+}
+
+const EXECUTABLE: Expect = Expect {
+    dir: false,
+    owner: Owner::Wrapper,
+    mask: PERM_FILE_MASK,
+    mode: PERM_FILE_EXPECTED,
+    optional: false,
+};
+const PARENT: Expect = Expect {
+    dir: true,
+    owner: Owner::Wrapper,
+    mask: PERM_DIR_MASK,
+    mode: PERM_DIR_EXPECTED,
+    optional: false,
+};
+const ANCESTOR: Expect = Expect {
+    dir: true,
+    owner: Owner::RootOrWrapper,
+    mask: PERM_ANCESTOR_MASK,
+    mode: 0,
+    optional: false,
+};
+const POLICY: Expect = Expect {
+    dir: false,
+    owner: Owner::Wrapper,
+    mask: PERM_POLICY_MASK,
+    mode: PERM_POLICY_EXPECTED,
+    optional: true,
+};
+
+struct Doctor {
+    owner: u32,
+    problems: usize,
+}
+
+/// Check the installation of the wrapper at `path`, or of this executable, and whether all is
+/// well.
+pub fn run(path: Option<&str>, call_name: &str) -> bool {
+    let (exe, call_name) = match path {
+        Some(path) => match std::fs::canonicalize(path) {
+            Ok(exe) => {
+                let name = Path::new(path).file_name().and_then(OsStr::to_str);
+                let name = name.unwrap_or_default().to_string();
+                (exe, name)
+            }
+            Err(err) => {
+                println!("FAIL  wrapper {:?}: {}", path, err);
+                return false;
+            }
+        },
+        None => match std::env::current_exe() {
+            Ok(exe) => (exe, call_name.to_string()),
+            Err(err) => {
+                println!("FAIL  wrapper: unable to find the executable: {}", err);
+                return false;
+            }
+        },
+    };
+    let parent = exe.parent().unwrap_or_else(|| Path::new("/"));
+    let owner = match std::fs::symlink_metadata(&exe) {
+        Ok(metadata) => metadata.uid(),
+        Err(err) => {
+            println!("FAIL  wrapper {:?}: {}", exe, err);
+            return false;
+        }
+    };
+    let mut doctor = Doctor { owner, problems: 0 };
+
+    doctor.check("wrapper", &exe, &EXECUTABLE);
+    doctor.check("parent directory", parent, &PARENT);
+    for dir in parent.ancestors().skip(1) {
+        doctor.check("ancestor directory", dir, &ANCESTOR);
+    }
+    let target = Env::sibling_target(parent, &call_name);
+    let target_owner = if owner == 0 {
+        Owner::Any
+    } else {
+        Owner::Wrapper
+    };
+    doctor.check(
+        "target",
+        &target,
+        &Expect {
+            owner: target_owner,
+            ..EXECUTABLE
+        },
+    );
+    doctor.check("configuration", &Env::target_config(&target), &POLICY);
+    doctor.check("allow list", &Env::target_allow_list(&target), &POLICY);
+    doctor.check("signature", &Env::target_signature(&target), &POLICY);
+
+    if doctor.problems == 0 {
+        println!("No problems found.");
+    } else {
+        println!("{} problem(s) found.", doctor.problems);
+    }
+    doctor.problems == 0
+}
+
+impl Doctor {
+    fn check(&mut self, what: &str, path: &Path, expect: &Expect) {
+        let metadata = match std::fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if expect.optional && err.kind() == std::io::ErrorKind::NotFound => {
+                println!("ok    {} {:?}: not present", what, path);
+                return;
+            }
+            Err(err) => {
+                self.fail(what, path, &err.to_string(), &[]);
+                return;
+            }
+        };
+        if metadata.file_type().is_symlink() {
+            let fix = [format!(
+                "replace the symbolic link with what it points to: {:?}",
+                path
+            )];
+            self.fail(what, path, "is a symbolic link", &fix);
+            return;
+        }
+        if metadata.is_dir() != expect.dir {
+            let kind = if expect.dir { "a directory" } else { "a file" };
+            self.fail(what, path, &format!("is not {}", kind), &[]);
+            return;
+        }
+
+        let mode = metadata.mode() & 0o7777;
+        let owner_ok = match expect.owner {
+            Owner::Wrapper => metadata.uid() == self.owner,
+            Owner::RootOrWrapper => metadata.uid() == 0 || metadata.uid() == self.owner,
+            Owner::Any => true,
+        };
+        let mode_ok = mode & expect.mask == expect.mode;
+        let actual = format!("owner {}, mode {:04o}", user_name(metadata.uid()), mode);
+        let expected = format!(
+            "expected owner {}, mode {}",
+            self.expected_owner(expect.owner),
+            describe_mode(expect)
+        );
+        let mut fix = Vec::new();
+        if !owner_ok {
+            fix.push(format!("chown {} {}", user_name(self.owner), quote(path)));
+        }
+        if !mode_ok {
+            fix.push(format!("chmod {} {}", chmod(mode, expect), quote(path)));
+        }
+        if fix.is_empty() {
+            println!("ok    {} {:?}: {}", what, path, actual);
+        } else {
+            self.fail(what, path, &format!("{}, {}", actual, expected), &fix);
+        }
+        if !expect.dir && expect.mode & 0o4000 != 0 {
+            links(what, path, &metadata);
+        }
+    }
+
+    fn fail(&mut self, what: &str, path: &Path, problem: &str, fix: &[String]) {
+        self.problems += 1;
+        println!("FAIL  {} {:?}: {}", what, path, problem);
+        for fix in fix {
+            println!("      fix: {}", fix);
+        }
+    }
+
+    fn expected_owner(&self, owner: Owner) -> String {
+        match owner {
+            Owner::Wrapper => user_name(self.owner),
+            Owner::RootOrWrapper if self.owner == 0 => "root".to_string(),
+            Owner::RootOrWrapper => format!("root or {}", user_name(self.owner)),
+            Owner::Any => "any user".to_string(),
+        }
+    }
+}
+
+/// Hard links are only refused unless the configuration allows them, so they only warn.
+fn links(what: &str, path: &Path, metadata: &Metadata) {
+    if metadata.nlink() > 1 {
+        println!(
+            "warn  {} {:?}: has {} hard links, refused unless allow_hard_links is configured",
+            what,
+            path,
+            metadata.nlink()
+        );
+    }
+}
+
+fn user_name(uid: u32) -> String {
+    match Env::user_by_uid(uid) {
+        Ok(Some(user)) => user.name,
+        _ => uid.to_string(),
+    }
+}
+
+/// The expectation of a mode in words.
+fn describe_mode(expect: &Expect) -> String {
+    let mut words = Vec::new();
+    if expect.mode & 0o4000 != 0 {
+        words.push("setuid");
+    }
+    if expect.mode & 0o500 == 0o500 {
+        words.push(if expect.dir {
+            "readable and searchable by the owner"
+        } else {
+            "readable and executable by the owner"
+        });
+    }
+    words.push("not writable by group or others");
+    words.join(", ")
+}
+
+/// The symbolic `chmod` mode that adds the missing bits and removes the refused ones, leaving
+/// every other bit as it is.
+fn chmod(mode: u32, expect: &Expect) -> String {
+    let missing = expect.mode & !mode;
+    let refused = mode & expect.mask & !expect.mode;
+    let mut parts = Vec::new();
+    let add: String = [(0o400, 'r'), (0o100, 'x'), (0o4000, 's')]
+        .iter()
+        .filter(|(bit, _)| missing & bit != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if !add.is_empty() {
+        parts.push(format!("u+{}", add));
+    }
+    let remove: String = [(0o020, 'g'), (0o002, 'o')]
+        .iter()
+        .filter(|(bit, _)| refused & bit != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if !remove.is_empty() {
+        parts.push(format!("{}-w", remove));
+    }
+    parts.join(",")
+}
+
+/// A path quoted for a POSIX shell.
+fn quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/._-+".contains(c))
+    {
+        return path.into_owned();
+    }
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
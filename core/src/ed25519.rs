@@ -0,0 +1,615 @@
+//! Ed25519 signature verification (RFC 8032), only used for checking signed targets.
+//!
+//! Nothing here handles secrets, so the arithmetic is written for clarity over constant time.
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// The curve constant `d`, little endian.
+const D: [u8; 32] = [
+    0xa3, 0x78, 0x59, 0x13, 0xca, 0x4d, 0xeb, 0x75, 0xab, 0xd8, 0x41, 0x41, 0x4d, 0x0a, 0x70, 0x00,
+    0x98, 0xe8, 0x79, 0x77, 0x79, 0x40, 0xc7, 0x8c, 0x73, 0xfe, 0x6f, 0x2b, 0xee, 0x6c, 0x03, 0x52,
+];
+/// `2 * d`, little endian.
+const D2: [u8; 32] = [
+    0x59, 0xf1, 0xb2, 0x26, 0x94, 0x9b, 0xd6, 0xeb, 0x56, 0xb1, 0x83, 0x82, 0x9a, 0x14, 0xe0, 0x00,
+    0x30, 0xd1, 0xf3, 0xee, 0xf2, 0x80, 0x8e, 0x19, 0xe7, 0xfc, 0xdf, 0x56, 0xdc, 0xd9, 0x06, 0x24,
+];
+/// A square root of -1, little endian.
+const SQRT_M1: [u8; 32] = [
+    0xb0, 0xa0, 0x0e, 0x4a, 0x27, 0x1b, 0xee, 0xc4, 0x78, 0xe4, 0x2f, 0xad, 0x06, 0x18, 0x43, 0x2f,
+    0xa7, 0xd7, 0xfb, 0x3d, 0x99, 0x00, 0x4d, 0x2b, 0x0b, 0xdf, 0xc1, 0x4f, 0x80, 0x24, 0x83, 0x2b,
+];
+/// The encoded base point.
+const BASE: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+/// `p - 2`, the exponent for inversion.
+const P_MINUS_2: [u8; 32] = [
+    0xeb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+];
+/// `(p - 5) / 8`, the exponent for square roots.
+const P_MINUS_5_DIV_8: [u8; 32] = [
+    0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x0f,
+];
+/// The group order `L` as little endian 64-bit limbs.
+const L: [u64; 4] = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0x0000000000000000,
+    0x1000000000000000,
+];
+
+/// Verify a signature of `msg` made by the key `public_key`.
+pub(crate) fn verify(public_key: &[u8; 32], msg: &[u8], signature: &[u8; 64]) -> bool {
+    let a = match Point::decode(public_key) {
+        Some(a) => a,
+        None => return false,
+    };
+    let mut r = [0u8; 32];
+    r.copy_from_slice(&signature[..32]);
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..]);
+    let s_limbs = limbs(&s);
+    if !less_than(&s_limbs, &L) {
+        return false;
+    }
+    let k = reduce(&sha512(&[&r, public_key, msg]));
+
+    let base = match Point::decode(&BASE) {
+        Some(b) => b,
+        None => return false,
+    };
+    let check = base.mul(&s).add(&a.neg().mul(&k));
+    check.encode() == r
+}
+
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let load = |i: usize| {
+            let mut w = [0u8; 8];
+            w.copy_from_slice(&b[i..i + 8]);
+            u64::from_le_bytes(w)
+        };
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.0;
+        for _ in 0..3 {
+            h = Fe::carry(h).0;
+        }
+        // every limb is below 2^51 now, subtract p if the value is at least p
+        let mut q = (h[0] + 19) >> 51;
+        for limb in h.iter().skip(1) {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let mut out = [0u8; 32];
+        let mut acc: u128 = 0;
+        let mut bits = 0;
+        let mut pos = 0;
+        for limb in h.iter() {
+            acc |= (*limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                out[pos] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                pos += 1;
+            }
+        }
+        out[pos] = acc as u8;
+        out
+    }
+
+    fn carry(mut h: [u64; 5]) -> Fe {
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        Fe(h)
+    }
+
+    fn add(&self, o: &Fe) -> Fe {
+        let mut h = [0; 5];
+        for (i, h) in h.iter_mut().enumerate() {
+            *h = self.0[i] + o.0[i];
+        }
+        Fe::carry(h)
+    }
+
+    fn sub(&self, o: &Fe) -> Fe {
+        // add 2p first so that no limb underflows
+        const TWO_P: [u64; 5] = [
+            0xfffffffffffda,
+            0xffffffffffffe,
+            0xffffffffffffe,
+            0xffffffffffffe,
+            0xffffffffffffe,
+        ];
+        let a = Fe::carry(self.0);
+        let b = Fe::carry(o.0);
+        let mut h = [0; 5];
+        for (i, h) in h.iter_mut().enumerate() {
+            *h = a.0[i] + TWO_P[i] - b.0[i];
+        }
+        Fe::carry(h)
+    }
+
+    fn neg(&self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(&self, o: &Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = o.0.map(u128::from);
+        let b19 = b.map(|x| x * 19);
+        let r = [
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        let mut h = [0u64; 5];
+        let mut c: u128 = 0;
+        for i in 0..5 {
+            let v = r[i] + c;
+            h[i] = (v as u64) & MASK;
+            c = v >> 51;
+        }
+        let v = h[0] as u128 + c * 19;
+        h[0] = (v as u64) & MASK;
+        h[1] += (v >> 51) as u64;
+        Fe::carry(h)
+    }
+
+    fn pow(&self, exp: &[u8; 32]) -> Fe {
+        let mut r = Fe::ONE;
+        for i in (0..256).rev() {
+            r = r.mul(&r);
+            if exp[i / 8] >> (i % 8) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    fn invert(&self) -> Fe {
+        self.pow(&P_MINUS_2)
+    }
+
+    fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn is_zero(&self) -> bool {
+        self.to_bytes() == [0; 32]
+    }
+
+    fn eq(&self, o: &Fe) -> bool {
+        self.to_bytes() == o.to_bytes()
+    }
+}
+
+/// A point in extended coordinates, `x = X/Z`, `y = Y/Z`, `x * y = T/Z`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    fn decode(b: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(b);
+        let mut unsigned = *b;
+        unsigned[31] &= 0x7f;
+        if y.to_bytes() != unsigned {
+            return None;
+        }
+        let sign = b[31] >> 7 == 1;
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let y2 = y.mul(&y);
+        let u = y2.sub(&Fe::ONE);
+        let v = Fe::from_bytes(&D).mul(&y2).add(&Fe::ONE);
+        let v3 = v.mul(&v).mul(&v);
+        let v7 = v3.mul(&v3).mul(&v);
+        let mut x = u.mul(&v3).mul(&u.mul(&v7).pow(&P_MINUS_5_DIV_8));
+        let vx2 = v.mul(&x).mul(&x);
+        if !vx2.eq(&u) {
+            if !vx2.eq(&u.neg()) {
+                return None;
+            }
+            x = x.mul(&Fe::from_bytes(&SQRT_M1));
+        }
+        if x.is_zero() && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(&y),
+        })
+    }
+
+    fn encode(&self) -> [u8; 32] {
+        let zinv = self.z.invert();
+        let x = self.x.mul(&zinv);
+        let y = self.y.mul(&zinv);
+        let mut out = y.to_bytes();
+        out[31] |= (x.is_negative() as u8) << 7;
+        out
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: self.x.neg(),
+            y: self.y,
+            z: self.z,
+            t: self.t.neg(),
+        }
+    }
+
+    /// Unified addition, also valid for doubling.
+    fn add(&self, o: &Point) -> Point {
+        let a = self.y.sub(&self.x).mul(&o.y.sub(&o.x));
+        let b = self.y.add(&self.x).mul(&o.y.add(&o.x));
+        let c = self.t.mul(&Fe::from_bytes(&D2)).mul(&o.t);
+        let d = self.z.add(&self.z).mul(&o.z);
+        let e = b.sub(&a);
+        let f = d.sub(&c);
+        let g = d.add(&c);
+        let h = b.add(&a);
+        Point {
+            x: e.mul(&f),
+            y: g.mul(&h),
+            z: f.mul(&g),
+            t: e.mul(&h),
+        }
+    }
+
+    fn mul(&self, scalar: &[u8; 32]) -> Point {
+        let mut r = Point::IDENTITY;
+        for i in (0..256).rev() {
+            r = r.add(&r);
+            if scalar[i / 8] >> (i % 8) & 1 == 1 {
+                r = r.add(self);
+            }
+        }
+        r
+    }
+}
+
+fn limbs(b: &[u8; 32]) -> [u64; 4] {
+    let mut l = [0u64; 4];
+    for (l, c) in l.iter_mut().zip(b.chunks_exact(8)) {
+        let mut w = [0u8; 8];
+        w.copy_from_slice(c);
+        *l = u64::from_le_bytes(w);
+    }
+    l
+}
+
+fn less_than(a: &[u64; 4], b: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+/// Reduce a 512-bit little endian number modulo `L`.
+fn reduce(h: &[u8; 64]) -> [u8; 32] {
+    let mut r = [0u64; 4];
+    for i in (0..512).rev() {
+        let bit = (h[i / 8] >> (i % 8) & 1) as u64;
+        let mut carry = bit;
+        for limb in r.iter_mut() {
+            let next = *limb >> 63;
+            *limb = (*limb << 1) | carry;
+            carry = next;
+        }
+        if !less_than(&r, &L) {
+            let mut borrow = 0u64;
+            for (limb, l) in r.iter_mut().zip(L.iter()) {
+                let (v, b1) = limb.overflowing_sub(*l);
+                let (v, b2) = v.overflowing_sub(borrow);
+                *limb = v;
+                borrow = (b1 || b2) as u64;
+            }
+        }
+    }
+    let mut out = [0u8; 32];
+    for (o, l) in out.chunks_exact_mut(8).zip(r.iter()) {
+        o.copy_from_slice(&l.to_le_bytes());
+    }
+    out
+}
+
+const SHA512_K: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+/// The initial SHA-512 state, shared with BLAKE2b as its IV.
+pub(crate) const SHA512_H0: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+/// SHA-512 over the concatenation of `parts`.
+fn sha512(parts: &[&[u8]]) -> [u8; 64] {
+    let mut state = SHA512_H0;
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    let mut block = [0u8; 128];
+    let mut fill = 0;
+    for part in parts {
+        for b in part.iter() {
+            block[fill] = *b;
+            fill += 1;
+            if fill == 128 {
+                sha512_compress(&mut state, &block);
+                fill = 0;
+            }
+        }
+    }
+    let mut tail = [0u8; 256];
+    tail[..fill].copy_from_slice(&block[..fill]);
+    tail[fill] = 0x80;
+    let end = if fill < 112 { 128 } else { 256 };
+    tail[end - 16..end].copy_from_slice(&(len as u128 * 8).to_be_bytes());
+    for block in tail[..end].chunks_exact(128) {
+        sha512_compress(&mut state, block);
+    }
+    let mut out = [0u8; 64];
+    for (o, s) in out.chunks_exact_mut(8).zip(state.iter()) {
+        o.copy_from_slice(&s.to_be_bytes());
+    }
+    out
+}
+
+fn sha512_compress(state: &mut [u64; 8], block: &[u8]) {
+    let mut w = [0u64; 80];
+    for (w, b) in w.iter_mut().zip(block.chunks_exact(8)) {
+        let mut x = [0u8; 8];
+        x.copy_from_slice(b);
+        *w = u64::from_be_bytes(x);
+    }
+    for i in 16..80 {
+        let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+        let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..80 {
+        let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(SHA512_K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        let mut out = [0u8; N];
+        for (o, i) in out.iter_mut().zip((0..hex.len()).step_by(2)) {
+            *o = u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    /// Public key, message, and signature of the first three tests of RFC 8032, section 7.1.
+    const RFC_8032: [(&str, &[u8], &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            b"\x72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            b"\xaf\x82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+             18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn verifies_the_rfc_8032_vectors() {
+        for (key, msg, signature) in RFC_8032 {
+            assert!(verify(&bytes(key), msg, &bytes(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_altered_signatures() {
+        let (key, msg, signature) = RFC_8032[2];
+        let (key, signature) = (bytes(key), bytes::<64>(signature));
+        assert!(!verify(&key, b"\xaf\x83", &signature));
+        assert!(!verify(&bytes(RFC_8032[1].0), msg, &signature));
+        for at in [0, 31, 32, 63] {
+            let mut altered = signature;
+            altered[at] ^= 1;
+            assert!(!verify(&key, msg, &altered));
+        }
+    }
+
+    #[test]
+    fn rejects_a_non_canonical_s() {
+        // the first signature of RFC 8032 with the order of the group added to S
+        let (key, msg, _) = RFC_8032[0];
+        let signature = bytes(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             4c8c7872aa064e049dbb3013fbf29380d25bf5f0595bbe24655141438e7a101b",
+        );
+        assert!(!verify(&bytes(key), msg, &signature));
+    }
+
+    #[test]
+    fn rejects_keys_that_are_not_points() {
+        let (_, msg, signature) = RFC_8032[0];
+        let signature = bytes(signature);
+        // y = 2 has no x on the curve
+        let mut key = [0u8; 32];
+        key[0] = 2;
+        assert!(Point::decode(&key).is_none());
+        assert!(!verify(&key, msg, &signature));
+        // y = p, the non-canonical encoding of y = 0
+        let mut key = [0xffu8; 32];
+        key[0] = 0xed;
+        key[31] = 0x7f;
+        assert!(Point::decode(&key).is_none());
+        assert!(!verify(&key, msg, &signature));
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    ffi::OsStr,
+    fs::{File, Metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, ExitStatus},
+};
+
+/// An entry of the user database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct User {
+    pub(crate) name: String,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) home: PathBuf,
+    pub(crate) shell: PathBuf,
+}
+
+/// The filesystem a file resides on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Mount {
+    /// Name of an untrusted kind of filesystem, such as `nfs` or `fuse`.
+    pub(crate) untrusted: Option<&'static str>,
+    /// Whether the filesystem is mounted without honoring the SUID bit.
+    pub(crate) nosuid: bool,
+}
+
+/// The kind, mode, and links of an opened file, all that the checks look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stat {
+    pub(crate) kind: Kind,
+    /// Permission bits, including the setuid bit.
+    pub(crate) mode: u32,
+    pub(crate) nlink: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+impl Stat {
+    pub(crate) fn is_file(&self) -> bool {
+        self.kind == Kind::File
+    }
+    pub(crate) fn is_dir(&self) -> bool {
+        self.kind == Kind::Dir
+    }
+    pub(crate) fn is_symlink(&self) -> bool {
+        self.kind == Kind::Symlink
+    }
+}
+
+impl From<&Metadata> for Stat {
+    fn from(metadata: &Metadata) -> Stat {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_file() {
+            Kind::File
+        } else if file_type.is_dir() {
+            Kind::Dir
+        } else if file_type.is_symlink() {
+            Kind::Symlink
+        } else {
+            Kind::Other
+        };
+        Stat {
+            kind,
+            mode: metadata.mode() & 0o7777,
+            nlink: metadata.nlink(),
+        }
+    }
+}
+
+/// Where a proxied target is placed among process groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProcessGroup {
+    /// Share the wrapper's process group, as an executed target does.
+    Inherit,
+    /// Lead a new process group, which gets the terminal while the wrapper has it.
+    Own,
+    /// Lead a new session without a controlling terminal.
+    Session,
+}
+
+pub(crate) trait EnvTrait {
+    /// Gets the effective user id, might be different from the real user id if the SUID bit is set.
+    fn geteuid() -> u32;
+    /// Gets the real user id.
+    fn getuid() -> u32;
+    /// Gets the effective group id.
+    fn getegid() -> u32;
+    /// Gets the real group id.
+    fn getgid() -> u32;
+    /// Gets the path of the running executable.
+    fn current_exe() -> Result<PathBuf, std::io::Error>;
+    /// Open a directory without following symlinks in any component of its path, and get its
+    /// owner and [Stat]. The flag is only set if it is writable by no one but its owner.
+    fn open_dir(path: &Path) -> Result<(u32, Stat, bool, File), std::io::Error>;
+    /// Open an executable beneath an opened directory without following symlinks, and get its
+    /// owner and [Stat]. The flag is only set if it is a SUID file writable by no one but
+    /// its owner. Checking the opened file means it can not be swapped before it is executed.
+    fn open_target(dir: &File, name: &OsStr) -> Result<(u32, Stat, bool, File), std::io::Error>;
+    /// Open the already opened target for reading its contents.
+    fn read_target(file: &File) -> Result<File, std::io::Error>;
+    /// Inspect the filesystem of the opened target.
+    fn target_mount(target: &Path, file: &File) -> Result<Mount, std::io::Error>;
+    /// Get the path that executes exactly the opened target, falling back to the target path.
+    fn target_program(target: &Path, file: &File) -> PathBuf;
+    /// Find the first ancestor of a directory, up to the filesystem root, that is not owned by
+    /// root or `uid` or is writable by its group or others. Gives the path, owner, and mode.
+    fn insecure_ancestor(
+        dir: &Path,
+        uid: u32,
+    ) -> Result<Option<(PathBuf, u32, u32)>, std::io::Error>;
+    /// Compute the location for the target executable.
+    fn sibling_target(parent: &Path, file_name: &str) -> PathBuf;
+    /// Compute the location of the optional configuration file for a target executable.
+    fn target_config(target: &Path) -> PathBuf;
+    /// Compute the location of the optional invoker allowlist for a target executable.
+    fn target_allow_list(target: &Path) -> PathBuf;
+    /// Compute the location of the detached signature of a target executable.
+    fn target_signature(target: &Path) -> PathBuf;
+    /// Compute the location of the directory of targets dispatched to instead of a target.
+    fn target_dir(target: &Path) -> PathBuf;
+    /// Compute the location of the state file counting the runs of a target executable.
+    fn target_stats(target: &Path) -> PathBuf;
+    /// Open a policy file, such as a target configuration, and get its owner.
+    /// The flag is only set if the file is a regular file writable by no one but its owner.
+    fn open_policy_file(path: &Path) -> Result<(u32, File, bool), std::io::Error>;
+    /// Look up a user by id, `None` if the user database has no such entry.
+    fn user_by_uid(uid: u32) -> Result<Option<User>, std::io::Error>;
+    /// Look up the name of a group by id, `None` if the group database has no such entry.
+    fn group_name(gid: u32) -> Result<Option<String>, std::io::Error>;
+    /// Get the ids of all groups the user is a member of, including the primary group.
+    fn user_groups(user: &User) -> Result<Vec<u32>, std::io::Error>;
+    /// Whether the real user could read the file without the privileges of the wrapper.
+    fn invoker_can_read(path: &Path) -> bool;
+    /// Whether the real user could write the file, or create it if it does not exist.
+    fn invoker_can_write(path: &Path) -> bool;
+    /// Open a file the invoker named for writing, creating it with `mode`, as the invoker and
+    /// without following a symlink anywhere on its way. Anything but a file not writable by
+    /// everyone is refused, and it is truncated unless `append` is set.
+    fn open_invoker_file(path: &Path, mode: u32, append: bool) -> Result<File, std::io::Error>;
+    /// Open a directory the invoker named, as the invoker and without following a symlink
+    /// anywhere on its way, failing unless the invoker may write to it.
+    fn open_invoker_dir(path: &Path) -> Result<File, std::io::Error>;
+    /// Mark every descriptor above stderr inherited from the invoker close-on-exec, and get the
+    /// ones that were not already.
+    fn close_inherited_fds() -> Result<Vec<i32>, std::io::Error>;
+    /// Create a new file only the owner may read to record a terminal session into, in a
+    /// configured directory.
+    fn create_recording(path: &Path) -> Result<File, std::io::Error>;
+    /// Ask a question on the controlling terminal and read the answer, optionally without echo.
+    fn ask_terminal(prompt: &str, echo: bool) -> Result<String, std::io::Error>;
+    /// Continue in a daemon detached from the terminal and session of the invoker, with stdin
+    /// and stdout on `/dev/null`. The invoker's process exits once the target is started, as
+    /// told through the returned pipe, or with a failure if the daemon exits first.
+    fn daemonize() -> Result<File, std::io::Error>;
+    /// Get the name of the terminal connected to stdin, if any.
+    fn tty_name() -> Option<String>;
+    /// Write an audit record to the journal with its parts as `fields`, or to the system log if
+    /// the journal is not running.
+    fn syslog(message: &str, fields: &[(&str, String)]);
+    /// Write a record of a command run or refused to the kernel audit log, where there is one.
+    fn audit_command(message: &str);
+    /// Seconds left of a lockout of the invoker, see [crate::lockout].
+    fn locked_out(uid: u32) -> Option<u64>;
+    /// Count a failed invocation of the invoker, and get how long they are now locked out for.
+    fn count_failure(uid: u32) -> Option<u64>;
+    /// Forget the failures of the invoker.
+    fn reset_failures(uid: u32);
+
+    fn prepare_command<'a, A: IntoIterator<Item = &'a str>>(
+        command: &mut Command,
+        args: A,
+        opts: &super::Opts,
+    );
+    /// `PATH` the target is started with.
+    fn target_path(opts: &super::Opts) -> String;
+    fn wait_for(child: Command, opts: super::Opts) -> Result<ExitStatus, ExitCode>;
+    /// Exit with the status of a finished child, re-raising the signal that killed it.
+    fn terminate_like(status: ExitStatus) -> ExitCode;
+    /// Replace the current process with the prepared command, only returning on failure.
+    fn exec(child: Command, opts: super::Opts) -> ExitCode;
+}
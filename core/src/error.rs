@@ -0,0 +1,84 @@
+//! Rejections of an invocation, printed as a sentence or as a JSON line for supervisors.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::output::Json;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Print every rejection that follows as JSON.
+pub fn use_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+/// Why an invocation was rejected, and what it was rejected with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    /// One of the `RET_*` codes, before it is moved into a configured band.
+    pub code: u8,
+    pub message: String,
+    /// The file or directory that failed a check.
+    pub path: Option<PathBuf>,
+    /// What the check required, and what it found instead.
+    pub mismatch: Option<(String, String)>,
+}
+
+impl Rejection {
+    pub fn new(code: u8, message: impl Into<String>) -> Rejection {
+        Rejection {
+            code,
+            message: message.into(),
+            path: None,
+            mismatch: None,
+        }
+    }
+
+    pub fn path(self, path: &Path) -> Rejection {
+        Rejection {
+            path: Some(path.to_path_buf()),
+            ..self
+        }
+    }
+
+    pub fn mismatch(self, expected: impl Display, actual: impl Display) -> Rejection {
+        Rejection {
+            mismatch: Some((expected.to_string(), actual.to_string())),
+            ..self
+        }
+    }
+
+    /// Print the rejection to stderr, with the code the wrapper exits with.
+    pub fn print(&self, exit: u8) {
+        if !JSON.load(Ordering::Relaxed) {
+            eprintln!("{}", self.message);
+            return;
+        }
+        // a code shared by several names does not tell which one it was
+        let mut names = crate::exit::EXIT_CODES
+            .iter()
+            .filter(|e| e.code == self.code);
+        let name = match (names.next(), names.next()) {
+            (Some(e), None) => Json::from(e.name),
+            _ => Json::Null,
+        };
+        let (expected, actual) = match &self.mismatch {
+            Some((expected, actual)) => {
+                (Json::from(expected.as_str()), Json::from(actual.as_str()))
+            }
+            None => (Json::Null, Json::Null),
+        };
+        let error = Json::object([
+            ("code", Json::from(exit as u32)),
+            ("name", name),
+            ("message", Json::from(self.message.as_str())),
+            ("path", self.path.as_deref().map_or(Json::Null, Json::from)),
+            ("expected", expected),
+            ("actual", actual),
+        ]);
+        eprintln!("{}", Json::object([("error", error)]));
+    }
+}
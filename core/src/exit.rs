@@ -0,0 +1,226 @@
+//! Exit codes of the wrapper itself, with what they mean and how to resolve them.
+//!
+//! Every other exit code is the target's own, or `128 + N` if the target was killed by the
+//! signal `N`.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::output::Json;
+
+/// An exit code of the wrapper as explained by `--explain-exit-code`.
+#[derive(Clone, Copy)]
+pub struct Explained {
+    pub code: u8,
+    pub name: &'static str,
+    pub meaning: &'static str,
+    pub remedy: &'static str,
+}
+
+/// Define the `RET_*` constants and the table explaining them from the same list.
+macro_rules! exit_codes {
+    ($($(#[$attr:meta])* $name:ident = $code:expr, $meaning:literal, $remedy:literal;)*) => {
+        $(
+            $(#[$attr])*
+            pub const $name: u8 = $code;
+        )*
+        pub static EXIT_CODES: &[Explained] = &[$(Explained {
+            code: $name,
+            name: stringify!($name),
+            meaning: $meaning,
+            remedy: $remedy,
+        }),*];
+    };
+}
+
+exit_codes! {
+    RET_GENERIC_ERROR = 32 | 1,
+        "The command line was invalid or the target could not be started.",
+        "Check the message printed before exiting and the usage in --help.";
+    RET_ENV_ERROR = 32 | 2,
+        "The environment of the wrapper could not be inspected or prepared.",
+        "Check the message printed before exiting, such as a file that could not be opened.";
+    RET_PRIV_DROP = 32 | 4,
+        "The privileges could not be changed to those of the target's owner.",
+        "Check that the wrapper is owned by root or by the target's owner, and is setuid.";
+    RET_INVOKER_DENIED = 32 | 5,
+        "The invoking user is not allowed to run the target.",
+        "Add the user or one of their groups to the allowlist of the target.";
+    RET_OWNER_TARGET = 32 | 6,
+        "The target executable is not owned by the owner of the wrapper.",
+        "Run the doctor subcommand for the chown command that fixes it.";
+    RET_PERM_TARGET = 32 | 6,
+        "The target executable is a symbolic link, has too many links, or has the wrong mode.",
+        "Run the doctor subcommand for the chmod command that fixes it.";
+    RET_SANDBOX = 32 | 7,
+        "The configured sandbox of the target could not be set up.",
+        "Check the Landlock, seccomp, chroot, and namespace settings of the configuration.";
+    #[allow(clippy::identity_op)]
+    RET_OWNER_EXEC = 32 | 8 | 0,
+        "The wrapper is not owned by the user it runs as.",
+        "Run the doctor subcommand for the chown command that fixes it.";
+    RET_PERM_EXEC = 32 | 8 | 1,
+        "The wrapper is not setuid, is writable by others, or has too many links.",
+        "Run the doctor subcommand for the chmod command that fixes it.";
+    RET_OWNER_PARENT = 32 | 8 | 2,
+        "The directory of the wrapper, or one of its ancestors, has the wrong owner.",
+        "Run the doctor subcommand for the chown command that fixes it.";
+    RET_PERM_PARENT = 32 | 8 | 3,
+        "The directory of the wrapper, or one of its ancestors, is writable by others.",
+        "Run the doctor subcommand for the chmod command that fixes it.";
+    #[allow(clippy::identity_op)]
+    RET_CONFIG = 32 | 16 | 0,
+        "A configuration, allowlist, or key of the target is invalid.",
+        "Fix the file named in the message printed before exiting.";
+    RET_OWNER_CONFIG = 32 | 16 | 1,
+        "A configuration or allowlist of the target is not owned by the owner of the wrapper.",
+        "Run the doctor subcommand for the chown command that fixes it.";
+    RET_PERM_CONFIG = 32 | 16 | 2,
+        "A configuration or allowlist of the target is writable by others.",
+        "Run the doctor subcommand for the chmod command that fixes it.";
+    RET_AUTH_FAILED = 32 | 16 | 3,
+        "The invoking user could not be authenticated.",
+        "Enter the password of the invoking user, on a terminal.";
+    RET_PAM_SESSION = 32 | 16 | 4,
+        "The PAM account or session of the target was refused.",
+        "Check the PAM configuration of the service and the logs of the system.";
+    RET_TARGET_DIGEST = 32 | 16 | 5,
+        "The target executable does not match its pinned SHA-256 digest.",
+        "Update the sha256 of the configuration if the target was replaced on purpose.";
+    RET_TARGET_SIGNATURE = 32 | 16 | 6,
+        "The signature of the target executable is missing or invalid.",
+        "Sign the target with minisign as NAME.run-suid.sig with the trusted key.";
+    RET_UNTRUSTED_MOUNT = 32 | 16 | 7,
+        "The target executable is on a filesystem that is mounted nosuid or not trusted.",
+        "Move the target to a local filesystem mounted without nosuid.";
+    RET_TIMEOUT = 32 | 16 | 8,
+        "The target ran for longer than its timeout and was signaled.",
+        "Raise the timeout, or find out why the target did not finish in time.";
+    RET_NO_TTY = 32 | 16 | 9,
+        "The target requires a terminal, and the wrapper was not run on one.",
+        "Run the wrapper from an interactive terminal.";
+    RET_NOT_CONFIRMED = 32 | 16 | 10,
+        "Running the target was not confirmed on the terminal.",
+        "Answer yes when asked to confirm.";
+    RET_LOCKED_OUT = 32 | 16 | 11,
+        "The invoking user is locked out after too many failed attempts.",
+        "Wait for the lockout to pass, as printed before exiting.";
+    RET_BUSY = 32 | 16 | 12,
+        "As many instances of the target as allowed are running already.",
+        "Wait for one to exit, or pass --wait-lock to wait for it.";
+    RET_ARGS_DENIED = 32 | 16 | 13,
+        "An argument given to the target is not allowed by its configuration.",
+        "Leave out the argument, or allow it in the args patterns of the configuration.";
+    RET_ARGS_TOO_LONG = 32 | 16 | 14,
+        "More or longer arguments were given to the target than its configuration allows.",
+        "Give fewer or shorter arguments, or raise max_count or max_bytes of the args configuration.";
+    // the codes of a shell that is unable to run a command
+    RET_NOT_EXECUTABLE = 126,
+        "The target executable exists but could not be executed.",
+        "Check that the target is a valid executable for this system, on a filesystem mounted without noexec.";
+    RET_NO_TARGET = 127,
+        "No target executable was found next to the wrapper, or its interpreter was not found.",
+        "Install the target as NAME.run-suid next to the wrapper, see the install subcommand.";
+}
+
+/// First code of the configured band of the wrapper's own exit codes, zero if not moved.
+static BAND: AtomicU8 = AtomicU8::new(0);
+
+/// Move the wrapper's own exit codes into the band starting at `base`, so that they do not
+/// collide with the exit codes of the target.
+pub(crate) fn set_band(base: u8) {
+    BAND.store(base, Ordering::Relaxed);
+}
+
+/// The code the wrapper exits with for one of its own, within the configured band if any.
+///
+/// Only reads an atomic, so that it is safe to call in the forked child.
+pub(crate) fn own(code: u8) -> u8 {
+    match BAND.load(Ordering::Relaxed) {
+        base if base != 0 && (32..64).contains(&code) => base + (code - 32),
+        _ => code,
+    }
+}
+
+/// The exit code of a failure to execute the target, as a shell would exit.
+pub(crate) fn exec_failure(err: &std::io::Error) -> u8 {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => RET_NO_TARGET,
+        _ => RET_NOT_EXECUTABLE,
+    }
+}
+
+/// Everything the exit code may mean, the codes of the wrapper in the order they are defined.
+pub fn explain(code: u8) -> Vec<Explained> {
+    let mut explained: Vec<Explained> = EXIT_CODES
+        .iter()
+        .filter(|e| e.code == code)
+        .copied()
+        .collect();
+    // Windows has no signals to exit like
+    #[cfg(unix)]
+    if code > 128 {
+        let signal = (code - 128) as i32;
+        explained.push(Explained {
+            code,
+            name: "",
+            meaning: "The target was killed by a signal, which the wrapper exits like.",
+            remedy: if signal == libc::SIGKILL {
+                "Check whether the target was killed for running out of memory or its timeout."
+            } else {
+                "Check what sent the signal to the target."
+            },
+        });
+    }
+    if explained.is_empty() {
+        explained.push(Explained {
+            code,
+            name: "",
+            meaning: "Not an exit code of the wrapper, so the exit code of the target.",
+            remedy: "See the documentation of the target.",
+        });
+    }
+    explained
+}
+
+/// Print what an exit code means, as text or JSON.
+pub fn print_explained(code: u8, json: bool) {
+    let explained = explain(code);
+    if json {
+        let entries = explained.iter().map(|e| {
+            Json::object([
+                ("code", Json::from(e.code as u32)),
+                (
+                    "name",
+                    match e.name {
+                        "" => Json::Null,
+                        name => Json::from(name),
+                    },
+                ),
+                ("meaning", Json::from(e.meaning)),
+                ("remedy", Json::from(e.remedy)),
+            ])
+        });
+        println!("{}", Json::Array(entries.collect()));
+        return;
+    }
+    let mut s = String::new();
+    for e in explained {
+        match e.name {
+            "" if code > 128 => {
+                let _ = writeln!(s, "{}: signal {}", code, code - 128);
+            }
+            "" => {
+                let _ = writeln!(s, "{}:", code);
+            }
+            name => {
+                let _ = writeln!(s, "{}: {}", code, name);
+            }
+        }
+        let _ = writeln!(s, "  {}", e.meaning);
+        let _ = writeln!(s, "  {}", e.remedy);
+    }
+    print!("{}", s);
+}
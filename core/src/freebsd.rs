@@ -0,0 +1,250 @@
+//! FreeBSD specifics of the platform layer: the parent death signal through `procctl`, the
+//! filesystem of the target from `statfs`, and awareness of running inside a jail.
+//!
+//! Everything called from a `pre_exec` hook must be async-signal-safe: no allocation, no locks.
+
+use std::{
+    ffi::{CStr, OsStr},
+    fs::File,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, ExitStatus},
+};
+
+use crate::{
+    env::{EnvTrait, Mount, Stat, User},
+    nix::Nix,
+};
+
+/// Kinds of filesystems shared over the network or implemented by unprivileged processes.
+static UNTRUSTED_FILESYSTEMS: &[&str] = &["nfs", "smbfs", "fusefs", "p9fs"];
+
+/// Directories anyone may create files in, untrusted when backed by memory.
+static SCRATCH_DIRS: &[&str] = &["/tmp", "/var/tmp"];
+
+pub(crate) struct FreeBsd {}
+
+impl EnvTrait for FreeBsd {
+    #[inline]
+    fn geteuid() -> u32 {
+        Nix::geteuid()
+    }
+    #[inline]
+    fn getuid() -> u32 {
+        Nix::getuid()
+    }
+    #[inline]
+    fn getegid() -> u32 {
+        Nix::getegid()
+    }
+    #[inline]
+    fn getgid() -> u32 {
+        Nix::getgid()
+    }
+    #[inline]
+    fn current_exe() -> Result<PathBuf, std::io::Error> {
+        Nix::current_exe()
+    }
+    #[inline]
+    fn open_dir(path: &Path) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        Nix::open_dir(path)
+    }
+    #[inline]
+    fn open_target(dir: &File, name: &OsStr) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        Nix::open_target(dir, name)
+    }
+    #[inline]
+    fn read_target(file: &File) -> Result<File, std::io::Error> {
+        Nix::read_target(file)
+    }
+    fn target_mount(target: &Path, file: &File) -> Result<Mount, std::io::Error> {
+        target_mount(target, file)
+    }
+    #[inline]
+    fn target_program(target: &Path, file: &File) -> PathBuf {
+        Nix::target_program(target, file)
+    }
+    #[inline]
+    fn insecure_ancestor(
+        dir: &Path,
+        uid: u32,
+    ) -> Result<Option<(PathBuf, u32, u32)>, std::io::Error> {
+        Nix::insecure_ancestor(dir, uid)
+    }
+    #[inline]
+    fn sibling_target(parent: &Path, file_name: &str) -> PathBuf {
+        Nix::sibling_target(parent, file_name)
+    }
+    #[inline]
+    fn target_config(target: &Path) -> PathBuf {
+        Nix::target_config(target)
+    }
+    #[inline]
+    fn target_allow_list(target: &Path) -> PathBuf {
+        Nix::target_allow_list(target)
+    }
+    #[inline]
+    fn target_signature(target: &Path) -> PathBuf {
+        Nix::target_signature(target)
+    }
+    #[inline]
+    fn target_dir(target: &Path) -> PathBuf {
+        Nix::target_dir(target)
+    }
+    #[inline]
+    fn target_stats(target: &Path) -> PathBuf {
+        Nix::target_stats(target)
+    }
+    #[inline]
+    fn open_policy_file(path: &Path) -> Result<(u32, File, bool), std::io::Error> {
+        Nix::open_policy_file(path)
+    }
+    #[inline]
+    fn user_by_uid(uid: u32) -> Result<Option<User>, std::io::Error> {
+        Nix::user_by_uid(uid)
+    }
+    #[inline]
+    fn group_name(gid: u32) -> Result<Option<String>, std::io::Error> {
+        Nix::group_name(gid)
+    }
+    #[inline]
+    fn user_groups(user: &User) -> Result<Vec<u32>, std::io::Error> {
+        Nix::user_groups(user)
+    }
+    #[inline]
+    fn invoker_can_read(path: &Path) -> bool {
+        Nix::invoker_can_read(path)
+    }
+    #[inline]
+    fn invoker_can_write(path: &Path) -> bool {
+        Nix::invoker_can_write(path)
+    }
+    #[inline]
+    fn open_invoker_file(path: &Path, mode: u32, append: bool) -> Result<File, std::io::Error> {
+        Nix::open_invoker_file(path, mode, append)
+    }
+    #[inline]
+    fn open_invoker_dir(path: &Path) -> Result<File, std::io::Error> {
+        Nix::open_invoker_dir(path)
+    }
+    #[inline]
+    fn close_inherited_fds() -> Result<Vec<i32>, std::io::Error> {
+        Nix::close_inherited_fds()
+    }
+    #[inline]
+    fn create_recording(path: &Path) -> Result<File, std::io::Error> {
+        Nix::create_recording(path)
+    }
+    #[inline]
+    fn ask_terminal(prompt: &str, echo: bool) -> Result<String, std::io::Error> {
+        Nix::ask_terminal(prompt, echo)
+    }
+    #[inline]
+    fn daemonize() -> Result<File, std::io::Error> {
+        Nix::daemonize()
+    }
+    #[inline]
+    fn tty_name() -> Option<String> {
+        Nix::tty_name()
+    }
+    /// Records from within a jail are marked as such, as the jail shares the log of its host.
+    fn syslog(message: &str, fields: &[(&str, String)]) {
+        if jailed() {
+            Nix::syslog(&format!("JAILED=yes ; {}", message), fields);
+        } else {
+            Nix::syslog(message, fields);
+        }
+    }
+    #[inline]
+    fn audit_command(message: &str) {
+        Nix::audit_command(message)
+    }
+    #[inline]
+    fn locked_out(uid: u32) -> Option<u64> {
+        Nix::locked_out(uid)
+    }
+    #[inline]
+    fn count_failure(uid: u32) -> Option<u64> {
+        Nix::count_failure(uid)
+    }
+    #[inline]
+    fn reset_failures(uid: u32) {
+        Nix::reset_failures(uid)
+    }
+    #[inline]
+    fn prepare_command<'a, A: IntoIterator<Item = &'a str>>(
+        command: &mut Command,
+        args: A,
+        opts: &super::Opts,
+    ) {
+        Nix::prepare_command(command, args, opts)
+    }
+    #[inline]
+    fn target_path(opts: &super::Opts) -> String {
+        Nix::target_path(opts)
+    }
+    #[inline]
+    fn wait_for(child: Command, opts: super::Opts) -> Result<ExitStatus, ExitCode> {
+        Nix::wait_for(child, opts)
+    }
+    #[inline]
+    fn terminate_like(status: ExitStatus) -> ExitCode {
+        Nix::terminate_like(status)
+    }
+    #[inline]
+    fn exec(child: Command, opts: super::Opts) -> ExitCode {
+        Nix::exec(child, opts)
+    }
+}
+
+/// Inspect the filesystem of the opened target by its kind and mount flags.
+fn target_mount(target: &Path, file: &File) -> Result<Mount, std::io::Error> {
+    let mut st: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(file.as_raw_fd(), &mut st) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let kind = unsafe { CStr::from_ptr(st.f_fstypename.as_ptr()) }.to_bytes();
+    let untrusted = match UNTRUSTED_FILESYSTEMS
+        .iter()
+        .find(|name| name.as_bytes() == kind)
+    {
+        Some(name) => Some(*name),
+        None if kind == b"tmpfs" && SCRATCH_DIRS.iter().any(|d| target.starts_with(d)) => {
+            Some("tmpfs")
+        }
+        None => None,
+    };
+    Ok(Mount {
+        untrusted,
+        nosuid: st.f_flags & libc::MNT_NOSUID as u64 != 0,
+    })
+}
+
+/// Have the target signaled once its parent exits, as `PR_SET_PDEATHSIG` does on Linux.
+pub(crate) fn set_parent_death_signal(signal: i32) -> bool {
+    let mut signal = signal;
+    unsafe {
+        libc::procctl(
+            libc::P_PID,
+            libc::getpid() as libc::id_t,
+            libc::PROC_PDEATHSIG_CTL,
+            (&mut signal as *mut i32).cast(),
+        ) == 0
+    }
+}
+
+/// Whether the wrapper runs inside a jail.
+pub(crate) fn jailed() -> bool {
+    let mut jailed: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>();
+    let r = unsafe {
+        libc::sysctlbyname(
+            b"security.jail.jailed\0".as_ptr().cast(),
+            (&mut jailed as *mut libc::c_int).cast(),
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    };
+    r == 0 && jailed != 0
+}
@@ -0,0 +1,215 @@
+//! Shell-style wildcard patterns, matched against whole arguments of the target.
+//!
+//! `*` matches any run of characters, `?` any single one, and `[...]` one of a set of
+//! characters or ranges such as `[a-z_]`, negated by a leading `!`. A `\` takes the next
+//! character literally.
+
+/// Check that a pattern is complete, so that a typo is reported with the configuration.
+pub(crate) fn validate(pattern: &str) -> Result<(), &'static str> {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < p.len() {
+        i = match p[i] {
+            '\\' if i + 1 == p.len() => return Err("it ends in an escaping backslash"),
+            '\\' => i + 2,
+            '[' => match class(&p, i, '\0') {
+                Some((end, _)) => end,
+                None => return Err("a [ is never closed"),
+            },
+            _ => i + 1,
+        };
+    }
+    Ok(())
+}
+
+/// Whether all of `text` matches `pattern`.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let t = text.chars().collect::<Vec<_>>();
+    let (mut pi, mut ti) = (0, 0);
+    // the last `*` and where in the text it is tried to end next, for backtracking
+    let mut star = None;
+    while ti < t.len() {
+        if p.get(pi) == Some(&'*') {
+            star = Some((pi, ti));
+            pi += 1;
+            continue;
+        }
+        if let Some(next) = single(&p, pi, t[ti]) {
+            pi = next;
+            ti += 1;
+            continue;
+        }
+        match star {
+            Some((sp, st)) => {
+                star = Some((sp, st + 1));
+                pi = sp + 1;
+                ti = st + 1;
+            }
+            None => return false,
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Match `c` against the part of a pattern at `at` that is not a `*`, returning where the
+/// pattern continues.
+fn single(p: &[char], at: usize, c: char) -> Option<usize> {
+    match *p.get(at)? {
+        '?' => Some(at + 1),
+        '\\' => Some(at + 2).filter(|_| p.get(at + 1) == Some(&c)),
+        '[' => class(p, at, c)
+            .filter(|(_, found)| *found)
+            .map(|(end, _)| end),
+        l => Some(at + 1).filter(|_| l == c),
+    }
+}
+
+/// The end of the set starting at `at`, and whether `c` is in it, `None` if it is not closed.
+/// A `]` right after the opening one is a member of the set.
+fn class(p: &[char], at: usize, c: char) -> Option<(usize, bool)> {
+    let mut i = at + 1;
+    let negated = p.get(i) == Some(&'!');
+    if negated {
+        i += 1;
+    }
+    let start = i;
+    let mut found = false;
+    loop {
+        let lo = *p.get(i)?;
+        if lo == ']' && i > start {
+            return Some((i + 1, found != negated));
+        }
+        match (p.get(i + 1), p.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                found |= lo <= c && c <= hi;
+                i += 3;
+            }
+            _ => {
+                found |= lo == c;
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_stars_and_question_marks() {
+        for (pattern, text) in [
+            ("", ""),
+            ("*", ""),
+            ("*", "anything"),
+            ("--out=*", "--out=file"),
+            ("--out=*", "--out="),
+            ("*.txt", "notes.txt"),
+            ("a*b*c", "axxbyyc"),
+            ("a*b*c", "abbbc"),
+            ("**", "x"),
+            ("?", "x"),
+            ("-?", "-v"),
+            ("??*", "ab"),
+            ("é?", "éa"),
+        ] {
+            assert!(
+                matches(pattern, text),
+                "{:?} did not match {:?}",
+                pattern,
+                text
+            );
+        }
+        for (pattern, text) in [
+            ("", "x"),
+            ("?", ""),
+            ("?", "ab"),
+            ("-?", "-vv"),
+            ("*.txt", "notes.txt.sh"),
+            ("a*b*c", "axxbyy"),
+            ("??*", "a"),
+        ] {
+            assert!(!matches(pattern, text), "{:?} matched {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn matches_the_whole_argument() {
+        assert!(matches("--force", "--force"));
+        assert!(!matches("--force", "x--force"));
+        assert!(!matches("--force", "--forced"));
+        assert!(!matches("--force*", "x--force"));
+        assert!(!matches("*--force", "--force=1"));
+        assert!(matches("*--force*", "x--force=1"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        for (pattern, text) in [
+            ("[abc]", "b"),
+            ("[a-z_]", "q"),
+            ("[a-z_]", "_"),
+            ("[a-cx-z]", "y"),
+            ("[!a-z]", "Q"),
+            ("[]]", "]"),
+            ("[!]]", "x"),
+            ("[a-]", "-"),
+            ("[-a]", "-"),
+            ("[*?]", "*"),
+            ("-[0-9]*", "-9k"),
+        ] {
+            assert!(
+                matches(pattern, text),
+                "{:?} did not match {:?}",
+                pattern,
+                text
+            );
+        }
+        for (pattern, text) in [
+            ("[abc]", "d"),
+            ("[abc]", "ab"),
+            ("[abc]", ""),
+            ("[a-z_]", "-"),
+            ("[!a-z]", "q"),
+            ("[!]]", "]"),
+            ("[*?]", "x"),
+            ("[", "["),
+        ] {
+            assert!(!matches(pattern, text), "{:?} matched {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn matches_escapes_literally() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+        assert!(matches("a\\?", "a?"));
+        assert!(!matches("a\\?", "ab"));
+        assert!(matches("\\[x]", "[x]"));
+        assert!(!matches("\\[x]", "x"));
+        assert!(matches("\\\\", "\\"));
+        assert!(matches("x\\*\\**", "x**yz"));
+    }
+
+    #[test]
+    fn validates_patterns() {
+        for pattern in [
+            "", "*", "a?b", "[a-z]", "[]]", "[!]x]", "\\[", "\\\\", "[\\]",
+        ] {
+            assert_eq!(validate(pattern), Ok(()), "{:?}", pattern);
+        }
+        for (pattern, message) in [
+            ("[", "a [ is never closed"),
+            ("[a-z", "a [ is never closed"),
+            ("[]", "a [ is never closed"),
+            ("[!]", "a [ is never closed"),
+            ("x*[", "a [ is never closed"),
+            ("\\", "it ends in an escaping backslash"),
+            ("abc\\", "it ends in an escaping backslash"),
+            ("\\\\\\", "it ends in an escaping backslash"),
+        ] {
+            assert_eq!(validate(pattern), Err(message), "{:?}", pattern);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! illumos specifics of the platform layer: dropping the privileges(5) of the target to the
+//! basic set, the process contract of the wrapper, and waiting without `wait4`.
+//!
+//! Everything called from a `pre_exec` hook must be async-signal-safe: no allocation, no locks.
+
+use std::{
+    ffi::OsStr,
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, ExitStatus},
+};
+
+use crate::{
+    env::{EnvTrait, Mount, Stat, User},
+    nix::Nix,
+};
+
+#[allow(non_camel_case_types)]
+enum priv_set_t {}
+
+/// `PRIV_SET` of `priv_op_t`.
+const PRIV_SET: libc::c_int = 2;
+static PRIV_PERMITTED: &[u8] = b"Permitted\0";
+static PRIV_INHERITABLE: &[u8] = b"Inheritable\0";
+
+extern "C" {
+    fn priv_allocset() -> *mut priv_set_t;
+    fn priv_freeset(set: *mut priv_set_t);
+    fn priv_basicset(set: *mut priv_set_t);
+    fn setppriv(op: libc::c_int, which: *const libc::c_char, set: *const priv_set_t)
+        -> libc::c_int;
+    fn getctid() -> libc::ctid_t;
+}
+
+pub(crate) struct Illumos {}
+
+impl EnvTrait for Illumos {
+    #[inline]
+    fn geteuid() -> u32 {
+        Nix::geteuid()
+    }
+    #[inline]
+    fn getuid() -> u32 {
+        Nix::getuid()
+    }
+    #[inline]
+    fn getegid() -> u32 {
+        Nix::getegid()
+    }
+    #[inline]
+    fn getgid() -> u32 {
+        Nix::getgid()
+    }
+    #[inline]
+    fn current_exe() -> Result<PathBuf, std::io::Error> {
+        Nix::current_exe()
+    }
+    #[inline]
+    fn open_dir(path: &Path) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        Nix::open_dir(path)
+    }
+    #[inline]
+    fn open_target(dir: &File, name: &OsStr) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        Nix::open_target(dir, name)
+    }
+    #[inline]
+    fn read_target(file: &File) -> Result<File, std::io::Error> {
+        Nix::read_target(file)
+    }
+    #[inline]
+    fn target_mount(target: &Path, file: &File) -> Result<Mount, std::io::Error> {
+        Nix::target_mount(target, file)
+    }
+    #[inline]
+    fn target_program(target: &Path, file: &File) -> PathBuf {
+        Nix::target_program(target, file)
+    }
+    #[inline]
+    fn insecure_ancestor(
+        dir: &Path,
+        uid: u32,
+    ) -> Result<Option<(PathBuf, u32, u32)>, std::io::Error> {
+        Nix::insecure_ancestor(dir, uid)
+    }
+    #[inline]
+    fn sibling_target(parent: &Path, file_name: &str) -> PathBuf {
+        Nix::sibling_target(parent, file_name)
+    }
+    #[inline]
+    fn target_config(target: &Path) -> PathBuf {
+        Nix::target_config(target)
+    }
+    #[inline]
+    fn target_allow_list(target: &Path) -> PathBuf {
+        Nix::target_allow_list(target)
+    }
+    #[inline]
+    fn target_signature(target: &Path) -> PathBuf {
+        Nix::target_signature(target)
+    }
+    #[inline]
+    fn target_dir(target: &Path) -> PathBuf {
+        Nix::target_dir(target)
+    }
+    #[inline]
+    fn target_stats(target: &Path) -> PathBuf {
+        Nix::target_stats(target)
+    }
+    #[inline]
+    fn open_policy_file(path: &Path) -> Result<(u32, File, bool), std::io::Error> {
+        Nix::open_policy_file(path)
+    }
+    #[inline]
+    fn user_by_uid(uid: u32) -> Result<Option<User>, std::io::Error> {
+        Nix::user_by_uid(uid)
+    }
+    #[inline]
+    fn group_name(gid: u32) -> Result<Option<String>, std::io::Error> {
+        Nix::group_name(gid)
+    }
+    #[inline]
+    fn user_groups(user: &User) -> Result<Vec<u32>, std::io::Error> {
+        Nix::user_groups(user)
+    }
+    #[inline]
+    fn invoker_can_read(path: &Path) -> bool {
+        Nix::invoker_can_read(path)
+    }
+    #[inline]
+    fn invoker_can_write(path: &Path) -> bool {
+        Nix::invoker_can_write(path)
+    }
+    #[inline]
+    fn open_invoker_file(path: &Path, mode: u32, append: bool) -> Result<File, std::io::Error> {
+        Nix::open_invoker_file(path, mode, append)
+    }
+    #[inline]
+    fn open_invoker_dir(path: &Path) -> Result<File, std::io::Error> {
+        Nix::open_invoker_dir(path)
+    }
+    #[inline]
+    fn close_inherited_fds() -> Result<Vec<i32>, std::io::Error> {
+        Nix::close_inherited_fds()
+    }
+    #[inline]
+    fn create_recording(path: &Path) -> Result<File, std::io::Error> {
+        Nix::create_recording(path)
+    }
+    #[inline]
+    fn ask_terminal(prompt: &str, echo: bool) -> Result<String, std::io::Error> {
+        Nix::ask_terminal(prompt, echo)
+    }
+    #[inline]
+    fn daemonize() -> Result<File, std::io::Error> {
+        Nix::daemonize()
+    }
+    #[inline]
+    fn tty_name() -> Option<String> {
+        Nix::tty_name()
+    }
+    /// Records name the process contract, which ties the invocation to its SMF service.
+    fn syslog(message: &str, fields: &[(&str, String)]) {
+        Nix::syslog(&format!("CTID={} ; {}", contract_id(), message), fields);
+    }
+    #[inline]
+    fn audit_command(message: &str) {
+        Nix::audit_command(message)
+    }
+    #[inline]
+    fn locked_out(uid: u32) -> Option<u64> {
+        Nix::locked_out(uid)
+    }
+    #[inline]
+    fn count_failure(uid: u32) -> Option<u64> {
+        Nix::count_failure(uid)
+    }
+    #[inline]
+    fn reset_failures(uid: u32) {
+        Nix::reset_failures(uid)
+    }
+    #[inline]
+    fn prepare_command<'a, A: IntoIterator<Item = &'a str>>(
+        command: &mut Command,
+        args: A,
+        opts: &super::Opts,
+    ) {
+        Nix::prepare_command(command, args, opts)
+    }
+    #[inline]
+    fn target_path(opts: &super::Opts) -> String {
+        Nix::target_path(opts)
+    }
+    #[inline]
+    fn wait_for(child: Command, opts: super::Opts) -> Result<ExitStatus, ExitCode> {
+        Nix::wait_for(child, opts)
+    }
+    #[inline]
+    fn terminate_like(status: ExitStatus) -> ExitCode {
+        Nix::terminate_like(status)
+    }
+    #[inline]
+    fn exec(child: Command, opts: super::Opts) -> ExitCode {
+        Nix::exec(child, opts)
+    }
+}
+
+/// The process contract of the wrapper, which the target is started in as well.
+pub(crate) fn contract_id() -> i32 {
+    unsafe { getctid() as i32 }
+}
+
+/// The basic privileges, allocated before forking as the child may not allocate.
+pub(crate) struct BasicPrivileges(*mut priv_set_t);
+
+// only ever read, by the child it is handed to
+unsafe impl Send for BasicPrivileges {}
+unsafe impl Sync for BasicPrivileges {}
+
+impl BasicPrivileges {
+    /// `None` when the set could not be allocated, leaving only the change of ids.
+    pub(crate) fn new() -> Option<BasicPrivileges> {
+        let set = unsafe { priv_allocset() };
+        if set.is_null() {
+            return None;
+        }
+        unsafe { priv_basicset(set) };
+        Some(BasicPrivileges(set))
+    }
+
+    /// Shrink the permitted and inheritable privileges to the basic ones, from a `pre_exec`
+    /// hook once the ids have changed. The effective set shrinks with the permitted one.
+    pub(crate) fn restrict(&self) -> Result<(), &'static [u8]> {
+        for which in [PRIV_PERMITTED, PRIV_INHERITABLE] {
+            if unsafe { setppriv(PRIV_SET, which.as_ptr().cast(), self.0) } != 0 {
+                return Err(b"Unable to drop the privileges of the target user.\n");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BasicPrivileges {
+    fn drop(&mut self) {
+        unsafe { priv_freeset(self.0) };
+    }
+}
+
+/// `wait4` is missing on illumos, so the usage is that of every reaped child, which is only
+/// ever the target.
+pub(crate) unsafe fn wait4(
+    pid: libc::pid_t,
+    status: *mut libc::c_int,
+    options: libc::c_int,
+    usage: *mut libc::rusage,
+) -> libc::pid_t {
+    let r = libc::waitpid(pid, status, options);
+    if r > 0 && (libc::WIFEXITED(*status) || libc::WIFSIGNALED(*status)) {
+        libc::getrusage(libc::RUSAGE_CHILDREN, usage);
+    }
+    r
+}
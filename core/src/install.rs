@@ -0,0 +1,196 @@
+//! Installation of the wrapper next to a target, with the ownership and modes every check of
+//! the wrapper requires.
+
+use std::{
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use crate::{env::EnvTrait, sys, Env};
+
+/// Mode of an installed wrapper, which anyone may execute.
+const WRAPPER_MODE: u32 = 0o4511;
+/// Mode of an installed target, which only its owner may execute.
+const TARGET_MODE: u32 = 0o4500;
+
+/// Options of the `install` subcommand.
+#[derive(Debug, Default)]
+struct Install {
+    target: Option<PathBuf>,
+    dest: Option<PathBuf>,
+    link: bool,
+}
+
+fn parse(options: &[String]) -> Result<Install, String> {
+    let mut parsed = Install::default();
+    let mut iter = options.iter();
+    while let Some(arg) = iter.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let slot = match name {
+            "--target" => &mut parsed.target,
+            "--as" => &mut parsed.dest,
+            "--link" if inline.is_none() => {
+                parsed.link = true;
+                continue;
+            }
+            _ => return Err(format!("Unexpected argument of install: {:?}", arg)),
+        };
+        let value = match inline.or_else(|| iter.next().map(String::as_str)) {
+            Some(value) if Path::new(value).is_absolute() => value,
+            Some(value) => {
+                return Err(format!(
+                    "The path of {} must be absolute: {:?}",
+                    name, value
+                ))
+            }
+            None => return Err(format!("The option {} needs a value.", name)),
+        };
+        *slot = Some(PathBuf::from(value));
+    }
+    Ok(parsed)
+}
+
+/// Install the wrapper as `--as`, and move `--target` to where that wrapper looks for it.
+///
+/// Everything is owned by the invoker, whose privileges are the only ones used. The files are
+/// given their ownership and modes under temporary names before being renamed into place.
+pub fn run(options: &[String]) -> bool {
+    let install = match parse(options) {
+        Ok(Install {
+            target: Some(target),
+            dest: Some(dest),
+            link,
+        }) => (target, dest, link),
+        Ok(_) => {
+            eprintln!("Usage: install --target=PATH --as=PATH [--link]");
+            return false;
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+    let (source, dest, link) = install;
+    match install_files(&source, &dest, link) {
+        Ok(()) => crate::doctor::run(Some(&dest.to_string_lossy()), ""),
+        Err(err) => {
+            eprintln!("{}", err);
+            false
+        }
+    }
+}
+
+fn install_files(source: &Path, dest: &Path, link: bool) -> Result<(), String> {
+    let wrapper = std::env::current_exe()
+        .map_err(|e| format!("Unable to find the executable of the wrapper: {}", e))?;
+    let dir = dest.parent().unwrap_or_else(|| Path::new("/"));
+    let name = dest
+        .file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| format!("Invalid name of the wrapper: {:?}", dest))?;
+    // a link is resolved to the wrapper, which looks for the target next to itself
+    let target_dir = match link {
+        true => wrapper.parent().unwrap_or_else(|| Path::new("/")),
+        false => dir,
+    };
+    let target = Env::sibling_target(target_dir, name);
+    for path in [dest, &target] {
+        if std::fs::symlink_metadata(path).is_ok() {
+            return Err(format!("Refusing to replace an existing file: {:?}", path));
+        }
+    }
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o755)
+        .create(dir)
+        .map_err(|e| format!("Unable to create the directory {:?}: {}", dir, e))?;
+
+    match std::fs::symlink_metadata(source) {
+        Ok(metadata) if metadata.is_file() => {}
+        Ok(_) => return Err(format!("The target must be a file: {:?}", source)),
+        Err(err) => return Err(format!("Unable to find the target {:?}: {}", source, err)),
+    }
+
+    // the target is in place before the wrapper that runs it
+    let tmp = temporary(&target);
+    move_file(source, &tmp)?;
+    if let Err(err) = finish(&tmp, &target, TARGET_MODE) {
+        let _ = move_file(&tmp, source);
+        return Err(err);
+    }
+    let result = if link {
+        std::os::unix::fs::symlink(&wrapper, dest)
+            .map_err(|e| format!("Unable to link {:?} to the wrapper: {}", dest, e))
+    } else {
+        let tmp = temporary(dest);
+        let result = copy_file(&wrapper, &tmp).and_then(|_| finish(&tmp, dest, WRAPPER_MODE));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        result
+    };
+    if result.is_err() {
+        let _ = move_file(&target, source);
+    }
+    result
+}
+
+/// A name next to `path` that is hidden and not looked up by the wrapper.
+fn temporary(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.install", name))
+}
+
+fn move_file(source: &Path, dest: &Path) -> Result<(), String> {
+    match std::fs::rename(source, dest) {
+        Ok(()) => Ok(()),
+        // another filesystem, so the target is copied instead
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            copy_file(source, dest)?;
+            std::fs::remove_file(source)
+                .map_err(|e| format!("Unable to remove the target {:?}: {}", source, e))
+        }
+        Err(err) => Err(format!(
+            "Unable to move {:?} to {:?}: {}",
+            source, dest, err
+        )),
+    }
+}
+
+fn copy_file(source: &Path, dest: &Path) -> Result<(), String> {
+    let mut from = File::open(source).map_err(|e| format!("Unable to open {:?}: {}", source, e))?;
+    let mut to = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(dest)
+        .map_err(|e| format!("Unable to create {:?}: {}", dest, e))?;
+    std::io::copy(&mut from, &mut to)
+        .and_then(|_| to.sync_all())
+        .map_err(|e| format!("Unable to copy {:?} to {:?}: {}", source, dest, e))
+}
+
+/// Give a file the invoker as owner and its mode, then rename it into place.
+fn finish(tmp: &Path, dest: &Path, mode: u32) -> Result<(), String> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(tmp)
+        .and_then(|file| {
+            // changing the owner clears the setuid bit, so the mode is set after it
+            sys::fchown(&file, sys::getuid(), sys::getgid())?;
+            sys::fchmod(&file, mode)?;
+            match std::fs::symlink_metadata(dest) {
+                Ok(_) => Err(ErrorKind::AlreadyExists.into()),
+                Err(_) => std::fs::rename(tmp, dest),
+            }
+        })
+        .map_err(|e| format!("Unable to install {:?}: {}", dest, e))
+}
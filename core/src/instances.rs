@@ -0,0 +1,55 @@
+//! Limits on how many instances of a target run at once, as a lock file per allowed instance.
+
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Directory of the lock files unless configured.
+pub(crate) const LOCK_DIR: &str = run_dir!("run-suid/locks");
+
+/// How often the locks are tried again while waiting for one.
+const RETRY: Duration = Duration::from_millis(100);
+
+/// Take one of `max` locks of the target named by `key`, `None` if all are held.
+///
+/// With `wait` the locks are tried until one is free, or the optional timeout passes.
+pub(crate) fn acquire(
+    dir: &Path,
+    key: &str,
+    max: u32,
+    wait: Option<Option<Duration>>,
+) -> Result<Option<File>, String> {
+    crate::auth::private_dir(dir, "lock directory")?;
+    let mut files = Vec::new();
+    for i in 0..max {
+        let path = dir.join(format!("{}.{}", key, i));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+            .open(&path)
+            .map_err(|e| format!("Unable to open the lock {:?}: {}", path, e))?;
+        files.push(file);
+    }
+    let deadline = wait.and_then(|timeout| timeout.map(|d| Instant::now() + d));
+    loop {
+        for file in files.iter() {
+            if matches!(crate::sys::flock(file, true), Ok(true)) {
+                return file
+                    .try_clone()
+                    .map(Some)
+                    .map_err(|e| format!("Unable to keep the lock: {}", e));
+            }
+        }
+        match (wait, deadline) {
+            (None, _) => return Ok(None),
+            (Some(_), Some(at)) if Instant::now() >= at => return Ok(None),
+            _ => std::thread::sleep(RETRY),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Structured entries in the systemd journal, sent over its native socket so that the fields of
+//! every invocation can be queried with `journalctl -t run-suid`.
+
+use std::os::unix::net::UnixDatagram;
+
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Append a field, in the binary form if its value spans lines.
+fn push_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+/// Send `message` with `fields` as a notice of the authorization facility, `false` if the
+/// journal is not running or did not take it.
+pub(crate) fn send(message: &str, fields: &[(&str, String)]) -> bool {
+    let mut entry = Vec::new();
+    push_field(&mut entry, "MESSAGE", message);
+    push_field(&mut entry, "PRIORITY", &libc::LOG_NOTICE.to_string());
+    push_field(
+        &mut entry,
+        "SYSLOG_FACILITY",
+        &(libc::LOG_AUTHPRIV >> 3).to_string(),
+    );
+    push_field(&mut entry, "SYSLOG_IDENTIFIER", "run-suid");
+    for (name, value) in fields {
+        push_field(&mut entry, name, value);
+    }
+    match UnixDatagram::unbound() {
+        Ok(socket) => socket.send_to(&entry, SOCKET).is_ok(),
+        Err(_) => false,
+    }
+}
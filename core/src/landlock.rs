@@ -0,0 +1,164 @@
+//! Landlock filesystem rulesets applied to the child process right before `exec`.
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io::Error,
+    os::unix::prelude::{AsRawFd, FromRawFd, OsStrExt},
+    path::Path,
+};
+
+use crate::config::Landlock;
+
+const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_FS_IOCTL_DEV: u64 = 1 << 15;
+
+/// Rights that can be granted on a file, as opposed to a directory.
+const ACCESS_FS_FILE: u64 = ACCESS_FS_EXECUTE
+    | ACCESS_FS_WRITE_FILE
+    | ACCESS_FS_READ_FILE
+    | ACCESS_FS_TRUNCATE
+    | ACCESS_FS_IOCTL_DEV;
+const ACCESS_FS_READ_ONLY: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Get the Landlock ABI version supported by the kernel, `None` if Landlock is unavailable.
+pub(crate) fn abi_version() -> Option<i32> {
+    let r = unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_CREATE_RULESET,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if r > 0 {
+        Some(r as i32)
+    } else {
+        None
+    }
+}
+
+/// All filesystem rights known to the given ABI version.
+fn handled_access(abi: i32) -> u64 {
+    let mut access = (1 << 13) - 1;
+    if abi >= 2 {
+        access |= 1 << 13;
+    }
+    if abi >= 3 {
+        access |= ACCESS_FS_TRUNCATE;
+    }
+    if abi >= 5 {
+        access |= ACCESS_FS_IOCTL_DEV;
+    }
+    access
+}
+
+/// Create a ruleset granting read-only and read-write access beneath the configured paths.
+/// The target itself is always executable.
+pub(crate) fn create_ruleset(abi: i32, landlock: &Landlock, target: &Path) -> Result<File, String> {
+    let handled = handled_access(abi);
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_CREATE_RULESET,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        )
+    };
+    if fd < 0 {
+        return Err(format!(
+            "unable to create a Landlock ruleset: {}",
+            Error::last_os_error()
+        ));
+    }
+    let ruleset = unsafe { File::from_raw_fd(fd as i32) };
+
+    add_rule(&ruleset, target, ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE)?;
+    for path in landlock.read_only.iter() {
+        add_rule(&ruleset, path, ACCESS_FS_READ_ONLY & handled)?;
+    }
+    for path in landlock.read_write.iter() {
+        add_rule(&ruleset, path, handled)?;
+    }
+    Ok(ruleset)
+}
+
+fn add_rule(ruleset: &File, path: &Path, access: u64) -> Result<(), String> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format!("invalid Landlock path {:?}", path))?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(format!(
+            "unable to open Landlock path {:?}: {}",
+            path,
+            Error::last_os_error()
+        ));
+    }
+    let parent = unsafe { File::from_raw_fd(fd) };
+    let is_dir = parent.metadata().map(|m| m.is_dir()).unwrap_or(false);
+    let attr = PathBeneathAttr {
+        allowed_access: if is_dir {
+            access
+        } else {
+            access & ACCESS_FS_FILE
+        },
+        parent_fd: parent.as_raw_fd(),
+    };
+    let r = unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_ADD_RULE,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0u32,
+        )
+    };
+    if r != 0 {
+        return Err(format!(
+            "unable to add Landlock rule for {:?}: {}",
+            path,
+            Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+/// Enforce a ruleset on the calling process.
+///
+/// Runs between `fork` and `exec`.
+pub(crate) fn restrict_self(ruleset: i32) -> Result<(), &'static [u8]> {
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(b"Unable to set no_new_privs for the Landlock ruleset.\n");
+        }
+        if libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset, 0u32) != 0 {
+            return Err(b"Unable to enforce the Landlock ruleset.\n");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,2278 @@
+//! The checks and the setup of `run-suid`, which runs the sibling target of a setuid wrapper
+//! as the owner of both.
+//!
+//! [run] is everything the wrapper does once its command line is parsed. The checks of the
+//! ownership and modes of a wrapper and its target are in [check], and the [Rejection]s of
+//! them carry the exit codes of [exit].
+//!
+//! Windows only has a reduced mode, which runs the target as an administrator.
+
+// the reduced Windows mode leaves most of the checks and the configuration unused
+#![cfg_attr(windows, allow(dead_code))]
+#![deny(unsafe_code)]
+
+#[cfg(unix)]
+use std::{
+    ffi::{OsStr, OsString},
+    io::ErrorKind,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+    time::Duration,
+};
+
+/// A path beneath the directory of runtime state, which macOS, the BSDs, and illumos only have
+/// as `/var/run`.
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "illumos"
+)))]
+macro_rules! run_dir {
+    ($path:literal) => {
+        concat!("/run/", $path)
+    };
+}
+#[cfg(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "illumos"
+))]
+macro_rules! run_dir {
+    ($path:literal) => {
+        concat!("/var/run/", $path)
+    };
+}
+
+#[cfg(unix)]
+mod audit;
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+mod auditd;
+#[cfg(unix)]
+mod auditlog;
+#[cfg(unix)]
+mod auth;
+#[cfg(unix)]
+mod builtin;
+#[cfg(unix)]
+pub mod check;
+pub mod config;
+#[cfg(any(target_os = "linux", all(unix, feature = "polkit")))]
+mod dbus;
+#[cfg(unix)]
+pub mod doctor;
+mod ed25519;
+#[cfg(unix)]
+mod env;
+pub mod error;
+pub mod exit;
+mod glob;
+#[cfg(unix)]
+pub mod install;
+#[cfg(unix)]
+mod instances;
+#[cfg(unix)]
+mod lockout;
+mod minisign;
+#[cfg(test)]
+mod mock;
+mod options;
+mod output;
+mod sha256;
+mod toml;
+#[cfg(unix)]
+use audit::Audit;
+#[cfg(unix)]
+use config::Config;
+#[cfg(unix)]
+use env::{EnvTrait, ProcessGroup};
+pub use error::Rejection;
+#[cfg(unix)]
+use exit::*;
+pub use options::Options;
+#[cfg(unix)]
+use output::Json;
+#[cfg(unix)]
+#[cfg(target_os = "linux")]
+mod cgroup;
+#[cfg(target_os = "linux")]
+mod journal;
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+mod landlock;
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+mod linux;
+#[cfg(unix)]
+#[allow(unsafe_code)]
+mod nix;
+#[cfg(target_os = "openbsd")]
+#[allow(unsafe_code)]
+mod openbsd;
+#[cfg(feature = "pam")]
+#[allow(unsafe_code)]
+mod pam;
+#[cfg(all(unix, feature = "polkit"))]
+mod polkit;
+#[cfg(unix)]
+#[allow(unsafe_code)]
+mod pty;
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+mod seccomp;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+#[allow(unsafe_code)]
+mod spawn;
+#[cfg(unix)]
+mod stats;
+#[cfg(unix)]
+#[allow(unsafe_code)]
+mod sys;
+#[cfg(target_os = "linux")]
+mod systemd;
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[allow(unsafe_code)]
+mod utmp;
+#[cfg(all(unix, not(any(target_os = "freebsd", target_os = "illumos"))))]
+type Env = nix::Nix;
+#[cfg(target_os = "freebsd")]
+#[allow(unsafe_code)]
+mod freebsd;
+#[cfg(target_os = "freebsd")]
+type Env = freebsd::FreeBsd;
+#[cfg(target_os = "illumos")]
+#[allow(unsafe_code)]
+mod illumos;
+#[cfg(target_os = "illumos")]
+type Env = illumos::Illumos;
+
+#[cfg(windows)]
+#[allow(unsafe_code)]
+mod windows;
+#[cfg(windows)]
+pub use windows::run;
+
+/// Public key baked in at build time that every target must be signed with,
+/// unless its configuration names another.
+#[cfg(unix)]
+const BUILD_MINISIGN_KEY: Option<&str> = option_env!("RUN_SUID_MINISIGN_KEY");
+
+#[cfg(unix)]
+struct Opts {
+    verbose: bool,
+    dry_run: bool,
+    uid: u32,
+    gid: u32,
+    groups: Vec<u32>,
+    preserve_env: Vec<String>,
+    /// Identity of the target user and variables set by the caller, set after `PATH`.
+    env: Vec<(String, OsString)>,
+    /// Real uid, gid, and user name of the invoker, exposed to the target as `RUN_SUID_*`.
+    invoker: (u32, u32, Option<String>),
+    path: Option<String>,
+    /// Arguments of the configuration placed before all others.
+    prepend_args: Vec<String>,
+    /// `argv[0]` of the target, which `Command` does not give back.
+    arg0: Option<OsString>,
+    /// File mode creation mask set right before the target is executed.
+    umask: Option<u32>,
+    nice: Option<i32>,
+    #[cfg(target_os = "linux")]
+    scheduler: Option<config::Scheduler>,
+    /// CPUs the target is confined to.
+    #[cfg(target_os = "linux")]
+    cpus: Option<Vec<usize>>,
+    #[cfg(target_os = "linux")]
+    oom_score_adj: Option<i32>,
+    /// SELinux context the target is executed in, instead of the one its label transitions to.
+    #[cfg(target_os = "linux")]
+    selinux_context: Option<String>,
+    /// AppArmor profile the target is executed in, and whether it may run unconfined instead.
+    #[cfg(target_os = "linux")]
+    apparmor_profile: Option<(String, bool)>,
+    rlimits: Vec<(config::Rlimit, Option<u64>)>,
+    /// Limits of core dumps the wrapper was started with, given back to the target.
+    core_limit: Option<libc::rlimit>,
+    /// Configured files the output of the target is written to, opened by the target user.
+    stdout_file: Option<PathBuf>,
+    stderr_file: Option<PathBuf>,
+    append_output: bool,
+    /// Descriptors handed to the target, as pairs of the caller's and the target's number.
+    pass_fds: Vec<(i32, i32)>,
+    /// Root directory of the target.
+    chroot: Option<PathBuf>,
+    /// Pseudo-terminal the target is run on, taken over by the wrapper once it is spawned.
+    pty: Option<pty::Pty>,
+    /// Name and uid of the target user its sessions on the pseudo-terminal are recorded as.
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    utmp: Option<(String, u32)>,
+    /// File the target writes its pid to, removed again once it exits.
+    pidfile: Option<PathBuf>,
+    /// Directory of the pid file, opened as the invoker, which it is written to and removed from.
+    pidfile_dir: Option<std::fs::File>,
+    /// Pipe to the invoker's process of a daemon, which exits once the target is started.
+    started: Option<std::fs::File>,
+    /// Report the resource usage of the target once it exits.
+    stats: bool,
+    #[cfg(target_os = "linux")]
+    private_tmp: bool,
+    #[cfg(target_os = "linux")]
+    no_new_privs: bool,
+    /// Isolate the target from the network, failing unless `false`.
+    #[cfg(target_os = "linux")]
+    no_network: Option<bool>,
+    /// Descriptor of `cgroup.procs` of the cgroup the target is moved into.
+    #[cfg(target_os = "linux")]
+    cgroup: Option<libc::c_int>,
+    caps: Option<u64>,
+    #[cfg(target_os = "linux")]
+    seccomp: Option<Vec<libc::sock_filter>>,
+    #[cfg(target_os = "linux")]
+    landlock: Option<std::fs::File>,
+    process_group: ProcessGroup,
+    /// Signal sent to the target when the thread that spawned it dies, or on FreeBSD the
+    /// process.
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    parent_death_signal: Option<i32>,
+    /// Delay after forwarding `SIGTERM` before the target is sent `SIGKILL`.
+    kill_after: Option<Duration>,
+    /// Longest time the target may run, and the signal it is sent after that.
+    timeout: Option<(Duration, i32)>,
+}
+
+/// Give up the privileges of the wrapper for the invoker's own, before a subcommand runs.
+#[cfg(unix)]
+pub fn drop_to_invoker() -> bool {
+    sys::drop_to_invoker()
+}
+
+/// Run the target next to the wrapper invoked as `name`, once every check has passed.
+///
+/// An invalid command line is given as its error, and is rejected and audited like every
+/// other rejection. `args` are the arguments of the target.
+#[cfg(unix)]
+pub fn run(name: &str, options: Result<Options, String>, args: Vec<String>) -> ExitCode {
+    run_in::<Env>(name, options, args)
+}
+
+/// [run] in the environment `E`.
+#[cfg(unix)]
+fn run_in<E: EnvTrait>(
+    name: &str,
+    options: Result<Options, String>,
+    mut args: Vec<String>,
+) -> ExitCode {
+    let fname = name;
+    let mut gid = E::getegid();
+    let uid = E::getuid();
+    let mut audit = Audit::<E>::new(uid, args.clone());
+    // before anything secret is read, so that none of it reaches swap or a core dump
+    let core_limit = sys::disable_core_dumps();
+    #[cfg(target_os = "linux")]
+    let dumpable = linux::set_not_dumpable();
+    // only with the privilege to lock more than `RLIMIT_MEMLOCK`, as every later allocation
+    // fails once that is exceeded
+    let locked = match E::geteuid() {
+        0 => sys::lock_memory(),
+        _ => Ok(()),
+    };
+    #[cfg(target_os = "openbsd")]
+    if let Err(err) = openbsd::pledge(openbsd::WRAPPER_PROMISES) {
+        return audit.reject(Rejection::new(
+            RET_SANDBOX,
+            format!("Unable to pledge the wrapper: {}", err),
+        ));
+    }
+    if let Some(secs) = E::locked_out(uid) {
+        return audit.reject(Rejection::new(
+            RET_LOCKED_OUT,
+            format!("Too many failed attempts, try again in {} seconds.", secs),
+        ));
+    }
+
+    let cli = match options {
+        Ok(cli) => cli,
+        Err(err) => {
+            return audit.reject(Rejection::new(RET_GENERIC_ERROR, err));
+        }
+    };
+    if cli.json {
+        output::use_json();
+    }
+    if cli.quiet {
+        output::use_quiet();
+    }
+    let verbose = cli.verbose && !cli.quiet;
+    if verbose {
+        if let Err(err) = &core_limit {
+            output::verbose(format_args!(
+                "unable to disable core dumps of the wrapper, continuing without it: {}",
+                err
+            ));
+        }
+        #[cfg(target_os = "linux")]
+        if let Err(err) = &dumpable {
+            output::verbose(format_args!(
+                "unable to make the wrapper not dumpable, continuing without it: {}",
+                err
+            ));
+        }
+        if let Err(err) = &locked {
+            output::verbose(format_args!(
+                "unable to lock the memory of the wrapper, continuing without it: {}",
+                err
+            ));
+        }
+    }
+
+    let listen = listen_fds();
+    // descriptors of the invoker must never reach the privileged target
+    match E::close_inherited_fds() {
+        Ok(fds) if verbose && !fds.is_empty() => {
+            output::verbose(format_args!(
+                "marking inherited file descriptors {:?} close-on-exec",
+                fds
+            ));
+        }
+        Ok(_) => {}
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to mark inherited file descriptors close-on-exec: {}",
+                    err
+                ),
+            ));
+        }
+    }
+
+    let cwd = match std::env::current_dir().and_then(std::fs::canonicalize) {
+        Ok(f) => f,
+        Err(e) => {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!("Unable to get the current directory: {}", e),
+            ));
+        }
+    };
+
+    audit.cwd = Some(cwd.clone());
+
+    let exe = match E::current_exe() {
+        Ok(path) if path.is_absolute() => path,
+        Ok(path) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("The path of the executable is not absolute: {:?}", path),
+            ));
+        }
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to find the name of the executable: {}", err),
+            ));
+        }
+    };
+    let euid = E::geteuid();
+    let wrapper = match check::wrapper_in::<E>(&exe, euid) {
+        Ok(wrapper) => wrapper,
+        Err(rejection) => return audit.reject(rejection),
+    };
+    let exe_links = wrapper.links;
+
+    // Links to the wrapper select their own sibling target by the name they were invoked as,
+    // while the target is still looked up next to the wrapper that passed the checks above.
+    let call_name = std::path::Path::new(&fname)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or(&wrapper.name);
+    if builtin::ENABLED && cli.target.is_some() {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "The target can not be chosen with --target, as the policy is built into the wrapper.",
+        ));
+    }
+    let call_name = cli.target.as_deref().unwrap_or(call_name);
+    audit.target = Some(match builtin::TARGET_PATH {
+        Some(path) => PathBuf::from(path),
+        None => wrapper.sibling(call_name),
+    });
+    // without a target of its own, the first argument selects one in a directory of targets
+    let dispatch_dir = match builtin::TARGET_PATH {
+        Some(_) => None,
+        None => check::dispatch_dir_in::<E>(&wrapper, call_name),
+    };
+    let checked = match (builtin::TARGET_PATH, dispatch_dir) {
+        (Some(path), _) => check::target_at_in::<E>(&wrapper, Path::new(path)),
+        (None, Some(dir)) if args.is_empty() => {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!(
+                    "The first argument must name the target in {:?} to run.",
+                    dir
+                ),
+            ));
+        }
+        (None, Some(dir)) => {
+            let name = args.remove(0);
+            audit.target = Some(dir.join(&name));
+            audit.args = args.clone();
+            check::member_in::<E>(&wrapper, &dir, &name)
+        }
+        (None, None) => check::target_in::<E>(&wrapper, call_name),
+    };
+    let check::Target {
+        path: target,
+        owner: tar_uid,
+        links: tar_links,
+        file: tar_file,
+    } = match checked {
+        Ok(target) => target,
+        Err(rejection) => return audit.reject(rejection),
+    };
+    audit.target_uid = Some(tar_uid);
+    let tar_name = target.file_name().unwrap_or_default();
+
+    let tar_user = match E::user_by_uid(tar_uid) {
+        Ok(user) => user,
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to look up the owner of the target executable: {}",
+                    err
+                ),
+            ));
+        }
+    };
+    let mut groups = vec![gid];
+    if euid == 0 {
+        if let Some(user) = &tar_user {
+            gid = user.gid;
+            groups = vec![gid];
+            if !cli.no_init_groups {
+                match E::user_groups(user) {
+                    Ok(g) => groups = g,
+                    Err(err) => {
+                        return audit.reject(Rejection::new(
+                            RET_ENV_ERROR,
+                            format!("Unable to get the groups of the target user: {}", err),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let config_path = E::target_config(&target);
+    let mut config = match E::open_policy_file(&config_path) {
+        Ok(_) if builtin::ENABLED => {
+            return audit.reject(
+                Rejection::new(
+                    RET_CONFIG,
+                    format!(
+                        "The configuration file {:?} is refused, as the policy is built into the wrapper.",
+                        config_path
+                    ),
+                )
+                .path(&config_path),
+            );
+        }
+        Ok((cfg_uid, file, true)) if cfg_uid == euid => match Config::read(file) {
+            Ok(config) => config,
+            Err(err) => {
+                return audit.reject(
+                    Rejection::new(
+                        RET_CONFIG,
+                        format!("Invalid configuration file {:?}: {}", config_path, err),
+                    )
+                    .path(&config_path),
+                );
+            }
+        },
+        Ok((cfg_uid, _, true)) => {
+            return audit.reject(
+                Rejection::new(
+                    RET_OWNER_CONFIG,
+                    "The the owner of the configuration file is not the same as the executable.",
+                )
+                .path(&config_path)
+                .mismatch(format!("uid {}", euid), format!("uid {}", cfg_uid)),
+            );
+        }
+        Ok((_, file, false)) => {
+            let actual = file.metadata().map_or(String::new(), |m| {
+                check::actual_mode(env::Stat::from(&m).mode)
+            });
+            return audit.reject(
+                Rejection::new(
+                    RET_PERM_CONFIG,
+                    format!(
+                        "The configuration file must be a file writable by only the owning user: {:?}",
+                        config_path
+                    ),
+                )
+                .path(&config_path)
+                .mismatch("a file not writable by group or others", actual),
+            );
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound && builtin::ENABLED => {
+            match builtin::config() {
+                Ok(config) => config,
+                Err(err) => {
+                    return audit.reject(Rejection::new(
+                        RET_CONFIG,
+                        format!("The policy built into the wrapper is invalid: {}", err),
+                    ));
+                }
+            }
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Config::default(),
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to open the configuration file {:?}: {}",
+                    config_path, err
+                ),
+            ));
+        }
+    };
+    audit.enabled = config.syslog.unwrap_or(true);
+    audit.log_file = config.audit_log.clone();
+    audit.stats_file = Some(E::target_stats(&target)).filter(|_| config.keep_stats);
+    if let Some(base) = config.exit.band {
+        exit::set_band(base);
+    }
+
+    if verbose {
+        output::verbose(format_args!(
+            "the executable has {} hard links and the target {}",
+            exe_links, tar_links
+        ));
+    }
+    // another link in a directory writable by someone else escapes the directory checks
+    if !config.allow_hard_links {
+        if exe_links > 1 {
+            return audit.reject(Rejection::new(
+                RET_PERM_EXEC,
+                format!(
+                    "The executable must not have more than one hard link: {:?} has {}",
+                    exe, exe_links
+                ),
+            ));
+        }
+        if tar_links > 1 {
+            return audit.reject(Rejection::new(
+                RET_PERM_TARGET,
+                format!(
+                    "The target executable must not have more than one hard link: {:?} has {}",
+                    target, tar_links
+                ),
+            ));
+        }
+    }
+
+    if let Some(expected) = &config.sha256 {
+        let actual = match E::read_target(&tar_file).and_then(sha256::digest) {
+            Ok(digest) => digest,
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_ENV_ERROR,
+                    format!("Unable to read the target executable {:?}: {}", target, err),
+                ));
+            }
+        };
+        if actual != *expected {
+            return audit.reject(Rejection::new(
+                RET_TARGET_DIGEST,
+                format!(
+                    "The target executable does not match its pinned SHA-256 digest: {:?} is {}",
+                    target,
+                    sha256::to_hex(&actual)
+                ),
+            ));
+        }
+    }
+
+    match E::target_mount(&target, &tar_file) {
+        Ok(mount) if mount.nosuid => {
+            return audit.reject(Rejection::new(
+                RET_UNTRUSTED_MOUNT,
+                format!(
+                    "The target executable is on a filesystem mounted nosuid: {:?}",
+                    target
+                ),
+            ));
+        }
+        Ok(mount) => {
+            if let Some(kind) = mount.untrusted {
+                if !config.allowed_filesystems.iter().any(|f| f == kind) {
+                    return audit.reject(Rejection::new(
+                        RET_UNTRUSTED_MOUNT,
+                        format!(
+                            "The target executable is on an untrusted {} filesystem: {:?}",
+                            kind, target
+                        ),
+                    ));
+                }
+            }
+        }
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!(
+                    "Unable to inspect the filesystem of the target executable {:?}: {}",
+                    target, err
+                ),
+            ));
+        }
+    }
+
+    let minisign_key = match (config.minisign_key.take(), BUILD_MINISIGN_KEY) {
+        (Some(key), _) => Some(key),
+        (None, Some(key)) => match minisign::PublicKey::parse(key) {
+            Some(key) => Some(key),
+            None => {
+                return audit.reject(Rejection::new(
+                    RET_CONFIG,
+                    "The built in minisign key is invalid.",
+                ));
+            }
+        },
+        (None, None) => None,
+    };
+    if let Some(key) = minisign_key {
+        let sig_path = E::target_signature(&target);
+        let signature = match E::open_policy_file(&sig_path) {
+            Ok((sig_uid, file, true)) if sig_uid == euid => {
+                use std::io::Read;
+                let mut input = String::new();
+                match file.take(1 << 16).read_to_string(&mut input) {
+                    Ok(_) => minisign::Signature::parse(&input),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            Ok((_, _, true)) => {
+                return audit.reject(Rejection::new(
+                    RET_OWNER_CONFIG,
+                    "The the owner of the signature is not the same as the executable.",
+                ));
+            }
+            Ok((_, _, false)) => {
+                return audit.reject(Rejection::new(
+                    RET_PERM_CONFIG,
+                    format!(
+                        "The signature must be a file writable by only the owning user: {:?}",
+                        sig_path
+                    ),
+                ));
+            }
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_TARGET_SIGNATURE,
+                    format!("Unable to open the signature {:?}: {}", sig_path, err),
+                ));
+            }
+        };
+        let verified = signature.and_then(|sig| match E::read_target(&tar_file) {
+            Ok(file) => sig.verify(&key, file),
+            Err(err) => Err(err.to_string()),
+        });
+        if let Err(err) = verified {
+            return audit.reject(Rejection::new(
+                RET_TARGET_SIGNATURE,
+                format!(
+                    "The signature of the target executable {:?} is not valid: {}",
+                    target, err
+                ),
+            ));
+        }
+    }
+
+    let allow_path = E::target_allow_list(&target);
+    let mut allowed = config.allowed_invokers.take();
+    match E::open_policy_file(&allow_path) {
+        Ok(_) if builtin::ENABLED => {
+            return audit.reject(
+                Rejection::new(
+                    RET_CONFIG,
+                    format!(
+                        "The allowlist {:?} is refused, as the policy is built into the wrapper.",
+                        allow_path
+                    ),
+                )
+                .path(&allow_path),
+            );
+        }
+        Ok((allow_uid, mut file, true)) if allow_uid == euid => {
+            use std::io::Read;
+            let mut input = String::new();
+            let parsed = file
+                .read_to_string(&mut input)
+                .map_err(|e| e.to_string())
+                .and_then(|_| config::parse_allow_list(&input).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(list) => allowed.get_or_insert_with(Vec::new).extend(list),
+                Err(err) => {
+                    return audit.reject(Rejection::new(
+                        RET_CONFIG,
+                        format!("Invalid allowlist {:?}: {}", allow_path, err),
+                    ));
+                }
+            }
+        }
+        Ok((_, _, true)) => {
+            return audit.reject(Rejection::new(
+                RET_OWNER_CONFIG,
+                "The the owner of the allowlist is not the same as the executable.",
+            ));
+        }
+        Ok((_, _, false)) => {
+            return audit.reject(Rejection::new(
+                RET_PERM_CONFIG,
+                format!(
+                    "The allowlist must be a file writable by only the owning user: {:?}",
+                    allow_path
+                ),
+            ));
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to open the allowlist {:?}: {}", allow_path, err),
+            ));
+        }
+    }
+    let name = match E::user_by_uid(uid) {
+        Ok(user) => user.map(|u| u.name),
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to look up the invoking user: {}", err),
+            ));
+        }
+    };
+    if let Some(allowed) = &allowed {
+        if !allowed.iter().any(|a| a.matches(uid, name.as_deref())) {
+            let message = match &name {
+                Some(name) => format!(
+                    "The user {} (uid {}) is not allowed to run {:?}.",
+                    name, uid, target
+                ),
+                None => format!("The uid {} is not allowed to run {:?}.", uid, target),
+            };
+            return audit.reject(Rejection::new(RET_INVOKER_DENIED, message).path(&allow_path));
+        }
+    }
+    // only shown to those allowed to run the target, and not counted as a run of it
+    if cli.show_stats {
+        let stats_path = E::target_stats(&target);
+        return match stats::Stats::read(&stats_path) {
+            Ok(Some(stats)) if output::is_json() => {
+                println!("{}", stats.json());
+                ExitCode::SUCCESS
+            }
+            Ok(Some(stats)) => {
+                print!("{}", stats.describe());
+                ExitCode::SUCCESS
+            }
+            Ok(None) if output::is_json() => {
+                println!("{}", Json::Null);
+                ExitCode::SUCCESS
+            }
+            Ok(None) => {
+                println!("No runs of {:?} are counted.", target);
+                ExitCode::SUCCESS
+            }
+            Err(err) => audit.reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to read the statistics {:?}: {}", stats_path, err),
+            )),
+        };
+    }
+    let mut invoker_groups = Vec::new();
+    if !config.group_overrides.is_empty() {
+        let mut gids = sys::getgroups();
+        gids.push(E::getgid());
+        gids.sort_unstable();
+        gids.dedup();
+        for gid in gids {
+            match E::group_name(gid) {
+                Ok(group) => invoker_groups.push((gid, group)),
+                Err(err) => {
+                    return audit.reject(Rejection::new(
+                        RET_ENV_ERROR,
+                        format!("Unable to look up the groups of the invoking user: {}", err),
+                    ));
+                }
+            }
+        }
+    }
+    // resolved before anything they replace is used
+    for section in config.apply_overrides(uid, name.as_deref(), &invoker_groups) {
+        if verbose {
+            output::verbose(format_args!("applying the overrides of [{}]", section));
+        }
+    }
+    if config.require_tty && E::tty_name().is_none() {
+        return audit.reject(Rejection::new(
+            RET_NO_TTY,
+            format!("The target {:?} may only be run from a terminal.", target),
+        ));
+    }
+
+    // checked before the patterns, which are not matched against oversized arguments at all
+    if let Some((what, given, max)) = config.args.exceeded(&args) {
+        return audit.reject(Rejection::new(
+            RET_ARGS_TOO_LONG,
+            format!(
+                "{} {} were given to {:?}, more than the {} allowed.",
+                given, what, target, max
+            ),
+        ));
+    }
+    if let Some((arg, denied)) = config.args.refused(&args) {
+        let reason = match denied {
+            config::Refusal::Denied(pattern) => format!("it matches {:?}", pattern),
+            config::Refusal::NotAllowed => "it matches no allowed pattern".to_string(),
+            config::Refusal::Option => "it is not an allowed option".to_string(),
+        };
+        let message = format!(
+            "The argument {:?} may not be given to {:?}, as {}.",
+            arg, target, reason
+        );
+        audit.args = args.clone();
+        return audit.reject(Rejection::new(RET_ARGS_DENIED, message));
+    }
+    if args.is_empty() {
+        args.extend(config.default_args);
+    } else {
+        config.args.end_options(&mut args);
+    }
+    audit.args = args.clone();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let cwd = config.working_dir.unwrap_or(cwd);
+    let mut preserve_env = cli.preserve_env;
+    preserve_env.extend(config.preserve_env);
+    preserve_env.sort_unstable();
+    preserve_env.dedup();
+    let mut env = Vec::new();
+    if config.pass_locale.unwrap_or(true) {
+        for (name, value) in std::env::vars_os() {
+            let name = match name.into_string() {
+                Ok(name) if config::is_locale_env(&name) => name,
+                _ => continue,
+            };
+            if value
+                .to_str()
+                .map_or(false, |v| config::is_safe_locale_value(&name, v))
+            {
+                env.push(((name, value), false));
+            }
+        }
+    }
+    // like `sudo -H`, the target sees the home and shell of its own user
+    if let Some(user) = &tar_user {
+        env.push((("HOME".to_owned(), user.home.clone().into()), false));
+        env.push((("USER".to_owned(), user.name.clone().into()), false));
+        env.push((("LOGNAME".to_owned(), user.name.clone().into()), false));
+        env.push((("SHELL".to_owned(), user.shell.clone().into()), false));
+    }
+    for (path, by_invoker) in [(&config.env_file, false), (&cli.env_file, true)] {
+        if let Some(path) = path {
+            match read_env_file::<E>(path, by_invoker) {
+                Ok(vars) => env.extend(vars.into_iter().map(|(k, v)| ((k, v.into()), by_invoker))),
+                Err((code, err)) => {
+                    return audit.reject(Rejection::new(code, err));
+                }
+            }
+        }
+    }
+    env.extend(cli.env.into_iter().map(|(k, v)| ((k, v.into()), true)));
+    if let Some(((name, _), _)) = env
+        .iter()
+        .find(|((n, _), by_invoker)| *by_invoker && !config.allowed_env.contains(n))
+    {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            format!(
+                "The environment variable {} may not be set for {:?}.",
+                name, target
+            ),
+        ));
+    }
+
+    // a multiplexer picks what it does by its name, so the invoker may only pick allowed ones
+    if let Some(name) = cli
+        .arg0
+        .as_ref()
+        .filter(|n| !config.allowed_arg0.contains(n))
+    {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            format!("The name {:?} may not be given to {:?}.", name, target),
+        ));
+    }
+
+    let cli_caps = cli
+        .caps
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let caps = match resolve_caps(
+        &cli_caps,
+        config.caps.as_deref(),
+        cli.caps.is_some(),
+        tar_uid,
+    ) {
+        Ok(caps) => caps,
+        Err(err) => {
+            return audit.reject(Rejection::new(RET_GENERIC_ERROR, err));
+        }
+    };
+    if caps.is_some() && euid != 0 {
+        return audit.reject(Rejection::new(
+            RET_ENV_ERROR,
+            "Capabilities can only be managed by a wrapper owned by root.",
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    let seccomp = match config.seccomp.as_ref().map(seccomp::build_filter) {
+        Some(Ok(filter)) => Some(filter),
+        Some(Err(err)) => {
+            return audit.reject(Rejection::new(
+                RET_CONFIG,
+                format!(
+                    "Invalid seccomp configuration in {:?}: {}",
+                    config_path, err
+                ),
+            ));
+        }
+        None => None,
+    };
+    #[cfg(not(target_os = "linux"))]
+    if config.seccomp.is_some() {
+        return audit.reject(Rejection::new(
+            RET_CONFIG,
+            "Seccomp filters are not supported on this platform.",
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    let landlock = match &config.landlock {
+        Some(rules) => match landlock::abi_version() {
+            Some(abi) => match landlock::create_ruleset(abi, rules, &target) {
+                Ok(ruleset) => Some(ruleset),
+                Err(err) => {
+                    return audit.reject(Rejection::new(
+                        RET_SANDBOX,
+                        format!("Unable to prepare the Landlock ruleset: {}", err),
+                    ));
+                }
+            },
+            None if rules.required || cli.require_landlock => {
+                return audit.reject(Rejection::new(
+                    RET_SANDBOX,
+                    "Landlock is required but not supported by the kernel.",
+                ));
+            }
+            None => {
+                if verbose {
+                    output::verbose(format_args!(
+                        "Landlock is not supported by the kernel, continuing without it"
+                    ));
+                }
+                None
+            }
+        },
+        None => None,
+    };
+    #[cfg(not(target_os = "linux"))]
+    if config.landlock.is_some() {
+        if config.landlock.as_ref().map_or(false, |l| l.required) || cli.require_landlock {
+            return audit.reject(Rejection::new(
+                RET_SANDBOX,
+                "Landlock is required but not supported on this platform.",
+            ));
+        }
+        if verbose {
+            output::verbose(format_args!(
+                "Landlock is not supported on this platform, continuing without it"
+            ));
+        }
+    }
+
+    // the invoker may only narrow down the CPUs the configuration allows
+    let cpus = match (cli.cpus, config.cpus) {
+        (Some(a), Some(b)) => Some(a.into_iter().filter(|c| b.contains(c)).collect::<Vec<_>>()),
+        (a, b) => a.or(b),
+    };
+    if cpus.as_ref().map_or(false, Vec::is_empty) {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            format!("None of the requested CPUs are allowed for {:?}.", target),
+        ));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if cpus.is_some() && verbose {
+        output::verbose(format_args!(
+            "CPU affinity is not supported on this platform, continuing without it"
+        ));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if (cli.oom_score_adj.is_some() || config.oom_score_adj.is_some()) && verbose {
+        output::verbose(format_args!(
+            "OOM score adjustments are not supported on this platform, continuing without it"
+        ));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if config.scheduler.is_some() && verbose {
+        output::verbose(format_args!(
+            "Scheduling policies are not supported on this platform, continuing without it"
+        ));
+    }
+
+    // a pledged process may never change its root directory
+    #[cfg(target_os = "openbsd")]
+    if config.chroot.is_some() {
+        return audit.reject(Rejection::new(
+            RET_SANDBOX,
+            "A root directory is not supported on this platform.",
+        ));
+    }
+    if let Some(root) = &config.chroot {
+        match E::open_dir(root) {
+            Ok((owner, m, true, _)) if m.is_dir() && (owner == 0 || owner == euid) => {}
+            Ok((owner, _, _, _)) if owner != 0 && owner != euid => {
+                return audit.reject(Rejection::new(RET_SANDBOX, format!("The the owner of the root directory must be root or the owner of the executable: {:?}", root)));
+            }
+            Ok(_) => {
+                return audit.reject(Rejection::new(RET_SANDBOX, format!("The root directory permissions must be writable by only the owning user: {:?}", root)));
+            }
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_SANDBOX,
+                    format!("Unable to open the root directory {:?}: {}", root, err),
+                ));
+            }
+        }
+        match E::insecure_ancestor(root, euid) {
+            Ok(None) => {}
+            Ok(Some((dir, owner, mode))) => {
+                return audit.reject(Rejection::new(RET_SANDBOX, format!("Every ancestor directory of the root directory must be owned by root or the owner of the executable and writable by only its owner: {:?} (owner {}, mode {:o})", dir, owner, mode & 0o7777)));
+            }
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_SANDBOX,
+                    format!(
+                        "Unable to check the ancestors of the root directory: {}",
+                        err
+                    ),
+                ));
+            }
+        }
+        if !target.starts_with(root) {
+            return audit.reject(Rejection::new(
+                RET_SANDBOX,
+                format!(
+                    "The target must reside beneath the root directory {:?}: {:?}",
+                    root, target
+                ),
+            ));
+        }
+    }
+
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let mut seen = Vec::new();
+        for (from, to) in cli.pass_fds.iter() {
+            if !sys::is_open(*from) {
+                return audit.reject(Rejection::new(
+                    RET_GENERIC_ERROR,
+                    format!("The descriptor {} to pass to the target is not open.", from),
+                ));
+            }
+            if *to == tar_file.as_raw_fd() || seen.contains(to) {
+                return audit.reject(Rejection::new(
+                    RET_GENERIC_ERROR,
+                    format!("The descriptor {} can not be passed to the target.", to),
+                ));
+            }
+            seen.push(*to);
+        }
+    }
+
+    // the configured output files can not be moved away by the invoker, whose own are only
+    // opened once the target is about to start
+    let output_file =
+        |name, configured: &Option<PathBuf>, given: &Option<PathBuf>| match (configured, given) {
+            (Some(_), Some(_)) => Err(format!(
+                "The {} of {:?} is already written to a configured file.",
+                name, target
+            )),
+            (None, Some(file)) if !E::invoker_can_write(file) => Err(format!(
+                "The {} file is not writable by the invoker: {:?}",
+                name, file
+            )),
+            (configured, _) => Ok(configured.clone()),
+        };
+    let (stdout_file, stderr_file) = match (
+        output_file("stdout", &config.stdout_file, &cli.stdout_file),
+        output_file("stderr", &config.stderr_file, &cli.stderr_file),
+    ) {
+        (Ok(stdout), Ok(stderr)) => (stdout, stderr),
+        (Err(err), _) | (_, Err(err)) => {
+            return audit.reject(Rejection::new(RET_GENERIC_ERROR, err));
+        }
+    };
+
+    // a configured recording can not be avoided by the invoker
+    match (&config.record_dir, &cli.record) {
+        (Some(_), Some(_)) => {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!("The session of {:?} is already recorded.", target),
+            ));
+        }
+        (None, Some(file)) if !E::invoker_can_write(file) => {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!("The recording is not writable by the invoker: {:?}", file),
+            ));
+        }
+        _ => {}
+    }
+    if let Some(dir) = cli.pidfile.as_deref().and_then(Path::parent) {
+        if !E::invoker_can_write(dir) {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!(
+                    "The directory of the pid file must be writable by the invoker: {:?}",
+                    dir
+                ),
+            ));
+        }
+    }
+    if cli.daemon && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Running as a daemon needs --proxy instead of --exec.",
+        ));
+    }
+    if cli.pidfile.is_some() && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Removing the pid file needs --proxy instead of --exec.",
+        ));
+    }
+
+    let on_pty = cli.pty || config.record_dir.is_some() || cli.record.is_some();
+    if on_pty && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Running the target on a pseudo-terminal needs --proxy instead of --exec.",
+        ));
+    }
+    if config.utmp && !on_pty {
+        if verbose {
+            output::verbose(format_args!(
+                "not recording the session in utmp, as the target does not run on a pseudo-terminal"
+            ));
+        }
+        config.utmp = false;
+    }
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    if config.utmp && verbose {
+        output::verbose(format_args!(
+            "utmp accounting is not supported on this platform, continuing without it"
+        ));
+    }
+
+    // the shorter delay wins, as neither the invoker nor the policy may extend the other
+    let shorter = |a: Option<Duration>, b: Option<Duration>| match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    let kill_after = shorter(cli.kill_after, config.kill_after);
+    let timeout = shorter(cli.timeout, config.timeout);
+    if kill_after.is_some() && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Killing the target after SIGTERM needs --proxy instead of --exec.",
+        ));
+    }
+    if cli.stats && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Reporting the resource usage of the target needs --proxy instead of --exec.",
+        ));
+    }
+    if timeout.is_some() && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "A timeout needs --proxy instead of --exec.",
+        ));
+    }
+    if !config.exit.remap.is_empty() && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Remapping the exit code of the target needs --proxy instead of --exec.",
+        ));
+    }
+    if config.cgroup.is_some() && cli.exec {
+        return audit.reject(Rejection::new(
+            RET_GENERIC_ERROR,
+            "Removing the cgroup of the target needs --proxy instead of --exec.",
+        ));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if config.selinux_context.is_some() {
+        return audit.reject(Rejection::new(
+            RET_SANDBOX,
+            "SELinux contexts are not supported on this platform.",
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    let apparmor = linux::apparmor_enabled();
+    #[cfg(not(target_os = "linux"))]
+    let apparmor = false;
+    if config.apparmor_profile.is_some() && !apparmor {
+        if !config.apparmor_optional {
+            return audit.reject(Rejection::new(
+                RET_SANDBOX,
+                "AppArmor is not enabled, which the profile of the target needs.",
+            ));
+        }
+        if verbose {
+            output::verbose(format_args!(
+                "AppArmor is not enabled, continuing without the profile of the target"
+            ));
+        }
+        config.apparmor_profile = None;
+    }
+    #[cfg(not(target_os = "linux"))]
+    if cli.private_tmp || config.private_tmp {
+        return audit.reject(Rejection::new(
+            RET_SANDBOX,
+            "A private /tmp is not supported on this platform.",
+        ));
+    }
+    #[cfg(not(target_os = "linux"))]
+    match cli.no_network {
+        Some(true) => {
+            return audit.reject(Rejection::new(
+                RET_SANDBOX,
+                "Network isolation is not supported on this platform.",
+            ));
+        }
+        Some(false) if verbose => output::verbose(format_args!(
+            "Network isolation is not supported on this platform, continuing without it"
+        )),
+        _ => {}
+    }
+    #[cfg(not(target_os = "linux"))]
+    if config.cgroup.is_some() && verbose {
+        output::verbose(format_args!(
+            "Cgroups are not supported on this platform, continuing without it"
+        ));
+    }
+
+    let opts = Opts {
+        verbose,
+        dry_run: cli.dry_run,
+        uid: tar_uid,
+        gid,
+        groups,
+        preserve_env,
+        env: env.into_iter().map(|(v, _)| v).collect(),
+        invoker: (uid, E::getgid(), name),
+        path: config.path,
+        prepend_args: config.prepend_args,
+        arg0: None,
+        // the masks are combined, as neither the invoker nor the policy may loosen the other
+        umask: match (cli.umask, config.umask) {
+            (Some(a), Some(b)) => Some(a | b),
+            (a, b) => a.or(b),
+        },
+        // the nicer of the two wins, as the invoker may not raise the configured niceness
+        nice: match (cli.nice, config.nice) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        },
+        #[cfg(target_os = "linux")]
+        scheduler: config.scheduler,
+        #[cfg(target_os = "linux")]
+        cpus,
+        #[cfg(target_os = "linux")]
+        oom_score_adj: match (cli.oom_score_adj, config.oom_score_adj) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        },
+        #[cfg(target_os = "linux")]
+        selinux_context: config.selinux_context.clone(),
+        #[cfg(target_os = "linux")]
+        apparmor_profile: config
+            .apparmor_profile
+            .clone()
+            .map(|profile| (profile, config.apparmor_optional)),
+        rlimits: config.rlimits,
+        core_limit: core_limit.ok().filter(|limit| limit.rlim_cur != 0),
+        stdout_file,
+        stderr_file,
+        append_output: cli.append || config.append_output,
+        pass_fds: cli.pass_fds,
+        chroot: config.chroot.clone(),
+        pty: None,
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        utmp: Some(tar_uid).filter(|_| config.utmp).map(|uid| {
+            let name = tar_user.as_ref().map(|user| user.name.clone());
+            (name.unwrap_or_else(|| uid.to_string()), uid)
+        }),
+        pidfile: cli.pidfile.clone(),
+        pidfile_dir: None,
+        started: None,
+        stats: cli.stats,
+        #[cfg(target_os = "linux")]
+        private_tmp: cli.private_tmp || config.private_tmp,
+        // the invoker may only lift the default, never what the configuration asks for
+        #[cfg(target_os = "linux")]
+        no_new_privs: config
+            .no_new_privs
+            .unwrap_or(tar_uid != 0 && !cli.allow_new_privs),
+        #[cfg(target_os = "linux")]
+        no_network: cli.no_network,
+        #[cfg(target_os = "linux")]
+        cgroup: None,
+        caps,
+        #[cfg(target_os = "linux")]
+        seccomp,
+        #[cfg(target_os = "linux")]
+        landlock,
+        process_group: ProcessGroup::Inherit,
+        #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+        parent_death_signal: None,
+        kill_after,
+        timeout: timeout.map(|d| (d, config.timeout_signal.unwrap_or(libc::SIGTERM))),
+    };
+
+    if let Some(auth) = config.auth.as_ref().filter(|_| !opts.dry_run) {
+        let service = config
+            .pam
+            .as_ref()
+            .map_or("run-suid", |p| p.service.as_str());
+        if let Err((code, err)) = authenticate::<E>(auth, service, uid, opts.invoker.2.as_deref()) {
+            return audit.reject(Rejection::new(code, err));
+        }
+    }
+
+    #[cfg(feature = "pam")]
+    let pam = match &config.pam {
+        Some(cfg) if !opts.dry_run => match start_pam::<E>(cfg, uid, tar_uid) {
+            Ok(pam) => Some(pam),
+            Err((code, err)) => {
+                return audit.reject(Rejection::new(code, err));
+            }
+        },
+        _ => None,
+    };
+    #[cfg(not(feature = "pam"))]
+    if config.pam.is_some() {
+        return audit.reject(Rejection::new(
+            RET_CONFIG,
+            "PAM support is not compiled into this executable.",
+        ));
+    }
+
+    #[cfg(feature = "polkit")]
+    if let Some(action) = config.polkit_action.as_ref().filter(|_| !opts.dry_run) {
+        if verbose {
+            output::verbose(format_args!("asking polkit to authorize {:?}", action));
+        }
+        let program = target.to_string_lossy();
+        let command_line = std::iter::once(&*program)
+            .chain(opts.prepend_args.iter().map(String::as_str))
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let details = [("program", &*program), ("command_line", &*command_line)];
+        match polkit::check_authorization(action, std::process::id(), uid, &details) {
+            Ok(true) => {}
+            Ok(false) => {
+                return audit.reject(Rejection::new(
+                    RET_AUTH_FAILED,
+                    format!("polkit did not authorize {:?} for {:?}.", action, target),
+                ));
+            }
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_AUTH_FAILED,
+                    format!("Unable to ask polkit for authorization: {}", err),
+                ));
+            }
+        }
+    }
+    #[cfg(not(feature = "polkit"))]
+    if config.polkit_action.is_some() {
+        return audit.reject(Rejection::new(
+            RET_CONFIG,
+            "polkit support is not compiled into this executable.",
+        ));
+    }
+
+    if (cli.confirm || config.confirm) && !opts.dry_run {
+        use std::fmt::Write;
+        let mut prompt = format!("Run {:?}", target);
+        for a in args.iter() {
+            write!(prompt, " {:?}", a).unwrap();
+        }
+        write!(
+            prompt,
+            "\nas uid {} (gid {}) for uid {}? Type yes to continue: ",
+            opts.uid, opts.gid, uid
+        )
+        .unwrap();
+        match E::ask_terminal(&prompt, true) {
+            Ok(answer) if answer.trim() == "yes" => {}
+            Ok(_) => {
+                return audit.reject(Rejection::new(
+                    RET_NOT_CONFIRMED,
+                    format!("The run of {:?} was not confirmed.", target),
+                ));
+            }
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_NO_TTY,
+                    format!("Unable to ask for confirmation on the terminal: {}", err),
+                ));
+            }
+        }
+    }
+
+    if let Err(rejection) = audit.accept(opts.dry_run) {
+        return audit.reject(rejection);
+    }
+
+    if opts.dry_run && output::is_json() {
+        let argv = std::iter::once(target.as_os_str())
+            .chain(opts.prepend_args.iter().map(OsStr::new))
+            .chain(args.iter().map(OsStr::new))
+            .map(Json::from);
+        let out = Json::object([
+            ("dry_run", Json::from(true)),
+            ("wrapper", Json::from(exe.as_path())),
+            ("target", Json::from(target.as_path())),
+            ("invoker_uid", Json::from(uid)),
+            ("uid", Json::from(opts.uid)),
+            ("gid", Json::from(opts.gid)),
+            ("groups", Json::array(opts.groups.iter().copied())),
+            ("working_dir", Json::from(cwd.as_path())),
+            ("path", Json::from(E::target_path(&opts))),
+            (
+                "umask",
+                Json::from(opts.umask.map(|m| format!("{:03o}", m))),
+            ),
+            ("argv", Json::array(argv)),
+            #[cfg(target_os = "linux")]
+            (
+                "selinux_context",
+                Json::from(match &opts.selinux_context {
+                    Some(context) => Some(context.clone()),
+                    None => linux::default_exec_context(&target).ok().flatten(),
+                }),
+            ),
+            (
+                "hard_links",
+                Json::object([
+                    ("wrapper", Json::from(exe_links)),
+                    ("target", Json::from(tar_links)),
+                ]),
+            ),
+        ]);
+        println!("{}", out);
+        return ExitCode::SUCCESS;
+    }
+    if opts.dry_run && cli.quiet {
+        return ExitCode::SUCCESS;
+    }
+    if opts.dry_run {
+        use std::fmt::Write;
+        let mut out = String::new();
+        out.push_str("Dry run: would have succeeded in starting the process: ");
+        write!(out, "{:?}", target).unwrap();
+        for a in opts.prepend_args.iter().map(String::as_str).chain(args) {
+            write!(out, " {:?}", a).unwrap();
+        }
+        println!("{}", out);
+        println!("Dry run: PATH={}", E::target_path(&opts));
+        #[cfg(target_os = "linux")]
+        match (&opts.selinux_context, linux::default_exec_context(&target)) {
+            (Some(context), Ok(None)) => println!(
+                "Dry run: SELinux context {}, though SELinux is not enabled",
+                context
+            ),
+            (None, Ok(None)) => {}
+            (Some(context), Ok(Some(default))) => println!(
+                "Dry run: SELinux context {}, instead of the default {}",
+                context, default
+            ),
+            (None, Ok(Some(default))) => println!("Dry run: SELinux context {}", default),
+            (_, Err(err)) => println!(
+                "Dry run: unable to compute the default SELinux context: {}",
+                err
+            ),
+        }
+        println!(
+            "Dry run: the executable has {} hard links and the target {}",
+            exe_links, tar_links
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    // the kernel keeps the login uid and audit session across the change of ids
+    #[cfg(target_os = "linux")]
+    match linux::set_loginuid(uid) {
+        Ok(true) if verbose => {
+            output::verbose(format_args!("set the login uid to {}", uid));
+        }
+        Ok(_) => {}
+        Err(err) if verbose => {
+            output::verbose(format_args!("unable to set the login uid: {}", err));
+        }
+        Err(_) => {}
+    }
+
+    // everything the target is confined by belongs to the process that stays around
+    let opts = if cli.daemon {
+        match E::daemonize() {
+            Ok(started) => Opts {
+                started: Some(started),
+                ..opts
+            },
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_GENERIC_ERROR,
+                    format!("Unable to start a daemon: {}", err),
+                ));
+            }
+        }
+    } else {
+        opts
+    };
+
+    // held until the target exits, by the target itself when executed
+    let lock = match config.max_instances {
+        Some(max) => {
+            let dir = config
+                .lock_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(instances::LOCK_DIR));
+            let key = sha256::digest(target.as_os_str().to_string_lossy().as_bytes())
+                .map(|d| sha256::to_hex(&d))
+                .unwrap_or_default();
+            if verbose && cli.wait_lock.is_some() {
+                output::verbose(format_args!("waiting for one of {} instances", max));
+            }
+            match instances::acquire(&dir, &key, max, cli.wait_lock) {
+                Ok(Some(lock)) => Some(lock),
+                Ok(None) => {
+                    return audit.reject(Rejection::new(
+                        RET_BUSY,
+                        format!(
+                            "The target {:?} is already running as often as allowed at once ({}).",
+                            target, max
+                        ),
+                    ));
+                }
+                Err(err) => {
+                    return audit.reject(Rejection::new(RET_ENV_ERROR, err));
+                }
+            }
+        }
+        None => None,
+    };
+
+    // beneath a new root the target can only be found by its path relative to it
+    let (program, arg0) = match config.chroot.as_deref().map(|r| target.strip_prefix(r)) {
+        Some(Ok(rel)) => (Path::new("/").join(rel), Path::new("/").join(rel)),
+        _ => (E::target_program(&target, &tar_file), target.clone()),
+    };
+    let arg0 = match cli.arg0.as_ref().or(config.arg0.as_ref()) {
+        Some(name) => PathBuf::from(name),
+        None => arg0,
+    };
+    let mut command = Command::new(program);
+    command
+        .arg0(&arg0)
+        .current_dir(cwd)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .env_clear();
+    if cli.daemon {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+    }
+    // An open PAM session or cgroup has to be closed once the target exits, which needs the proxy.
+    let proxy = cli.proxy
+        || opts.kill_after.is_some()
+        || opts.timeout.is_some()
+        || opts.pidfile.is_some()
+        || cli.daemon
+        || cli.stats
+        || !config.exit.remap.is_empty()
+        || on_pty;
+    #[cfg(target_os = "linux")]
+    let proxy = proxy || config.cgroup.is_some();
+    #[cfg(feature = "pam")]
+    let proxy = proxy || pam.as_ref().map_or(false, pam::Pam::has_session);
+    // Only a proxying wrapper stays around as the parent of the target.
+    let opts = Opts {
+        arg0: Some(arg0.into_os_string()),
+        // a pseudo-terminal is only controlling the session it was acquired by
+        process_group: match (proxy, config.new_session || on_pty) {
+            (false, _) => ProcessGroup::Inherit,
+            (true, false) => ProcessGroup::Own,
+            (true, true) => ProcessGroup::Session,
+        },
+        ..opts
+    };
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    let opts = Opts {
+        parent_death_signal: Some(libc::SIGTERM)
+            .filter(|_| proxy && config.kill_with_wrapper.unwrap_or(true)),
+        ..opts
+    };
+    #[cfg(target_os = "linux")]
+    let cgroup = match &config.cgroup {
+        Some(cfg) => {
+            let name = format!("{}-{}", tar_name.to_string_lossy(), std::process::id());
+            match cgroup::Cgroup::create(cfg, &name) {
+                Ok(cgroup) => Some(cgroup),
+                Err(err) => {
+                    return audit.reject(Rejection::new(RET_SANDBOX, err));
+                }
+            }
+        }
+        None => None,
+    };
+    #[cfg(target_os = "linux")]
+    let opts = Opts {
+        cgroup: cgroup.as_ref().map(cgroup::Cgroup::procs_fd),
+        ..opts
+    };
+    #[cfg(target_os = "linux")]
+    if cli.scope || config.scope {
+        let pid = std::process::id();
+        let name = systemd::scope_name(&tar_name.to_string_lossy(), pid);
+        let description = format!("run-suid {}", target.display());
+        if !systemd::booted() {
+            if verbose {
+                output::verbose(format_args!(
+                    "systemd is not running, continuing without a scope"
+                ));
+            }
+        } else if let Err(err) = systemd::start_scope(&name, &description, pid) {
+            if verbose {
+                output::verbose(format_args!(
+                    "unable to start the scope {}, continuing without it: {}",
+                    name, err
+                ));
+            }
+        } else if verbose {
+            output::verbose(format_args!("running the target in the scope {}", name));
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if (cli.scope || config.scope) && verbose {
+        output::verbose(format_args!(
+            "systemd scopes are not supported on this platform, continuing without one"
+        ));
+    }
+    let recording = match (&config.record_dir, &cli.record) {
+        (Some(dir), _) => {
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let name = format!(
+                "{}-{}-{}.cast",
+                tar_name.to_string_lossy(),
+                secs,
+                std::process::id()
+            );
+            Some(E::create_recording(&dir.join(name)))
+        }
+        // opened as the invoker, so that it is never a file the invoker could not write
+        (None, Some(file)) => Some(E::open_invoker_file(file, 0o600, false)),
+        (None, None) => None,
+    };
+    let recording = match recording.transpose() {
+        Ok(recording) => recording,
+        Err(err) => {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!("Unable to record the session: {}", err),
+            ));
+        }
+    };
+    let pty = match Some(recording).filter(|_| on_pty).map(pty::Pty::open) {
+        Some(Ok(pty)) => Some(pty),
+        Some(Err(err)) => {
+            return audit.reject(Rejection::new(
+                RET_GENERIC_ERROR,
+                format!("Unable to open a pseudo-terminal: {}", err),
+            ));
+        }
+        None => None,
+    };
+    if let Some(pty) = &pty {
+        match (pty.slave(), pty.slave(), pty.slave()) {
+            (Ok(stdin), Ok(stdout), Ok(stderr)) => {
+                command.stdin(stdin).stdout(stdout).stderr(stderr);
+            }
+            (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+                return audit.reject(Rejection::new(
+                    RET_GENERIC_ERROR,
+                    format!("Unable to open the terminal of the target: {}", err),
+                ));
+            }
+        }
+    }
+    let opts = Opts { pty, ..opts };
+    // opened as the invoker, as the privileges of the wrapper or the target must never
+    // resolve a path of the invoker
+    let output = |file: &Option<PathBuf>| match file {
+        Some(file) => match E::open_invoker_file(file, 0o640, opts.append_output) {
+            Ok(opened) => Ok(Some(opened)),
+            Err(err) => Err(format!(
+                "Unable to open the output file {:?}: {}",
+                file, err
+            )),
+        },
+        None => Ok(None),
+    };
+    match (output(&cli.stdout_file), output(&cli.stderr_file)) {
+        (Ok(stdout), Ok(stderr)) => {
+            if let Some(stdout) = stdout {
+                command.stdout(stdout);
+            }
+            if let Some(stderr) = stderr {
+                command.stderr(stderr);
+            }
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            return audit.reject(Rejection::new(RET_SANDBOX, err));
+        }
+    }
+    let pidfile_dir = match cli.pidfile.as_deref().and_then(Path::parent) {
+        Some(dir) => match E::open_invoker_dir(dir) {
+            Ok(dir) => Some(dir),
+            Err(err) => {
+                return audit.reject(Rejection::new(
+                    RET_GENERIC_ERROR,
+                    format!(
+                        "Unable to open the directory of the pid file {:?}: {}",
+                        dir, err
+                    ),
+                ));
+            }
+        },
+        None => None,
+    };
+    let opts = Opts {
+        pidfile_dir,
+        ..opts
+    };
+    #[cfg(target_os = "openbsd")]
+    {
+        let mut paths = vec![
+            (target.clone(), "rx"),
+            (PathBuf::from(command.get_program()), "rx"),
+            (PathBuf::from(run_dir!("run-suid")), "rwc"),
+        ];
+        if let Some(cwd) = command.get_current_dir() {
+            paths.push((cwd.to_path_buf(), "r"));
+        }
+        for file in [&opts.stdout_file, &opts.stderr_file, &opts.pidfile] {
+            paths.extend(file.iter().map(|f| (f.clone(), "rwc")));
+        }
+        if let Err(err) = openbsd::confine(&paths) {
+            return audit.reject(Rejection::new(
+                RET_SANDBOX,
+                format!("Unable to unveil the paths of the target: {}", err),
+            ));
+        }
+    }
+    E::prepare_command(&mut command, args, &opts);
+    #[cfg(feature = "pam")]
+    if let Some(pam) = &pam {
+        for (k, v) in pam.env() {
+            if config::is_env_name(&k) && !config::is_env_denied(&k) {
+                command.env(k, v);
+            }
+        }
+    }
+
+    // the target is only seen as the activated service if it takes over the pid of the wrapper
+    if let Some((count, names)) = listen {
+        if proxy {
+            if verbose {
+                output::verbose(format_args!(
+                    "not passing the socket activation descriptors to a proxied target"
+                ));
+            }
+        } else {
+            for fd in 3..3 + count {
+                sys::clear_cloexec(fd);
+            }
+            command
+                .env("LISTEN_PID", std::process::id().to_string())
+                .env("LISTEN_FDS", count.to_string());
+            if let Some(names) = names {
+                command.env("LISTEN_FDNAMES", names);
+            }
+        }
+    }
+
+    if !proxy {
+        #[cfg(feature = "pam")]
+        std::mem::drop(pam);
+        if let Some(lock) = &lock {
+            // kept above the descriptors passed to the target, which could replace it
+            let above = opts
+                .pass_fds
+                .iter()
+                .map(|(_, to)| to + 1)
+                .max()
+                .unwrap_or(3);
+            sys::dup_above(lock, above);
+        }
+        return E::exec(command, opts);
+    }
+
+    let status = E::wait_for(command, opts);
+    std::mem::drop(lock);
+    #[cfg(target_os = "linux")]
+    std::mem::drop(cgroup);
+    #[cfg(feature = "pam")]
+    std::mem::drop(pam);
+    let remapped = status.as_ref().ok().and_then(|status| {
+        let code = status.code()?;
+        let remap = config.exit.remap.iter();
+        remap
+            .filter(|(from, _)| *from as i32 == code)
+            .map(|(_, to)| *to)
+            .next()
+    });
+    match (status, remapped) {
+        (Ok(_), Some(code)) => ExitCode::from(code),
+        (Ok(status), None) => E::terminate_like(status),
+        (Err(code), _) => code,
+    }
+}
+
+/// Descriptors passed by a socket activating service manager, starting at 3, and their names.
+#[cfg(unix)]
+fn listen_fds() -> Option<(i32, Option<String>)> {
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let count = std::env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    if pid != std::process::id() || !(1..=1024).contains(&count) {
+        return None;
+    }
+    let names = std::env::var("LISTEN_FDNAMES").ok().filter(|names| {
+        names.len() <= 255 * count as usize
+            && names
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"._-:".contains(&b))
+    });
+    Some((count, names))
+}
+
+/// Read an environment file owned by root, in directories only root can change.
+/// A file named by the invoker must also be readable by them.
+#[cfg(unix)]
+fn read_env_file<E: EnvTrait>(
+    path: &Path,
+    by_invoker: bool,
+) -> Result<Vec<(String, String)>, (u8, String)> {
+    if by_invoker && !E::invoker_can_read(path) {
+        return Err((
+            RET_PERM_CONFIG,
+            format!(
+                "The environment file is not readable by the invoker: {:?}",
+                path
+            ),
+        ));
+    }
+    let file = match E::open_policy_file(path) {
+        Ok((0, file, true)) => file,
+        Ok((_, _, true)) => {
+            return Err((
+                RET_OWNER_CONFIG,
+                format!(
+                    "The the owner of the environment file is not root: {:?}",
+                    path
+                ),
+            ))
+        }
+        Ok((_, _, false)) => {
+            return Err((
+                RET_PERM_CONFIG,
+                format!(
+                    "The environment file must be a file writable by only the owning user: {:?}",
+                    path
+                ),
+            ))
+        }
+        Err(err) => {
+            return Err((
+                RET_ENV_ERROR,
+                format!("Unable to open the environment file {:?}: {}", path, err),
+            ))
+        }
+    };
+    match E::insecure_ancestor(path, 0) {
+        Ok(None) => {}
+        Ok(Some((dir, owner, mode))) => {
+            return Err((
+                if owner != 0 { RET_OWNER_CONFIG } else { RET_PERM_CONFIG },
+                format!(
+                    "Every ancestor directory of the environment file must be owned by root and writable by only root: {:?} (owner {}, mode {:o})",
+                    dir, owner, mode & 0o7777
+                ),
+            ))
+        }
+        Err(err) => {
+            return Err((
+                RET_ENV_ERROR,
+                format!("Unable to check the ancestors of the environment file: {}", err),
+            ))
+        }
+    }
+    config::read_env_file(file).map_err(|err| {
+        (
+            RET_CONFIG,
+            format!("Invalid environment file {:?}: {}", path, err),
+        )
+    })
+}
+
+/// Authenticate the invoker, unless they did so within the grace period on the same terminal.
+#[cfg(unix)]
+#[cfg_attr(not(feature = "pam"), allow(unused_variables))]
+fn authenticate<E: EnvTrait>(
+    auth: &config::Auth,
+    service: &str,
+    uid: u32,
+    name: Option<&str>,
+) -> Result<(), (u8, String)> {
+    let key = auth::session_key(E::tty_name().as_deref());
+    if let Some(grace) = auth.grace {
+        let fresh = auth::is_fresh(&auth.timestamp_dir, uid, &key, grace)
+            .map_err(|e| (RET_PERM_CONFIG, e))?;
+        if fresh {
+            return Ok(());
+        }
+    }
+    let name = name.ok_or_else(|| {
+        (
+            RET_ENV_ERROR,
+            format!("No user database entry for uid {}.", uid),
+        )
+    })?;
+    let authenticated = match auth.method {
+        #[cfg(feature = "pam")]
+        config::AuthMethod::Pam => {
+            let tty = E::tty_name();
+            pam::Pam::start(service, name, tty.as_deref())
+                .map_err(|e| (RET_PAM_SESSION, format!("Unable to start PAM: {}", e)))?
+                .authenticate()
+                .map_err(|e| (RET_AUTH_FAILED, format!("Authentication failed: {}", e)))
+        }
+        #[cfg(all(feature = "shadow", target_os = "linux"))]
+        config::AuthMethod::Shadow => {
+            let mut tries = 0;
+            loop {
+                let password = E::ask_terminal(&format!("Password for {}: ", name), false)
+                    .map_err(|e| (RET_NO_TTY, format!("Unable to ask for a password: {}", e)))?;
+                match auth::check_shadow(name, &password) {
+                    Ok(true) => break Ok(()),
+                    Ok(false) if tries < 2 => eprintln!("Sorry, try again."),
+                    Ok(false) => break Err((RET_AUTH_FAILED, "Authentication failed.".into())),
+                    Err(err) => break Err((RET_AUTH_FAILED, err)),
+                }
+                tries += 1;
+            }
+        }
+        #[allow(unreachable_patterns)]
+        method => Err((
+            RET_CONFIG,
+            format!(
+                "Authentication by {:?} is not compiled into this executable.",
+                method
+            ),
+        )),
+    };
+    authenticated?;
+    if auth.grace.is_some() {
+        auth::touch(&auth.timestamp_dir, uid, &key).map_err(|e| (RET_PERM_CONFIG, e))?;
+    }
+    Ok(())
+}
+
+/// Authenticate the invoker and open a session for the target user, as configured.
+#[cfg(feature = "pam")]
+fn start_pam<E: EnvTrait>(
+    cfg: &config::Pam,
+    uid: u32,
+    tar_uid: u32,
+) -> Result<pam::Pam, (u8, String)> {
+    let name_of = |id| match E::user_by_uid(id) {
+        Ok(Some(user)) => Ok(user.name),
+        Ok(None) => Err((
+            RET_ENV_ERROR,
+            format!("No user database entry for uid {}.", id),
+        )),
+        Err(err) => Err((
+            RET_ENV_ERROR,
+            format!("Unable to look up uid {}: {}", id, err),
+        )),
+    };
+    let invoker = name_of(uid)?;
+    let tty = E::tty_name();
+    let mut pam = pam::Pam::start(&cfg.service, &invoker, tty.as_deref())
+        .map_err(|e| (RET_PAM_SESSION, format!("Unable to start PAM: {}", e)))?;
+    if cfg.authenticate {
+        pam.authenticate()
+            .map_err(|e| (RET_AUTH_FAILED, format!("Authentication failed: {}", e)))?;
+    }
+    if cfg.session {
+        pam.open_session(&name_of(tar_uid)?).map_err(|e| {
+            (
+                RET_PAM_SESSION,
+                format!("Unable to open a PAM session: {}", e),
+            )
+        })?;
+    }
+    Ok(pam)
+}
+
+/// Combine the capabilities requested on the command line with those allowed by the configuration.
+///
+/// The caller can only narrow the set. Capabilities for non-root targets are granted as ambient
+/// capabilities, so they must come from the configuration.
+#[cfg(target_os = "linux")]
+fn resolve_caps(
+    cli: &[&str],
+    config: Option<&[String]>,
+    cli_given: bool,
+    tar_uid: u32,
+) -> Result<Option<u64>, String> {
+    let cli = if cli_given {
+        Some(linux::parse_caps(cli).map_err(|e| format!("Invalid --caps: {}", e))?)
+    } else {
+        None
+    };
+    let config = match config {
+        Some(names) => Some(
+            linux::parse_caps(names)
+                .map_err(|e| format!("Invalid caps in configuration: {}", e))?,
+        ),
+        None => None,
+    };
+    match (config, cli) {
+        (Some(config), Some(cli)) => Ok(Some(config & cli)),
+        (Some(config), None) => Ok(Some(config)),
+        (None, Some(_)) if tar_uid != 0 => {
+            Err("Capabilities for a non-root target must be granted by its configuration.".into())
+        }
+        (None, cli) => Ok(cli),
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn resolve_caps(
+    _cli: &[&str],
+    config: Option<&[String]>,
+    cli_given: bool,
+    _tar_uid: u32,
+) -> Result<Option<u64>, String> {
+    if cli_given || config.is_some() {
+        return Err("Capabilities are not supported on this platform.".into());
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        env::User,
+        mock::{Entry, MockEnv, State},
+    };
+
+    const OWNER: u32 = 1000;
+    const INVOKER: u32 = 1001;
+    const CONFIG: &str = "/opt/app/bin/tool.run-suid.toml";
+    const ALLOW_LIST: &str = "/opt/app/bin/tool.run-suid.allow";
+
+    fn user(name: &str, uid: u32) -> (User, Vec<u32>) {
+        let user = User {
+            name: name.to_owned(),
+            uid,
+            gid: uid,
+            home: PathBuf::from("/home").join(name),
+            shell: PathBuf::from("/bin/sh"),
+        };
+        (user, vec![uid])
+    }
+
+    /// A healthy installation of `/opt/app/bin/tool` owned by [OWNER], invoked by `alice`.
+    fn installed() -> State {
+        let mut state = State {
+            euid: OWNER,
+            uid: INVOKER,
+            egid: OWNER,
+            gid: INVOKER,
+            exe: PathBuf::from("/opt/app/bin/tool"),
+            users: vec![user("app", OWNER), user("alice", INVOKER)],
+            ..State::default()
+        };
+        for (path, entry) in [
+            ("/opt/app/bin", Entry::dir(OWNER, 0o500)),
+            ("/opt/app/bin/tool", Entry::file(OWNER, 0o4511)),
+            ("/opt/app/bin/tool.run-suid", Entry::file(OWNER, 0o4500)),
+        ] {
+            state.files.insert(PathBuf::from(path), entry);
+        }
+        state
+    }
+
+    /// An installation with a policy file of the owner.
+    fn with_policy(path: &str, contents: &str) -> State {
+        let mut state = installed();
+        state
+            .files
+            .insert(PathBuf::from(path), Entry::file(OWNER, 0o644));
+        state
+            .contents
+            .insert(PathBuf::from(path), contents.to_owned());
+        state
+    }
+
+    /// Run the wrapper in `state` and get its exit code and the state it left.
+    fn run_with(state: State, options: Options, args: &[&str]) -> (ExitCode, State) {
+        MockEnv::set(state);
+        let args = args.iter().map(|a| a.to_string()).collect();
+        let code = run_in::<MockEnv>("tool", Ok(options), args);
+        (code, MockEnv::state())
+    }
+
+    fn dry_run() -> Options {
+        Options {
+            dry_run: true,
+            ..Options::default()
+        }
+    }
+
+    fn exits(code: u8) -> ExitCode {
+        ExitCode::from(own(code))
+    }
+
+    #[test]
+    fn rejects_a_locked_out_invoker() {
+        let state = State {
+            locked_out: Some(60),
+            ..installed()
+        };
+        let (code, state) = run_with(state, Options::default(), &[]);
+        assert_eq!(code, exits(RET_LOCKED_OUT));
+        assert!(state.records.iter().any(|r| r.contains("RESULT=rejected")));
+    }
+
+    #[test]
+    fn rejects_an_invoker_the_configuration_denies() {
+        let state = with_policy(CONFIG, "allowed_invokers = [\"bob\", 1002]\n");
+        let (code, state) = run_with(state, Options::default(), &[]);
+        assert_eq!(code, exits(RET_INVOKER_DENIED));
+        assert_eq!(state.failures, 1);
+        assert!(state
+            .records
+            .iter()
+            .any(|r| r.contains("UID=1001") && r.contains("RESULT=rejected")));
+    }
+
+    #[test]
+    fn rejects_an_invoker_missing_from_the_allowlist() {
+        let state = with_policy(ALLOW_LIST, "# operators\nbob\n1002\n");
+        let (code, state) = run_with(state, Options::default(), &[]);
+        assert_eq!(code, exits(RET_INVOKER_DENIED));
+        assert_eq!(state.failures, 1);
+
+        let state = with_policy(ALLOW_LIST, "bob\nalice\n");
+        let (code, state) = run_with(state, dry_run(), &[]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(state.failures, 0);
+    }
+
+    #[test]
+    fn rejects_an_allowlist_writable_by_others() {
+        let mut state = with_policy(ALLOW_LIST, "alice\n");
+        state
+            .files
+            .insert(PathBuf::from(ALLOW_LIST), Entry::file(OWNER, 0o666));
+        let (code, _) = run_with(state, Options::default(), &[]);
+        assert_eq!(code, exits(RET_PERM_CONFIG));
+    }
+
+    #[test]
+    fn rejects_refused_arguments() {
+        let config = "[args]\ndeny = [\"--force*\"]\noptions = [\"--verbose\"]\n";
+        for args in [&["--force"][..], &["--verbose", "--force=yes"], &["-x"]] {
+            let (code, state) = run_with(with_policy(CONFIG, config), Options::default(), args);
+            assert_eq!(code, exits(RET_ARGS_DENIED), "{:?}", args);
+            assert!(state.records.iter().any(|r| r.contains("RESULT=rejected")));
+        }
+
+        let (code, _) = run_with(with_policy(CONFIG, config), dry_run(), &["--verbose", "x"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+
+        let config = "[args]\nallow = [\"status\", \"restart\"]\n";
+        let (code, _) = run_with(with_policy(CONFIG, config), Options::default(), &["stop"]);
+        assert_eq!(code, exits(RET_ARGS_DENIED));
+    }
+
+    #[test]
+    fn rejects_denied_environment_variables() {
+        let options = Options {
+            env: vec![("LD_PRELOAD".to_owned(), "/tmp/evil.so".to_owned())],
+            ..Options::default()
+        };
+        let state = with_policy(CONFIG, "allowed_env = [\"TZ\"]\n");
+        let (code, _) = run_with(state.clone(), options, &[]);
+        assert_eq!(code, exits(RET_GENERIC_ERROR));
+        let options = Options {
+            env: vec![("TZ".to_owned(), "UTC".to_owned())],
+            ..dry_run()
+        };
+        let (code, state) = run_with(state, options, &[]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(state.records.iter().any(|r| r.contains("RESULT=accepted")));
+
+        // not even the configuration can allow a variable on the denylist
+        for name in ["LD_PRELOAD", "LD_AUDIT", "DYLD_INSERT_LIBRARIES"] {
+            let config = format!("allowed_env = [\"{}\"]\n", name);
+            let (code, _) = run_with(with_policy(CONFIG, &config), Options::default(), &[]);
+            assert_eq!(code, exits(RET_CONFIG), "{}", name);
+        }
+    }
+}
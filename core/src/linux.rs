@@ -0,0 +1,410 @@
+//! Linux specific hardening of the child process.
+//!
+//! Everything called from a `pre_exec` hook must be async-signal-safe: no allocation, no locks.
+
+use crate::config::Scheduler;
+
+/// Capability names in kernel order, without the `cap_` prefix.
+static CAP_NAMES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Parse a list of capability names, such as `cap_net_bind_service`, into a bit mask.
+pub(crate) fn parse_caps<S: AsRef<str>>(names: &[S]) -> Result<u64, String> {
+    let mut mask = 0;
+    for name in names {
+        let name = name.as_ref();
+        let lower = name.to_ascii_lowercase();
+        let bare = lower.strip_prefix("cap_").unwrap_or(&lower);
+        match CAP_NAMES.iter().position(|c| *c == bare) {
+            Some(bit) => mask |= 1 << bit,
+            None => return Err(format!("unknown capability {:?}", name)),
+        }
+    }
+    Ok(mask)
+}
+
+/// Format a capability mask as a list of names.
+pub(crate) fn format_caps(mask: u64) -> Vec<String> {
+    CAP_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, name)| format!("cap_{}", name))
+        .collect()
+}
+
+/// Remove every capability not in `mask` from the bounding set, and ask the kernel to keep
+/// the permitted set across the coming uid change if the target is not root.
+///
+/// Must run before the credentials are changed, while `CAP_SETPCAP` is still effective.
+pub(crate) fn restrict_bounding_set(mask: u64, keep_caps: bool) -> Result<(), &'static [u8]> {
+    for cap in 0..64 {
+        if mask & (1 << cap) != 0 {
+            continue;
+        }
+        if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0) } != 0 {
+            if errno() == libc::EINVAL {
+                break;
+            }
+            return Err(b"Unable to drop capabilities from the bounding set.\n");
+        }
+    }
+    if keep_caps && unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) } != 0 {
+        return Err(b"Unable to keep capabilities across the uid change.\n");
+    }
+    Ok(())
+}
+
+/// Reduce the effective, permitted, and inheritable sets to `mask`, raising them as ambient
+/// capabilities so that they survive `exec` for a non-root target.
+///
+/// Must run after the credentials are changed.
+pub(crate) fn apply_caps(mask: u64, ambient: bool) -> Result<(), &'static [u8]> {
+    let mut header = CapHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let data = [
+        CapData {
+            effective: mask as u32,
+            permitted: mask as u32,
+            inheritable: mask as u32,
+        },
+        CapData {
+            effective: (mask >> 32) as u32,
+            permitted: (mask >> 32) as u32,
+            inheritable: (mask >> 32) as u32,
+        },
+    ];
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+        return Err(b"Unable to set the capabilities of the target.\n");
+    }
+    if !ambient {
+        return Ok(());
+    }
+    for cap in 0..64 {
+        if mask & (1 << cap) == 0 {
+            continue;
+        }
+        let r = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                cap as libc::c_ulong,
+                0,
+                0,
+            )
+        };
+        if r != 0 {
+            return Err(b"Unable to raise an ambient capability of the target.\n");
+        }
+    }
+    Ok(())
+}
+
+/// Switch to a scheduling policy without a static priority.
+pub(crate) fn set_scheduler(policy: Scheduler) -> Result<(), &'static [u8]> {
+    let policy = match policy {
+        Scheduler::Other => libc::SCHED_OTHER,
+        Scheduler::Batch => libc::SCHED_BATCH,
+        Scheduler::Idle => libc::SCHED_IDLE,
+    };
+    let param = libc::sched_param { sched_priority: 0 };
+    if unsafe { libc::sched_setscheduler(0, policy, &param) } != 0 {
+        return Err(b"Unable to set the scheduling policy of the target.\n");
+    }
+    Ok(())
+}
+
+/// Build the affinity mask of the given CPUs, outside of the `pre_exec` hook.
+pub(crate) fn cpu_set(cpus: &[usize]) -> libc::cpu_set_t {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for cpu in cpus {
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+    }
+    set
+}
+
+/// Confine the process to the CPUs of the mask.
+pub(crate) fn set_affinity(set: &libc::cpu_set_t) -> Result<(), &'static [u8]> {
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) } != 0 {
+        return Err(b"Unable to set the CPU affinity of the target.\n");
+    }
+    Ok(())
+}
+
+/// Write an already formatted adjustment to the OOM score of the process.
+pub(crate) fn set_oom_score_adj(value: &[u8]) -> Result<(), &'static [u8]> {
+    let path = b"/proc/self/oom_score_adj\0";
+    let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(b"Unable to open the OOM score adjustment of the target.\n");
+    }
+    let written = unsafe { libc::write(fd, value.as_ptr().cast(), value.len()) };
+    unsafe { libc::close(fd) };
+    if written != value.len() as isize {
+        return Err(b"Unable to adjust the OOM score of the target.\n");
+    }
+    Ok(())
+}
+
+/// Have the next `exec` enter an SELinux context, as `setexeccon` does, with the context
+/// terminated by its NUL.
+pub(crate) fn set_exec_context(context: &[u8]) -> Result<(), &'static [u8]> {
+    let path = b"/proc/self/attr/exec\0";
+    let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(b"Unable to open the SELinux exec context of the target.\n");
+    }
+    let written = unsafe { libc::write(fd, context.as_ptr().cast(), context.len()) };
+    unsafe { libc::close(fd) };
+    if written != context.len() as isize {
+        return Err(b"Unable to set the SELinux context of the target.\n");
+    }
+    Ok(())
+}
+
+/// Whether AppArmor is enabled, without which a change of profile is silently ignored.
+pub(crate) fn apparmor_enabled() -> bool {
+    std::fs::read("/sys/module/apparmor/parameters/enabled").map_or(false, |v| v.starts_with(b"Y"))
+}
+
+/// Have the next `exec` enter an AppArmor profile, as `aa_change_onexec` does, with the
+/// command already formatted as `exec <profile>`.
+///
+/// The attribute of AppArmor itself is preferred, as `/proc/self/attr/exec` belongs to another
+/// security module on kernels that stack them.
+pub(crate) fn change_profile_on_exec(command: &[u8]) -> Result<(), &'static [u8]> {
+    let mut fd = unsafe {
+        libc::open(
+            b"/proc/self/attr/apparmor/exec\0".as_ptr().cast(),
+            libc::O_WRONLY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 && errno() == libc::ENOENT {
+        fd = unsafe {
+            libc::open(
+                b"/proc/self/attr/exec\0".as_ptr().cast(),
+                libc::O_WRONLY | libc::O_CLOEXEC,
+            )
+        };
+    }
+    if fd < 0 {
+        return Err(b"Unable to open the AppArmor exec attribute of the target.\n");
+    }
+    let written = unsafe { libc::write(fd, command.as_ptr().cast(), command.len()) };
+    unsafe { libc::close(fd) };
+    if written != command.len() as isize {
+        return Err(b"Unable to change to the AppArmor profile of the target.\n");
+    }
+    Ok(())
+}
+
+/// The context SELinux runs the file at `target` in when the wrapper executes it without
+/// setting one, `None` if SELinux is not enabled.
+pub(crate) fn default_exec_context(
+    target: &std::path::Path,
+) -> Result<Option<String>, std::io::Error> {
+    use std::io::{Read, Write};
+    use std::os::unix::ffi::OsStrExt;
+
+    if !std::path::Path::new("/sys/fs/selinux/enforce").exists() {
+        return Ok(None);
+    }
+    let trim = |s: &[u8]| {
+        String::from_utf8_lossy(s)
+            .trim_end_matches(['\0', '\n'])
+            .to_string()
+    };
+    let current = trim(&std::fs::read("/proc/self/attr/current")?);
+    let path = std::ffi::CString::new(target.as_os_str().as_bytes())?;
+    let mut label = vec![0u8; 256];
+    let n = unsafe {
+        libc::lgetxattr(
+            path.as_ptr(),
+            b"security.selinux\0".as_ptr().cast(),
+            label.as_mut_ptr().cast(),
+            label.len(),
+        )
+    };
+    if n < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let label = trim(&label[..n as usize]);
+    let class = std::fs::read_to_string("/sys/fs/selinux/class/process/index")?;
+    // the transaction file answers the write with the computed context
+    let mut create = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/sys/fs/selinux/create")?;
+    write!(create, "{} {} {}", current, label, class.trim())?;
+    let mut computed = Vec::new();
+    create.read_to_end(&mut computed)?;
+    Ok(Some(trim(&computed)))
+}
+
+/// Unshare the mount namespace and mount an empty tmpfs on `/tmp` and, if it exists, `/var/tmp`,
+/// without propagating anything back to the caller's namespace.
+///
+/// The working directory is entered again afterwards, as it may have been hidden.
+pub(crate) fn private_tmp(cwd: &std::ffi::CStr) -> Result<(), &'static [u8]> {
+    let none = std::ptr::null::<libc::c_char>();
+    if unsafe { libc::unshare(libc::CLONE_NEWNS) } != 0 {
+        return Err(b"Unable to create a mount namespace for the target.\n");
+    }
+    let root = b"/\0".as_ptr().cast();
+    let flags = libc::MS_REC | libc::MS_SLAVE;
+    if unsafe { libc::mount(none, root, none, flags, std::ptr::null()) } != 0 {
+        return Err(b"Unable to make the mounts of the target private.\n");
+    }
+    for (dir, required) in [(&b"/tmp\0"[..], true), (&b"/var/tmp\0"[..], false)] {
+        let r = unsafe {
+            libc::mount(
+                b"tmpfs\0".as_ptr().cast(),
+                dir.as_ptr().cast(),
+                b"tmpfs\0".as_ptr().cast(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                b"mode=1777\0".as_ptr().cast(),
+            )
+        };
+        if r != 0 && (required || errno() != libc::ENOENT) {
+            return Err(b"Unable to mount a private temporary directory for the target.\n");
+        }
+    }
+    if unsafe { libc::chdir(cwd.as_ptr()) } != 0 {
+        return Err(b"The working directory is hidden by the private temporary directory.\n");
+    }
+    Ok(())
+}
+
+/// `struct ifreq`, only as much as is needed to set the flags of an interface.
+#[repr(C)]
+struct IfReq {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    flags: libc::c_short,
+    _pad: [u8; 22],
+}
+
+/// Unshare the network namespace, leaving the target with only the loopback interface.
+pub(crate) fn isolate_network() -> Result<(), &'static [u8]> {
+    if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+        return Err(b"Unable to create a network namespace for the target.\n");
+    }
+    let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if sock < 0 {
+        return Err(b"Unable to bring up the loopback interface of the target.\n");
+    }
+    let mut req = IfReq {
+        name: [0; libc::IFNAMSIZ],
+        flags: 0,
+        _pad: [0; 22],
+    };
+    req.name[..2].copy_from_slice(&[b'l' as libc::c_char, b'o' as libc::c_char]);
+    let up = unsafe {
+        libc::ioctl(sock, libc::SIOCGIFFLAGS as _, &mut req) == 0 && {
+            req.flags |= libc::IFF_UP as libc::c_short;
+            libc::ioctl(sock, libc::SIOCSIFFLAGS as _, &req) == 0
+        }
+    };
+    unsafe { libc::close(sock) };
+    if !up {
+        return Err(b"Unable to bring up the loopback interface of the target.\n");
+    }
+    Ok(())
+}
+
+/// Mark the wrapper as not dumpable, so that it leaves no core dump and can not be traced or
+/// have its memory read by the invoker. `exec` resets it for the target.
+pub(crate) fn set_not_dumpable() -> Result<(), std::io::Error> {
+    match unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// The login uid of a process outside of any login session.
+const UNSET_LOGINUID: &str = "4294967295";
+
+/// Attribute the wrapper and everything it starts to the invoker in the kernel audit log, if
+/// it was started outside of a login session, `false` if the login uid was already set or the
+/// kernel does not audit. The audit session is inherited unchanged otherwise.
+///
+/// Setting it requires `CAP_AUDIT_CONTROL`, and it may be set only once.
+pub(crate) fn set_loginuid(uid: u32) -> Result<bool, std::io::Error> {
+    use std::io::Write;
+
+    let current = match std::fs::read_to_string("/proc/self/loginuid") {
+        Ok(current) => current,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    if current.trim() != UNSET_LOGINUID {
+        return Ok(false);
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open("/proc/self/loginuid")?
+        .write_all(uid.to_string().as_bytes())?;
+    Ok(true)
+}
+
+fn errno() -> libc::c_int {
+    unsafe { *libc::__errno_location() }
+}
@@ -0,0 +1,109 @@
+//! Failed invocations counted per invoker, who is locked out for a doubling period once too
+//! many failed in a row.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory the failures are counted in, owned by root.
+const STATE_DIR: &str = match option_env!("RUN_SUID_LOCKOUT_DIR") {
+    Some(dir) => dir,
+    None => run_dir!("run-suid/failures"),
+};
+/// Failures in a row before the invoker is locked out.
+const MAX_FAILURES: u64 = 5;
+/// Lockout after the first failure past the limit, doubled for every further one.
+const BASE_SECS: u64 = 30;
+const MAX_SECS: u64 = 3600;
+
+/// Number of failures in a row and when the last one happened.
+struct Failures {
+    file: File,
+    count: u64,
+    last: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// How long the invoker is locked out after `count` failures in a row.
+fn lockout_secs(count: u64) -> u64 {
+    if count < MAX_FAILURES {
+        return 0;
+    }
+    let doublings = (count - MAX_FAILURES).min(16) as u32;
+    (BASE_SECS << doublings).min(MAX_SECS)
+}
+
+/// Open and lock the failures of an invoker, only when the wrapper runs as root.
+fn open(uid: u32) -> Option<Failures> {
+    if crate::sys::geteuid() != 0 {
+        return None;
+    }
+    let dir = PathBuf::from(STATE_DIR);
+    if let Err(err) = crate::auth::private_dir(&dir, "lockout directory") {
+        crate::output::warn(format_args!("{}", err));
+        return None;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(dir.join(uid.to_string()))
+        .ok()?;
+    if crate::sys::flock(&file, false).is_err() {
+        return None;
+    }
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    let mut fields = content.split_whitespace().map(str::parse::<u64>);
+    let (count, last) = match (fields.next(), fields.next()) {
+        (Some(Ok(count)), Some(Ok(last))) => (count, last),
+        _ => (0, 0),
+    };
+    Some(Failures { file, count, last })
+}
+
+impl Failures {
+    fn save(&mut self) {
+        let content = format!("{} {}\n", self.count, self.last);
+        let _ = self
+            .file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.set_len(0))
+            .and_then(|_| self.file.write_all(content.as_bytes()));
+    }
+}
+
+/// Seconds left of a lockout of the invoker, if locked out.
+pub(crate) fn remaining(uid: u32) -> Option<u64> {
+    let failures = open(uid)?;
+    let until = failures.last + lockout_secs(failures.count);
+    Some(until.saturating_sub(now())).filter(|secs| *secs > 0)
+}
+
+/// Count a failure of the invoker, and get how long they are now locked out for.
+pub(crate) fn fail(uid: u32) -> Option<u64> {
+    let mut failures = open(uid)?;
+    failures.count += 1;
+    failures.last = now();
+    failures.save();
+    Some(lockout_secs(failures.count)).filter(|secs| *secs > 0)
+}
+
+/// Forget the failures of the invoker after a run that passed every check.
+pub(crate) fn reset(uid: u32) {
+    if let Some(mut failures) = open(uid).filter(|f| f.count > 0) {
+        failures.count = 0;
+        failures.save();
+    }
+}
@@ -0,0 +1,350 @@
+//! Detached minisign signatures of target executables.
+
+use std::io::Read;
+
+use crate::ed25519::{self, SHA512_H0};
+
+/// A minisign public key, as found on the second line of a `.pub` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PublicKey {
+    key_id: [u8; 8],
+    key: [u8; 32],
+}
+
+impl PublicKey {
+    /// Parse the base64 encoded key.
+    pub(crate) fn parse(s: &str) -> Option<PublicKey> {
+        let raw = base64(s.trim())?;
+        if raw.len() != 42 || &raw[..2] != b"Ed" {
+            return None;
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&raw[2..10]);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&raw[10..]);
+        Some(PublicKey { key_id, key })
+    }
+}
+
+/// A parsed `.minisig` style signature file.
+pub(crate) struct Signature {
+    prehashed: bool,
+    key_id: [u8; 8],
+    signature: [u8; 64],
+    trusted_comment: String,
+    global_signature: [u8; 64],
+}
+
+impl Signature {
+    pub(crate) fn parse(s: &str) -> Result<Signature, String> {
+        let mut lines = s.lines();
+        let mut next = |what: &str| {
+            lines
+                .next()
+                .map(|l| l.trim_end_matches('\r'))
+                .ok_or_else(|| format!("missing {}", what))
+        };
+        if !next("untrusted comment")?.starts_with("untrusted comment: ") {
+            return Err("the first line must be an untrusted comment".to_string());
+        }
+        let raw = base64(next("signature")?).ok_or("the signature is not valid base64")?;
+        if raw.len() != 74 {
+            return Err("the signature has the wrong length".to_string());
+        }
+        let prehashed = match &raw[..2] {
+            b"Ed" => false,
+            b"ED" => true,
+            _ => return Err("unsupported signature algorithm".to_string()),
+        };
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&raw[2..10]);
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(&raw[10..]);
+        let trusted_comment = next("trusted comment")?
+            .strip_prefix("trusted comment: ")
+            .ok_or("the third line must be a trusted comment")?
+            .to_owned();
+        let raw =
+            base64(next("global signature")?).ok_or("the global signature is not valid base64")?;
+        if raw.len() != 64 {
+            return Err("the global signature has the wrong length".to_string());
+        }
+        let mut global_signature = [0u8; 64];
+        global_signature.copy_from_slice(&raw);
+        Ok(Signature {
+            prehashed,
+            key_id,
+            signature,
+            trusted_comment,
+            global_signature,
+        })
+    }
+
+    /// Check that the signature covers everything readable from `input`.
+    pub(crate) fn verify<R: Read>(&self, key: &PublicKey, mut input: R) -> Result<(), String> {
+        if self.key_id != key.key_id {
+            return Err("the signature was made with a different key".to_string());
+        }
+        let mut msg = Vec::new();
+        if self.prehashed {
+            msg.extend_from_slice(&blake2b(input).map_err(|e| e.to_string())?);
+        } else {
+            input.read_to_end(&mut msg).map_err(|e| e.to_string())?;
+        }
+        if !ed25519::verify(&key.key, &msg, &self.signature) {
+            return Err("the signature does not match".to_string());
+        }
+        let mut global = self.signature.to_vec();
+        global.extend_from_slice(self.trusted_comment.as_bytes());
+        if !ed25519::verify(&key.key, &global, &self.global_signature) {
+            return Err("the trusted comment signature does not match".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn base64(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=');
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// BLAKE2b-512 of everything readable from `input`, as used by prehashed signatures.
+fn blake2b<R: Read>(mut input: R) -> std::io::Result<[u8; 64]> {
+    let mut h = SHA512_H0;
+    h[0] ^= 0x0101_0000 ^ 64;
+    let mut block = [0u8; 128];
+    let mut fill = 0;
+    let mut len: u128 = 0;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for b in buf[..n].iter() {
+            // the last block is only compressed once it is known to be the last
+            if fill == 128 {
+                len += 128;
+                blake2b_compress(&mut h, &block, len, false);
+                fill = 0;
+            }
+            block[fill] = *b;
+            fill += 1;
+        }
+    }
+    len += fill as u128;
+    block[fill..].iter_mut().for_each(|b| *b = 0);
+    blake2b_compress(&mut h, &block, len, true);
+
+    let mut out = [0u8; 64];
+    for (o, s) in out.chunks_exact_mut(8).zip(h.iter()) {
+        o.copy_from_slice(&s.to_le_bytes());
+    }
+    Ok(out)
+}
+
+fn blake2b_compress(h: &mut [u64; 8], block: &[u8; 128], len: u128, last: bool) {
+    let mut m = [0u64; 16];
+    for (m, b) in m.iter_mut().zip(block.chunks_exact(8)) {
+        let mut w = [0u8; 8];
+        w.copy_from_slice(b);
+        *m = u64::from_le_bytes(w);
+    }
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&SHA512_H0);
+    v[12] ^= len as u64;
+    v[13] ^= (len >> 64) as u64;
+    if last {
+        v[14] = !v[14];
+    }
+    for round in 0..12 {
+        let s = &SIGMA[round % 10];
+        g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A throwaway key with the id `3a1f5e7c9b2d4068`, which signed [target] in both formats.
+    const KEY: &str = "RWQ6H158my1AaGUozFLiaqR22a2hB1aRfdmsJU7ZuHpTgHhkJNP/KDFQ";
+
+    const LINES: &[u8] = b"#!/bin/sh\necho signed target\n";
+
+    const LEGACY: &str = "untrusted comment: signature from minisign secret key
+RWQ6H158my1AaOy8hMnmuFN4jQZA+ec9f665PajpErcfiij6ro0jJRzlq7na2bohNILBxbtvGoayL66u3JYXNOSKBaZ3np22Tg0=
+trusted comment: timestamp:1700000000\tfile:tool
+6dKDk+Za0rcV9U/n2yNXJ/onTTBUUO7KA9bTDXW7MooWYAhAASd6AYVQEqtifLDeUcP5tSKX8z3DvFwWsDP2BA==
+";
+
+    const PREHASHED: &str = "untrusted comment: signature from minisign secret key
+RUQ6H158my1AaBn4YRChnaMnsgmRl3zL9Ocv8sRVKgBjYqdhV+hY7QYBxXwgW837Fg5DDFB6JEwYmLgKFNSgaFYQ2T2dCDDOkwk=
+trusted comment: timestamp:1700000000\tfile:tool
+DhoYW56jmvwykvQCwE5mWAOPCp0Cv5UX9ws1UA3o3Abu+t6MYXsTBK3YJ/jXRpd5Iz52D6OjsNNrbNazr7u7Aw==
+";
+
+    fn target() -> Vec<u8> {
+        // longer than a block of BLAKE2b
+        LINES.repeat(6)
+    }
+
+    fn hex(digest: &[u8; 64]) -> String {
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn hashes_the_blake2b_examples() {
+        assert_eq!(
+            hex(&blake2b(&b""[..]).unwrap()),
+            "786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419\
+             d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce"
+        );
+        assert_eq!(
+            hex(&blake2b(&b"abc"[..]).unwrap()),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        // exactly one block, which must be compressed as the last
+        assert_eq!(
+            hex(&blake2b(&[b'a'; 128][..]).unwrap()),
+            "fc6c71f688f43ea7d60817478808f3cac753e61571865c95adbc2d9122c943a7\
+             6b92c2cb1047ef3fe7bf6e436ec1d0a99a9e5b216780bf7fed9d7ca91d3a8f3b"
+        );
+        assert_eq!(
+            hex(&blake2b(&[b'a'; 300][..]).unwrap()),
+            "a2ff3040eda405b929c2fc2fd93e8add6ac3bb5369b679bae170ac6956863ca0\
+             06285f132a868000fc3fae5bc696e5d17fe3fddfb4a342876c40451184742986"
+        );
+    }
+
+    #[test]
+    fn verifies_both_formats() {
+        let key = PublicKey::parse(KEY).unwrap();
+        for signature in [LEGACY, PREHASHED] {
+            let signature = Signature::parse(signature).unwrap();
+            assert_eq!(signature.verify(&key, &target()[..]), Ok(()));
+        }
+        assert!(!Signature::parse(LEGACY).unwrap().prehashed);
+        assert!(Signature::parse(PREHASHED).unwrap().prehashed);
+    }
+
+    #[test]
+    fn rejects_another_target() {
+        let key = PublicKey::parse(KEY).unwrap();
+        let mut altered = target();
+        altered[0] = b' ';
+        for signature in [LEGACY, PREHASHED] {
+            let signature = Signature::parse(signature).unwrap();
+            assert_eq!(
+                signature.verify(&key, &altered[..]),
+                Err("the signature does not match".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_a_tampered_trusted_comment() {
+        let key = PublicKey::parse(KEY).unwrap();
+        for signature in [LEGACY, PREHASHED] {
+            let tampered = signature.replace("file:tool", "file:tool2");
+            let signature = Signature::parse(&tampered).unwrap();
+            assert_eq!(
+                signature.verify(&key, &target()[..]),
+                Err("the trusted comment signature does not match".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_another_key_id() {
+        // the same key under the id `3a1f5e7c9b2d4069`
+        let key = PublicKey::parse("RWQ6H158my1AaWUozFLiaqR22a2hB1aRfdmsJU7ZuHpTgHhkJNP/KDFQ");
+        let signature = Signature::parse(PREHASHED).unwrap();
+        assert_eq!(
+            signature.verify(&key.unwrap(), &target()[..]),
+            Err("the signature was made with a different key".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(
+            PublicKey::parse("RWQ6H158my1A*GUozFLiaqR22a2hB1aRfdms"),
+            None
+        );
+        assert_eq!(PublicKey::parse(&KEY[..40]), None);
+        assert_eq!(
+            Signature::parse(&LEGACY.replace("RWQ6H158my1AaOy8", "RWQ6H158my1A!Oy8")).err(),
+            Some("the signature is not valid base64".to_string())
+        );
+        assert_eq!(
+            Signature::parse(&LEGACY.replace("6dKDk+Za0rcV", "6dKDk+Za0rc")).err(),
+            Some("the global signature has the wrong length".to_string())
+        );
+        assert_eq!(
+            Signature::parse(&LEGACY.replace("trusted comment: timestamp", "timestamp")).err(),
+            Some("the third line must be a trusted comment".to_string())
+        );
+        assert_eq!(
+            Signature::parse(&LEGACY[..LEGACY.rfind("6dKD").unwrap()]).err(),
+            Some("missing global signature".to_string())
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! An environment made up by a test, with fake ids, files, users, and scripted errors.
+//!
+//! The state is kept per thread, so that tests running in parallel do not see each other's.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, ExitStatus},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    env::{EnvTrait, Kind, Mount, Stat, User},
+    nix::{self, PERM_ANCESTOR_MASK, PERM_DIR_EXPECTED, PERM_DIR_MASK},
+};
+
+/// A fake file, directory, or symbolic link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub(crate) uid: u32,
+    pub(crate) stat: Stat,
+}
+
+impl Entry {
+    pub(crate) fn file(uid: u32, mode: u32) -> Entry {
+        Entry::new(Kind::File, uid, mode)
+    }
+    pub(crate) fn dir(uid: u32, mode: u32) -> Entry {
+        Entry::new(Kind::Dir, uid, mode)
+    }
+    pub(crate) fn symlink(uid: u32) -> Entry {
+        Entry::new(Kind::Symlink, uid, 0o777)
+    }
+    fn new(kind: Kind, uid: u32, mode: u32) -> Entry {
+        Entry {
+            uid,
+            stat: Stat {
+                kind,
+                mode,
+                nlink: 1,
+            },
+        }
+    }
+    pub(crate) fn links(self, nlink: u64) -> Entry {
+        Entry {
+            stat: Stat { nlink, ..self.stat },
+            ..self
+        }
+    }
+}
+
+/// Everything [MockEnv] answers with.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct State {
+    pub(crate) euid: u32,
+    pub(crate) uid: u32,
+    pub(crate) egid: u32,
+    pub(crate) gid: u32,
+    /// Files by path, where a directory missing from it is owned by root and writable by only
+    /// root.
+    pub(crate) files: HashMap<PathBuf, Entry>,
+    /// The error number opening or inspecting a path fails with.
+    pub(crate) errors: HashMap<PathBuf, i32>,
+    /// What reading a file gives, nothing for one missing from it.
+    pub(crate) contents: HashMap<PathBuf, String>,
+    pub(crate) users: Vec<(User, Vec<u32>)>,
+    /// The path of the running executable.
+    pub(crate) exe: PathBuf,
+    /// Seconds left of a lockout of the invoker.
+    pub(crate) locked_out: Option<u64>,
+    /// Failed invocations counted towards a lockout.
+    pub(crate) failures: u64,
+    /// Audit records written, in the system log or the kernel audit log.
+    pub(crate) records: Vec<String>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+    /// The directory last opened, which targets are looked up in.
+    static OPENED: RefCell<PathBuf> = RefCell::new(PathBuf::new());
+}
+
+/// An [EnvTrait] answering from the [State] of the current thread.
+pub(crate) struct MockEnv;
+
+impl MockEnv {
+    /// Replace the state of the current thread.
+    pub(crate) fn set(state: State) {
+        STATE.with(|s| *s.borrow_mut() = state);
+    }
+
+    /// The state of the current thread, with what was recorded so far.
+    pub(crate) fn state() -> State {
+        STATE.with(|s| s.borrow().clone())
+    }
+
+    fn lookup(path: &Path) -> Result<Entry, std::io::Error> {
+        STATE.with(|s| {
+            let s = s.borrow();
+            if let Some(errno) = s.errors.get(path) {
+                return Err(std::io::Error::from_raw_os_error(*errno));
+            }
+            match s.files.get(path) {
+                Some(entry) => Ok(*entry),
+                None if s.files.keys().any(|f| f.starts_with(path)) => Ok(Entry::dir(0, 0o755)),
+                None => Err(std::io::Error::from_raw_os_error(libc::ENOENT)),
+            }
+        })
+    }
+
+    /// A descriptor standing in for an opened file.
+    fn handle() -> Result<File, std::io::Error> {
+        File::open("/dev/null")
+    }
+
+    /// A descriptor reading the contents of a file, through a temporary file removed at once.
+    fn read(path: &Path) -> Result<File, std::io::Error> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let contents = match STATE.with(|s| s.borrow().contents.get(path).cloned()) {
+            Some(contents) => contents,
+            None => return MockEnv::handle(),
+        };
+        let temp = std::env::temp_dir().join(format!(
+            "run-suid-mock-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?
+            .write_all(contents.as_bytes())?;
+        let file = File::open(&temp);
+        std::fs::remove_file(&temp)?;
+        file
+    }
+}
+
+impl EnvTrait for MockEnv {
+    fn geteuid() -> u32 {
+        STATE.with(|s| s.borrow().euid)
+    }
+    fn getuid() -> u32 {
+        STATE.with(|s| s.borrow().uid)
+    }
+    fn getegid() -> u32 {
+        STATE.with(|s| s.borrow().egid)
+    }
+    fn getgid() -> u32 {
+        STATE.with(|s| s.borrow().gid)
+    }
+    fn current_exe() -> Result<PathBuf, std::io::Error> {
+        Ok(STATE.with(|s| s.borrow().exe.clone()))
+    }
+    fn open_dir(path: &Path) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        let entry = MockEnv::lookup(path)?;
+        if entry.stat.is_symlink() {
+            return Err(std::io::Error::from_raw_os_error(libc::ELOOP));
+        }
+        OPENED.with(|o| *o.borrow_mut() = path.to_path_buf());
+        let secure = entry.stat.is_dir() && entry.stat.mode & PERM_DIR_MASK == PERM_DIR_EXPECTED;
+        Ok((entry.uid, entry.stat, secure, MockEnv::handle()?))
+    }
+    fn open_target(_dir: &File, name: &OsStr) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        let entry = MockEnv::lookup(&OPENED.with(|o| o.borrow().join(name)))?;
+        let secure = entry.stat.is_file()
+            && entry.stat.mode & nix::PERM_FILE_MASK == nix::PERM_FILE_EXPECTED;
+        Ok((entry.uid, entry.stat, secure, MockEnv::handle()?))
+    }
+    fn read_target(_file: &File) -> Result<File, std::io::Error> {
+        MockEnv::handle()
+    }
+    fn target_mount(_target: &Path, _file: &File) -> Result<Mount, std::io::Error> {
+        Ok(Mount {
+            untrusted: None,
+            nosuid: false,
+        })
+    }
+    fn target_program(target: &Path, _file: &File) -> PathBuf {
+        target.to_path_buf()
+    }
+    fn insecure_ancestor(
+        dir: &Path,
+        uid: u32,
+    ) -> Result<Option<(PathBuf, u32, u32)>, std::io::Error> {
+        for dir in dir.ancestors().skip(1) {
+            let entry = MockEnv::lookup(dir)?;
+            let (owner, mode) = (entry.uid, entry.stat.mode);
+            if !entry.stat.is_dir()
+                || (owner != 0 && owner != uid)
+                || mode & PERM_ANCESTOR_MASK != 0
+            {
+                return Ok(Some((dir.to_path_buf(), owner, mode)));
+            }
+        }
+        Ok(None)
+    }
+    fn sibling_target(parent: &Path, file_name: &str) -> PathBuf {
+        nix::Nix::sibling_target(parent, file_name)
+    }
+    fn target_config(target: &Path) -> PathBuf {
+        nix::Nix::target_config(target)
+    }
+    fn target_allow_list(target: &Path) -> PathBuf {
+        nix::Nix::target_allow_list(target)
+    }
+    fn target_signature(target: &Path) -> PathBuf {
+        nix::Nix::target_signature(target)
+    }
+    fn target_dir(target: &Path) -> PathBuf {
+        nix::Nix::target_dir(target)
+    }
+    fn target_stats(target: &Path) -> PathBuf {
+        nix::Nix::target_stats(target)
+    }
+    fn open_policy_file(path: &Path) -> Result<(u32, File, bool), std::io::Error> {
+        let entry = MockEnv::lookup(path)?;
+        let secure = entry.stat.is_file() && entry.stat.mode & nix::PERM_POLICY_MASK == 0;
+        Ok((entry.uid, MockEnv::read(path)?, secure))
+    }
+    fn user_by_uid(uid: u32) -> Result<Option<User>, std::io::Error> {
+        Ok(STATE.with(|s| {
+            let s = s.borrow();
+            let user = s.users.iter().find(|(user, _)| user.uid == uid);
+            user.map(|(user, _)| user.clone())
+        }))
+    }
+    fn group_name(_gid: u32) -> Result<Option<String>, std::io::Error> {
+        Ok(None)
+    }
+    fn user_groups(user: &User) -> Result<Vec<u32>, std::io::Error> {
+        Ok(STATE.with(|s| {
+            let s = s.borrow();
+            let groups = s.users.iter().find(|(u, _)| u.uid == user.uid);
+            groups.map_or_else(|| vec![user.gid], |(_, groups)| groups.clone())
+        }))
+    }
+    fn invoker_can_read(path: &Path) -> bool {
+        MockEnv::lookup(path).is_ok()
+    }
+    fn invoker_can_write(path: &Path) -> bool {
+        !STATE.with(|s| s.borrow().errors.contains_key(path))
+    }
+    fn open_invoker_file(path: &Path, _mode: u32, _append: bool) -> Result<File, std::io::Error> {
+        match STATE.with(|s| s.borrow().errors.get(path).copied()) {
+            Some(errno) => Err(std::io::Error::from_raw_os_error(errno)),
+            None => MockEnv::handle(),
+        }
+    }
+    fn open_invoker_dir(path: &Path) -> Result<File, std::io::Error> {
+        MockEnv::lookup(path).and_then(|_| MockEnv::handle())
+    }
+    fn close_inherited_fds() -> Result<Vec<i32>, std::io::Error> {
+        Ok(Vec::new())
+    }
+    fn create_recording(_path: &Path) -> Result<File, std::io::Error> {
+        MockEnv::handle()
+    }
+    fn ask_terminal(_prompt: &str, _echo: bool) -> Result<String, std::io::Error> {
+        Err(std::io::Error::from_raw_os_error(libc::ENOTTY))
+    }
+    fn daemonize() -> Result<File, std::io::Error> {
+        Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+    }
+    fn tty_name() -> Option<String> {
+        None
+    }
+    fn syslog(message: &str, _fields: &[(&str, String)]) {
+        STATE.with(|s| s.borrow_mut().records.push(message.to_owned()));
+    }
+    fn audit_command(message: &str) {
+        STATE.with(|s| s.borrow_mut().records.push(message.to_owned()));
+    }
+    fn locked_out(_uid: u32) -> Option<u64> {
+        STATE.with(|s| s.borrow().locked_out)
+    }
+    fn count_failure(_uid: u32) -> Option<u64> {
+        STATE.with(|s| s.borrow_mut().failures += 1);
+        None
+    }
+    fn reset_failures(_uid: u32) {
+        STATE.with(|s| s.borrow_mut().failures = 0);
+    }
+
+    fn prepare_command<'a, A: IntoIterator<Item = &'a str>>(
+        command: &mut Command,
+        args: A,
+        _opts: &crate::Opts,
+    ) {
+        command.args(args);
+    }
+    fn target_path(_opts: &crate::Opts) -> String {
+        "/usr/bin:/bin".to_string()
+    }
+    fn wait_for(_child: Command, _opts: crate::Opts) -> Result<ExitStatus, ExitCode> {
+        unimplemented!("a mocked environment does not run targets")
+    }
+    fn terminate_like(_status: ExitStatus) -> ExitCode {
+        unimplemented!("a mocked environment does not run targets")
+    }
+    fn exec(_child: Command, _opts: crate::Opts) -> ExitCode {
+        unimplemented!("a mocked environment does not run targets")
+    }
+}
@@ -0,0 +1,2347 @@
+use std::{
+    collections::BTreeSet,
+    ffi::{CStr, CString, OsStr, OsString},
+    fs::File,
+    os::unix::prelude::{CommandExt, ExitStatusExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, ExitStatus},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    env::{EnvTrait, Mount, ProcessGroup, Stat, User},
+    exit::own,
+    output::{self, Json},
+    RET_GENERIC_ERROR, RET_PRIV_DROP, RET_SANDBOX, RET_TIMEOUT,
+};
+
+pub(crate) struct Nix {}
+
+impl EnvTrait for Nix {
+    #[inline]
+    fn geteuid() -> u32 {
+        crate::sys::geteuid()
+    }
+    #[inline]
+    fn getuid() -> u32 {
+        crate::sys::getuid()
+    }
+    #[inline]
+    fn getegid() -> u32 {
+        crate::sys::getegid()
+    }
+    #[inline]
+    fn getgid() -> u32 {
+        crate::sys::getgid()
+    }
+    #[inline]
+    fn current_exe() -> Result<PathBuf, std::io::Error> {
+        std::env::current_exe()
+    }
+    #[inline]
+    fn open_dir(path: &Path) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        open_dir(path)
+    }
+    #[inline]
+    fn open_target(dir: &File, name: &OsStr) -> Result<(u32, Stat, bool, File), std::io::Error> {
+        open_target(dir, name)
+    }
+    #[inline]
+    fn read_target(file: &File) -> Result<File, std::io::Error> {
+        read_target(file)
+    }
+    #[inline]
+    fn target_mount(target: &Path, file: &File) -> Result<Mount, std::io::Error> {
+        target_mount(target, file)
+    }
+    #[inline]
+    fn target_program(target: &Path, file: &File) -> PathBuf {
+        target_program(target, file)
+    }
+    #[inline]
+    fn insecure_ancestor(
+        dir: &Path,
+        uid: u32,
+    ) -> Result<Option<(PathBuf, u32, u32)>, std::io::Error> {
+        insecure_ancestor(dir, uid)
+    }
+    #[inline]
+    fn sibling_target(parent: &Path, file_name: &str) -> PathBuf {
+        sibling_target(parent, file_name)
+    }
+    #[inline]
+    fn target_config(target: &Path) -> PathBuf {
+        target_config(target)
+    }
+    #[inline]
+    fn target_allow_list(target: &Path) -> PathBuf {
+        target_allow_list(target)
+    }
+    #[inline]
+    fn target_signature(target: &Path) -> PathBuf {
+        target_signature(target)
+    }
+    #[inline]
+    fn target_dir(target: &Path) -> PathBuf {
+        target_dir(target)
+    }
+    #[inline]
+    fn target_stats(target: &Path) -> PathBuf {
+        target_stats(target)
+    }
+    #[inline]
+    fn open_policy_file(path: &Path) -> Result<(u32, File, bool), std::io::Error> {
+        open_policy_file(path)
+    }
+    #[inline]
+    fn user_by_uid(uid: u32) -> Result<Option<User>, std::io::Error> {
+        user_by_uid(uid)
+    }
+    #[inline]
+    fn group_name(gid: u32) -> Result<Option<String>, std::io::Error> {
+        group_name(gid)
+    }
+    #[inline]
+    fn user_groups(user: &User) -> Result<Vec<u32>, std::io::Error> {
+        user_groups(user)
+    }
+    #[inline]
+    fn invoker_can_read(path: &Path) -> bool {
+        invoker_can_read(path)
+    }
+    #[inline]
+    fn invoker_can_write(path: &Path) -> bool {
+        invoker_can_write(path)
+    }
+    #[inline]
+    fn open_invoker_file(path: &Path, mode: u32, append: bool) -> Result<File, std::io::Error> {
+        open_invoker_file(path, mode, append)
+    }
+    #[inline]
+    fn open_invoker_dir(path: &Path) -> Result<File, std::io::Error> {
+        open_invoker_dir(path)
+    }
+    #[inline]
+    fn close_inherited_fds() -> Result<Vec<i32>, std::io::Error> {
+        close_inherited_fds()
+    }
+    #[inline]
+    fn create_recording(path: &Path) -> Result<File, std::io::Error> {
+        create_recording(path)
+    }
+    #[inline]
+    fn ask_terminal(prompt: &str, echo: bool) -> Result<String, std::io::Error> {
+        ask_terminal(prompt, echo)
+    }
+    #[inline]
+    fn daemonize() -> Result<File, std::io::Error> {
+        daemonize()
+    }
+    #[inline]
+    fn tty_name() -> Option<String> {
+        tty_name()
+    }
+    /// Not both, as the system log of a system with the journal ends up in the journal as well.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn syslog(message: &str, fields: &[(&str, String)]) {
+        #[cfg(target_os = "linux")]
+        if crate::journal::send(message, fields) {
+            return;
+        }
+        syslog(message)
+    }
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn audit_command(message: &str) {
+        #[cfg(target_os = "linux")]
+        crate::auditd::send_user_cmd(message);
+    }
+    #[inline]
+    fn locked_out(uid: u32) -> Option<u64> {
+        crate::lockout::remaining(uid)
+    }
+    #[inline]
+    fn count_failure(uid: u32) -> Option<u64> {
+        crate::lockout::fail(uid)
+    }
+    #[inline]
+    fn reset_failures(uid: u32) {
+        crate::lockout::reset(uid)
+    }
+    #[inline]
+    fn prepare_command<'a, A: IntoIterator<Item = &'a str>>(
+        command: &mut Command,
+        args: A,
+        opts: &super::Opts,
+    ) {
+        prepare_command(command, args, opts)
+    }
+    #[inline]
+    fn target_path(opts: &super::Opts) -> String {
+        target_path(opts)
+    }
+    #[inline]
+    fn wait_for(child: Command, opts: super::Opts) -> Result<ExitStatus, ExitCode> {
+        wait_for(child, opts)
+    }
+    #[inline]
+    fn terminate_like(status: ExitStatus) -> ExitCode {
+        terminate_like(status)
+    }
+    #[inline]
+    fn exec(child: Command, opts: super::Opts) -> ExitCode {
+        exec(child, opts)
+    }
+}
+
+pub(crate) const PERM_FILE_MASK: u32 = 0o4522;
+pub(crate) const PERM_FILE_EXPECTED: u32 = 0o4500;
+pub(crate) const PERM_DIR_MASK: u32 = 0o522;
+pub(crate) const PERM_DIR_EXPECTED: u32 = 0o500;
+pub(crate) const PERM_ANCESTOR_MASK: u32 = 0o022;
+pub(crate) const PERM_POLICY_MASK: u32 = 0o022;
+pub(crate) const PERM_POLICY_EXPECTED: u32 = 0o000;
+
+#[cfg(target_os = "linux")]
+const O_LOOKUP: libc::c_int = libc::O_PATH;
+#[cfg(not(target_os = "linux"))]
+const O_LOOKUP: libc::c_int = libc::O_RDONLY;
+
+#[cfg(target_os = "linux")]
+const SYS_OPENAT2: libc::c_long = 437;
+#[cfg(target_os = "linux")]
+const RESOLVE_NO_SYMLINKS: u64 = 0x04;
+const RESOLVE_BENEATH: u64 = 0x08;
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct OpenHow {
+    flags: u64,
+    mode: u64,
+    resolve: u64,
+}
+
+/// Open `path` relative to `dir` without following any symlink, creating it with `mode`,
+/// `None` if `openat2` is not available on this kernel.
+#[cfg(target_os = "linux")]
+fn openat2(
+    dir: libc::c_int,
+    path: &CStr,
+    flags: libc::c_int,
+    mode: u32,
+    resolve: u64,
+) -> Option<Result<File, std::io::Error>> {
+    use std::os::unix::prelude::FromRawFd;
+
+    let how = OpenHow {
+        flags: (flags | libc::O_CLOEXEC) as u64,
+        mode: mode as u64,
+        resolve: resolve | RESOLVE_NO_SYMLINKS,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            SYS_OPENAT2,
+            dir,
+            path.as_ptr(),
+            &how as *const OpenHow,
+            std::mem::size_of::<OpenHow>(),
+        )
+    };
+    if fd >= 0 {
+        return Some(Ok(unsafe { File::from_raw_fd(fd as libc::c_int) }));
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOSYS) | Some(libc::EPERM) => None,
+        _ => Some(Err(err)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn openat2(
+    _dir: libc::c_int,
+    _path: &CStr,
+    _flags: libc::c_int,
+    _mode: u32,
+    _resolve: u64,
+) -> Option<Result<File, std::io::Error>> {
+    None
+}
+
+/// Open a single path component beneath `dir` without following it if it is a symlink.
+fn openat_nofollow(
+    dir: libc::c_int,
+    name: &CStr,
+    flags: libc::c_int,
+) -> Result<File, std::io::Error> {
+    use std::os::unix::prelude::FromRawFd;
+
+    let fd = unsafe {
+        libc::openat(
+            dir,
+            name.as_ptr(),
+            flags | O_LOOKUP | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn is_symlink_at(dir: libc::c_int, name: &CStr) -> bool {
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    let r = unsafe { libc::fstatat(dir, name.as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW) };
+    r == 0 && st.st_mode & libc::S_IFMT == libc::S_IFLNK
+}
+
+/// Open `path` with `flags`, creating it with `mode`, without following a symlink anywhere on
+/// its way.
+fn open_nofollow(path: &Path, flags: libc::c_int, mode: u32) -> Result<File, std::io::Error> {
+    use std::{
+        os::unix::prelude::{AsRawFd, FromRawFd, OsStrExt},
+        path::Component,
+    };
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    if let Some(file) = openat2(libc::AT_FDCWD, &c_path, flags, mode, 0) {
+        return file;
+    }
+    // walk the path one component at a time on kernels without `openat2`
+    let mut components = path.components().collect::<Vec<_>>();
+    let last = components.pop();
+    let mut dir = openat_nofollow(libc::AT_FDCWD, &CString::new(".")?, libc::O_DIRECTORY)?;
+    for c in components {
+        let name = match c {
+            Component::RootDir => CString::new("/")?,
+            Component::CurDir => continue,
+            c => CString::new(c.as_os_str().as_bytes())?,
+        };
+        dir = match openat_nofollow(dir.as_raw_fd(), &name, libc::O_DIRECTORY) {
+            Ok(next) => next,
+            Err(_) if is_symlink_at(dir.as_raw_fd(), &name) => {
+                return Err(std::io::Error::from_raw_os_error(libc::ELOOP))
+            }
+            Err(e) => return Err(e),
+        };
+    }
+    let name = CString::new(last.map_or(&b"."[..], |c| c.as_os_str().as_bytes()))?;
+    let flags = flags | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags, mode as libc::c_uint) };
+    if fd < 0 {
+        return Err(match is_symlink_at(dir.as_raw_fd(), &name) {
+            true => std::io::Error::from_raw_os_error(libc::ELOOP),
+            false => std::io::Error::last_os_error(),
+        });
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn open_dir(path: &Path) -> Result<(u32, Stat, bool, File), std::io::Error> {
+    use std::os::unix::prelude::{AsRawFd, OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let dir = match openat2(libc::AT_FDCWD, &c_path, O_LOOKUP | libc::O_DIRECTORY, 0, 0) {
+        Some(dir) => dir?,
+        None => {
+            // walk the path one component at a time on kernels without `openat2`
+            let mut dir = File::open("/")?;
+            for c in path.components().skip(1) {
+                let name = CString::new(c.as_os_str().as_bytes())?;
+                dir = match openat_nofollow(dir.as_raw_fd(), &name, libc::O_DIRECTORY) {
+                    Ok(next) => next,
+                    Err(_) if is_symlink_at(dir.as_raw_fd(), &name) => {
+                        return Err(std::io::Error::from_raw_os_error(libc::ELOOP))
+                    }
+                    Err(e) => return Err(e),
+                };
+            }
+            dir
+        }
+    };
+    let metadata = dir.metadata()?;
+    let m = metadata.permissions().mode();
+    let b = metadata.is_dir() && m & PERM_DIR_MASK == PERM_DIR_EXPECTED;
+
+    Ok((metadata.uid(), Stat::from(&metadata), b, dir))
+}
+
+fn open_target(dir: &File, name: &OsStr) -> Result<(u32, Stat, bool, File), std::io::Error> {
+    use std::os::unix::prelude::{AsRawFd, OsStrExt};
+
+    let c_name = CString::new(name.as_bytes())?;
+    let file = match openat2(dir.as_raw_fd(), &c_name, O_LOOKUP, 0, RESOLVE_BENEATH) {
+        Some(file) => file?,
+        None => openat_nofollow(dir.as_raw_fd(), &c_name, 0)?,
+    };
+    // the descriptor has to survive exec for an interpreter to open a script through it
+    #[cfg(target_os = "linux")]
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let metadata = file.metadata()?;
+    let m = metadata.permissions().mode();
+    let b = metadata.is_file() && m & PERM_FILE_MASK == PERM_FILE_EXPECTED;
+
+    Ok((metadata.uid(), Stat::from(&metadata), b, file))
+}
+
+/// Filesystems that are shared over the network or implemented by unprivileged processes.
+#[cfg(target_os = "linux")]
+static UNTRUSTED_FILESYSTEMS: &[(u32, &str)] = &[
+    (0x6969, "nfs"),
+    (0x517b, "smb"),
+    (0xff53_4d42, "cifs"),
+    (0xfe53_4d42, "smb2"),
+    (0x6573_5546, "fuse"),
+    (0x0102_1997, "9p"),
+    (0x00c3_6400, "ceph"),
+    (0x5346_414f, "afs"),
+    (0x6b41_4653, "afs"),
+    (0x7375_7245, "coda"),
+];
+
+/// Directories anyone may create files in, untrusted when backed by memory.
+#[cfg(target_os = "linux")]
+static SCRATCH_DIRS: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+
+#[cfg(target_os = "linux")]
+fn target_mount(target: &Path, file: &File) -> Result<Mount, std::io::Error> {
+    use std::os::unix::prelude::AsRawFd;
+
+    const TMPFS_MAGIC: u32 = 0x0102_1994;
+    const RAMFS_MAGIC: u32 = 0x8584_58f6;
+
+    let mut st: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(file.as_raw_fd(), &mut st) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let kind = st.f_type as u32;
+    let untrusted = match UNTRUSTED_FILESYSTEMS
+        .iter()
+        .find(|(magic, _)| *magic == kind)
+    {
+        Some((_, name)) => Some(*name),
+        None if (kind == TMPFS_MAGIC || kind == RAMFS_MAGIC)
+            && SCRATCH_DIRS.iter().any(|d| target.starts_with(d)) =>
+        {
+            Some("tmpfs")
+        }
+        None => None,
+    };
+    Ok(Mount {
+        untrusted,
+        nosuid: is_nosuid(file)?,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn target_mount(_target: &Path, file: &File) -> Result<Mount, std::io::Error> {
+    Ok(Mount {
+        untrusted: None,
+        nosuid: is_nosuid(file)?,
+    })
+}
+
+fn is_nosuid(file: &File) -> Result<bool, std::io::Error> {
+    use std::os::unix::prelude::AsRawFd;
+
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatvfs(file.as_raw_fd(), &mut st) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(st.f_flag as u64 & libc::ST_NOSUID as u64 != 0)
+}
+
+#[cfg(target_os = "linux")]
+fn read_target(file: &File) -> Result<File, std::io::Error> {
+    // reopening through the descriptor gives the same inode, readable this time
+    File::open(target_program(Path::new(""), file))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_target(file: &File) -> Result<File, std::io::Error> {
+    use std::io::{Seek, SeekFrom};
+    let mut file = file.try_clone()?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+#[cfg(target_os = "linux")]
+fn target_program(_target: &Path, file: &File) -> PathBuf {
+    use std::os::unix::prelude::AsRawFd;
+    PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn target_program(target: &Path, _file: &File) -> PathBuf {
+    PathBuf::from(target)
+}
+
+fn insecure_ancestor(dir: &Path, uid: u32) -> Result<Option<(PathBuf, u32, u32)>, std::io::Error> {
+    for dir in dir.ancestors().skip(1) {
+        let metadata = std::fs::symlink_metadata(dir)?;
+        let owner = metadata.uid();
+        let m = metadata.permissions().mode();
+        if !metadata.is_dir() || (owner != 0 && owner != uid) || m & PERM_ANCESTOR_MASK != 0 {
+            return Ok(Some((PathBuf::from(dir), owner, m)));
+        }
+    }
+    Ok(None)
+}
+
+fn sibling_target(parent: &Path, file_name: &str) -> PathBuf {
+    let mut r = PathBuf::from(parent);
+    if let Some(a) = file_name.split('.').next_back() {
+        let pos = file_name.len() - a.len();
+        if pos != 0 {
+            r.push(format!(
+                "{}.run-suid.{}",
+                &file_name[..(pos - 1)],
+                &file_name[pos..]
+            ));
+            return r;
+        }
+    }
+    r.push(format!("{}.run-suid", file_name));
+    r
+}
+
+fn with_suffix(target: &Path, suffix: &str) -> PathBuf {
+    let mut r = OsString::from(target.as_os_str());
+    r.push(suffix);
+    PathBuf::from(r)
+}
+
+fn target_config(target: &Path) -> PathBuf {
+    with_suffix(target, ".toml")
+}
+
+fn target_allow_list(target: &Path) -> PathBuf {
+    with_suffix(target, ".allow")
+}
+
+fn target_signature(target: &Path) -> PathBuf {
+    with_suffix(target, ".sig")
+}
+
+fn target_dir(target: &Path) -> PathBuf {
+    with_suffix(target, ".d")
+}
+
+fn target_stats(target: &Path) -> PathBuf {
+    with_suffix(target, ".stats")
+}
+
+fn open_policy_file(path: &Path) -> Result<(u32, File, bool), std::io::Error> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let m = metadata.permissions().mode();
+    let b = metadata.is_file() && m & PERM_POLICY_MASK == PERM_POLICY_EXPECTED;
+
+    Ok((metadata.uid(), file, b))
+}
+
+fn invoker_can_read(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    match CString::new(path.as_os_str().as_bytes()) {
+        // access checks against the real user and group ids
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::R_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+fn invoker_can_write(path: &Path) -> bool {
+    let exists = std::fs::symlink_metadata(path).is_ok();
+    let checked = match path.parent() {
+        Some(dir) if !exists => dir,
+        _ => path,
+    };
+    let checked = if checked.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        checked
+    };
+    // access checks against the real user and group ids
+    unsafe { libc::access(c_path(checked).as_ptr(), libc::W_OK) == 0 }
+}
+
+fn open_invoker_file(path: &Path, mode: u32, append: bool) -> Result<File, std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    // not blocking on a FIFO without a reader before its type is checked
+    let mut flags = libc::O_WRONLY | libc::O_CREAT | libc::O_NOCTTY | libc::O_NONBLOCK;
+    if append {
+        flags |= libc::O_APPEND;
+    }
+    let file = crate::sys::as_invoker(|| open_nofollow(path, flags, mode))??;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.mode() & 0o002 != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "not a file, or writable by everyone",
+        ));
+    }
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, flags & libc::O_APPEND) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if !append {
+        file.set_len(0)?;
+    }
+    Ok(file)
+}
+
+fn open_invoker_dir(path: &Path) -> Result<File, std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    let dir = crate::sys::as_invoker(|| open_nofollow(path, O_LOOKUP | libc::O_DIRECTORY, 0))??;
+    // access checks against the real user and group ids
+    let dot = CString::new(".")?;
+    if unsafe { libc::faccessat(dir.as_raw_fd(), dot.as_ptr(), libc::W_OK, 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(dir)
+}
+
+#[cfg(target_os = "linux")]
+const FD_DIR: &str = "/proc/self/fd";
+#[cfg(not(target_os = "linux"))]
+const FD_DIR: &str = "/dev/fd";
+
+fn close_inherited_fds() -> Result<Vec<i32>, std::io::Error> {
+    // collected first, as the listing itself is an open descriptor
+    let fds = std::fs::read_dir(FD_DIR)?
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<i32>().ok())
+        .filter(|fd| *fd > 2)
+        .collect::<Vec<_>>();
+    let mut marked = Vec::new();
+    for fd in fds {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags < 0 || flags & libc::FD_CLOEXEC != 0 {
+            continue;
+        }
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        marked.push(fd);
+    }
+    Ok(marked)
+}
+
+fn create_recording(path: &Path) -> Result<File, std::io::Error> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(path)
+}
+
+fn user_by_uid(uid: u32) -> Result<Option<User>, std::io::Error> {
+    let mut size = match unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) } {
+        n if n > 0 => n as usize,
+        _ => 1024,
+    };
+    loop {
+        let mut buf = vec![0 as libc::c_char; size];
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let r = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), size, &mut result) };
+        if r == libc::ERANGE && size < 1 << 20 {
+            size *= 2;
+            continue;
+        }
+        // Bionic has no /etc/passwd to search, and reports an id Android did not assign as
+        // missing instead of returning no user
+        #[cfg(target_os = "android")]
+        if r == libc::ENOENT {
+            return Ok(None);
+        }
+        if r != 0 {
+            return Err(std::io::Error::from_raw_os_error(r));
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+        let path = |p: *const libc::c_char| {
+            use std::os::unix::ffi::OsStrExt;
+            if p.is_null() {
+                return PathBuf::new();
+            }
+            PathBuf::from(OsStr::from_bytes(unsafe { CStr::from_ptr(p) }.to_bytes()))
+        };
+        return Ok(Some(User {
+            name: name.to_string_lossy().into_owned(),
+            uid: pwd.pw_uid,
+            gid: pwd.pw_gid,
+            home: path(pwd.pw_dir),
+            shell: path(pwd.pw_shell),
+        }));
+    }
+}
+
+fn group_name(gid: u32) -> Result<Option<String>, std::io::Error> {
+    let mut size = match unsafe { libc::sysconf(libc::_SC_GETGR_R_SIZE_MAX) } {
+        n if n > 0 => n as usize,
+        _ => 1024,
+    };
+    loop {
+        let mut buf = vec![0 as libc::c_char; size];
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let r = unsafe { libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), size, &mut result) };
+        if r == libc::ERANGE && size < 1 << 20 {
+            size *= 2;
+            continue;
+        }
+        #[cfg(target_os = "android")]
+        if r == libc::ENOENT {
+            return Ok(None);
+        }
+        if r != 0 {
+            return Err(std::io::Error::from_raw_os_error(r));
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        let name = unsafe { CStr::from_ptr(grp.gr_name) };
+        return Ok(Some(name.to_string_lossy().into_owned()));
+    }
+}
+
+/// macOS lists the groups of a user as signed ids.
+#[cfg(not(target_os = "macos"))]
+type GroupListId = libc::gid_t;
+#[cfg(target_os = "macos")]
+type GroupListId = libc::c_int;
+
+fn user_groups(user: &User) -> Result<Vec<u32>, std::io::Error> {
+    let name = CString::new(user.name.as_str())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut groups: Vec<GroupListId> = vec![0; 32];
+    loop {
+        let mut n = groups.len() as libc::c_int;
+        let r = unsafe {
+            libc::getgrouplist(name.as_ptr(), user.gid as _, groups.as_mut_ptr(), &mut n)
+        };
+        if r >= 0 {
+            groups.truncate(n as usize);
+            #[allow(clippy::unnecessary_cast)]
+            let groups = groups.into_iter().map(|g| g as u32).collect();
+            return Ok(groups);
+        }
+        if n as usize <= groups.len() {
+            if groups.len() >= 1 << 16 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "too many supplementary groups",
+                ));
+            }
+            n = groups.len() as libc::c_int * 2;
+        }
+        groups.resize(n as usize, 0);
+    }
+}
+
+fn ask_terminal(prompt: &str, echo: bool) -> Result<String, std::io::Error> {
+    use std::io::{Read, Write};
+    use std::os::unix::{fs::OpenOptionsExt, io::AsRawFd};
+
+    // not stdin, which may be redirected by whatever started the wrapper
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_CLOEXEC)
+        .open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    let restore = !echo && unsafe { libc::tcgetattr(fd, &mut saved) } == 0;
+    if restore {
+        let mut quiet = saved;
+        quiet.c_lflag &= !libc::ECHO;
+        quiet.c_lflag |= libc::ECHONL;
+        unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &quiet) };
+    }
+    let mut answer = Vec::new();
+    let r = tty.write_all(prompt.as_bytes()).and_then(|_| {
+        let mut byte = [0u8];
+        while answer.len() < 1024 && tty.read(&mut byte)? == 1 && byte[0] != b'\n' {
+            answer.push(byte[0]);
+        }
+        Ok(())
+    });
+    if restore {
+        unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &saved) };
+    }
+    r?;
+    if answer.last() == Some(&b'\r') {
+        answer.pop();
+    }
+    Ok(String::from_utf8_lossy(&answer).into_owned())
+}
+
+fn daemonize() -> Result<File, std::io::Error> {
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    let (notified, started) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()),
+        0 => {}
+        pid => {
+            drop(started);
+            unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+            let mut byte = [0u8];
+            let n = unsafe { libc::read(notified.as_raw_fd(), byte.as_mut_ptr().cast(), 1) };
+            std::process::exit(if n == 1 {
+                0
+            } else {
+                own(RET_GENERIC_ERROR) as i32
+            });
+        }
+    }
+    drop(notified);
+    // a new session without a terminal, whose leader exits so that none is ever acquired
+    if unsafe { libc::setsid() } < 0 {
+        fail_in_child(
+            b"Unable to create a session for the daemon.\n",
+            RET_GENERIC_ERROR,
+        );
+    }
+    match unsafe { libc::fork() } {
+        -1 => fail_in_child(b"Unable to start the daemon.\n", RET_GENERIC_ERROR),
+        0 => {}
+        _ => unsafe { libc::_exit(0) },
+    }
+    unsafe { libc::chdir(b"/\0".as_ptr().cast()) };
+    let null = File::open("/dev/null")?;
+    for fd in [0, 1] {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(started)
+}
+
+/// Let the invoker's process of a daemon exit, and move stderr to `/dev/null` too.
+fn detach(started: File) {
+    use std::{io::Write, os::unix::io::AsRawFd};
+
+    let _ = (&started).write_all(&[0]);
+    if let Ok(null) = std::fs::OpenOptions::new().write(true).open("/dev/null") {
+        unsafe { libc::dup2(null.as_raw_fd(), 2) };
+    }
+}
+
+fn tty_name() -> Option<String> {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::ttyname_r(0, buf.as_mut_ptr(), buf.len()) } != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy();
+    Some(name.strip_prefix("/dev/").unwrap_or(&name).to_owned())
+}
+
+#[cfg(not(target_os = "illumos"))]
+const LOG_FACILITY: libc::c_int = libc::LOG_AUTHPRIV;
+/// illumos has no facility of its own for private authorization messages.
+#[cfg(target_os = "illumos")]
+const LOG_FACILITY: libc::c_int = libc::LOG_AUTH;
+
+fn syslog(message: &str) {
+    static IDENT: &[u8] = b"run-suid\0";
+    let message = match CString::new(message.replace('\0', "\\0")) {
+        Ok(m) => m,
+        Err(_) => return,
+    };
+    unsafe {
+        libc::openlog(IDENT.as_ptr().cast(), libc::LOG_PID, LOG_FACILITY);
+        libc::syslog(
+            LOG_FACILITY | libc::LOG_NOTICE,
+            b"%s\0".as_ptr().cast(),
+            message.as_ptr(),
+        );
+        libc::closelog();
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "android")))]
+static PATHS: &[&str] = &[
+    "/usr/local/sbin",
+    "/usr/local/bin",
+    "/usr/sbin",
+    "/usr/bin",
+    "/sbin",
+    "/bin",
+];
+
+/// Homebrew installs to `/opt/homebrew` on Apple silicon and to `/usr/local` on Intel.
+#[cfg(target_os = "macos")]
+static PATHS: &[&str] = &[
+    "/opt/homebrew/sbin",
+    "/opt/homebrew/bin",
+    "/usr/local/sbin",
+    "/usr/local/bin",
+    "/usr/sbin",
+    "/usr/bin",
+    "/sbin",
+    "/bin",
+];
+
+#[cfg(target_os = "android")]
+static PATHS: &[&str] = &["/system/bin", "/system/xbin"];
+
+/// `PATH` of a target when the caller's has none of [PATHS].
+#[cfg(not(target_os = "android"))]
+const FALLBACK_PATH: &str = "/bin";
+#[cfg(target_os = "android")]
+const FALLBACK_PATH: &str = "/system/bin:/system/xbin";
+
+fn target_path(opts: &super::Opts) -> String {
+    match &opts.path {
+        Some(path) => path.clone(),
+        None => secure_path(),
+    }
+}
+
+/// Filter the caller's `PATH` down to the well-known system directories.
+fn secure_path() -> String {
+    let cur_path: BTreeSet<_> = match std::env::var("PATH") {
+        Ok(path) => path.split(':').map(str::to_owned).collect(),
+        Err(_) => BTreeSet::new(),
+    };
+    let mut path = String::with_capacity(64);
+    for p in PATHS {
+        let p = *p;
+        if cur_path.contains(p) && is_system_dir(p) {
+            path.push_str(p);
+            path.push(':');
+        }
+    }
+    if !path.is_empty() {
+        path.pop();
+    } else {
+        path.push_str(FALLBACK_PATH);
+    }
+    path
+}
+
+/// Whether a directory of [PATHS] is only writable by root. Homebrew gives its directories to
+/// the user who installed it, so they are left out of the `PATH` of a target on macOS.
+#[cfg(target_os = "macos")]
+fn is_system_dir(path: &str) -> bool {
+    match std::fs::metadata(path) {
+        Ok(m) => m.is_dir() && m.uid() == 0 && m.mode() & PERM_ANCESTOR_MASK == 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn is_system_dir(_path: &str) -> bool {
+    true
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+/// `None` for a limit the platform does not have.
+fn rlimit_resource(resource: crate::config::Rlimit) -> Option<RlimitResource> {
+    use crate::config::Rlimit;
+
+    Some(match resource {
+        Rlimit::As => libc::RLIMIT_AS,
+        Rlimit::Core => libc::RLIMIT_CORE,
+        Rlimit::Cpu => libc::RLIMIT_CPU,
+        Rlimit::Data => libc::RLIMIT_DATA,
+        Rlimit::Fsize => libc::RLIMIT_FSIZE,
+        #[cfg(not(target_os = "illumos"))]
+        Rlimit::Memlock => libc::RLIMIT_MEMLOCK,
+        Rlimit::Nofile => libc::RLIMIT_NOFILE,
+        #[cfg(not(target_os = "illumos"))]
+        Rlimit::Nproc => libc::RLIMIT_NPROC,
+        Rlimit::Stack => libc::RLIMIT_STACK,
+        // resource controls take their place, which are set by the zone or project
+        #[cfg(target_os = "illumos")]
+        Rlimit::Memlock | Rlimit::Nproc => return None,
+    })
+}
+
+/// Write the pid of the calling process to a new file and move it into place, from a
+/// `pre_exec` hook. Only a file of the `invoker` is replaced, as the directory may hold files
+/// of others the target user could replace.
+fn write_pidfile(
+    dir: libc::c_int,
+    tmp: &CStr,
+    name: &CStr,
+    invoker: u32,
+) -> Result<(), &'static [u8]> {
+    let mut digits = [0u8; 21];
+    let mut at = digits.len() - 1;
+    digits[at] = b'\n';
+    let mut pid = unsafe { libc::getpid() } as u32;
+    loop {
+        at -= 1;
+        digits[at] = b'0' + (pid % 10) as u8;
+        pid /= 10;
+        if pid == 0 {
+            break;
+        }
+    }
+    let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    let fd = unsafe { libc::openat(dir, tmp.as_ptr(), flags, 0o644 as libc::c_uint) };
+    if fd < 0 {
+        return Err(b"Unable to create the pid file.\n");
+    }
+    let len = digits.len() - at;
+    let written = unsafe { libc::write(fd, digits[at..].as_ptr().cast(), len) };
+    unsafe { libc::close(fd) };
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatat(dir, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) } == 0
+        && (stat.st_mode & libc::S_IFMT != libc::S_IFREG || stat.st_uid != invoker)
+    {
+        unsafe { libc::unlinkat(dir, tmp.as_ptr(), 0) };
+        return Err(b"The pid file may only replace a file of the invoker.\n");
+    }
+    if written != len as isize
+        || unsafe { libc::renameat(dir, tmp.as_ptr(), dir, name.as_ptr()) } != 0
+    {
+        unsafe { libc::unlinkat(dir, tmp.as_ptr(), 0) };
+        return Err(b"Unable to write the pid file.\n");
+    }
+    Ok(())
+}
+
+/// Remove the pid file `name` of a target that exited from the directory it was written to,
+/// unless it was replaced since.
+fn remove_pidfile(dir: &File, name: &CStr, pid: i32) {
+    use std::{
+        io::Read,
+        os::unix::prelude::{AsRawFd, FromRawFd},
+    };
+
+    let flags = libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_NONBLOCK | libc::O_CLOEXEC;
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+    if fd < 0 {
+        return;
+    }
+    let mut written = String::new();
+    let _ = unsafe { File::from_raw_fd(fd) }
+        .take(32)
+        .read_to_string(&mut written);
+    if written.trim() == pid.to_string() {
+        unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), 0) };
+    }
+}
+
+/// Open an output file of the target without following a symbolic link, and refuse a file
+/// anyone may write to, before truncating it unless appending.
+fn redirect_output(file: &CStr, mode: libc::c_int, fd: libc::c_int) -> Result<(), &'static [u8]> {
+    let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_NOFOLLOW | libc::O_CLOEXEC | mode;
+    let out = unsafe { libc::open(file.as_ptr(), flags, 0o640 as libc::c_uint) };
+    if out < 0 {
+        return Err(b"Unable to open an output file of the target.\n");
+    }
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(out, &mut stat) } != 0
+        || stat.st_mode & libc::S_IFMT != libc::S_IFREG
+        || stat.st_mode & 0o002 != 0
+    {
+        return Err(b"An output file of the target must be a file not writable by everyone.\n");
+    }
+    if mode & libc::O_APPEND == 0 && unsafe { libc::ftruncate(out, 0) } != 0 {
+        return Err(b"Unable to truncate an output file of the target.\n");
+    }
+    if unsafe { libc::dup2(out, fd) } < 0 {
+        return Err(b"Unable to redirect the output of the target.\n");
+    }
+    Ok(())
+}
+
+fn pidfile_name(file: &Path) -> CString {
+    use std::os::unix::ffi::OsStrExt;
+
+    CString::new(file.file_name().unwrap_or_default().as_bytes()).unwrap_or_default()
+}
+
+fn c_path(path: &Path) -> CString {
+    use std::os::unix::ffi::OsStrExt;
+
+    CString::new(path.as_os_str().as_bytes()).unwrap_or_default()
+}
+
+/// The working directory as seen by the target, beneath its root directory if it has one.
+fn target_cwd(command: &Command, opts: &super::Opts) -> PathBuf {
+    let cwd = command.get_current_dir().unwrap_or_else(|| Path::new("."));
+    match &opts.chroot {
+        Some(root) => match cwd.strip_prefix(root) {
+            Ok(rel) => Path::new("/").join(rel),
+            Err(_) => PathBuf::from("/"),
+        },
+        None => cwd.to_path_buf(),
+    }
+}
+
+fn prepare_command<'a, A: IntoIterator<Item = &'a str>>(
+    command: &mut Command,
+    args: A,
+    opts: &super::Opts,
+) {
+    command.args(opts.prepend_args.iter());
+    command.args(args);
+    command.env_clear();
+    for name in opts.preserve_env.iter() {
+        if crate::config::is_env_denied(name) {
+            continue;
+        }
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    let path = target_path(opts);
+    // Entered first, so that everything the target does is accounted to the cgroup.
+    #[cfg(target_os = "linux")]
+    if let Some(procs) = opts.cgroup {
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::cgroup::enter(procs) {
+                    fail_in_child(msg, RET_SANDBOX);
+                }
+                Ok(())
+            });
+        }
+    }
+    // Set before the ids change, as only root may raise the priority.
+    #[cfg(target_os = "linux")]
+    let (scheduling, scheduler) = (
+        opts.nice.is_some() || opts.scheduler.is_some(),
+        opts.scheduler,
+    );
+    #[cfg(not(target_os = "linux"))]
+    let scheduling = opts.nice.is_some();
+    if scheduling {
+        let nice = opts.nice;
+        unsafe {
+            command.pre_exec(move || {
+                #[cfg(target_os = "linux")]
+                if let Some(policy) = scheduler {
+                    if let Err(msg) = crate::linux::set_scheduler(policy) {
+                        fail_in_child(msg, RET_GENERIC_ERROR);
+                    }
+                }
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                        fail_in_child(
+                            b"Unable to set the niceness of the target.\n",
+                            RET_GENERIC_ERROR,
+                        );
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(cpus) = &opts.cpus {
+        let set = crate::linux::cpu_set(cpus);
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::linux::set_affinity(&set) {
+                    fail_in_child(msg, RET_GENERIC_ERROR);
+                }
+                Ok(())
+            });
+        }
+    }
+    // Set before the ids change, as only a privileged process may lower the score.
+    #[cfg(target_os = "linux")]
+    if let Some(adj) = opts.oom_score_adj {
+        let adj = adj.to_string().into_bytes();
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::linux::set_oom_score_adj(&adj) {
+                    fail_in_child(msg, RET_GENERIC_ERROR);
+                }
+                Ok(())
+            });
+        }
+    }
+    // Set before the root changes, beneath which /proc may be missing.
+    #[cfg(target_os = "linux")]
+    if let Some(context) = &opts.selinux_context {
+        let context = CString::new(context.as_str()).unwrap_or_default();
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::linux::set_exec_context(context.as_bytes_with_nul()) {
+                    fail_in_child(msg, RET_SANDBOX);
+                }
+                Ok(())
+            });
+        }
+    }
+    // Fails closed, unless running unconfined instead is allowed.
+    #[cfg(target_os = "linux")]
+    if let Some((profile, optional)) = &opts.apparmor_profile {
+        let change = format!("exec {}", profile).into_bytes();
+        let optional = *optional;
+        unsafe {
+            command.pre_exec(
+                move || match crate::linux::change_profile_on_exec(&change) {
+                    Err(msg) if !optional => fail_in_child(msg, RET_SANDBOX),
+                    _ => Ok(()),
+                },
+            );
+        }
+    }
+    // Entered before the ids change, as that needs privileges.
+    if let Some(root) = &opts.chroot {
+        let root = c_path(root);
+        let cwd = c_path(&target_cwd(command, opts));
+        unsafe {
+            command.pre_exec(move || {
+                if libc::chroot(root.as_ptr()) != 0 {
+                    fail_in_child(b"Unable to change the root directory.\n", RET_SANDBOX);
+                }
+                if libc::chdir(cwd.as_ptr()) != 0 {
+                    fail_in_child(
+                        b"Unable to enter the working directory beneath the root directory.\n",
+                        RET_SANDBOX,
+                    );
+                }
+                Ok(())
+            });
+        }
+    }
+    // Mounted before the ids change, as that needs privileges.
+    #[cfg(target_os = "linux")]
+    if opts.private_tmp {
+        let cwd = c_path(&target_cwd(command, opts));
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::linux::private_tmp(&cwd) {
+                    fail_in_child(msg, RET_SANDBOX);
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(required) = opts.no_network {
+        unsafe {
+            command.pre_exec(move || match crate::linux::isolate_network() {
+                Err(msg) if required => fail_in_child(msg, RET_SANDBOX),
+                _ => Ok(()),
+            });
+        }
+    }
+    // The soft limit lowered for the wrapper itself, before the configured limits.
+    if let Some(limit) = opts.core_limit {
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+                    fail_in_child(
+                        b"Unable to restore the core dump limit of the target.\n",
+                        RET_GENERIC_ERROR,
+                    );
+                }
+                Ok(())
+            });
+        }
+    }
+    // Set before the ids change, as raising a hard limit needs privileges.
+    if !opts.rlimits.is_empty() {
+        let rlimits = opts
+            .rlimits
+            .iter()
+            .filter_map(|(resource, limit)| {
+                let limit = limit.map_or(libc::RLIM_INFINITY, |l| l as libc::rlim_t);
+                let limit = libc::rlimit {
+                    rlim_cur: limit,
+                    rlim_max: limit,
+                };
+                match rlimit_resource(*resource) {
+                    Some(resource) => Some((resource, limit)),
+                    None => {
+                        if opts.verbose {
+                            output::verbose(format_args!(
+                                "the resource limit {:?} is not supported on this platform, continuing without it",
+                                resource
+                            ));
+                        }
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            command.pre_exec(move || {
+                for (resource, limit) in rlimits.iter() {
+                    if libc::setrlimit(*resource, limit) != 0 {
+                        fail_in_child(
+                            b"Unable to set a resource limit of the target.\n",
+                            RET_GENERIC_ERROR,
+                        );
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+    let (uid, gid, caps) = (opts.uid, opts.gid, opts.caps);
+    let groups = if unsafe { libc::geteuid() } == 0 {
+        Some(opts.groups.clone())
+    } else {
+        None
+    };
+    // a target running as root keeps every privilege, as it would with a plain setuid
+    #[cfg(target_os = "illumos")]
+    let basic = crate::illumos::BasicPrivileges::new().filter(|_| uid != 0);
+    unsafe {
+        command.pre_exec(move || {
+            #[cfg(target_os = "linux")]
+            if let Some(mask) = caps {
+                if let Err(msg) = crate::linux::restrict_bounding_set(mask, uid != 0) {
+                    fail_in_child(msg, RET_PRIV_DROP);
+                }
+            }
+            if let Err(msg) = drop_privileges(uid, gid, groups.as_deref()) {
+                fail_in_child(msg, RET_PRIV_DROP);
+            }
+            #[cfg(target_os = "illumos")]
+            if let Some(basic) = &basic {
+                if let Err(msg) = basic.restrict() {
+                    fail_in_child(msg, RET_PRIV_DROP);
+                }
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(mask) = caps {
+                if let Err(msg) = crate::linux::apply_caps(mask, uid != 0) {
+                    fail_in_child(msg, RET_PRIV_DROP);
+                }
+            }
+            Ok(())
+        });
+    }
+    if let Some(mask) = opts.umask {
+        unsafe {
+            command.pre_exec(move || {
+                libc::umask(mask as libc::mode_t);
+                Ok(())
+            });
+        }
+    }
+    command.env("PATH", path);
+    for (name, value) in opts.env.iter() {
+        if crate::config::is_env_name(name) && !crate::config::is_env_denied(name) {
+            command.env(name, value);
+        }
+    }
+    let (uid, gid, name) = &opts.invoker;
+    command.env("RUN_SUID_UID", uid.to_string());
+    command.env("RUN_SUID_GID", gid.to_string());
+    if let Some(name) = name {
+        command.env("RUN_SUID_USER", name);
+    }
+
+    // Configured files are opened after the ids change, so that only what the target user may
+    // write is written. Those of the invoker are already open.
+    for (fd, file) in [(1, &opts.stdout_file), (2, &opts.stderr_file)] {
+        if let Some(file) = file {
+            let file = c_path(file);
+            let mode = if opts.append_output {
+                libc::O_APPEND
+            } else {
+                0
+            };
+            unsafe {
+                command.pre_exec(move || {
+                    if let Err(msg) = redirect_output(&file, mode, fd) {
+                        fail_in_child(msg, RET_SANDBOX);
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+
+    // Written by the target itself, as the target user and with the pid the wrapper waits for,
+    // into the directory the invoker opened.
+    if let Some(file) = &opts.pidfile {
+        use std::os::unix::io::AsRawFd;
+
+        let dir = opts
+            .pidfile_dir
+            .as_ref()
+            .and_then(|dir| dir.try_clone().ok());
+        let name = pidfile_name(file);
+        let invoker = opts.invoker.0;
+        let tmp = format!(".{}.{}", name.to_string_lossy(), std::process::id());
+        let tmp = CString::new(tmp).unwrap_or_default();
+        unsafe {
+            command.pre_exec(move || {
+                let dir = match &dir {
+                    Some(dir) => dir.as_raw_fd(),
+                    None => fail_in_child(
+                        b"Unable to open the directory of the pid file.\n",
+                        RET_GENERIC_ERROR,
+                    ),
+                };
+                if let Err(msg) = write_pidfile(dir, &tmp, &name, invoker) {
+                    fail_in_child(msg, RET_GENERIC_ERROR);
+                }
+                Ok(())
+            });
+        }
+    }
+
+    // Set after the ids change, as that clears the parent death signal.
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    if let Some(signal) = opts.parent_death_signal {
+        let parent = unsafe { libc::getpid() };
+        unsafe {
+            command.pre_exec(move || {
+                #[cfg(target_os = "linux")]
+                let set =
+                    libc::prctl(libc::PR_SET_PDEATHSIG, signal as libc::c_ulong, 0, 0, 0) == 0;
+                #[cfg(target_os = "freebsd")]
+                let set = crate::freebsd::set_parent_death_signal(signal);
+                if !set {
+                    fail_in_child(b"Unable to set the parent death signal.\n", RET_PRIV_DROP);
+                }
+                // the wrapper may have died before the signal was set
+                if libc::getppid() != parent {
+                    fail_in_child(
+                        b"The wrapper exited before the target started.\n",
+                        RET_PRIV_DROP,
+                    );
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if opts.no_new_privs {
+        unsafe {
+            command.pre_exec(|| {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    fail_in_child(b"Unable to set no_new_privs.\n", RET_SANDBOX);
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(ruleset) = &opts.landlock {
+        use std::os::unix::prelude::AsRawFd;
+
+        let ruleset = ruleset.as_raw_fd();
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::landlock::restrict_self(ruleset) {
+                    fail_in_child(msg, RET_SANDBOX);
+                }
+                Ok(())
+            });
+        }
+    }
+
+    match opts.process_group {
+        ProcessGroup::Inherit => {}
+        ProcessGroup::Own => {
+            let foreground = is_foreground();
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setpgid(0, 0) != 0 {
+                        fail_in_child(b"Unable to create a process group.\n", RET_GENERIC_ERROR);
+                    }
+                    if foreground {
+                        give_terminal(libc::getpid());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        ProcessGroup::Session => {
+            let pty = opts.pty.is_some();
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setsid() < 0 {
+                        fail_in_child(b"Unable to create a session.\n", RET_GENERIC_ERROR);
+                    }
+                    // the pseudo-terminal on stdin becomes the controlling terminal of the session
+                    if pty && libc::ioctl(0, libc::TIOCSCTTY as _, 0) != 0 {
+                        fail_in_child(b"Unable to acquire the terminal.\n", RET_GENERIC_ERROR);
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+
+    // Done after every hook that uses a descriptor of its own, which may be replaced here.
+    if !opts.pass_fds.is_empty() {
+        let above = opts.pass_fds.iter().map(|(_, to)| *to).max().unwrap_or(0) + 1;
+        let mut fds = opts
+            .pass_fds
+            .iter()
+            .map(|(from, to)| (*from, *to, -1))
+            .collect::<Vec<_>>();
+        unsafe {
+            command.pre_exec(move || {
+                // copied out of the way first, as a source may be the target of another pair
+                for (from, _, tmp) in fds.iter_mut() {
+                    *tmp = libc::fcntl(*from, libc::F_DUPFD_CLOEXEC, above);
+                    if *tmp < 0 {
+                        fail_in_child(
+                            b"Unable to pass a descriptor to the target.\n",
+                            RET_GENERIC_ERROR,
+                        );
+                    }
+                }
+                for (_, to, tmp) in fds.iter() {
+                    if libc::dup2(*tmp, *to) < 0 {
+                        fail_in_child(
+                            b"Unable to pass a descriptor to the target.\n",
+                            RET_GENERIC_ERROR,
+                        );
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    // The syscall filter must be the last hook, as it may deny what other hooks need.
+    #[cfg(target_os = "linux")]
+    if let Some(filter) = opts.seccomp.clone() {
+        unsafe {
+            command.pre_exec(move || {
+                if let Err(msg) = crate::seccomp::install(&filter) {
+                    fail_in_child(msg, RET_SANDBOX);
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Whether none of the hooks [prepare_command] installs would change anything, so that the
+/// target can be started with `posix_spawn`. Every hook added there must be ruled out here.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+fn spawnable(command: &Command, opts: &super::Opts) -> bool {
+    use crate::sys;
+
+    let ids = sys::getuid() == opts.uid
+        && sys::geteuid() == opts.uid
+        && sys::getgid() == opts.gid
+        && sys::getegid() == opts.gid;
+    // only replaced when running as root, and the primary group counts whether listed or not
+    let groups = sys::geteuid() != 0 || {
+        let (mut own, mut target) = (sys::getgroups(), opts.groups.clone());
+        own.push(opts.gid);
+        target.push(opts.gid);
+        own.sort_unstable();
+        own.dedup();
+        target.sort_unstable();
+        target.dedup();
+        own == target
+    };
+    // started in the working directory of the wrapper, which is the canonical one
+    let cwd = match command.get_current_dir() {
+        Some(cwd) => std::env::current_dir().map_or(false, |own| own == cwd),
+        None => true,
+    };
+    // the terminal is handed to the group of the child before it runs
+    let group = match opts.process_group {
+        ProcessGroup::Inherit => true,
+        ProcessGroup::Own => !is_foreground(),
+        ProcessGroup::Session => false,
+    };
+    #[cfg(target_os = "linux")]
+    let platform = opts.cgroup.is_none()
+        && opts.scheduler.is_none()
+        && opts.cpus.is_none()
+        && opts.oom_score_adj.is_none()
+        && opts.selinux_context.is_none()
+        && opts.apparmor_profile.is_none()
+        && !opts.private_tmp
+        && opts.no_network.is_none()
+        && !opts.no_new_privs
+        && opts.landlock.is_none()
+        && opts.seccomp.is_none()
+        && opts.parent_death_signal.is_none();
+    #[cfg(target_os = "freebsd")]
+    let platform = opts.parent_death_signal.is_none();
+    #[cfg(target_os = "macos")]
+    let platform = true;
+    ids && groups
+        && cwd
+        && group
+        && platform
+        && opts.caps.is_none()
+        && opts.nice.is_none()
+        && opts.chroot.is_none()
+        && opts.rlimits.is_empty()
+        && opts.core_limit.is_none()
+        && opts.umask.is_none()
+        && opts.stdout_file.is_none()
+        && opts.stderr_file.is_none()
+        && opts.pidfile.is_none()
+        && opts.pass_fds.is_empty()
+        && opts.pty.is_none()
+}
+
+/// Start the child with `posix_spawn` if [spawnable], or else with `fork` and `exec` running
+/// its hooks. `mask` is the signal mask the child starts with.
+fn start_child(
+    child: &mut Command,
+    opts: &super::Opts,
+    mask: &libc::sigset_t,
+) -> Result<i32, std::io::Error> {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    if let Some(arg0) = opts.arg0.as_deref().filter(|_| spawnable(child, opts)) {
+        if opts.verbose {
+            output::verbose(format_args!("starting the child with posix_spawn"));
+        }
+        let own_group = opts.process_group == ProcessGroup::Own;
+        // a daemon has its standard input and output closed by `run`
+        let null_stdio = opts.started.is_some();
+        return crate::spawn::spawn(child, arg0, mask, own_group, null_stdio);
+    }
+    // the mask is set last, as the other hooks run with the signals of the wrapper blocked
+    let mask = *mask;
+    unsafe {
+        child.pre_exec(move || {
+            libc::pthread_sigmask(libc::SIG_SETMASK, &mask, std::ptr::null_mut());
+            Ok(())
+        });
+    }
+    child.spawn().map(|child| child.id() as i32)
+}
+
+/// Transition the real, effective, and saved ids to the target user.
+///
+/// Runs between `fork` and `exec`, so only async-signal-safe calls are allowed here.
+/// When capabilities are kept, they are not yet effective when the regain check runs.
+/// Supplementary groups can only be replaced when the wrapper is running as root,
+/// otherwise `groups` is `None` and the inherited groups are kept.
+fn drop_privileges(uid: u32, gid: u32, groups: Option<&[u32]>) -> Result<(), &'static [u8]> {
+    unsafe {
+        if let Some(groups) = groups {
+            if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 {
+                return Err(b"Unable to set the supplementary groups of the target user.\n");
+            }
+            if libc::getgroups(0, std::ptr::null_mut()) != groups.len() as libc::c_int {
+                return Err(b"The supplementary groups of the target user did not stick.\n");
+            }
+        }
+        set_ids(uid, gid)?;
+        if uid != 0 && setreuid(u32::MAX, 0) == 0 {
+            return Err(b"Privileges could be regained after dropping them.\n");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "illumos"
+)))]
+use libc::setreuid;
+
+// missing from the libc crate on these platforms
+#[cfg(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "illumos"
+))]
+extern "C" {
+    pub(crate) fn setreuid(ruid: libc::uid_t, euid: libc::uid_t) -> libc::c_int;
+}
+#[cfg(any(target_os = "macos", target_os = "illumos"))]
+extern "C" {
+    pub(crate) fn setregid(rgid: libc::gid_t, egid: libc::gid_t) -> libc::c_int;
+}
+
+/// Set the real, effective, and saved ids, and check that all of them were set.
+#[cfg(not(any(target_os = "macos", target_os = "illumos")))]
+unsafe fn set_ids(uid: u32, gid: u32) -> Result<(), &'static [u8]> {
+    if libc::setresgid(gid, gid, gid) != 0 {
+        return Err(b"Unable to set the group id of the target user.\n");
+    }
+    if libc::setresuid(uid, uid, uid) != 0 {
+        return Err(b"Unable to set the user id of the target user.\n");
+    }
+
+    let (mut r, mut e, mut s) = (0, 0, 0);
+    if libc::getresgid(&mut r, &mut e, &mut s) != 0 || r != gid || e != gid || s != gid {
+        return Err(b"The group id of the target user did not stick.\n");
+    }
+    if libc::getresuid(&mut r, &mut e, &mut s) != 0 || r != uid || e != uid || s != uid {
+        return Err(b"The user id of the target user did not stick.\n");
+    }
+    Ok(())
+}
+
+/// macOS and illumos have no `setresuid`, but changing the real id with `setreuid` sets the
+/// saved id to the effective one. The saved id can not be read back, so only failing to regain
+/// root shows it.
+#[cfg(any(target_os = "macos", target_os = "illumos"))]
+unsafe fn set_ids(uid: u32, gid: u32) -> Result<(), &'static [u8]> {
+    if setregid(gid, gid) != 0 {
+        return Err(b"Unable to set the group id of the target user.\n");
+    }
+    if setreuid(uid, uid) != 0 {
+        return Err(b"Unable to set the user id of the target user.\n");
+    }
+
+    if libc::getgid() != gid || libc::getegid() != gid {
+        return Err(b"The group id of the target user did not stick.\n");
+    }
+    if libc::getuid() != uid || libc::geteuid() != uid {
+        return Err(b"The user id of the target user did not stick.\n");
+    }
+    Ok(())
+}
+
+/// Write a static message to stderr and terminate a forked child with the given code.
+fn fail_in_child(msg: &[u8], code: u8) -> ! {
+    unsafe {
+        libc::write(2, msg.as_ptr().cast(), msg.len());
+        libc::_exit(crate::exit::own(code) as i32)
+    }
+}
+
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "android"
+)))]
+const SIGPOLL_OR_EMT: i32 = libc::SIGPOLL;
+/// macOS and the BSDs have no `SIGPOLL`, so the signal that is not otherwise forwarded takes
+/// its place.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+const SIGPOLL_OR_EMT: i32 = libc::SIGEMT;
+/// Bionic only keeps `SIGPOLL` as a legacy alias, so the same signal is named as `SIGIO`.
+#[cfg(target_os = "android")]
+const SIGPOLL_OR_EMT: i32 = libc::SIGIO;
+
+static CAPTURED_SIGS_CONST: [i32; 21] = {
+    use libc::*;
+
+    [
+        SIGABRT,
+        SIGALRM, // SIGCHLD,
+        SIGCONT,
+        SIGFPE,
+        SIGHUP,
+        SIGILL,
+        SIGINT, // SIGKILL,
+        SIGPIPE,
+        SIGPOLL_OR_EMT, // SIGRTMIN..=SIGRTMAX are only known at runtime,
+        SIGQUIT,        // SIGSEGV,
+        // SIGSTOP can not be caught,
+        SIGSYS,
+        SIGTERM,
+        SIGTSTP,
+        SIGTTIN,
+        SIGTTOU, // SIGTRAP,
+        SIGURG,
+        SIGUSR1,
+        SIGUSR2,
+        SIGWINCH,
+        SIGXCPU,
+        SIGXFSZ,
+    ]
+};
+
+#[cfg(target_os = "linux")]
+const SI_QUEUE: libc::c_int = -1;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn sigqueue(pid: libc::pid_t, sig: libc::c_int, value: libc::sigval) -> libc::c_int;
+}
+
+#[cfg(target_os = "linux")]
+fn realtime_signals() -> std::ops::RangeInclusive<i32> {
+    libc::SIGRTMIN()..=libc::SIGRTMAX()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn realtime_signals() -> std::ops::RangeInclusive<i32> {
+    1..=0
+}
+
+/// Every signal forwarded to the child.
+fn captured_signals() -> impl Iterator<Item = i32> {
+    CAPTURED_SIGS_CONST
+        .iter()
+        .copied()
+        .chain(realtime_signals())
+}
+
+/// The signals taken by [wait_for]: `SIGCHLD`, and every forwarded one that is not ignored.
+fn waited_set() -> libc::sigset_t {
+    unsafe {
+        let mut set = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGCHLD);
+        for signum in captured_signals() {
+            // left to be ignored by the target as well
+            let mut old: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(signum, std::ptr::null(), &mut old) == 0
+                && old.sa_sigaction == libc::SIG_IGN
+            {
+                continue;
+            }
+            libc::sigaddset(&mut set, signum);
+        }
+        set
+    }
+}
+
+#[cfg(target_os = "illumos")]
+extern "C" {
+    fn sigtimedwait(
+        set: *const libc::sigset_t,
+        info: *mut libc::siginfo_t,
+        timeout: *const libc::timespec,
+    ) -> libc::c_int;
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd"
+))]
+use libc::sigtimedwait;
+
+/// Take one of the blocked signals in `set`, `None` if `timeout` passed before any was pending.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+fn wait_signal(
+    set: &libc::sigset_t,
+    timeout: Option<Duration>,
+) -> Result<Option<libc::siginfo_t>, std::io::Error> {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let ts = timeout.map(|d| {
+        let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+        ts.tv_sec = d.as_secs().min(libc::time_t::MAX as u64) as libc::time_t;
+        ts.tv_nsec = d.subsec_nanos() as _;
+        ts
+    });
+    let ts = ts
+        .as_ref()
+        .map_or(std::ptr::null(), |ts| ts as *const libc::timespec);
+    if unsafe { sigtimedwait(set, &mut info, ts) } > 0 {
+        return Ok(Some(info));
+    }
+    let e = std::io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EAGAIN) => Ok(None),
+        _ => Err(e),
+    }
+}
+
+/// Take one of the blocked signals in `set`, `None` if `timeout` passed before any was pending.
+///
+/// Without `sigtimedwait` the pending signals are polled for, and taken with `sigwait`.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "illumos"
+)))]
+fn wait_signal(
+    set: &libc::sigset_t,
+    timeout: Option<Duration>,
+) -> Result<Option<libc::siginfo_t>, std::io::Error> {
+    const POLL: Duration = Duration::from_millis(20);
+    let deadline = timeout.map(|d| Instant::now() + d);
+    loop {
+        let mut pending: libc::sigset_t = unsafe { std::mem::zeroed() };
+        unsafe { libc::sigpending(&mut pending) };
+        let found = captured_signals()
+            .chain(Some(libc::SIGCHLD))
+            .find(|s| unsafe {
+                libc::sigismember(set, *s) == 1 && libc::sigismember(&pending, *s) == 1
+            });
+        if let Some(signum) = found {
+            let mut only: libc::sigset_t = unsafe { std::mem::zeroed() };
+            let mut taken = 0;
+            let r = unsafe {
+                libc::sigemptyset(&mut only);
+                libc::sigaddset(&mut only, signum);
+                libc::sigwait(&only, &mut taken)
+            };
+            if r != 0 {
+                return Err(std::io::Error::from_raw_os_error(r));
+            }
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            info.si_signo = taken;
+            return Ok(Some(info));
+        }
+        let left = deadline.map(|at| at.saturating_duration_since(Instant::now()));
+        if left == Some(Duration::ZERO) {
+            return Ok(None);
+        }
+        std::thread::sleep(left.map_or(POLL, |left| left.min(POLL)));
+    }
+}
+
+/// The value attached to a signal sent with `sigqueue`.
+#[cfg(target_os = "linux")]
+unsafe fn queued_value(info: *const libc::siginfo_t) -> Option<u64> {
+    if info.is_null() || (*info).si_code != SI_QUEUE {
+        return None;
+    }
+    Some((*info).si_value().sival_ptr as usize as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn queued_value(_info: *const libc::siginfo_t) -> Option<u64> {
+    None
+}
+
+/// Send a signal to the child, or its whole group, with its `sigqueue` value if the
+/// platform allows it. A value can only be queued to the child itself.
+#[cfg(target_os = "linux")]
+fn send_signal(pid: i32, group: bool, signal: i32, value: Option<u64>) {
+    match value {
+        Some(value) => unsafe {
+            let value = libc::sigval {
+                sival_ptr: value as usize as *mut libc::c_void,
+            };
+            sigqueue(pid, signal, value)
+        },
+        None => unsafe { libc::kill(if group { -pid } else { pid }, signal) },
+    };
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_signal(pid: i32, group: bool, signal: i32, _value: Option<u64>) {
+    unsafe { libc::kill(if group { -pid } else { pid }, signal) };
+}
+
+/// Send a signal to the child, or its whole group if `group`.
+fn forward_signal(pid: i32, group: bool, signal: i32, value: Option<u64>, v: bool) {
+    if v {
+        match value {
+            Some(value) => output::verbose(format_args!(
+                "sending signal {} with value {}",
+                signal, value
+            )),
+            None => output::verbose(format_args!("sending signal {}", signal)),
+        }
+    }
+    send_signal(pid, group, signal, value);
+}
+
+fn describe(child: &Command, opts: &super::Opts) -> String {
+    use std::fmt::Write;
+    if output::is_json() {
+        return describe_json(child, opts).to_string();
+    }
+    let mut out = String::new();
+    write!(out, "{:?}", child.get_program()).unwrap();
+    for a in child.get_args() {
+        write!(out, " {:?}", a).unwrap();
+    }
+    write!(
+        out,
+        " {{ uid: {}, gid: {}, groups: {:?}, args: {{",
+        opts.uid, opts.gid, opts.groups
+    )
+    .unwrap();
+    for a in child.get_envs() {
+        write!(out, " {:?}: {:?},", a.0, a.1).unwrap();
+    }
+    write!(out, "}}").unwrap();
+    #[cfg(target_os = "linux")]
+    if let Some(mask) = opts.caps {
+        write!(out, ", caps: {:?}", crate::linux::format_caps(mask)).unwrap();
+    }
+    write!(out, " }}").unwrap();
+    out
+}
+
+fn describe_json(child: &Command, opts: &super::Opts) -> Json {
+    let env = child
+        .get_envs()
+        .map(|(k, v)| (k.to_string_lossy().into_owned(), Json::from(v)))
+        .collect();
+    #[cfg(target_os = "linux")]
+    let caps = Json::from(opts.caps.map(|m| Json::array(crate::linux::format_caps(m))));
+    #[cfg(not(target_os = "linux"))]
+    let caps = Json::Null;
+    Json::object([
+        ("verbose", Json::from("executing")),
+        ("program", Json::from(child.get_program())),
+        ("args", Json::array(child.get_args())),
+        ("uid", Json::from(opts.uid)),
+        ("gid", Json::from(opts.gid)),
+        ("groups", Json::array(opts.groups.iter().copied())),
+        ("env", Json::Object(env)),
+        ("caps", caps),
+    ])
+}
+
+fn exec(mut child: Command, opts: super::Opts) -> ExitCode {
+    if opts.verbose {
+        eprintln!("{}", describe(&child, &opts));
+    }
+    let e = child.exec();
+    eprintln!("Unable to execute command: {}", e);
+    ExitCode::from(crate::exit::exec_failure(&e))
+}
+
+/// Whether the wrapper's process group is in the foreground of the terminal on stdin.
+fn is_foreground() -> bool {
+    unsafe { libc::isatty(0) == 1 && libc::tcgetpgrp(0) == libc::getpgrp() }
+}
+
+/// Hand the terminal on stdin to a process group, as a shell does when running a job.
+///
+/// `SIGTTOU` is blocked meanwhile, as the caller may itself be in a background group.
+fn give_terminal(pgid: i32) {
+    unsafe {
+        let mut set = std::mem::zeroed();
+        let mut old = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+        libc::tcsetpgrp(0, pgid);
+        libc::pthread_sigmask(libc::SIG_SETMASK, &old, std::ptr::null_mut());
+    }
+}
+
+#[cfg(target_os = "illumos")]
+use crate::illumos::wait4;
+#[cfg(not(target_os = "illumos"))]
+use libc::wait4;
+
+/// Reap the child once it terminated, `None` while it is still running. The wrapper stops
+/// whenever the child is stopped so that the shell sees the job as suspended, and continuing
+/// the wrapper continues the child.
+///
+/// A child in its own group holds the terminal while running in the foreground, so it is
+/// taken back before stopping and handed out again once continued in the foreground.
+fn reap(
+    pid: i32,
+    group: ProcessGroup,
+    v: bool,
+) -> Result<Option<(ExitStatus, libc::rusage)>, std::io::Error> {
+    loop {
+        let mut status = 0;
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let options = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+        match unsafe { wait4(pid, &mut status, options, &mut usage) } {
+            0 => return Ok(None),
+            r if r < 0 => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+            _ => {}
+        }
+        if libc::WIFSTOPPED(status) {
+            if v {
+                output::verbose(format_args!(
+                    "child stopped by signal {}, stopping",
+                    libc::WSTOPSIG(status)
+                ));
+            }
+            let own = group == ProcessGroup::Own && unsafe { libc::tcgetpgrp(0) } == pid;
+            if own {
+                give_terminal(unsafe { libc::getpgrp() });
+            }
+            unsafe { libc::kill(libc::getpid(), libc::SIGSTOP) };
+            if own && is_foreground() {
+                give_terminal(pid);
+            }
+            if group != ProcessGroup::Inherit {
+                unsafe { libc::kill(-pid, libc::SIGCONT) };
+            }
+        } else if libc::WIFCONTINUED(status) {
+            if v {
+                output::verbose(format_args!("child continued"));
+            }
+        } else {
+            return Ok(Some((ExitStatus::from_raw(status), usage)));
+        }
+    }
+}
+
+/// Start the child and wait for it on this thread alone, taking every forwarded signal and
+/// `SIGCHLD` with [wait_signal] instead of handling them.
+fn wait_for(mut child: Command, mut opts: super::Opts) -> Result<ExitStatus, ExitCode> {
+    let v = opts.verbose;
+    let pty = opts.pty.take();
+    let kill_after = opts.kill_after;
+    let timeout = opts.timeout;
+    let group = opts.process_group;
+    let grouped = group != ProcessGroup::Inherit;
+    let foreground = group == ProcessGroup::Own && is_foreground();
+    // blocked before anything is started, so that signals stay pending until taken below, and
+    // never unblocked again as the wrapper exits right after the child; the relay thread
+    // inherits the mask, while the child gets the one of the invoker back
+    let set = waited_set();
+    let mut unblocked: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut unblocked) };
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    let tty = pty.as_ref().map(|pty| pty.name().to_owned());
+    let relay = match pty.map(crate::pty::Pty::relay) {
+        Some(Ok(relay)) => Some(relay),
+        Some(Err(e)) => {
+            eprintln!("Unable to relay the terminal of the target: {}", e);
+            return Err(ExitCode::from(own(RET_GENERIC_ERROR)));
+        }
+        None => None,
+    };
+    if v {
+        eprintln!("{}", describe(&child, &opts));
+    }
+    let start = Instant::now();
+    let cpid = match start_child(&mut child, &opts, &unblocked) {
+        Ok(cpid) => cpid,
+        Err(e) => {
+            eprintln!("Unable to execute command: {}", e);
+            if let Some(relay) = relay {
+                relay.finish();
+            }
+            return Err(ExitCode::from(crate::exit::exec_failure(&e)));
+        }
+    };
+    // the target stays in the contract of the wrapper, which its service restarts by
+    #[cfg(target_os = "illumos")]
+    if v {
+        output::verbose(format_args!(
+            "waiting for child {} in process contract {}",
+            cpid,
+            crate::illumos::contract_id()
+        ));
+    }
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    let session = match (opts.utmp.take(), tty) {
+        (Some((user, uid)), Some(tty)) => {
+            match crate::utmp::Session::start(&user, uid, &tty, cpid) {
+                Ok(session) => Some(session),
+                Err(e) => {
+                    if v {
+                        output::verbose(format_args!(
+                            "unable to record the session in utmp: {}",
+                            e
+                        ));
+                    }
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    if let Some(started) = opts.started.take() {
+        detach(started);
+    }
+    // also done here, so that the group exists before any signal is forwarded to it
+    if group == ProcessGroup::Own {
+        unsafe { libc::setpgid(cpid, cpid) };
+        if foreground {
+            give_terminal(cpid);
+        }
+    }
+    if v {
+        output::verbose(format_args!("waiting for child {:?}", cpid));
+    }
+    let mut kill_at: Option<Instant> = None;
+    let mut timeout_at = timeout.map(|(d, _)| Instant::now() + d);
+    let mut timed_out = false;
+    let result = loop {
+        let at = kill_at.into_iter().chain(timeout_at).min();
+        let wait = at.map(|at| at.saturating_duration_since(Instant::now()));
+        let info = match wait_signal(&set, wait) {
+            Ok(Some(info)) => info,
+            Ok(None) => {
+                if kill_at == at {
+                    if v {
+                        output::verbose(format_args!(
+                            "the child is still running after SIGTERM, killing it"
+                        ));
+                    }
+                    forward_signal(cpid, grouped, libc::SIGKILL, None, v);
+                    kill_at = None;
+                } else if let Some((d, signal)) = timeout {
+                    if v {
+                        output::verbose(format_args!("the child is still running after {:?}", d));
+                    }
+                    forward_signal(cpid, grouped, signal, None, v);
+                    timed_out = true;
+                    timeout_at = None;
+                    if kill_at.is_none() {
+                        kill_at = kill_after.map(|d| Instant::now() + d);
+                    }
+                }
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                eprintln!("Unable to wait for a signal: {}", e);
+                break Err(ExitCode::from(own(RET_GENERIC_ERROR)));
+            }
+        };
+        let signal = info.si_signo;
+        if signal == libc::SIGCHLD {
+            match reap(cpid, group, v) {
+                Ok(None) => {}
+                Ok(Some(exited)) => break Ok(exited),
+                Err(e) => {
+                    eprintln!("Unable to wait for child: {}", e);
+                    break Err(ExitCode::from(own(RET_GENERIC_ERROR)));
+                }
+            }
+        } else if let (Some(relay), libc::SIGWINCH) = (&relay, signal) {
+            // resizing the pseudo-terminal signals its foreground process group
+            relay.resize();
+        } else {
+            forward_signal(cpid, grouped, signal, unsafe { queued_value(&info) }, v);
+            if signal == libc::SIGTERM && kill_at.is_none() {
+                kill_at = kill_after.map(|d| Instant::now() + d);
+            }
+        }
+    };
+    if let (Some(file), Some(dir)) = (&opts.pidfile, &opts.pidfile_dir) {
+        remove_pidfile(dir, &pidfile_name(file), cpid);
+    }
+    if foreground {
+        give_terminal(unsafe { libc::getpgrp() });
+    }
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    if let Some(session) = session {
+        session.end();
+    }
+    if let Some(relay) = relay {
+        relay.finish();
+    }
+    let (status, usage) = result?;
+    if v {
+        output::verbose(format_args!("child process exited {:?}", status));
+    }
+    if opts.stats {
+        Usage::new(start.elapsed(), status, &usage).report();
+    }
+    if timed_out {
+        return Err(ExitCode::from(own(RET_TIMEOUT)));
+    }
+    Ok(status)
+}
+
+/// Resources used by the target, reported by `--stats` once it exited.
+struct Usage {
+    elapsed: Duration,
+    user: Duration,
+    system: Duration,
+    /// Largest resident set size in KiB.
+    max_rss: u64,
+    core_dumped: bool,
+}
+
+impl Usage {
+    fn new(elapsed: Duration, status: ExitStatus, usage: &libc::rusage) -> Usage {
+        let time = |t: libc::timeval| {
+            Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+        };
+        // reported in bytes instead of KiB on macOS
+        #[cfg(target_os = "macos")]
+        let max_rss = usage.ru_maxrss as u64 / 1024;
+        #[cfg(not(target_os = "macos"))]
+        let max_rss = usage.ru_maxrss as u64;
+        Usage {
+            elapsed,
+            user: time(usage.ru_utime),
+            system: time(usage.ru_stime),
+            max_rss,
+            core_dumped: status.core_dumped(),
+        }
+    }
+
+    fn report(&self) {
+        if output::is_json() {
+            let stats = Json::object([
+                ("elapsed_ms", Json::from(self.elapsed.as_millis() as u64)),
+                ("user_ms", Json::from(self.user.as_millis() as u64)),
+                ("system_ms", Json::from(self.system.as_millis() as u64)),
+                ("max_rss_kib", Json::from(self.max_rss)),
+                ("core_dumped", Json::from(self.core_dumped)),
+            ]);
+            eprintln!("{}", Json::object([("stats", stats)]));
+        } else {
+            eprintln!(
+                "Stats: elapsed {:.3}s, user {:.3}s, system {:.3}s, max rss {} KiB{}",
+                self.elapsed.as_secs_f64(),
+                self.user.as_secs_f64(),
+                self.system.as_secs_f64(),
+                self.max_rss,
+                if self.core_dumped {
+                    ", core dumped"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+}
+
+/// Terminate the wrapper the way the child terminated, so that a death by signal is seen as
+/// such by the shell or supervisor instead of as an exit code.
+///
+/// The wrapper never dumps core, as its memory holds secrets of the owner, so a core dump of
+/// the child is not seen in the status of the wrapper. `--stats` reports it instead.
+fn terminate_like(status: ExitStatus) -> ExitCode {
+    let signal = match status.signal() {
+        Some(signal) => signal,
+        None => return ExitCode::from(status.code().unwrap_or(255) as u8),
+    };
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        let mut set = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+        libc::raise(signal);
+    }
+    // only reached for signals that do not terminate by default
+    ExitCode::from((128 + signal) as u8)
+}
@@ -0,0 +1,59 @@
+//! OpenBSD confinement of the wrapper itself with `pledge` and `unveil`.
+//!
+//! The wrapper pledges before doing anything else, and once every check has passed unveils only
+//! the paths still needed to start the target. Neither carries over to the target, as it is
+//! executed without any promises and `execve` drops the unveiled paths.
+
+use std::{
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// Everything the wrapper does while checking and preparing the target.
+pub(crate) const WRAPPER_PROMISES: &str =
+    "stdio rpath wpath cpath flock fattr chown proc exec id getpw tty unveil";
+
+/// What is left to start the target and proxy it until it exits.
+const TARGET_PROMISES: &str = "stdio rpath wpath cpath flock proc exec id tty";
+
+/// Restrict the wrapper to `promises`, leaving whatever it executes unrestricted.
+pub(crate) fn pledge(promises: &str) -> Result<(), std::io::Error> {
+    let promises = CString::new(promises)?;
+    if unsafe { libc::pledge(promises.as_ptr(), std::ptr::null()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn unveil(path: &Path, permissions: &str) -> Result<(), std::io::Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let permissions = CString::new(permissions)?;
+    if unsafe { libc::unveil(c_path.as_ptr(), permissions.as_ptr()) } != 0 {
+        let err = std::io::Error::last_os_error();
+        // nothing is left to hide of a path that does not exist
+        if err.raw_os_error() == Some(libc::ENOENT) {
+            return Ok(());
+        }
+        return Err(std::io::Error::new(
+            err.kind(),
+            format!("{:?}: {}", path, err),
+        ));
+    }
+    Ok(())
+}
+
+/// Unveil only `paths` with their permissions, lock them, and drop the promises only needed
+/// by the checks.
+pub(crate) fn confine(paths: &[(PathBuf, &str)]) -> Result<(), std::io::Error> {
+    // the runtime linker and libraries of a dynamically linked target
+    unveil(Path::new("/usr/libexec/ld.so"), "rx")?;
+    unveil(Path::new("/usr/lib"), "r")?;
+    for (path, permissions) in paths {
+        unveil(path, permissions)?;
+    }
+    if unsafe { libc::unveil(std::ptr::null(), std::ptr::null()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    pledge(TARGET_PROMISES)
+}
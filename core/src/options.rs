@@ -0,0 +1,61 @@
+//! Options of one invocation of the wrapper, as given on its command line.
+
+use std::time::Duration;
+
+/// Options given to the wrapper for running its target.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub verbose: bool,
+    /// Overrides `verbose`, only failures are reported.
+    pub quiet: bool,
+    pub dry_run: bool,
+    /// Print the counted runs of the target instead of running it.
+    pub show_stats: bool,
+    pub no_init_groups: bool,
+    pub require_landlock: bool,
+    pub exec: bool,
+    pub proxy: bool,
+    pub private_tmp: bool,
+    /// Run the target in a transient systemd scope of its own.
+    pub scope: bool,
+    pub allow_new_privs: bool,
+    /// Run the target on a pseudo-terminal of its own.
+    pub pty: bool,
+    /// Ask for confirmation on the terminal before running the target.
+    pub confirm: bool,
+    /// Wait for a free instance of the target, for at most the time given if any.
+    pub wait_lock: Option<Option<Duration>>,
+    /// Isolate the target from the network, `Some(false)` if only tried with `--no-network=try`.
+    pub no_network: Option<bool>,
+    pub json: bool,
+    /// Print rejections as JSON, with `--error-format=json`.
+    pub json_errors: bool,
+    pub target: Option<String>,
+    /// Name the target sees as `argv[0]`, if the configuration allows it.
+    pub arg0: Option<String>,
+    /// Capabilities to keep, `Some` even if empty once `--caps` was given.
+    pub caps: Option<Vec<String>>,
+    pub preserve_env: Vec<String>,
+    /// Variables to set in the target's environment, checked against the configuration later.
+    pub env: Vec<(String, String)>,
+    pub env_file: Option<std::path::PathBuf>,
+    pub stdout_file: Option<std::path::PathBuf>,
+    pub stderr_file: Option<std::path::PathBuf>,
+    pub append: bool,
+    /// File the terminal session of the target is recorded to.
+    pub record: Option<std::path::PathBuf>,
+    /// Continue in the background once the target is started.
+    pub daemon: bool,
+    /// Report the resource usage of the target once it exits.
+    pub stats: bool,
+    /// File the pid of the target is written to while it runs.
+    pub pidfile: Option<std::path::PathBuf>,
+    /// Descriptors of the caller handed to the target, and the numbers it gets them as.
+    pub pass_fds: Vec<(i32, i32)>,
+    pub kill_after: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub umask: Option<u32>,
+    pub nice: Option<i32>,
+    pub cpus: Option<Vec<usize>>,
+    pub oom_score_adj: Option<i32>,
+}
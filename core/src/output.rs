@@ -0,0 +1,176 @@
+//! Output of dry runs, warnings and verbose diagnostics, either as free-form text or as JSON
+//! for tools.
+
+use std::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Print everything that follows as JSON.
+pub(crate) fn use_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Leave out the warnings about what a run goes on without.
+pub(crate) fn use_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+/// Print a warning about something the run goes on without to stderr, unless quiet, as a
+/// single JSON line if JSON was selected.
+#[cfg(unix)]
+pub(crate) fn warn(message: fmt::Arguments) {
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+    if is_json() {
+        eprintln!(
+            "{}",
+            Json::object([("warning", Json::from(message.to_string()))])
+        );
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Print a verbose diagnostic to stderr, as a single JSON line if JSON was selected.
+pub(crate) fn verbose(message: fmt::Arguments) {
+    if is_json() {
+        eprintln!(
+            "{}",
+            Json::object([("verbose", Json::from(message.to_string()))])
+        );
+    } else {
+        eprintln!("Verbose: {}", message);
+    }
+}
+
+/// A JSON value, only as much as is needed to describe a run.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn object<K: Into<String>, I: IntoIterator<Item = (K, Json)>>(fields: I) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub(crate) fn array<T: Into<Json>, I: IntoIterator<Item = T>>(items: I) -> Json {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Number(n as i64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Json {
+        Json::Number(n as i64)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(n: i32) -> Json {
+        Json::Number(n as i64)
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<&std::ffi::OsStr> for Json {
+    fn from(s: &std::ffi::OsStr) -> Json {
+        Json::String(s.to_string_lossy().into_owned())
+    }
+}
+
+impl From<&std::path::Path> for Json {
+    fn from(p: &std::path::Path) -> Json {
+        p.as_os_str().into()
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(v: Option<T>) -> Json {
+        v.map_or(Json::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
@@ -0,0 +1,259 @@
+//! PAM authentication of the invoking user and sessions for the target user.
+//!
+//! Only built with the `pam` feature, which links against `libpam`.
+
+use std::{
+    ffi::{CStr, CString},
+    io::{Error, ErrorKind},
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+};
+
+use crate::{env::EnvTrait, Env};
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_BUF_ERR: c_int = 5;
+const PAM_CONV_ERR: c_int = 19;
+
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+const PAM_ERROR_MSG: c_int = 3;
+const PAM_TEXT_INFO: c_int = 4;
+
+const PAM_USER: c_int = 2;
+const PAM_TTY: c_int = 3;
+const PAM_RUSER: c_int = 8;
+
+const PAM_DISALLOW_NULL_AUTHTOK: c_int = 0x0001;
+const PAM_ESTABLISH_CRED: c_int = 0x0002;
+const PAM_DELETE_CRED: c_int = 0x0004;
+
+#[repr(C)]
+struct PamHandle {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+#[repr(C)]
+struct PamConv {
+    conv: extern "C" fn(c_int, *mut *const PamMessage, *mut *mut PamResponse, *mut c_void) -> c_int,
+    appdata_ptr: *mut c_void,
+}
+
+#[link(name = "pam")]
+extern "C" {
+    fn pam_start(
+        service: *const c_char,
+        user: *const c_char,
+        conv: *const PamConv,
+        pamh: *mut *mut PamHandle,
+    ) -> c_int;
+    fn pam_end(pamh: *mut PamHandle, status: c_int) -> c_int;
+    fn pam_set_item(pamh: *mut PamHandle, item_type: c_int, item: *const c_void) -> c_int;
+    fn pam_authenticate(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_acct_mgmt(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_setcred(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_open_session(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_close_session(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_getenvlist(pamh: *mut PamHandle) -> *mut *mut c_char;
+    fn pam_strerror(pamh: *mut PamHandle, errnum: c_int) -> *const c_char;
+}
+
+/// An open PAM transaction. Dropping it closes the session, if one was opened, and ends it.
+pub(crate) struct Pam {
+    handle: *mut PamHandle,
+    _conv: Box<PamConv>,
+    status: c_int,
+    cred: bool,
+    session: bool,
+}
+
+impl Pam {
+    /// Start a transaction for the invoking user.
+    pub(crate) fn start(service: &str, invoker: &str, tty: Option<&str>) -> Result<Pam, Error> {
+        let service = cstring(service)?;
+        let invoker = cstring(invoker)?;
+        let conv = Box::new(PamConv {
+            conv: converse,
+            appdata_ptr: ptr::null_mut(),
+        });
+        let mut handle = ptr::null_mut();
+        let r = unsafe { pam_start(service.as_ptr(), invoker.as_ptr(), &*conv, &mut handle) };
+        if r != PAM_SUCCESS || handle.is_null() {
+            return Err(Error::new(ErrorKind::Other, "unable to start PAM"));
+        }
+        let mut pam = Pam {
+            handle,
+            _conv: conv,
+            status: PAM_SUCCESS,
+            cred: false,
+            session: false,
+        };
+        pam.set_item(PAM_RUSER, &invoker)?;
+        if let Some(tty) = tty {
+            pam.set_item(PAM_TTY, &cstring(tty)?)?;
+        }
+        Ok(pam)
+    }
+
+    fn set_item(&mut self, item: c_int, value: &CStr) -> Result<(), Error> {
+        let r = unsafe { pam_set_item(self.handle, item, value.as_ptr().cast()) };
+        self.check(r)
+    }
+
+    fn check(&mut self, r: c_int) -> Result<(), Error> {
+        self.status = r;
+        if r == PAM_SUCCESS {
+            return Ok(());
+        }
+        let msg = unsafe { CStr::from_ptr(pam_strerror(self.handle, r)) };
+        Err(Error::new(
+            ErrorKind::PermissionDenied,
+            msg.to_string_lossy().into_owned(),
+        ))
+    }
+
+    /// Authenticate the invoking user and check that the account is usable.
+    pub(crate) fn authenticate(&mut self) -> Result<(), Error> {
+        let r = unsafe { pam_authenticate(self.handle, PAM_DISALLOW_NULL_AUTHTOK) };
+        self.check(r)?;
+        let r = unsafe { pam_acct_mgmt(self.handle, PAM_DISALLOW_NULL_AUTHTOK) };
+        self.check(r)
+    }
+
+    /// Open a session for the target user, applying modules such as pam_limits to this process.
+    pub(crate) fn open_session(&mut self, target_user: &str) -> Result<(), Error> {
+        self.set_item(PAM_USER, &cstring(target_user)?)?;
+        let r = unsafe { pam_setcred(self.handle, PAM_ESTABLISH_CRED) };
+        self.check(r)?;
+        self.cred = true;
+        let r = unsafe { pam_open_session(self.handle, 0) };
+        self.check(r)?;
+        self.session = true;
+        Ok(())
+    }
+
+    /// Whether a session was opened that has to be closed after the target exits.
+    pub(crate) fn has_session(&self) -> bool {
+        self.session
+    }
+
+    /// Environment variables set by modules such as pam_env.
+    pub(crate) fn env(&self) -> Vec<(String, String)> {
+        let list = unsafe { pam_getenvlist(self.handle) };
+        let mut vars = Vec::new();
+        if list.is_null() {
+            return vars;
+        }
+        let mut i = 0;
+        loop {
+            let entry = unsafe { *list.add(i) };
+            if entry.is_null() {
+                break;
+            }
+            let s = unsafe { CStr::from_ptr(entry) }
+                .to_string_lossy()
+                .into_owned();
+            if let Some((k, v)) = s.split_once('=') {
+                vars.push((k.to_owned(), v.to_owned()));
+            }
+            unsafe { libc::free(entry.cast()) };
+            i += 1;
+        }
+        unsafe { libc::free(list.cast()) };
+        vars
+    }
+}
+
+impl Drop for Pam {
+    fn drop(&mut self) {
+        unsafe {
+            if self.session {
+                pam_close_session(self.handle, 0);
+            }
+            if self.cred {
+                pam_setcred(self.handle, PAM_DELETE_CRED);
+            }
+            pam_end(self.handle, self.status);
+        }
+    }
+}
+
+fn cstring(s: &str) -> Result<CString, Error> {
+    CString::new(s).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+extern "C" fn converse(
+    num_msg: c_int,
+    msg: *mut *const PamMessage,
+    resp: *mut *mut PamResponse,
+    _appdata: *mut c_void,
+) -> c_int {
+    if num_msg <= 0 || msg.is_null() || resp.is_null() {
+        return PAM_CONV_ERR;
+    }
+    let n = num_msg as usize;
+    let replies =
+        unsafe { libc::calloc(n, std::mem::size_of::<PamResponse>()) } as *mut PamResponse;
+    if replies.is_null() {
+        return PAM_BUF_ERR;
+    }
+    for i in 0..n {
+        let m = unsafe { &**msg.add(i) };
+        let text = if m.msg.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(m.msg) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        let answer = match m.msg_style {
+            PAM_PROMPT_ECHO_OFF => Env::ask_terminal(&text, false).ok(),
+            PAM_PROMPT_ECHO_ON => Env::ask_terminal(&text, true).ok(),
+            PAM_ERROR_MSG | PAM_TEXT_INFO => {
+                eprintln!("{}", text);
+                continue;
+            }
+            _ => None,
+        };
+        let answer = match answer.and_then(|a| CString::new(a).ok()) {
+            Some(a) => a,
+            None => {
+                free_replies(replies, n);
+                return PAM_CONV_ERR;
+            }
+        };
+        let copy = unsafe { libc::strdup(answer.as_ptr()) };
+        if copy.is_null() {
+            free_replies(replies, n);
+            return PAM_BUF_ERR;
+        }
+        unsafe { (*replies.add(i)).resp = copy };
+    }
+    unsafe { *resp = replies };
+    PAM_SUCCESS
+}
+
+fn free_replies(replies: *mut PamResponse, n: usize) {
+    for i in 0..n {
+        let r = unsafe { (*replies.add(i)).resp };
+        if !r.is_null() {
+            unsafe {
+                libc::memset(r.cast(), 0, libc::strlen(r));
+                libc::free(r.cast());
+            }
+        }
+    }
+    unsafe { libc::free(replies.cast()) };
+}
@@ -0,0 +1,88 @@
+//! Authorization of the invoking user by polkit, asked over the D-Bus system bus the way
+//! `pkexec` does, so that an authentication agent of the desktop can prompt for it.
+//!
+//! Only built with the `polkit` feature.
+
+use std::io::{Error, ErrorKind};
+
+use crate::dbus::{Bus, Writer};
+
+/// `AllowUserInteraction` of `CheckAuthorization`, letting an agent prompt the user.
+const ALLOW_USER_INTERACTION: u32 = 1;
+
+/// Ask polkit whether the process `pid` of the user `uid` may perform `action`, waiting for the
+/// user to authenticate if polkit asks for it. `details` are shown by the agent.
+pub(crate) fn check_authorization(
+    action: &str,
+    pid: u32,
+    uid: u32,
+    details: &[(&str, &str)],
+) -> Result<bool, Error> {
+    let mut bus = Bus::system()?;
+    // (sa{sv})sa{ss}us: the subject, the action, its details, the flags, and no cancellation
+    let mut body = Writer(Vec::new());
+    body.string("unix-process");
+    let subject = body.array(8);
+    body.align(8);
+    body.string("pid");
+    body.signature("u");
+    body.u32(pid);
+    body.align(8);
+    body.string("start-time");
+    body.signature("t");
+    body.u64(start_time());
+    // given, as polkit can not tell the invoker from the setuid wrapper itself
+    body.align(8);
+    body.string("uid");
+    body.signature("i");
+    body.u32(uid);
+    body.end_array(subject);
+    body.string(action);
+    let entries = body.array(8);
+    for (key, value) in details {
+        body.align(8);
+        body.string(key);
+        body.string(value);
+    }
+    body.end_array(entries);
+    body.u32(ALLOW_USER_INTERACTION);
+    body.string("");
+
+    let reply = bus.call(
+        (
+            "org.freedesktop.PolicyKit1",
+            "/org/freedesktop/PolicyKit1/Authority",
+        ),
+        ("org.freedesktop.PolicyKit1.Authority", "CheckAuthorization"),
+        "(sa{sv})sa{ss}us",
+        &body.0,
+    )?;
+    // (bba{ss}), of which only whether the subject is authorized matters
+    match reply.body().u32() {
+        Some(authorized) => Ok(authorized == 1),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            "the reply of polkit is empty",
+        )),
+    }
+}
+
+/// When the wrapper started, in clock ticks since boot, which polkit compares with its own
+/// view of the process so that a reused pid is not mistaken for it.
+#[cfg(target_os = "linux")]
+fn start_time() -> u64 {
+    // the 22nd field, counted after the name which may itself hold spaces
+    std::fs::read_to_string("/proc/self/stat")
+        .ok()
+        .and_then(|stat| {
+            let rest = &stat[stat.rfind(')')? + 1..];
+            rest.split_whitespace().nth(19)?.parse().ok()
+        })
+        .unwrap_or(0)
+}
+
+/// Left for polkit to look up itself.
+#[cfg(not(target_os = "linux"))]
+fn start_time() -> u64 {
+    0
+}
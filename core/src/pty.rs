@@ -0,0 +1,324 @@
+//! Pseudo-terminals interposed between the caller's terminal and the target, and recordings
+//! of everything passing through them.
+
+use std::{
+    fs::File,
+    io::{Error, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd},
+    },
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::output::Json;
+
+/// Sent over the control pipe of a [Relay].
+const RESIZE: u8 = b'w';
+const STOP: u8 = b's';
+
+/// A pseudo-terminal for the target, optionally recorded.
+pub(crate) struct Pty {
+    master: File,
+    slave: File,
+    /// Path of the terminal side, such as `/dev/pts/3`.
+    name: PathBuf,
+    record: Option<Recorder>,
+}
+
+impl Pty {
+    /// Open a pseudo-terminal with the settings and size of the terminal on stdin, if any.
+    pub(crate) fn open(record: Option<File>) -> Result<Pty, Error> {
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) };
+        if master < 0 {
+            return Err(Error::last_os_error());
+        }
+        let master = unsafe { File::from_raw_fd(master) };
+        let fd = master.as_raw_fd();
+        if unsafe { libc::grantpt(fd) } != 0 || unsafe { libc::unlockpt(fd) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        let mut name = [0 as libc::c_char; 128];
+        if unsafe { ptsname_r(fd, name.as_mut_ptr(), name.len()) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        let flags = libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC;
+        let slave = unsafe { libc::open(name.as_ptr(), flags) };
+        if slave < 0 {
+            return Err(Error::last_os_error());
+        }
+        let slave = unsafe { File::from_raw_fd(slave) };
+        let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+        let name = PathBuf::from(std::ffi::OsStr::from_bytes(name.to_bytes()));
+        let mut term: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(0, &mut term) } == 0 {
+            unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &term) };
+        }
+        let (cols, rows) = copy_size(fd);
+        let record = match record {
+            Some(file) => Some(Recorder::start(file, cols, rows)?),
+            None => None,
+        };
+        Ok(Pty {
+            master,
+            slave,
+            name,
+            record,
+        })
+    }
+
+    /// Another descriptor of the terminal side, for the standard streams of the target.
+    pub(crate) fn slave(&self) -> Result<File, Error> {
+        self.slave.try_clone()
+    }
+
+    /// Path of the terminal the target runs on.
+    pub(crate) fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// Put the caller's terminal in raw mode and relay everything until told to stop.
+    pub(crate) fn relay(self) -> Result<Relay, Error> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        let (control, notify) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        for fd in fds {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+        let Pty {
+            master,
+            slave,
+            mut record,
+            ..
+        } = self;
+        // the target holds its own descriptors by now
+        drop(slave);
+        let raw = RawMode::enter();
+        let handle = std::thread::Builder::new()
+            .name("relay-terminal".to_string())
+            .spawn(move || {
+                relay(&master, &control, record.as_mut());
+                drop(raw);
+            })?;
+        Ok(Relay { handle, notify })
+    }
+}
+
+/// The thread relaying between the caller's terminal and a [Pty].
+pub(crate) struct Relay {
+    handle: JoinHandle<()>,
+    notify: File,
+}
+
+impl Relay {
+    /// Pass on a change of the size of the caller's terminal.
+    pub(crate) fn resize(&self) {
+        let _ = (&self.notify).write_all(&[RESIZE]);
+    }
+
+    /// Relay what the target left behind and restore the caller's terminal.
+    pub(crate) fn finish(self) {
+        let _ = (&self.notify).write_all(&[STOP]);
+        let _ = self.handle.join();
+    }
+}
+
+/// Restores the settings of the terminal on stdin when dropped.
+struct RawMode(Option<libc::termios>);
+
+impl RawMode {
+    fn enter() -> RawMode {
+        let mut term: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(0, &mut term) } != 0 {
+            return RawMode(None);
+        }
+        let mut raw = term;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(0, libc::TCSAFLUSH, &raw);
+        }
+        RawMode(Some(term))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(term) = &self.0 {
+            unsafe { libc::tcsetattr(0, libc::TCSAFLUSH, term) };
+        }
+    }
+}
+
+fn relay(master: &File, control: &File, mut record: Option<&mut Recorder>) {
+    let mut fds = [
+        libc::pollfd {
+            fd: control.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: 0,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let mut buf = [0u8; 4096];
+    loop {
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            if Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        }
+        if fds[0].revents != 0 {
+            let n = read(control.as_raw_fd(), &mut buf[..1]);
+            if n <= 0 || buf[0] == STOP {
+                // whatever the target wrote before it exited is still waiting
+                unsafe { libc::fcntl(master.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+                while copy(master.as_raw_fd(), 1, &mut buf, record.as_deref_mut(), "o") {}
+                return;
+            }
+            let (cols, rows) = copy_size(master.as_raw_fd());
+            if let Some(record) = record.as_deref_mut() {
+                record.event("r", format!("{}x{}", cols, rows).as_bytes());
+            }
+        }
+        if fds[1].revents != 0 && !copy(master.as_raw_fd(), 1, &mut buf, record.as_deref_mut(), "o")
+        {
+            // the target and everything it started closed the terminal
+            fds[1].fd = -1;
+        }
+        if fds[2].revents != 0 && !copy(0, master.as_raw_fd(), &mut buf, record.as_deref_mut(), "i")
+        {
+            // passed on as the end of file character of a terminal in canonical mode
+            unsafe { libc::write(master.as_raw_fd(), [4u8].as_ptr().cast(), 1) };
+            fds[2].fd = -1;
+        }
+    }
+}
+
+/// Copy one read from `from` to `to`, false once nothing more can be read.
+fn copy(
+    from: libc::c_int,
+    to: libc::c_int,
+    buf: &mut [u8],
+    record: Option<&mut Recorder>,
+    kind: &str,
+) -> bool {
+    let n = read(from, buf);
+    if n <= 0 {
+        return false;
+    }
+    let data = &buf[..n as usize];
+    let mut written = 0;
+    while written < data.len() {
+        let w = unsafe { libc::write(to, data[written..].as_ptr().cast(), data.len() - written) };
+        if w <= 0 {
+            break;
+        }
+        written += w as usize;
+    }
+    if let Some(record) = record {
+        record.event(kind, data);
+    }
+    true
+}
+
+fn read(fd: libc::c_int, buf: &mut [u8]) -> isize {
+    loop {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if n < 0 && Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+            continue;
+        }
+        return n;
+    }
+}
+
+/// Give the pseudo-terminal the size of the terminal on stdin, or 80x24, and get that size.
+fn copy_size(master: libc::c_int) -> (u16, u16) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(0, libc::TIOCGWINSZ, &mut size) } != 0 {
+        size.ws_col = 80;
+        size.ws_row = 24;
+    }
+    unsafe { libc::ioctl(master, libc::TIOCSWINSZ, &size) };
+    (size.ws_col, size.ws_row)
+}
+
+/// Writes an asciicast v2 recording, one JSON line per event.
+struct Recorder {
+    file: File,
+    start: Instant,
+    /// Incomplete UTF-8 sequences at the end of the last output and input.
+    pending: [Vec<u8>; 2],
+}
+
+impl Recorder {
+    fn start(mut file: File, cols: u16, rows: u16) -> Result<Recorder, Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let header = Json::object([
+            ("version", Json::from(2u32)),
+            ("width", Json::from(cols as u32)),
+            ("height", Json::from(rows as u32)),
+            ("timestamp", Json::from(timestamp)),
+        ]);
+        writeln!(file, "{}", header)?;
+        Ok(Recorder {
+            file,
+            start: Instant::now(),
+            pending: [Vec::new(), Vec::new()],
+        })
+    }
+
+    fn event(&mut self, kind: &str, data: &[u8]) {
+        let pending = &mut self.pending[(kind == "i") as usize];
+        pending.extend_from_slice(data);
+        // a multi-byte character may be split across reads
+        let valid = match std::str::from_utf8(pending) {
+            Ok(_) => pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => pending.len(),
+        };
+        let text = String::from_utf8_lossy(&pending[..valid]).into_owned();
+        pending.drain(..valid);
+        if text.is_empty() {
+            return;
+        }
+        let _ = writeln!(
+            self.file,
+            "[{:.6}, {}, {}]",
+            self.start.elapsed().as_secs_f64(),
+            Json::from(kind),
+            Json::from(text)
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+use libc::ptsname_r;
+
+/// `ptsname` copied out right away, as no other thread opens terminals.
+#[cfg(not(target_os = "linux"))]
+unsafe fn ptsname_r(fd: libc::c_int, buf: *mut libc::c_char, len: usize) -> libc::c_int {
+    let name = libc::ptsname(fd);
+    if name.is_null() {
+        return -1;
+    }
+    let name = std::ffi::CStr::from_ptr(name).to_bytes_with_nul();
+    if name.len() > len {
+        return -1;
+    }
+    std::ptr::copy_nonoverlapping(name.as_ptr().cast(), buf, name.len());
+    0
+}
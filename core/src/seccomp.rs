@@ -0,0 +1,360 @@
+//! Seccomp-bpf syscall allowlists installed on the child process right before `exec`.
+
+use crate::config::Seccomp;
+#[cfg(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "aarch64"
+))]
+use crate::config::SeccompAction;
+
+/// `BPF_LD | BPF_W | BPF_ABS`
+const BPF_LD_W_ABS: u16 = 0x20;
+/// `BPF_JMP | BPF_JEQ | BPF_K`
+const BPF_JMP_JEQ_K: u16 = 0x15;
+/// `BPF_JMP | BPF_JGE | BPF_K`
+const BPF_JMP_JGE_K: u16 = 0x35;
+/// `BPF_RET | BPF_K`
+const BPF_RET_K: u16 = 0x06;
+
+/// Offsets into `struct seccomp_data`.
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+
+macro_rules! syscalls {
+    ($($sys:ident),* $(,)?) => {
+        &[$((stringify!($sys), libc::$sys as u32)),*]
+    };
+}
+
+/// Syscalls with the same name on every supported architecture.
+#[cfg(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "aarch64"
+))]
+static SYSCALLS: &[(&str, u32)] = syscalls! {
+    SYS_accept, SYS_accept4, SYS_acct, SYS_add_key, SYS_adjtimex, SYS_bind, SYS_bpf, SYS_brk,
+    SYS_capget, SYS_capset, SYS_chdir, SYS_chroot, SYS_clock_adjtime, SYS_clock_getres,
+    SYS_clock_gettime, SYS_clock_nanosleep, SYS_clock_settime, SYS_clone, SYS_clone3, SYS_close,
+    SYS_close_range, SYS_connect, SYS_copy_file_range, SYS_delete_module, SYS_dup, SYS_dup3,
+    SYS_epoll_create1, SYS_epoll_ctl, SYS_epoll_pwait, SYS_epoll_pwait2, SYS_eventfd2, SYS_execve,
+    SYS_execveat, SYS_exit, SYS_exit_group, SYS_faccessat, SYS_faccessat2, SYS_fallocate,
+    SYS_fanotify_init, SYS_fanotify_mark, SYS_fchdir, SYS_fchmod, SYS_fchmodat, SYS_fchown,
+    SYS_fchownat, SYS_fcntl, SYS_fdatasync, SYS_fgetxattr, SYS_finit_module, SYS_flistxattr,
+    SYS_flock, SYS_fremovexattr, SYS_fsconfig, SYS_fsetxattr, SYS_fsmount, SYS_fsopen, SYS_fspick,
+    SYS_fstat, SYS_fstatfs, SYS_fsync, SYS_ftruncate, SYS_futex, SYS_get_mempolicy,
+    SYS_get_robust_list, SYS_getcpu, SYS_getcwd, SYS_getdents64, SYS_getegid, SYS_geteuid,
+    SYS_getgid, SYS_getgroups, SYS_getitimer, SYS_getpeername, SYS_getpgid, SYS_getpid,
+    SYS_getppid, SYS_getpriority, SYS_getrandom, SYS_getresgid, SYS_getresuid, SYS_getrusage,
+    SYS_getsid, SYS_getsockname, SYS_getsockopt, SYS_gettid, SYS_gettimeofday, SYS_getuid,
+    SYS_getxattr, SYS_init_module, SYS_inotify_add_watch, SYS_inotify_init1, SYS_inotify_rm_watch,
+    SYS_io_cancel, SYS_io_destroy, SYS_io_getevents, SYS_io_setup, SYS_io_submit,
+    SYS_io_uring_enter, SYS_io_uring_register, SYS_io_uring_setup, SYS_ioctl, SYS_ioprio_get,
+    SYS_ioprio_set, SYS_kcmp, SYS_kexec_load, SYS_keyctl, SYS_kill, SYS_lgetxattr, SYS_linkat,
+    SYS_listen, SYS_listxattr, SYS_llistxattr, SYS_lookup_dcookie, SYS_lremovexattr, SYS_lseek,
+    SYS_lsetxattr, SYS_madvise, SYS_mbind, SYS_membarrier, SYS_memfd_create, SYS_migrate_pages,
+    SYS_mincore, SYS_mkdirat, SYS_mknodat, SYS_mlock, SYS_mlock2, SYS_mlockall, SYS_mmap,
+    SYS_mount, SYS_mount_setattr, SYS_move_mount, SYS_move_pages, SYS_mprotect, SYS_mq_getsetattr,
+    SYS_mq_notify, SYS_mq_open, SYS_mq_timedreceive, SYS_mq_timedsend, SYS_mq_unlink, SYS_mremap,
+    SYS_msgctl, SYS_msgget, SYS_msgrcv, SYS_msgsnd, SYS_msync, SYS_munlock, SYS_munlockall,
+    SYS_munmap, SYS_name_to_handle_at, SYS_nanosleep, SYS_newfstatat, SYS_nfsservctl,
+    SYS_open_by_handle_at, SYS_open_tree, SYS_openat, SYS_openat2, SYS_perf_event_open,
+    SYS_personality, SYS_pidfd_getfd, SYS_pidfd_open, SYS_pidfd_send_signal, SYS_pipe2,
+    SYS_pivot_root, SYS_pkey_alloc, SYS_pkey_free, SYS_pkey_mprotect, SYS_ppoll, SYS_prctl,
+    SYS_pread64, SYS_preadv, SYS_preadv2, SYS_prlimit64, SYS_process_madvise, SYS_process_vm_readv,
+    SYS_process_vm_writev, SYS_pselect6, SYS_ptrace, SYS_pwrite64, SYS_pwritev, SYS_pwritev2,
+    SYS_quotactl, SYS_read, SYS_readahead, SYS_readlinkat, SYS_readv, SYS_reboot, SYS_recvfrom,
+    SYS_recvmmsg, SYS_recvmsg, SYS_remap_file_pages, SYS_removexattr, SYS_renameat2,
+    SYS_request_key, SYS_restart_syscall, SYS_rt_sigaction, SYS_rt_sigpending, SYS_rt_sigprocmask,
+    SYS_rt_sigqueueinfo, SYS_rt_sigreturn, SYS_rt_sigsuspend, SYS_rt_sigtimedwait,
+    SYS_rt_tgsigqueueinfo, SYS_sched_get_priority_max, SYS_sched_get_priority_min,
+    SYS_sched_getaffinity, SYS_sched_getattr, SYS_sched_getparam, SYS_sched_getscheduler,
+    SYS_sched_rr_get_interval, SYS_sched_setaffinity, SYS_sched_setattr, SYS_sched_setparam,
+    SYS_sched_setscheduler, SYS_sched_yield, SYS_seccomp, SYS_semctl, SYS_semget, SYS_semop,
+    SYS_semtimedop, SYS_sendmmsg, SYS_sendmsg, SYS_sendto, SYS_set_mempolicy, SYS_set_robust_list,
+    SYS_set_tid_address, SYS_setdomainname, SYS_setfsgid, SYS_setfsuid, SYS_setgid, SYS_setgroups,
+    SYS_sethostname, SYS_setitimer, SYS_setns, SYS_setpgid, SYS_setpriority, SYS_setregid,
+    SYS_setresgid, SYS_setresuid, SYS_setreuid, SYS_setsid, SYS_setsockopt, SYS_settimeofday,
+    SYS_setuid, SYS_setxattr, SYS_shmat, SYS_shmctl, SYS_shmdt, SYS_shmget, SYS_shutdown,
+    SYS_sigaltstack, SYS_signalfd4, SYS_socket, SYS_socketpair, SYS_splice, SYS_statfs, SYS_statx,
+    SYS_swapoff, SYS_swapon, SYS_symlinkat, SYS_sync, SYS_syncfs, SYS_sysinfo, SYS_syslog, SYS_tee,
+    SYS_tgkill, SYS_timer_create, SYS_timer_delete, SYS_timer_getoverrun, SYS_timer_gettime,
+    SYS_timer_settime, SYS_timerfd_create, SYS_timerfd_gettime, SYS_timerfd_settime, SYS_times,
+    SYS_tkill, SYS_truncate, SYS_umask, SYS_umount2, SYS_uname, SYS_unlinkat, SYS_unshare,
+    SYS_userfaultfd, SYS_utimensat, SYS_vhangup, SYS_vmsplice, SYS_wait4, SYS_waitid, SYS_write,
+    SYS_writev,
+};
+
+/// Syscalls missing from some of the libc bindings, numbered identically on every architecture.
+#[cfg(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "aarch64"
+))]
+static NUMBERED_SYSCALLS: &[(&str, u32)] = &[
+    ("SYS_landlock_create_ruleset", 444),
+    ("SYS_landlock_add_rule", 445),
+    ("SYS_landlock_restrict_self", 446),
+    ("SYS_memfd_secret", 447),
+    ("SYS_process_mrelease", 448),
+    ("SYS_futex_waitv", 449),
+    ("SYS_set_mempolicy_home_node", 450),
+    ("SYS_cachestat", 451),
+    ("SYS_fchmodat2", 452),
+];
+
+#[cfg(all(target_arch = "x86_64", target_pointer_width = "64"))]
+mod arch {
+    pub(super) const AUDIT_ARCH: u32 = 0xC000_003E;
+    /// Syscalls issued through the x32 ABI have this bit set and are always denied.
+    pub(super) const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+    /// Syscalls missing from some of the libc bindings for this architecture.
+    pub(super) static NUMBERED_SYSCALLS: &[(&str, u32)] = &[
+        ("SYS_create_module", 174),
+        ("SYS_get_kernel_syms", 177),
+        ("SYS_query_module", 178),
+        ("SYS_rseq", 334),
+    ];
+    /// Legacy syscalls only available on this architecture.
+    pub(super) static SYSCALLS: &[(&str, u32)] = syscalls! {
+        SYS__sysctl, SYS_access, SYS_afs_syscall, SYS_alarm, SYS_arch_prctl, SYS_chmod, SYS_chown,
+        SYS_creat, SYS_dup2, SYS_epoll_create, SYS_epoll_ctl_old, SYS_epoll_wait,
+        SYS_epoll_wait_old, SYS_eventfd, SYS_fadvise64, SYS_fork, SYS_futimesat,
+        SYS_get_thread_area, SYS_getdents, SYS_getpgrp, SYS_getpmsg, SYS_getrlimit,
+        SYS_inotify_init, SYS_ioperm, SYS_iopl, SYS_kexec_file_load, SYS_lchown, SYS_link,
+        SYS_lstat, SYS_mkdir, SYS_mknod, SYS_modify_ldt, SYS_open, SYS_pause, SYS_pipe, SYS_poll,
+        SYS_putpmsg, SYS_readlink, SYS_rename, SYS_renameat, SYS_rmdir, SYS_security, SYS_select,
+        SYS_sendfile, SYS_set_thread_area, SYS_setrlimit, SYS_signalfd, SYS_stat, SYS_symlink,
+        SYS_sync_file_range, SYS_sysfs, SYS_time, SYS_tuxcall, SYS_unlink, SYS_uselib, SYS_ustat,
+        SYS_utime, SYS_utimes, SYS_vfork, SYS_vserver,
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    pub(super) const AUDIT_ARCH: u32 = 0xC000_00B7;
+    pub(super) const X32_SYSCALL_BIT: Option<u32> = None;
+    /// Syscalls missing from some of the libc bindings for this architecture.
+    pub(super) static NUMBERED_SYSCALLS: &[(&str, u32)] = &[
+        ("SYS_renameat", 38),
+        ("SYS_sync_file_range", 84),
+        ("SYS_getrlimit", 163),
+        ("SYS_setrlimit", 164),
+        ("SYS_rseq", 293),
+    ];
+    pub(super) static SYSCALLS: &[(&str, u32)] = &[];
+}
+
+/// Look up the number of a syscall on the current architecture.
+#[cfg(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "aarch64"
+))]
+fn syscall_number(name: &str) -> Option<u32> {
+    SYSCALLS
+        .iter()
+        .chain(arch::SYSCALLS.iter())
+        .chain(NUMBERED_SYSCALLS.iter())
+        .chain(arch::NUMBERED_SYSCALLS.iter())
+        .find(|(sys, _)| sys.strip_prefix("SYS_") == Some(name))
+        .map(|(_, nr)| *nr)
+}
+
+fn stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// Compile the configured allowlist into a BPF program.
+///
+/// `execve` is always allowed, since the filter is installed before the target is executed.
+#[cfg(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "aarch64"
+))]
+pub(crate) fn build_filter(seccomp: &Seccomp) -> Result<Vec<libc::sock_filter>, String> {
+    let deny = match seccomp.action {
+        SeccompAction::Kill => libc::SECCOMP_RET_KILL_PROCESS,
+        SeccompAction::Errno(errno) => libc::SECCOMP_RET_ERRNO | errno as u32,
+    };
+    let mut allowed = vec![libc::SYS_execve as u32];
+    for name in seccomp.allow.iter() {
+        match syscall_number(name) {
+            Some(nr) => allowed.push(nr),
+            None => return Err(format!("unknown syscall {:?}", name)),
+        }
+    }
+    allowed.sort_unstable();
+    allowed.dedup();
+
+    let mut prog = vec![
+        stmt(BPF_LD_W_ABS, OFFSET_ARCH),
+        jump(BPF_JMP_JEQ_K, arch::AUDIT_ARCH, 1, 0),
+        stmt(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD_W_ABS, OFFSET_NR),
+    ];
+    if let Some(bit) = arch::X32_SYSCALL_BIT {
+        prog.push(jump(BPF_JMP_JGE_K, bit, 0, 1));
+        prog.push(stmt(BPF_RET_K, deny));
+    }
+    for nr in allowed {
+        prog.push(jump(BPF_JMP_JEQ_K, nr, 0, 1));
+        prog.push(stmt(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+    }
+    prog.push(stmt(BPF_RET_K, deny));
+    if prog.len() > u16::MAX as usize {
+        return Err("the syscall allowlist is too long".to_string());
+    }
+    Ok(prog)
+}
+
+#[cfg(not(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "aarch64"
+)))]
+pub(crate) fn build_filter(_seccomp: &Seccomp) -> Result<Vec<libc::sock_filter>, String> {
+    Err("seccomp filters are not supported on this architecture".to_string())
+}
+
+/// Install a compiled filter on the calling process.
+///
+/// Runs between `fork` and `exec`, and must be the last step before `exec`.
+pub(crate) fn install(prog: &[libc::sock_filter]) -> Result<(), &'static [u8]> {
+    let fprog = libc::sock_fprog {
+        len: prog.len() as u16,
+        filter: prog.as_ptr() as *mut _,
+    };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(b"Unable to set no_new_privs for the seccomp filter.\n");
+        }
+        if libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &fprog as *const libc::sock_fprog,
+        ) != 0
+        {
+            return Err(b"Unable to install the seccomp filter.\n");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(
+    test,
+    any(
+        all(target_arch = "x86_64", target_pointer_width = "64"),
+        target_arch = "aarch64"
+    )
+))]
+mod tests {
+    use super::*;
+
+    fn seccomp(allow: &[&str], action: SeccompAction) -> Seccomp {
+        Seccomp {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            action,
+        }
+    }
+
+    /// Run a program the way the kernel would for a syscall, returning the action taken.
+    fn run(prog: &[libc::sock_filter], arch: u32, nr: u32) -> u32 {
+        let mut pc = 0;
+        let mut acc = 0;
+        loop {
+            let ins = prog[pc];
+            pc += 1;
+            match ins.code {
+                BPF_LD_W_ABS if ins.k == OFFSET_ARCH => acc = arch,
+                BPF_LD_W_ABS if ins.k == OFFSET_NR => acc = nr,
+                BPF_JMP_JEQ_K if acc == ins.k => pc += ins.jt as usize,
+                BPF_JMP_JEQ_K => pc += ins.jf as usize,
+                BPF_JMP_JGE_K if acc >= ins.k => pc += ins.jt as usize,
+                BPF_JMP_JGE_K => pc += ins.jf as usize,
+                BPF_RET_K => return ins.k,
+                code => panic!("unexpected instruction {:#x} at {}", code, pc - 1),
+            }
+        }
+    }
+
+    #[test]
+    fn checks_the_architecture_first() {
+        let prog = build_filter(&seccomp(&["read"], SeccompAction::Errno(1))).unwrap();
+        assert_eq!(prog[0].code, BPF_LD_W_ABS);
+        assert_eq!(prog[0].k, OFFSET_ARCH);
+        assert_eq!((prog[1].code, prog[1].k), (BPF_JMP_JEQ_K, arch::AUDIT_ARCH));
+        assert_eq!(
+            (prog[2].code, prog[2].k),
+            (BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS)
+        );
+        let read = libc::SYS_read as u32;
+        assert_eq!(run(&prog, arch::AUDIT_ARCH, read), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(
+            run(&prog, 0x4000_0003, read),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+    }
+
+    #[test]
+    fn allows_every_listed_syscall() {
+        let names = ["read", "write", "openat", "exit_group", "rseq", "read"];
+        let prog = build_filter(&seccomp(&names, SeccompAction::Kill)).unwrap();
+        for name in names.iter().chain(["execve"].iter()) {
+            let nr = syscall_number(name).unwrap();
+            assert_eq!(
+                run(&prog, arch::AUDIT_ARCH, nr),
+                libc::SECCOMP_RET_ALLOW,
+                "{} was not allowed",
+                name
+            );
+        }
+        let allows = prog
+            .iter()
+            .filter(|ins| ins.code == BPF_RET_K && ins.k == libc::SECCOMP_RET_ALLOW)
+            .count();
+        assert_eq!(allows, names.len());
+    }
+
+    #[test]
+    fn applies_the_default_action() {
+        let getpid = libc::SYS_getpid as u32;
+        let prog = build_filter(&seccomp(&["read"], SeccompAction::Kill)).unwrap();
+        assert_eq!(
+            run(&prog, arch::AUDIT_ARCH, getpid),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+        let prog = build_filter(&seccomp(&["read"], SeccompAction::Errno(13))).unwrap();
+        assert_eq!(
+            run(&prog, arch::AUDIT_ARCH, getpid),
+            libc::SECCOMP_RET_ERRNO | 13
+        );
+        let last = prog.last().unwrap();
+        assert_eq!(
+            (last.code, last.k),
+            (BPF_RET_K, libc::SECCOMP_RET_ERRNO | 13)
+        );
+        if let Some(bit) = arch::X32_SYSCALL_BIT {
+            let read = libc::SYS_read as u32;
+            assert_eq!(
+                run(&prog, arch::AUDIT_ARCH, bit | read),
+                libc::SECCOMP_RET_ERRNO | 13
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_syscalls() {
+        for name in ["reed", "SYS_read", "", "READ"] {
+            assert_eq!(
+                build_filter(&seccomp(&["read", name], SeccompAction::Kill)).err(),
+                Some(format!("unknown syscall {:?}", name))
+            );
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Starting the target with `posix_spawn` instead of `fork` and `exec`, which neither copies the
+//! page tables of a wrapper with a large resident set nor runs any code of the wrapper in the
+//! child.
+//!
+//! `posix_spawn` can not run `pre_exec` hooks, so this is only used when none of them would
+//! change anything, see `nix::spawnable`.
+
+use std::{
+    ffi::{CString, OsStr},
+    os::unix::ffi::OsStrExt,
+    process::Command,
+};
+
+/// The file actions and attributes of a spawn, destroyed again when dropped.
+struct Spawn {
+    actions: libc::posix_spawn_file_actions_t,
+    attr: libc::posix_spawnattr_t,
+}
+
+impl Spawn {
+    fn new() -> Result<Box<Spawn>, std::io::Error> {
+        // boxed, as the initialized structures may not be moved
+        let mut spawn = Box::new(Spawn {
+            actions: unsafe { std::mem::zeroed() },
+            attr: unsafe { std::mem::zeroed() },
+        });
+        check(unsafe { libc::posix_spawn_file_actions_init(&mut spawn.actions) })?;
+        if let Err(e) = check(unsafe { libc::posix_spawnattr_init(&mut spawn.attr) }) {
+            unsafe { libc::posix_spawn_file_actions_destroy(&mut spawn.actions) };
+            return Err(e);
+        }
+        Ok(spawn)
+    }
+}
+
+impl Drop for Spawn {
+    fn drop(&mut self) {
+        unsafe {
+            libc::posix_spawnattr_destroy(&mut self.attr);
+            libc::posix_spawn_file_actions_destroy(&mut self.actions);
+        }
+    }
+}
+
+/// `posix_spawn` and its helpers return the error instead of setting `errno`.
+fn check(r: libc::c_int) -> Result<(), std::io::Error> {
+    match r {
+        0 => Ok(()),
+        e => Err(std::io::Error::from_raw_os_error(e)),
+    }
+}
+
+fn c_string(s: &OsStr) -> Result<CString, std::io::Error> {
+    CString::new(s.as_bytes()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+/// Start the program of `command` with its arguments and environment, which must have been
+/// cleared, in the working directory of the wrapper.
+///
+/// The child gets `mask` as its signal mask and `SIGPIPE` back at its default, as `Command`
+/// does. It leads a new process group if `own_group`, and has `/dev/null` as its standard
+/// input and output if `null_stdio`.
+pub(crate) fn spawn(
+    command: &Command,
+    arg0: &OsStr,
+    mask: &libc::sigset_t,
+    own_group: bool,
+    null_stdio: bool,
+) -> Result<libc::pid_t, std::io::Error> {
+    let program = c_string(command.get_program())?;
+    let mut args = vec![c_string(arg0)?];
+    for arg in command.get_args() {
+        args.push(c_string(arg)?);
+    }
+    let mut env = Vec::new();
+    for (name, value) in command.get_envs() {
+        if let Some(value) = value {
+            let mut pair = name.as_bytes().to_vec();
+            pair.push(b'=');
+            pair.extend_from_slice(value.as_bytes());
+            env.push(c_string(OsStr::from_bytes(&pair))?);
+        }
+    }
+    let argv = args
+        .iter()
+        .map(|a| a.as_ptr() as *mut libc::c_char)
+        .chain(Some(std::ptr::null_mut()))
+        .collect::<Vec<_>>();
+    let envp = env
+        .iter()
+        .map(|e| e.as_ptr() as *mut libc::c_char)
+        .chain(Some(std::ptr::null_mut()))
+        .collect::<Vec<_>>();
+
+    let mut spawn = Spawn::new()?;
+    let mut flags = libc::POSIX_SPAWN_SETSIGMASK | libc::POSIX_SPAWN_SETSIGDEF;
+    if own_group {
+        flags |= libc::POSIX_SPAWN_SETPGROUP;
+    }
+    unsafe {
+        let mut default: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut default);
+        libc::sigaddset(&mut default, libc::SIGPIPE);
+        check(libc::posix_spawnattr_setsigdefault(
+            &mut spawn.attr,
+            &default,
+        ))?;
+        check(libc::posix_spawnattr_setsigmask(&mut spawn.attr, mask))?;
+        if own_group {
+            check(libc::posix_spawnattr_setpgroup(&mut spawn.attr, 0))?;
+        }
+        check(libc::posix_spawnattr_setflags(
+            &mut spawn.attr,
+            flags as libc::c_short,
+        ))?;
+        if null_stdio {
+            for fd in 0..3 {
+                check(libc::posix_spawn_file_actions_addopen(
+                    &mut spawn.actions,
+                    fd,
+                    b"/dev/null\0".as_ptr().cast(),
+                    libc::O_RDWR,
+                    0,
+                ))?;
+            }
+        }
+        let mut pid = 0;
+        check(libc::posix_spawn(
+            &mut pid,
+            program.as_ptr(),
+            &spawn.actions,
+            &spawn.attr,
+            argv.as_ptr(),
+            envp.as_ptr(),
+        ))?;
+        Ok(pid)
+    }
+}
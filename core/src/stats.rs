@@ -0,0 +1,153 @@
+//! Counters of the runs of a target, kept in a state file next to it so that abuse, or a
+//! wrapper no one runs any more, stands out.
+
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{Error, ErrorKind, Read, Write},
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::output::Json;
+
+/// What the state file of a target holds, as lines of a name and a number.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Stats {
+    /// Invocations that got as far as the configuration of the target, dry runs aside.
+    pub(crate) runs: u64,
+    /// Those of the runs that were rejected.
+    pub(crate) failures: u64,
+    /// When the last run was, in seconds since the epoch.
+    pub(crate) last_run: Option<u64>,
+    pub(crate) last_invoker: Option<u32>,
+}
+
+impl Stats {
+    fn parse(content: &str) -> Stats {
+        let mut stats = Stats::default();
+        for line in content.lines() {
+            let (name, value) = match line.split_once(' ') {
+                Some((name, value)) => (name, value.trim()),
+                None => continue,
+            };
+            match name {
+                "runs" => stats.runs = value.parse().unwrap_or_default(),
+                "failures" => stats.failures = value.parse().unwrap_or_default(),
+                "last_run" => stats.last_run = value.parse().ok(),
+                "last_invoker" => stats.last_invoker = value.parse().ok(),
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    fn format(&self) -> String {
+        let mut out = format!("runs {}\nfailures {}\n", self.runs, self.failures);
+        if let Some(time) = self.last_run {
+            out.push_str(&format!("last_run {}\n", time));
+        }
+        if let Some(uid) = self.last_invoker {
+            out.push_str(&format!("last_invoker {}\n", uid));
+        }
+        out
+    }
+
+    /// Read the statistics of a target, `None` if none are kept.
+    pub(crate) fn read(path: &Path) -> Result<Option<Stats>, Error> {
+        let mut content = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut content)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        Ok(Some(Stats::parse(&content)))
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        Json::object([
+            ("runs", Json::from(self.runs)),
+            ("failures", Json::from(self.failures)),
+            ("last_run", Json::from(self.last_run)),
+            ("last_invoker", Json::from(self.last_invoker)),
+        ])
+    }
+
+    /// The statistics as lines for a person, with the last run relative to now.
+    pub(crate) fn describe(&self) -> String {
+        let mut out = format!("runs: {}\nfailures: {}\n", self.runs, self.failures);
+        match self.last_run {
+            Some(time) => out.push_str(&format!(
+                "last run: {} seconds ago\n",
+                now().saturating_sub(time)
+            )),
+            None => out.push_str("last run: never\n"),
+        }
+        if let Some(uid) = self.last_invoker {
+            out.push_str(&format!("last invoker: uid {}\n", uid));
+        }
+        out
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Count a run by `uid`, as a failure unless `ok`. Only done when the wrapper runs as root, so
+/// that the file is owned by root.
+pub(crate) fn count(path: &Path, uid: u32, ok: bool) -> Result<(), Error> {
+    if crate::sys::geteuid() != 0 {
+        return Ok(());
+    }
+    let mut file = lock(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let mut stats = Stats::parse(&content);
+    stats.runs += 1;
+    if !ok {
+        stats.failures += 1;
+    }
+    stats.last_run = Some(now());
+    stats.last_invoker = Some(uid);
+
+    // replaced as a whole, so that the file is never seen half written
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let mut out = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(&temp)?;
+    out.write_all(stats.format().as_bytes())?;
+    out.sync_all()?;
+    std::fs::rename(&temp, path)
+}
+
+/// Open and lock the state file, again if it was replaced while waiting for the lock, as the
+/// lock only holds for the file it was taken on.
+fn lock(path: &Path) -> Result<File, Error> {
+    loop {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+            .open(path)?;
+        crate::sys::flock(&file, false)?;
+        let locked = file.metadata()?;
+        match std::fs::symlink_metadata(path) {
+            Ok(now) if now.dev() == locked.dev() && now.ino() == locked.ino() => return Ok(file),
+            Ok(_) => continue,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
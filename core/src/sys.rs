@@ -0,0 +1,236 @@
+//! Safe wrappers of the system calls the checks and the setup share, so that the only unsafe
+//! code outside of the platform backends is here.
+//!
+//! Every wrapper upholds the same invariants: pointers handed to the system are to live,
+//! properly sized Rust values for the duration of the call, descriptors are only borrowed from
+//! an owner that outlives the call, and nothing returned by the system is kept beyond it.
+//! Functions documented as async-signal-safe neither allocate nor lock, so that they may run
+//! between `fork` and `exec`.
+
+use std::{fs::File, os::unix::io::AsRawFd};
+
+/// The real user id of the wrapper, which is the invoker's.
+pub(crate) fn getuid() -> u32 {
+    // SAFETY: always succeeds and touches no memory
+    unsafe { libc::getuid() }
+}
+
+/// The effective user id of the wrapper, which is its owner's when setuid.
+pub(crate) fn geteuid() -> u32 {
+    // SAFETY: always succeeds and touches no memory
+    unsafe { libc::geteuid() }
+}
+
+pub(crate) fn getgid() -> u32 {
+    // SAFETY: always succeeds and touches no memory
+    unsafe { libc::getgid() }
+}
+
+pub(crate) fn getegid() -> u32 {
+    // SAFETY: always succeeds and touches no memory
+    unsafe { libc::getegid() }
+}
+
+/// The supplementary groups of the wrapper.
+pub(crate) fn getgroups() -> Vec<u32> {
+    // SAFETY: the buffer is sized by the first call, and only its filled part is kept
+    let n = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if n <= 0 {
+        return Vec::new();
+    }
+    let mut groups = vec![0; n as usize];
+    let n = unsafe { libc::getgroups(n, groups.as_mut_ptr()) };
+    groups.truncate(n.max(0) as usize);
+    groups
+}
+
+/// The session of the wrapper, `-1` if it can not be found.
+pub(crate) fn getsid() -> i32 {
+    // SAFETY: touches no memory
+    unsafe { libc::getsid(0) }
+}
+
+/// Give up the effective and saved ids, keeping only the invoker's own, and check that none of
+/// the wrapper's are left.
+#[cfg(not(any(target_os = "macos", target_os = "illumos")))]
+pub(crate) fn drop_to_invoker() -> bool {
+    let (uid, gid) = (getuid(), getgid());
+    let (mut r, mut e, mut s) = (0, 0, 0);
+    // SAFETY: the ids read back are live values, and failing leaves the ids as they were
+    unsafe {
+        libc::setresgid(gid, gid, gid) == 0
+            && libc::setresuid(uid, uid, uid) == 0
+            && libc::getresgid(&mut r, &mut e, &mut s) == 0
+            && (r, e, s) == (gid, gid, gid)
+            && libc::getresuid(&mut r, &mut e, &mut s) == 0
+            && (r, e, s) == (uid, uid, uid)
+    }
+}
+
+/// macOS and illumos have no `setresuid`, but setting the real id with `setreuid` sets the
+/// saved id as well. It can not be read back, so only failing to take the owner's id back
+/// shows it is gone.
+#[cfg(any(target_os = "macos", target_os = "illumos"))]
+pub(crate) fn drop_to_invoker() -> bool {
+    let (uid, gid) = (getuid(), getgid());
+    let (euid, egid) = (geteuid(), getegid());
+    // SAFETY: touches no memory, and failing leaves the ids as they were
+    unsafe {
+        crate::nix::setregid(gid, gid) == 0
+            && crate::nix::setreuid(uid, uid) == 0
+            && (getgid(), getegid(), getuid(), geteuid()) == (gid, gid, uid, uid)
+            && (egid == gid || libc::setegid(egid) != 0)
+            && (euid == uid || libc::seteuid(euid) != 0)
+    }
+}
+
+/// Run `f` with the effective ids of the invoker, so that whatever it opens is checked against
+/// the invoker's own permissions, and take the effective ids of the wrapper back afterwards.
+pub(crate) fn as_invoker<T>(f: impl FnOnce() -> T) -> Result<T, std::io::Error> {
+    let (euid, egid) = (geteuid(), getegid());
+    // SAFETY: touches no memory, and the saved ids let the wrapper take its ids back
+    unsafe {
+        if libc::setegid(libc::getgid()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if libc::seteuid(libc::getuid()) != 0 {
+            let err = std::io::Error::last_os_error();
+            libc::setegid(egid);
+            return Err(err);
+        }
+    }
+    let result = f();
+    // SAFETY: as above
+    if unsafe { libc::seteuid(euid) != 0 || libc::setegid(egid) != 0 } {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+/// Lock a file exclusively, `false` if `nonblocking` and it is held by someone else.
+pub(crate) fn flock(file: &File, nonblocking: bool) -> Result<bool, std::io::Error> {
+    let op = match nonblocking {
+        true => libc::LOCK_EX | libc::LOCK_NB,
+        false => libc::LOCK_EX,
+    };
+    // SAFETY: the descriptor is borrowed from `file`, which outlives the call
+    if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) if nonblocking => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Change the owner of an opened file, which clears its setuid bit.
+pub(crate) fn fchown(file: &File, uid: u32, gid: u32) -> Result<(), std::io::Error> {
+    // SAFETY: the descriptor is borrowed from `file`, which outlives the call
+    if unsafe { libc::fchown(file.as_raw_fd(), uid, gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub(crate) fn fchmod(file: &File, mode: u32) -> Result<(), std::io::Error> {
+    // SAFETY: the descriptor is borrowed from `file`, which outlives the call
+    if unsafe { libc::fchmod(file.as_raw_fd(), mode as libc::mode_t) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Keep every page of the wrapper, present and future, in memory, so that no secret it reads
+/// is ever written to swap. The locks are neither inherited by `fork` nor kept across `exec`.
+pub(crate) fn lock_memory() -> Result<(), std::io::Error> {
+    // SAFETY: touches no memory
+    match unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Lower the soft limit of the core dumps of the wrapper to nothing, returning the limits it
+/// had. The hard limit is kept, so that the target may be given the soft one back.
+pub(crate) fn disable_core_dumps() -> Result<libc::rlimit, std::io::Error> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: the limits are a live value, filled by the first call and read by the second
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let none = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: limit.rlim_max,
+        };
+        if libc::setrlimit(libc::RLIMIT_CORE, &none) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(limit)
+}
+
+/// Whether a descriptor inherited from the invoker is open.
+pub(crate) fn is_open(fd: i32) -> bool {
+    // SAFETY: only queries the flags, an unknown descriptor fails with `EBADF`
+    unsafe { libc::fcntl(fd, libc::F_GETFD) >= 0 }
+}
+
+/// Keep an inherited descriptor open across `exec`.
+pub(crate) fn clear_cloexec(fd: i32) {
+    // SAFETY: only changes the flags, an unknown descriptor fails with `EBADF`
+    unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+}
+
+/// Duplicate a descriptor of `file` to the lowest number at or above `min`, which is left open
+/// for the rest of the process, or `-1` if that failed.
+pub(crate) fn dup_above(file: &File, min: i32) -> i32 {
+    // SAFETY: the descriptor is borrowed from `file`, which outlives the call
+    unsafe { libc::fcntl(file.as_raw_fd(), libc::F_DUPFD, min) }
+}
+
+/// Write all of `bytes` in a single call, which is async-signal-safe.
+pub(crate) fn write_once(fd: i32, bytes: &[u8]) -> bool {
+    // SAFETY: the buffer is a live slice of exactly the given length
+    let n = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+    n == bytes.len() as isize
+}
+
+#[cfg(all(feature = "shadow", target_os = "linux"))]
+#[link(name = "crypt")]
+extern "C" {
+    fn crypt(key: *const libc::c_char, setting: *const libc::c_char) -> *mut libc::c_char;
+}
+
+/// The password hash of a user in the shadow file.
+#[cfg(all(feature = "shadow", target_os = "linux"))]
+pub(crate) fn shadow_hash(user: &std::ffi::CStr) -> Option<std::ffi::CString> {
+    // SAFETY: the entry points to static storage of libc, copied out before returning
+    unsafe {
+        let entry = libc::getspnam(user.as_ptr());
+        if entry.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr((*entry).sp_pwdp).to_owned())
+    }
+}
+
+/// Hash a password with the setting of an existing hash.
+#[cfg(all(feature = "shadow", target_os = "linux"))]
+pub(crate) fn crypt_with(
+    password: &std::ffi::CStr,
+    setting: &std::ffi::CStr,
+) -> Option<std::ffi::CString> {
+    // SAFETY: the result points to static storage of libcrypt, copied out before returning
+    unsafe {
+        let computed = crypt(password.as_ptr(), setting.as_ptr());
+        if computed.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr(computed).to_owned())
+    }
+}
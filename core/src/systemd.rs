@@ -0,0 +1,104 @@
+//! Transient systemd scopes the wrapper moves itself into before the target is run, so that
+//! the target is accounted, listed, and cleaned up like a service, as `systemd-run --scope`
+//! does.
+
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use crate::dbus::{Bus, Writer};
+
+const SYSTEMD: (&str, &str) = ("org.freedesktop.systemd1", "/org/freedesktop/systemd1");
+const MANAGER: &str = "org.freedesktop.systemd1.Manager";
+
+/// Whether the system was booted with systemd as its service manager, as `sd_booted` checks.
+pub(crate) fn booted() -> bool {
+    Path::new("/run/systemd/system").is_dir()
+}
+
+/// Name of the scope of a run of `target` by `pid`, with what a unit name may not hold
+/// replaced.
+pub(crate) fn scope_name(target: &str, pid: u32) -> String {
+    let target = target
+        .chars()
+        .take(200)
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | ':' | '_' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    format!("run-suid-{}-{}.scope", target, pid)
+}
+
+/// Start the scope `name` holding the process `pid`, and wait until it was moved into it.
+pub(crate) fn start_scope(name: &str, description: &str, pid: u32) -> Result<(), Error> {
+    let mut bus = Bus::system()?;
+    // subscribed first, so that the job can not be missed
+    let mut rule = Writer(Vec::new());
+    rule.string(&format!(
+        "type='signal',sender='{}',path='{}',interface='{}',member='JobRemoved'",
+        SYSTEMD.0, SYSTEMD.1, MANAGER
+    ));
+    bus.call(
+        ("org.freedesktop.DBus", "/org/freedesktop/DBus"),
+        ("org.freedesktop.DBus", "AddMatch"),
+        "s",
+        &rule.0,
+    )?;
+
+    // ssa(sv)a(sa(sv)): the unit, the job mode, its properties, and no auxiliary units
+    let mut body = Writer(Vec::new());
+    body.string(name);
+    body.string("fail");
+    let properties = body.array(8);
+    body.align(8);
+    body.string("Description");
+    body.signature("s");
+    body.string(description);
+    body.align(8);
+    body.string("PIDs");
+    body.signature("au");
+    let pids = body.array(4);
+    body.u32(pid);
+    body.end_array(pids);
+    body.end_array(properties);
+    let aux = body.array(8);
+    body.end_array(aux);
+    let reply = bus.call(
+        SYSTEMD,
+        (MANAGER, "StartTransientUnit"),
+        "ssa(sv)a(sa(sv))",
+        &body.0,
+    )?;
+    let job = match reply.body().string() {
+        Some(job) => job.to_owned(),
+        None => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the reply of systemd names no job",
+            ))
+        }
+    };
+
+    // uoss: the id, the path, and the unit of the job, and how it ended
+    loop {
+        let signal = bus.signal(MANAGER, "JobRemoved")?;
+        let mut removed = signal.body();
+        removed.u32();
+        if removed.string() != Some(&job) {
+            continue;
+        }
+        removed.string();
+        return match removed.string() {
+            Some("done") => Ok(()),
+            result => Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "the job starting it ended as {:?}",
+                    result.unwrap_or_default()
+                ),
+            )),
+        };
+    }
+}
@@ -0,0 +1,551 @@
+//! A small parser for the subset of TOML used by the per-target configuration files.
+//!
+//! Supported: comments, `[table]` and `[dotted.table]` headers, bare/quoted/dotted keys,
+//! basic and literal strings, integers (decimal, `0x`, `0o`, `0b`, with `_` separators),
+//! booleans, arrays, and inline tables. Floats, dates, multi-line strings, and arrays of
+//! tables are intentionally not supported.
+
+use std::{collections::BTreeMap, fmt};
+
+pub(crate) type Table = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    /// Short name of the value type, used in error messages.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ParseError {
+    pub(crate) line: usize,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a document into its root table.
+pub(crate) fn parse(input: &str) -> Result<Table, ParseError> {
+    let mut p = Parser {
+        src: input.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table::new();
+    let mut current: Vec<String> = Vec::new();
+    // headers seen so far, as a table may only be given one
+    let mut headers: Vec<Vec<String>> = Vec::new();
+    loop {
+        p.skip_ws_comments_newlines();
+        match p.peek() {
+            None => break,
+            Some(b'[') => {
+                p.pos += 1;
+                if p.peek() == Some(b'[') {
+                    return Err(p.error("arrays of tables are not supported"));
+                }
+                p.skip_ws();
+                let path = p.parse_key_path()?;
+                p.skip_ws();
+                p.expect(b']')?;
+                p.end_of_line()?;
+                let line = p.line;
+                if headers.contains(&path) {
+                    return Err(ParseError {
+                        line,
+                        message: format!("duplicate table {:?}", path.join(".")),
+                    });
+                }
+                table_at(&mut root, &path, true).map_err(|m| ParseError { line, message: m })?;
+                headers.push(path.clone());
+                current = path;
+            }
+            Some(_) => {
+                let line = p.line;
+                let path = p.parse_key_path()?;
+                p.skip_ws();
+                p.expect(b'=')?;
+                p.skip_ws();
+                let value = p.parse_value()?;
+                p.end_of_line()?;
+                let mut full = current.clone();
+                full.extend(path);
+                insert(&mut root, &full, value).map_err(|m| ParseError { line, message: m })?;
+            }
+        }
+    }
+    Ok(root)
+}
+
+fn table_at<'a>(
+    root: &'a mut Table,
+    path: &[String],
+    header: bool,
+) -> Result<&'a mut Table, String> {
+    let mut t = root;
+    for (i, k) in path.iter().enumerate() {
+        let entry = t
+            .entry(k.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        t = match entry {
+            Value::Table(t) => t,
+            _ if header && i + 1 == path.len() => {
+                return Err(format!("duplicate key {:?}", path.join(".")))
+            }
+            _ => return Err(format!("key {:?} is not a table", path[..=i].join("."))),
+        };
+    }
+    Ok(t)
+}
+
+fn insert(root: &mut Table, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = path.split_last().expect("key paths are never empty");
+    let t = table_at(root, parents, false)?;
+    if t.contains_key(last) {
+        return Err(format!("duplicate key {:?}", path.join(".")));
+    }
+    t.insert(last.clone(), value);
+    Ok(())
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(x) if x == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(x) => Err(self.error(format!("expected {:?}, found {:?}", c as char, x as char))),
+            None => Err(self.error(format!("expected {:?}, found end of input", c as char))),
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some(b'#') {
+            while !matches!(self.peek(), None | Some(b'\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn skip_ws_comments_newlines(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            match self.peek() {
+                Some(b'\n') => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                Some(b'\r') if self.src.get(self.pos + 1) == Some(&b'\n') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_ws();
+        self.skip_comment();
+        match self.peek() {
+            None | Some(b'\n') => Ok(()),
+            Some(b'\r') if self.src.get(self.pos + 1) == Some(&b'\n') => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected {:?} after value", c as char))),
+        }
+    }
+
+    fn parse_key_path(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = vec![self.parse_key()?];
+        loop {
+            self.skip_ws();
+            if self.peek() != Some(b'.') {
+                return Ok(path);
+            }
+            self.pos += 1;
+            self.skip_ws();
+            path.push(self.parse_key()?);
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(b'"') => self.parse_basic_string(),
+            Some(b'\'') => self.parse_literal_string(),
+            _ => {
+                let start = self.pos;
+                while let Some(b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-') = self.peek()
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(String::from_utf8_lossy(&self.src[start..self.pos]).into_owned())
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some(b'"') => self.parse_basic_string().map(Value::String),
+            Some(b'\'') => self.parse_literal_string().map(Value::String),
+            Some(b'[') => self.parse_array(),
+            Some(b'{') => self.parse_inline_table(),
+            Some(b't' | b'f') => self.parse_bool(),
+            Some(b'0'..=b'9' | b'+' | b'-') => self.parse_integer(),
+            Some(c) => Err(self.error(format!("unexpected {:?} at start of value", c as char))),
+            None => Err(self.error("expected a value, found end of input")),
+        }
+    }
+
+    fn parse_bool(&mut self) -> Result<Value, ParseError> {
+        let rest = &self.src[self.pos..];
+        if rest.starts_with(b"true") {
+            self.pos += 4;
+            Ok(Value::Boolean(true))
+        } else if rest.starts_with(b"false") {
+            self.pos += 5;
+            Ok(Value::Boolean(false))
+        } else {
+            Err(self.error("expected a boolean"))
+        }
+    }
+
+    fn parse_integer(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while let Some(b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'+' | b'-') = self.peek() {
+            self.pos += 1;
+        }
+        let raw = String::from_utf8_lossy(&self.src[start..self.pos]);
+        let (neg, digits) = match raw.as_bytes().first() {
+            Some(b'-') => (true, &raw[1..]),
+            Some(b'+') => (false, &raw[1..]),
+            _ => (false, &raw[..]),
+        };
+        let (radix, digits) = match digits.get(..2) {
+            Some("0x") => (16, &digits[2..]),
+            Some("0o") => (8, &digits[2..]),
+            Some("0b") => (2, &digits[2..]),
+            _ => (10, digits),
+        };
+        let clean = digits.replace('_', "");
+        if clean.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+            return Err(self.error(format!("invalid integer {:?}", raw)));
+        }
+        let n = i64::from_str_radix(&clean, radix)
+            .map_err(|_| self.error(format!("invalid integer {:?}", raw)))?;
+        Ok(Value::Integer(if neg { -n } else { n }))
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'\'')?;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'\'') => break,
+                None | Some(b'\n') => return Err(self.error("unterminated string")),
+                _ => self.pos += 1,
+            }
+        }
+        let s = std::str::from_utf8(&self.src[start..self.pos])
+            .map_err(|_| self.error("invalid UTF-8 in string"))?
+            .to_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                None | Some(b'\n') => return Err(self.error("unterminated string")),
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'u') => {
+                            let hex = self
+                                .src
+                                .get(self.pos + 1..self.pos + 5)
+                                .and_then(|h| std::str::from_utf8(h).ok())
+                                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?;
+                            self.pos += 4;
+                            hex
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    self.pos += 1;
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.pos += 1;
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws_comments_newlines();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_ws_comments_newlines();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {}
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, ParseError> {
+        self.expect(b'{')?;
+        let mut table = Table::new();
+        self.skip_ws();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_ws();
+            let path = self.parse_key_path()?;
+            self.skip_ws();
+            self.expect(b'=')?;
+            self.skip_ws();
+            let value = self.parse_value()?;
+            insert(&mut table, &path, value).map_err(|m| self.error(m))?;
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Table(table));
+                }
+                _ => return Err(self.error("expected ',' or '}' in inline table")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    fn error(input: &str) -> ParseError {
+        parse(input).expect_err("the document was accepted")
+    }
+
+    #[test]
+    fn parses_scalars_and_comments() {
+        let table = parse(
+            "# a comment\r\n\
+             name = \"tool\" # trailing\n\
+             \n\
+             count = 1_000\n\
+             neg = -12\n\
+             hex = 0xff\n\
+             oct = +0o17\n\
+             bin = 0b101\n\
+             on = true\n\
+             off = false\n",
+        )
+        .unwrap();
+        assert_eq!(table["name"], string("tool"));
+        assert_eq!(table["count"], Value::Integer(1000));
+        assert_eq!(table["neg"], Value::Integer(-12));
+        assert_eq!(table["hex"], Value::Integer(255));
+        assert_eq!(table["oct"], Value::Integer(15));
+        assert_eq!(table["bin"], Value::Integer(5));
+        assert_eq!(table["on"], Value::Boolean(true));
+        assert_eq!(table["off"], Value::Boolean(false));
+    }
+
+    #[test]
+    fn parses_strings_and_escapes() {
+        let table = parse(concat!(
+            r#"basic = "a \"quoted\" \\ tab\tnew\nline\r \u00e9""#,
+            "\n",
+            r#"literal = 'C:\no\escapes "here"'"#,
+            "\n",
+            r#""quoted key" = 'x'"#,
+            "\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            table["basic"],
+            string("a \"quoted\" \\ tab\tnew\nline\r \u{e9}")
+        );
+        assert_eq!(table["literal"], string(r#"C:\no\escapes "here""#));
+        assert_eq!(table["quoted key"], string("x"));
+    }
+
+    #[test]
+    fn parses_arrays() {
+        let table =
+            parse("a = []\nb = [1, \"two\", [true]]\nc = [\n  'x', # one\n  'y',\n]\n").unwrap();
+        assert_eq!(table["a"], Value::Array(vec![]));
+        assert_eq!(
+            table["b"],
+            Value::Array(vec![
+                Value::Integer(1),
+                string("two"),
+                Value::Array(vec![Value::Boolean(true)]),
+            ])
+        );
+        assert_eq!(table["c"], Value::Array(vec![string("x"), string("y")]));
+    }
+
+    #[test]
+    fn parses_nested_tables() {
+        let table = parse(
+            "top = 1\n\
+             [args]\n\
+             deny = ['x']\n\
+             [user.alice.args]\n\
+             max_count = 2\n\
+             [rlimits]\n\
+             nofile = 64\n\
+             core.soft = 0\n\
+             inline = { a = 1, b.c = 'd', e = {} }\n",
+        )
+        .unwrap();
+        let table_of = |v: &Value| match v {
+            Value::Table(t) => t.clone(),
+            v => panic!("not a table: {:?}", v),
+        };
+        assert_eq!(table["top"], Value::Integer(1));
+        assert_eq!(
+            table_of(&table["args"])["deny"],
+            Value::Array(vec![string("x")])
+        );
+        let alice = table_of(&table_of(&table["user"])["alice"]);
+        assert_eq!(table_of(&alice["args"])["max_count"], Value::Integer(2));
+        let rlimits = table_of(&table["rlimits"]);
+        assert_eq!(rlimits["nofile"], Value::Integer(64));
+        assert_eq!(table_of(&rlimits["core"])["soft"], Value::Integer(0));
+        let inline = table_of(&rlimits["inline"]);
+        assert_eq!(inline["a"], Value::Integer(1));
+        assert_eq!(table_of(&inline["b"])["c"], string("d"));
+        assert_eq!(inline["e"], Value::Table(Table::new()));
+    }
+
+    #[test]
+    fn rejects_syntax_errors() {
+        for input in [
+            "key",
+            "key =",
+            "= 1",
+            "key = 1 2",
+            "key = \"open",
+            "key = 'open",
+            "key = \"a\nb\"",
+            "key = \"\\x\"",
+            "key = \"\\u12\"",
+            "key = \"\\ud800\"",
+            "key = [1, 2",
+            "key = [1 2]",
+            "key = { a = 1",
+            "key = { a = 1 b = 2 }",
+            "key = truthy",
+            "key = 1.5",
+            "key = 0x",
+            "key = 1__",
+            "key = _1",
+            "key = 99999999999999999999",
+            "key = @",
+            "[table",
+            "[table] x = 1",
+            "[[tables]]",
+            "[]",
+            "a.",
+        ] {
+            assert!(parse(input).is_err(), "{:?} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        assert!(error("a = 1\na = 2\n").message.contains("duplicate key"));
+        assert!(error("[t]\na = 1\n[t]\n")
+            .message
+            .contains("duplicate table"));
+        assert!(parse("[t.u]\n[t]\n").is_ok());
+        assert!(error("a = 1\n[a]\n").message.contains("duplicate key"));
+        assert!(error("a = 1\na.b = 2\n").message.contains("not a table"));
+        assert!(error("a = { b = 1, b = 2 }")
+            .message
+            .contains("duplicate key"));
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let err = error("a = 1\n\n# comment\nb = \"open\n");
+        assert_eq!(err.line, 4);
+        assert_eq!(err.to_string(), "line 4: unterminated string");
+        assert_eq!(error("a = 1\r\nb = 2 3\r\n").line, 2);
+    }
+}
@@ -0,0 +1,105 @@
+//! Accounting of the terminal sessions of the target in utmp, wtmp and lastlog, so that `who`
+//! and `last` show them the way they show logins.
+
+use std::{
+    fs::OpenOptions,
+    io::{Error, Seek, SeekFrom, Write},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const WTMP: &[u8] = b"/var/log/wtmp\0";
+const LASTLOG: &str = "/var/log/lastlog";
+/// Size of `struct lastlog`: the time in 32 bits, the line and the host.
+const LASTLOG_SIZE: u64 = 4 + 32 + 256;
+
+extern "C" {
+    /// Not declared by `libc`, but part of glibc.
+    fn updwtmpx(file: *const libc::c_char, ut: *const libc::utmpx);
+}
+
+/// A session recorded in utmp and wtmp, ended again with [Session::end].
+pub(crate) struct Session {
+    entry: libc::utmpx,
+}
+
+impl Session {
+    /// Record a session of `user` on the terminal `tty`, led by `pid`.
+    pub(crate) fn start(user: &str, uid: u32, tty: &Path, pid: i32) -> Result<Session, Error> {
+        let line = tty.as_os_str().as_bytes();
+        let line = line.strip_prefix(b"/dev/").unwrap_or(line);
+        let mut entry: libc::utmpx = unsafe { std::mem::zeroed() };
+        entry.ut_type = libc::USER_PROCESS;
+        entry.ut_pid = pid;
+        entry.ut_session = pid as _;
+        copy(&mut entry.ut_line, line);
+        // the end of the line, as sshd does, such as "ts/3" for "pts/3"
+        copy(&mut entry.ut_id, &line[line.len().saturating_sub(4)..]);
+        copy(&mut entry.ut_user, user.as_bytes());
+        let now = stamp(&mut entry);
+        let session = Session { entry };
+        session.write()?;
+        lastlog(uid, line, now);
+        Ok(session)
+    }
+
+    /// Record that the session ended, as `login` does by clearing the user.
+    pub(crate) fn end(mut self) {
+        self.entry.ut_type = libc::DEAD_PROCESS;
+        self.entry.ut_user = [0; libc::__UT_NAMESIZE];
+        stamp(&mut self.entry);
+        let _ = self.write();
+    }
+
+    /// Write the entry to utmp and append it to wtmp, which is not checked as `login` does not.
+    fn write(&self) -> Result<(), Error> {
+        let written = unsafe {
+            libc::setutxent();
+            let written = match libc::pututxline(&self.entry).is_null() {
+                true => Err(Error::last_os_error()),
+                false => Ok(()),
+            };
+            libc::endutxent();
+            written
+        };
+        unsafe { updwtmpx(WTMP.as_ptr().cast(), &self.entry) };
+        written
+    }
+}
+
+/// Copy as much of `from` as fits, the rest staying zeroed.
+fn copy(to: &mut [libc::c_char], from: &[u8]) {
+    for (to, from) in to.iter_mut().zip(from) {
+        *to = *from as libc::c_char;
+    }
+}
+
+/// Set the time of an entry to now, returning the seconds.
+fn stamp(entry: &mut libc::utmpx) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    entry.ut_tv.tv_sec = now.as_secs() as _;
+    entry.ut_tv.tv_usec = now.subsec_micros() as _;
+    now.as_secs()
+}
+
+/// Update the last login of `uid` in lastlog, if the system still keeps one. Failures are
+/// ignored, as `login` ignores them.
+fn lastlog(uid: u32, line: &[u8], now: u64) {
+    let mut file = match OpenOptions::new().write(true).open(LASTLOG) {
+        Ok(file) => file,
+        Err(_) => return,
+    };
+    let mut record = [0; LASTLOG_SIZE as usize];
+    record[..4].copy_from_slice(&(now as u32).to_ne_bytes());
+    let line = &line[..line.len().min(32)];
+    record[4..4 + line.len()].copy_from_slice(line);
+    if file
+        .seek(SeekFrom::Start(uid as u64 * LASTLOG_SIZE))
+        .is_ok()
+    {
+        let _ = file.write_all(&record);
+    }
+}
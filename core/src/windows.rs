@@ -0,0 +1,385 @@
+//! The reduced Windows mode of the wrapper, which runs the sibling `<name>.run-suid.exe` as an
+//! administrator: with the token of a wrapper that is already elevated, or through a "runas"
+//! prompt otherwise.
+//!
+//! Administrators and the system take the place of root. They must own the wrapper, its
+//! directory, and the target, and nobody else may be granted to change them, as anyone who
+//! could replace the target would have it run with the privileges of an administrator.
+//!
+//! Console control events reach a target on the same console by themselves, and a target
+//! started through "runas" gets a console of its own, so the wrapper only has to outlive the
+//! events to exit like the target, as the unix wrapper does by forwarding signals.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::File,
+    os::windows::{ffi::OsStrExt, fs::OpenOptionsExt, io::AsRawHandle},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+use winapi::{
+    shared::{
+        minwindef::{BOOL, DWORD, FALSE, TRUE},
+        winerror::{ERROR_CANCELLED, ERROR_SUCCESS},
+    },
+    um::{
+        accctrl::SE_FILE_OBJECT,
+        aclapi::GetSecurityInfo,
+        consoleapi::SetConsoleCtrlHandler,
+        handleapi::CloseHandle,
+        processthreadsapi::{GetCurrentProcess, GetExitCodeProcess, OpenProcessToken},
+        securitybaseapi::{GetAce, GetTokenInformation, IsWellKnownSid},
+        shellapi::{ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW},
+        synchapi::WaitForSingleObject,
+        winbase::{LocalFree, FILE_FLAG_BACKUP_SEMANTICS, INFINITE},
+        winnt::{
+            TokenElevation, WinBuiltinAdministratorsSid, WinLocalSystemSid, ACCESS_ALLOWED_ACE,
+            ACCESS_ALLOWED_ACE_TYPE, ACE_HEADER, ACL, DACL_SECURITY_INFORMATION, DELETE,
+            FILE_APPEND_DATA, FILE_DELETE_CHILD, FILE_WRITE_ATTRIBUTES, FILE_WRITE_DATA,
+            FILE_WRITE_EA, GENERIC_ALL, GENERIC_WRITE, HANDLE, INHERIT_ONLY_ACE,
+            OWNER_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, PSID, TOKEN_ELEVATION, TOKEN_QUERY,
+            WRITE_DAC, WRITE_OWNER,
+        },
+        winuser::SW_SHOWNORMAL,
+    },
+};
+
+use crate::{exit::*, output, Options, Rejection};
+
+/// Access that lets the grantee replace the file or the entries of a directory.
+const WRITE_ACCESS: DWORD = FILE_WRITE_DATA
+    | FILE_APPEND_DATA
+    | FILE_WRITE_EA
+    | FILE_WRITE_ATTRIBUTES
+    | FILE_DELETE_CHILD
+    | DELETE
+    | WRITE_DAC
+    | WRITE_OWNER
+    | GENERIC_WRITE
+    | GENERIC_ALL;
+
+/// Run the target next to the wrapper invoked as `name`, once every check has passed.
+pub fn run(name: &str, options: Result<Options, String>, args: Vec<String>) -> ExitCode {
+    let cli = match options {
+        Ok(cli) => cli,
+        Err(err) => return reject(Rejection::new(RET_GENERIC_ERROR, err)),
+    };
+    if cli.json {
+        output::use_json();
+    }
+    if cli.quiet {
+        output::use_quiet();
+    }
+    let verbose = cli.verbose && !cli.quiet;
+
+    let exe = match std::env::current_exe() {
+        Ok(path) => path,
+        Err(err) => {
+            return reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to find the name of the executable: {}", err),
+            ));
+        }
+    };
+    let parent = exe.parent().unwrap_or_else(|| Path::new("\\"));
+    let call_name = Path::new(name)
+        .file_stem()
+        .or_else(|| exe.file_stem())
+        .and_then(OsStr::to_str)
+        .unwrap_or_default();
+    let call_name = cli.target.as_deref().unwrap_or(call_name);
+    let target = parent.join(format!("{}.run-suid.exe", call_name));
+
+    let dir = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(parent);
+    let checks = [
+        (
+            File::open(&exe),
+            exe.as_path(),
+            RET_OWNER_EXEC,
+            RET_PERM_EXEC,
+        ),
+        (dir, parent, RET_OWNER_PARENT, RET_PERM_PARENT),
+    ];
+    for (file, path, owner_code, perm_code) in checks {
+        if let Err(rejection) = file
+            .and_then(|file| check_access(&file))
+            .map_err(|err| {
+                Rejection::new(
+                    RET_ENV_ERROR,
+                    format!("Unable to inspect the access to {:?}: {}", path, err),
+                )
+            })
+            .and_then(|access| access.check(path, owner_code, perm_code))
+        {
+            return reject(rejection);
+        }
+    }
+    let file = match File::open(&target) {
+        Ok(file) => file,
+        Err(err) => {
+            return reject(
+                Rejection::new(
+                    exec_failure(&err),
+                    format!("Unable to open the target {:?}: {}", target, err),
+                )
+                .path(&target),
+            );
+        }
+    };
+    match check_access(&file) {
+        Ok(access) => {
+            if let Err(rejection) = access.check(&target, RET_OWNER_TARGET, RET_PERM_TARGET) {
+                return reject(rejection);
+            }
+        }
+        Err(err) => {
+            return reject(Rejection::new(
+                RET_ENV_ERROR,
+                format!("Unable to inspect the access to {:?}: {}", target, err),
+            ));
+        }
+    }
+    std::mem::drop(file);
+
+    let elevated = is_elevated();
+    if cli.dry_run {
+        if !cli.quiet {
+            println!(
+                "Dry run: would have succeeded in starting the process: {:?} {:?}",
+                target, args
+            );
+            match elevated {
+                true => println!("Dry run: with the elevated token of the wrapper"),
+                false => println!("Dry run: through a runas prompt"),
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    unsafe { SetConsoleCtrlHandler(Some(outlive_ctrl), TRUE) };
+    let code = if elevated {
+        if verbose {
+            output::verbose(format_args!("running {:?} with the elevated token", target));
+        }
+        Command::new(&target).args(&args).status().map(|s| {
+            // the exit code of a process on Windows is 32 bits, exit codes here only 8
+            s.code().map_or(255, |c| u8::try_from(c).unwrap_or(255))
+        })
+    } else {
+        if verbose {
+            output::verbose(format_args!("running {:?} through runas", target));
+        }
+        run_as(&target, &args)
+    };
+    match code {
+        Ok(code) => ExitCode::from(code),
+        Err(err) if err.raw_os_error() == Some(ERROR_CANCELLED as i32) => reject(Rejection::new(
+            RET_NOT_CONFIRMED,
+            "The elevation of the target was declined.",
+        )),
+        Err(err) => reject(Rejection::new(
+            exec_failure(&err),
+            format!("Unable to execute command: {}", err),
+        )),
+    }
+}
+
+fn reject(rejection: Rejection) -> ExitCode {
+    let exit = own(rejection.code);
+    rejection.print(exit);
+    ExitCode::from(exit)
+}
+
+/// Who owns a file and whether anyone else may change it.
+struct Access {
+    trusted_owner: bool,
+    /// Whether someone other than an administrator or the system is granted to change it.
+    untrusted_writer: bool,
+}
+
+impl Access {
+    fn check(&self, path: &Path, owner_code: u8, perm_code: u8) -> Result<(), Rejection> {
+        if !self.trusted_owner {
+            return Err(Rejection::new(
+                owner_code,
+                format!(
+                    "The owner of {:?} must be an administrator or the system.",
+                    path
+                ),
+            )
+            .path(path));
+        }
+        if self.untrusted_writer {
+            return Err(Rejection::new(
+                perm_code,
+                format!(
+                    "Only administrators and the system may be granted to change {:?}.",
+                    path
+                ),
+            )
+            .path(path));
+        }
+        Ok(())
+    }
+}
+
+fn is_trusted(sid: PSID) -> bool {
+    unsafe {
+        IsWellKnownSid(sid, WinBuiltinAdministratorsSid) != FALSE
+            || IsWellKnownSid(sid, WinLocalSystemSid) != FALSE
+    }
+}
+
+/// Inspect the owner and the access control list of an opened file or directory.
+fn check_access(file: &File) -> Result<Access, std::io::Error> {
+    let mut owner: PSID = std::ptr::null_mut();
+    let mut dacl: *mut ACL = std::ptr::null_mut();
+    let mut descriptor: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+    let r = unsafe {
+        GetSecurityInfo(
+            file.as_raw_handle() as HANDLE,
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION,
+            &mut owner,
+            std::ptr::null_mut(),
+            &mut dacl,
+            std::ptr::null_mut(),
+            &mut descriptor,
+        )
+    };
+    if r != ERROR_SUCCESS {
+        return Err(std::io::Error::from_raw_os_error(r as i32));
+    }
+    let trusted_owner = !owner.is_null() && is_trusted(owner);
+    // without a list everyone is granted everything
+    let mut untrusted_writer = dacl.is_null();
+    if !dacl.is_null() {
+        for i in 0..unsafe { (*dacl).AceCount } {
+            let mut ace = std::ptr::null_mut();
+            if unsafe { GetAce(dacl, i as DWORD, &mut ace) } == FALSE {
+                continue;
+            }
+            let header = unsafe { &*(ace as *const ACE_HEADER) };
+            // an inherited-only entry applies to the children only, which are checked themselves
+            if header.AceType != ACCESS_ALLOWED_ACE_TYPE || header.AceFlags & INHERIT_ONLY_ACE != 0
+            {
+                continue;
+            }
+            let allowed = unsafe { &*(ace as *const ACCESS_ALLOWED_ACE) };
+            let sid = &allowed.SidStart as *const DWORD as PSID;
+            if allowed.Mask & WRITE_ACCESS != 0 && !is_trusted(sid) {
+                untrusted_writer = true;
+            }
+        }
+    }
+    unsafe { LocalFree(descriptor.cast()) };
+    Ok(Access {
+        trusted_owner,
+        untrusted_writer,
+    })
+}
+
+/// Whether the wrapper runs with the full token of an administrator.
+fn is_elevated() -> bool {
+    let mut token: HANDLE = std::ptr::null_mut();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == FALSE {
+        return false;
+    }
+    let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+    let mut len = 0;
+    let r = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            (&mut elevation as *mut TOKEN_ELEVATION).cast(),
+            std::mem::size_of::<TOKEN_ELEVATION>() as DWORD,
+            &mut len,
+        )
+    };
+    unsafe { CloseHandle(token) };
+    r != FALSE && elevation.TokenIsElevated != 0
+}
+
+/// Keeps the wrapper waiting for the target through every console control event.
+unsafe extern "system" fn outlive_ctrl(_event: DWORD) -> BOOL {
+    TRUE
+}
+
+fn wide(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(Some(0)).collect()
+}
+
+/// Quote an argument the way the C runtime of the target splits its command line.
+fn quote(arg: &str, line: &mut String) {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"']) {
+        line.push_str(arg);
+        return;
+    }
+    line.push('"');
+    // backslashes are only special right before a quote
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escaped = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        line.extend(std::iter::repeat('\\').take(escaped));
+        line.push(c);
+        backslashes = 0;
+    }
+    line.extend(std::iter::repeat('\\').take(backslashes * 2));
+    line.push('"');
+}
+
+/// Start the target through the elevation prompt and wait for its exit code.
+fn run_as(target: &Path, args: &[String]) -> Result<u8, std::io::Error> {
+    let mut parameters = String::new();
+    for arg in args {
+        if !parameters.is_empty() {
+            parameters.push(' ');
+        }
+        quote(arg, &mut parameters);
+    }
+    let verb = wide(OsStr::new("runas"));
+    let file = wide(target.as_os_str());
+    let parameters = wide(OsStr::new(&parameters));
+    let cwd = std::env::current_dir().map_or_else(|_| OsString::new(), PathBuf::into_os_string);
+    let cwd = wide(&cwd);
+    let mut info: SHELLEXECUTEINFOW = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as DWORD;
+    info.fMask = SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC;
+    info.lpVerb = verb.as_ptr();
+    info.lpFile = file.as_ptr();
+    info.lpParameters = parameters.as_ptr();
+    info.lpDirectory = cwd.as_ptr();
+    info.nShow = SW_SHOWNORMAL;
+    if unsafe { ShellExecuteExW(&mut info) } == FALSE {
+        return Err(std::io::Error::last_os_error());
+    }
+    if info.hProcess.is_null() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "the target was started without a process to wait for",
+        ));
+    }
+    let mut code: DWORD = 0;
+    let waited = unsafe {
+        WaitForSingleObject(info.hProcess, INFINITE);
+        GetExitCodeProcess(info.hProcess, &mut code)
+    };
+    let err = std::io::Error::last_os_error();
+    unsafe { CloseHandle(info.hProcess) };
+    if waited == FALSE {
+        return Err(err);
+    }
+    Ok(u8::try_from(code).unwrap_or(255))
+}
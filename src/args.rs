@@ -0,0 +1,569 @@
+//! Command line options of the wrapper itself, everything after `--` is passed to the target.
+
+use run_suid_core::{config, Options};
+
+/// Options given to the wrapper.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Args {
+    /// `-h` only prints the usage, `--help` also the description and version.
+    pub(crate) help: Option<Help>,
+    pub(crate) version: bool,
+    /// Print what an exit code means instead of running the target.
+    pub(crate) explain_exit_code: Option<u8>,
+    /// Everything else, handed to the library.
+    pub(crate) options: Options,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Help {
+    Short,
+    Long,
+}
+
+/// The name of an option, its short form, and whether it takes a value.
+pub(crate) struct Spec {
+    pub(crate) long: &'static str,
+    pub(crate) short: Option<char>,
+    pub(crate) value: bool,
+    /// A flag that may also be given an inline value, as in `--OPTION=VALUE`.
+    pub(crate) optional: bool,
+}
+
+const fn flag(long: &'static str, short: Option<char>) -> Spec {
+    Spec {
+        long,
+        short,
+        value: false,
+        optional: false,
+    }
+}
+
+const fn valued(long: &'static str) -> Spec {
+    Spec {
+        long,
+        short: None,
+        value: true,
+        optional: false,
+    }
+}
+
+const fn optional(long: &'static str) -> Spec {
+    Spec {
+        long,
+        short: None,
+        value: false,
+        optional: true,
+    }
+}
+
+pub(crate) static SPECS: &[Spec] = &[
+    flag("help", Some('h')),
+    flag("version", None),
+    flag("verbose", Some('v')),
+    flag("quiet", Some('q')),
+    flag("dry-run", None),
+    flag("show-stats", None),
+    flag("no-init-groups", None),
+    flag("require-landlock", None),
+    flag("exec", None),
+    flag("proxy", None),
+    flag("private-tmp", None),
+    flag("scope", None),
+    flag("allow-new-privs", None),
+    flag("pty", None),
+    flag("confirm", None),
+    flag("daemon", None),
+    flag("stats", None),
+    optional("no-network"),
+    optional("wait-lock"),
+    valued("output"),
+    valued("error-format"),
+    valued("target"),
+    valued("arg0"),
+    valued("caps"),
+    valued("preserve-env"),
+    valued("env"),
+    valued("env-file"),
+    valued("pass-fd"),
+    valued("stdout-file"),
+    valued("stderr-file"),
+    flag("append", None),
+    valued("record"),
+    valued("pidfile"),
+    valued("kill-after"),
+    valued("timeout"),
+    valued("umask"),
+    valued("nice"),
+    valued("cpus"),
+    valued("oom-score-adj"),
+    valued("explain-exit-code"),
+];
+
+/// Split the arguments at the first `--`, into the wrapper's options and the target's arguments.
+pub(crate) fn split<I: IntoIterator<Item = String>>(args: I) -> (Vec<String>, Vec<String>) {
+    let mut options = Vec::new();
+    let mut args = args.into_iter();
+    for arg in args.by_ref() {
+        if arg == "--" {
+            break;
+        }
+        options.push(arg);
+    }
+    (options, args.collect())
+}
+
+/// Parse the options in front of `--`.
+pub(crate) fn parse(options: &[String]) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut iter = options.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let spec = match SPECS.iter().find(|s| s.long == name) {
+                Some(spec) => spec,
+                None => return Err(unknown(&format!("--{}", name))),
+            };
+            let value = match (spec.value, inline) {
+                (false, Some(value)) if spec.optional => Some(value),
+                (false, Some(_)) => return Err(format!("The option --{} takes no value.", name)),
+                (false, None) => None,
+                (true, Some(value)) => Some(value),
+                (true, None) => match iter.next() {
+                    Some(value) => Some(value.as_str()),
+                    None => return Err(format!("The option --{} needs a value.", name)),
+                },
+            };
+            apply(&mut parsed, spec, value)?;
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // clustered short flags such as `-vq`, the last one may take the next argument
+            for (i, c) in shorts.char_indices() {
+                let spec = match SPECS.iter().find(|s| s.short == Some(c)) {
+                    Some(spec) => spec,
+                    None => return Err(unknown(&format!("-{}", c))),
+                };
+                if c == 'h' {
+                    parsed.help.get_or_insert(Help::Short);
+                    continue;
+                }
+                if !spec.value {
+                    apply(&mut parsed, spec, None)?;
+                    continue;
+                }
+                let rest = &shorts[i + c.len_utf8()..];
+                let value = if !rest.is_empty() {
+                    rest
+                } else {
+                    match iter.next() {
+                        Some(value) => value.as_str(),
+                        None => return Err(format!("The option -{} needs a value.", c)),
+                    }
+                };
+                apply(&mut parsed, spec, Some(value))?;
+                break;
+            }
+        } else {
+            return Err(format!(
+                "Unexpected argument: {:?}, arguments of the target must follow --",
+                arg
+            ));
+        }
+    }
+    if parsed.options.exec && parsed.options.proxy {
+        return Err("The options --exec and --proxy are mutually exclusive.".to_string());
+    }
+    Ok(parsed)
+}
+
+fn apply(parsed: &mut Args, spec: &Spec, value: Option<&str>) -> Result<(), String> {
+    let value = value.unwrap_or_default();
+    match spec.long {
+        "help" => parsed.help = Some(Help::Long),
+        "version" => parsed.version = true,
+        "verbose" => parsed.options.verbose = true,
+        "quiet" => parsed.options.quiet = true,
+        "dry-run" => parsed.options.dry_run = true,
+        "show-stats" => parsed.options.show_stats = true,
+        "no-init-groups" => parsed.options.no_init_groups = true,
+        "require-landlock" => parsed.options.require_landlock = true,
+        "exec" => parsed.options.exec = true,
+        "proxy" => parsed.options.proxy = true,
+        "private-tmp" => parsed.options.private_tmp = true,
+        "scope" => parsed.options.scope = true,
+        "append" => parsed.options.append = true,
+        "allow-new-privs" => parsed.options.allow_new_privs = true,
+        "pty" => parsed.options.pty = true,
+        "confirm" => parsed.options.confirm = true,
+        "daemon" => parsed.options.daemon = true,
+        "stats" => parsed.options.stats = true,
+        "wait-lock" if value.is_empty() => parsed.options.wait_lock = Some(None),
+        "wait-lock" => match config::parse_seconds(value) {
+            Some(secs) => parsed.options.wait_lock = Some(Some(secs)),
+            None => {
+                return Err(format!(
+                    "Expected a positive number of seconds for --wait-lock: {:?}",
+                    value
+                ))
+            }
+        },
+        "no-network" => match value {
+            "" => parsed.options.no_network = Some(true),
+            "try" => parsed.options.no_network = Some(false),
+            _ => {
+                return Err(format!(
+                    "The only value of --no-network is try: {:?}",
+                    value
+                ))
+            }
+        },
+        "error-format" => match value {
+            "text" => parsed.options.json_errors = false,
+            "json" => parsed.options.json_errors = true,
+            _ => {
+                return Err(format!(
+                    "The error format must be text or json: {:?}",
+                    value
+                ))
+            }
+        },
+        "output" => match value {
+            "text" => parsed.options.json = false,
+            "json" => parsed.options.json = true,
+            _ => {
+                return Err(format!(
+                    "The output format must be text or json: {:?}",
+                    value
+                ))
+            }
+        },
+        "target" => {
+            if value.is_empty() || value == "." || value == ".." || value.contains(['/', '\0']) {
+                return Err(format!("The target must be a plain file name: {:?}", value));
+            }
+            parsed.options.target = Some(value.to_owned());
+        }
+        "arg0" => {
+            if !config::is_arg0(value) {
+                return Err(format!(
+                    "The name given by --arg0 must not be empty: {:?}",
+                    value
+                ));
+            }
+            parsed.options.arg0 = Some(value.to_owned());
+        }
+        "caps" => parsed.options.caps.get_or_insert_with(Vec::new).extend(
+            value
+                .split(',')
+                .filter(|n| !n.is_empty())
+                .map(str::to_owned),
+        ),
+        "preserve-env" => {
+            for name in value.split(',').filter(|n| !n.is_empty()) {
+                if !config::is_env_name(name) || config::is_env_denied(name) {
+                    return Err(format!(
+                        "Environment variable can not be preserved: {:?}",
+                        name
+                    ));
+                }
+                parsed.options.preserve_env.push(name.to_owned());
+            }
+        }
+        "env" => {
+            let (name, value) = match value.split_once('=') {
+                Some(pair) => pair,
+                None => return Err(format!("Expected NAME=VALUE for --env: {:?}", value)),
+            };
+            if !config::is_env_name(name) || config::is_env_denied(name) {
+                return Err(format!("Environment variable can not be set: {:?}", name));
+            }
+            parsed.options.env.push((name.to_owned(), value.to_owned()));
+        }
+        "env-file" => {
+            if !value.starts_with('/') {
+                return Err(format!(
+                    "The environment file must be an absolute path: {:?}",
+                    value
+                ));
+            }
+            parsed.options.env_file = Some(value.into());
+        }
+        "stdout-file" | "stderr-file" | "record" => {
+            if value.is_empty() {
+                return Err(format!("The option --{} needs a file name.", spec.long));
+            }
+            match spec.long {
+                "stdout-file" => parsed.options.stdout_file = Some(value.into()),
+                "stderr-file" => parsed.options.stderr_file = Some(value.into()),
+                _ => parsed.options.record = Some(value.into()),
+            }
+        }
+        "pidfile" => {
+            if !value.starts_with('/') || value.ends_with('/') {
+                return Err(format!(
+                    "The pid file must be an absolute path to a file: {:?}",
+                    value
+                ));
+            }
+            parsed.options.pidfile = Some(value.into());
+        }
+        "pass-fd" => {
+            let (from, to) = value.split_once(':').unwrap_or((value, value));
+            match (from.parse::<i32>(), to.parse::<i32>()) {
+                (Ok(from), Ok(to)) if from >= 0 && to > 2 => {
+                    parsed.options.pass_fds.push((from, to))
+                }
+                _ => {
+                    return Err(format!(
+                        "Expected N or N:M, with M above 2, for --pass-fd: {:?}",
+                        value
+                    ))
+                }
+            }
+        }
+        "kill-after" | "timeout" => {
+            let secs = match config::parse_seconds(value) {
+                Some(secs) => secs,
+                None => {
+                    return Err(format!(
+                        "Expected a positive number of seconds for --{}: {:?}",
+                        spec.long, value
+                    ))
+                }
+            };
+            if spec.long == "timeout" {
+                parsed.options.timeout = Some(secs);
+            } else {
+                parsed.options.kill_after = Some(secs);
+            }
+        }
+        "umask" => match config::parse_umask(value) {
+            Some(mask) => parsed.options.umask = Some(mask),
+            None => {
+                return Err(format!(
+                    "Expected an octal mask such as 027 for --umask: {:?}",
+                    value
+                ))
+            }
+        },
+        // only root may raise the priority, so the invoker can only lower it
+        "nice" => match value.parse::<i32>() {
+            Ok(n) if (0..=19).contains(&n) => parsed.options.nice = Some(n),
+            _ => {
+                return Err(format!(
+                    "Expected a niceness from 0 to 19 for --nice: {:?}",
+                    value
+                ))
+            }
+        },
+        "cpus" => match config::parse_cpus(value) {
+            Some(cpus) => parsed.options.cpus = Some(cpus),
+            None => {
+                return Err(format!(
+                    "Expected a list of CPUs such as 0-3,6 for --cpus: {:?}",
+                    value
+                ))
+            }
+        },
+        // lowering the score needs privileges, so the invoker can only make the target more killable
+        "oom-score-adj" => match value.parse::<i32>() {
+            Ok(n) if (0..=1000).contains(&n) => parsed.options.oom_score_adj = Some(n),
+            _ => {
+                return Err(format!(
+                    "Expected an adjustment from 0 to 1000 for --oom-score-adj: {:?}",
+                    value
+                ))
+            }
+        },
+        "explain-exit-code" => match value.parse::<u8>() {
+            Ok(code) => parsed.explain_exit_code = Some(code),
+            Err(_) => {
+                return Err(format!(
+                    "Expected an exit code from 0 to 255 for --explain-exit-code: {:?}",
+                    value
+                ))
+            }
+        },
+        _ => return Err(format!("The option --{} is not handled.", spec.long)),
+    }
+    Ok(())
+}
+
+/// Error for an unknown option, suggesting the closest known one.
+fn unknown(option: &str) -> String {
+    let name = option.trim_start_matches('-');
+    let closest = SPECS
+        .iter()
+        .map(|s| (distance(name, s.long), s.long))
+        .filter(|(d, long)| *d <= 2 || long.starts_with(name))
+        .min();
+    match closest {
+        Some((_, long)) if !name.is_empty() => {
+            format!("Unknown option: {}, did you mean --{}?", option, long)
+        }
+        _ => format!("Unknown option: {}, see --help", option),
+    }
+}
+
+/// Levenshtein distance between two names.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1)
+                .min(row[j] + 1)
+                .min(diag + (ca != *cb) as usize);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn parsed(args: &[&str]) -> Args {
+        parse(&strings(args)).unwrap()
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(&strings(args)) {
+            Ok(parsed) => panic!("{:?} was accepted as {:?}", args, parsed),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn takes_values_inline_or_separate() {
+        for args in [&["--target=tool"][..], &["--target", "tool"]] {
+            assert_eq!(parsed(args).options.target.as_deref(), Some("tool"));
+        }
+        let args = parsed(&["--env", "A=b=c", "--env=TZ=UTC", "--nice=5"]);
+        assert_eq!(
+            args.options.env,
+            [("A".into(), "b=c".into()), ("TZ".into(), "UTC".into())]
+        );
+        assert_eq!(args.options.nice, Some(5));
+        // a value that looks like an option is still taken as the value
+        assert_eq!(
+            parsed(&["--arg0", "--verbose"]).options.arg0.as_deref(),
+            Some("--verbose")
+        );
+        assert!(!parsed(&["--arg0", "--verbose"]).options.verbose);
+    }
+
+    #[test]
+    fn takes_optional_values_only_inline() {
+        assert_eq!(parsed(&["--no-network"]).options.no_network, Some(true));
+        assert_eq!(
+            parsed(&["--no-network=try"]).options.no_network,
+            Some(false)
+        );
+        assert_eq!(parsed(&["--wait-lock"]).options.wait_lock, Some(None));
+        assert!(error(&["--no-network=yes"]).contains("only value of --no-network"));
+        assert!(error(&["--wait-lock", "5"]).starts_with("Unexpected argument: \"5\""));
+    }
+
+    #[test]
+    fn clusters_short_flags() {
+        let args = parsed(&["-vq"]);
+        assert!(args.options.verbose && args.options.quiet);
+        assert_eq!(parsed(&["-vh"]).help, Some(Help::Short));
+        assert_eq!(parsed(&["-h", "--help"]).help, Some(Help::Long));
+        assert_eq!(parsed(&["--help", "-h"]).help, Some(Help::Long));
+        assert_eq!(error(&["-vx"]), "Unknown option: -x, see --help");
+    }
+
+    #[test]
+    fn requires_values() {
+        assert_eq!(error(&["--target"]), "The option --target needs a value.");
+        assert_eq!(
+            error(&["--verbose", "--env"]),
+            "The option --env needs a value."
+        );
+        assert_eq!(
+            error(&["--verbose=1"]),
+            "The option --verbose takes no value."
+        );
+        assert!(error(&["--env", "NOVALUE"]).starts_with("Expected NAME=VALUE"));
+        assert!(error(&["--stdout-file="]).contains("needs a file name"));
+        assert!(error(&["--nice", "-1"]).starts_with("Expected a niceness"));
+        assert!(error(&["--target", "../x"]).starts_with("The target must be"));
+        assert!(error(&["--env", "LD_PRELOAD=x"]).starts_with("Environment variable can not"));
+        assert!(error(&["--pidfile", "run/x.pid"]).starts_with("The pid file must be"));
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert_eq!(
+            error(&["--verbos"]),
+            "Unknown option: --verbos, did you mean --verbose?"
+        );
+        assert_eq!(
+            error(&["--dry-rn"]),
+            "Unknown option: --dry-rn, did you mean --dry-run?"
+        );
+        assert_eq!(
+            error(&["--frobnicate"]),
+            "Unknown option: --frobnicate, see --help"
+        );
+        assert_eq!(error(&["--=x"]), "Unknown option: --, see --help");
+        assert!(error(&["target-arg"]).starts_with("Unexpected argument"));
+        assert!(error(&["-"]).starts_with("Unexpected argument"));
+    }
+
+    #[test]
+    fn rejects_exec_with_proxy() {
+        assert!(parsed(&["--exec"]).options.exec);
+        assert!(parsed(&["--proxy"]).options.proxy);
+        assert_eq!(
+            error(&["--exec", "--proxy"]),
+            "The options --exec and --proxy are mutually exclusive."
+        );
+    }
+
+    #[test]
+    fn splits_at_the_first_double_dash() {
+        let (options, args) = split(strings(&["-v", "--", "-x", "--", "y"]));
+        assert_eq!(options, ["-v"]);
+        assert_eq!(args, ["-x", "--", "y"]);
+
+        let (options, args) = split(strings(&["--dry-run"]));
+        assert_eq!(options, ["--dry-run"]);
+        assert!(args.is_empty());
+
+        let (options, args) = split(strings(&["--", "--help"]));
+        assert!(options.is_empty());
+        assert_eq!(args, ["--help"]);
+        assert_eq!(parse(&options).unwrap(), Args::default());
+    }
+
+    #[test]
+    fn handles_every_option() {
+        for spec in SPECS {
+            let value = match spec.long {
+                "error-format" | "output" => "json",
+                "env" => "TZ=UTC",
+                "preserve-env" => "TZ",
+                "env-file" | "pidfile" => "/run/x",
+                "pass-fd" => "3",
+                "umask" => "027",
+                _ => "1",
+            };
+            let mut args = Args::default();
+            let value = Some(value).filter(|_| spec.value);
+            assert_eq!(apply(&mut args, spec, value), Ok(()), "--{}", spec.long);
+            assert_ne!(args, Args::default(), "--{}", spec.long);
+        }
+    }
+}
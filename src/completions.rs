@@ -0,0 +1,215 @@
+//! Completion scripts of the options of the wrapper for bash, zsh, and fish.
+//!
+//! Values of `--target` are completed with the sibling targets next to the wrapper, when the
+//! invoker may list its directory.
+
+use std::fmt::Write;
+
+use crate::args::{Spec, SPECS};
+
+/// What the value of an option is completed with.
+enum Value {
+    None,
+    File,
+    Target,
+    Choices(&'static str),
+}
+
+fn value(spec: &Spec) -> Value {
+    match spec.long {
+        "target" => Value::Target,
+        "output" | "error-format" => Value::Choices("text json"),
+        "no-network" => Value::Choices("try"),
+        "env-file" | "stdout-file" | "stderr-file" | "record" | "pidfile" => Value::File,
+        _ => Value::None,
+    }
+}
+
+/// The completion script for `shell` of the wrapper invoked as `name`.
+pub(crate) fn script(shell: &str, name: &str) -> Result<String, String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._+-".contains(c))
+    {
+        return Err(format!(
+            "Unable to complete a command with this name: {:?}",
+            name
+        ));
+    }
+    // the name of the completion functions, unique to the wrapper
+    let func: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match shell {
+        "bash" => Ok(bash(name, &func)),
+        "zsh" => Ok(zsh(name, &func)),
+        "fish" => Ok(fish(name, &func)),
+        _ => Err(format!(
+            "Completions are only generated for bash, zsh, and fish: {:?}",
+            shell
+        )),
+    }
+}
+
+const BASH: &str = r#"_run_suid_FUNC_targets() {
+    local exe dir f target
+    exe=$(command -v -- NAME) || return
+    dir=$(dirname -- "$(readlink -f -- "$exe")")
+    for f in "$dir"/*.run-suid "$dir"/*.run-suid.*; do
+        [[ -e $f ]] || continue
+        target=${f##*/}
+        case $target in *.toml|*.allow|*.sig) continue ;; esac
+        printf '%s\n' "${target/.run-suid/}"
+    done
+}
+
+_run_suid_FUNC() {
+    local cur=${COMP_WORDS[COMP_CWORD]} prev=${COMP_WORDS[COMP_CWORD-1]} i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        [[ ${COMP_WORDS[i]} == -- ]] && return
+    done
+    if [[ $cur == --*=* ]]; then
+        prev=${cur%%=*}=
+        cur=${cur#*=}
+    fi
+"#;
+
+const ZSH: &str = r#"#compdef NAME
+
+_run_suid_FUNC_targets() {
+    local exe=${commands[NAME]} f
+    local -a targets
+    [[ -n $exe ]] || return 1
+    for f in ${exe:A:h}/*.run-suid(N) ${exe:A:h}/*.run-suid.*(N); do
+        f=${f:t}
+        [[ $f == *.(toml|allow|sig) ]] && continue
+        targets+=(${f/.run-suid/})
+    done
+    compadd -a targets
+}
+
+_run_suid_FUNC() {
+    _arguments -s -S \
+"#;
+
+const FISH: &str = r#"function __run_suid_FUNC_targets
+    set -l exe (command -s NAME); or return
+    set -l dir (dirname (realpath $exe))
+    for f in $dir/*.run-suid $dir/*.run-suid.*
+        set -l target (basename $f)
+        string match -qr '\.(toml|allow|sig)$' -- $target; and continue
+        string replace .run-suid '' -- $target
+    end
+end
+
+"#;
+
+/// One of the scripts above for a wrapper.
+fn template(script: &str, name: &str, func: &str) -> String {
+    script.replace("FUNC", func).replace("NAME", name)
+}
+
+fn bash(name: &str, func: &str) -> String {
+    let mut s = template(BASH, name, func);
+    let _ = writeln!(s, "    case $prev in");
+    for spec in SPECS {
+        let words = match value(spec) {
+            Value::None if !spec.value && !spec.optional => continue,
+            // nothing is offered for a value that can not be completed
+            Value::None => None,
+            Value::File => Some("-f".to_string()),
+            Value::Target => Some(format!("-W \"$(_run_suid_{}_targets)\"", func)),
+            Value::Choices(choices) => Some(format!("-W '{}'", choices)),
+        };
+        // a value of an optional flag can only be given inline
+        let _ = match spec.optional {
+            true => writeln!(s, "        --{}=)", spec.long),
+            false => writeln!(s, "        --{0}|--{0}=)", spec.long),
+        };
+        if let Some(words) = words {
+            let _ = writeln!(
+                s,
+                "            COMPREPLY=($(compgen {} -- \"$cur\"))",
+                words
+            );
+        }
+        let _ = writeln!(s, "            return ;;");
+    }
+    let _ = writeln!(s, "    esac");
+    let mut options = Vec::new();
+    for spec in SPECS {
+        options.push(format!("--{}", spec.long));
+        if let Some(short) = spec.short {
+            options.push(format!("-{}", short));
+        }
+    }
+    let _ = writeln!(
+        s,
+        "    COMPREPLY=($(compgen -W '{} --' -- \"$cur\"))",
+        options.join(" ")
+    );
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s, "complete -o default -F _run_suid_{} {}", func, name);
+    s
+}
+
+fn zsh(name: &str, func: &str) -> String {
+    let mut s = template(ZSH, name, func);
+    for spec in SPECS {
+        let action = match value(spec) {
+            Value::None if spec.value || spec.optional => ":value: ".to_string(),
+            Value::None => String::new(),
+            Value::File => ":file:_files".to_string(),
+            Value::Target => format!(":target:_run_suid_{}_targets", func),
+            Value::Choices(choices) => format!(":value:({})", choices),
+        };
+        let long = match (spec.value, spec.optional) {
+            (true, _) => format!("{}={}", spec.long, action),
+            (false, true) => format!("{}=-:{}", spec.long, action),
+            (false, false) => spec.long.to_string(),
+        };
+        let _ = match spec.short {
+            Some(short) => writeln!(
+                s,
+                "        '(-{0} --{1})'{{-{0},--{2}}} \\",
+                short, spec.long, long
+            ),
+            None => writeln!(s, "        '--{}' \\", long),
+        };
+    }
+    let _ = writeln!(s, "        '*::argument of the target:_files'");
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "compdef _run_suid_{} {}", func, name);
+    s
+}
+
+fn fish(name: &str, func: &str) -> String {
+    let mut s = template(FISH, name, func);
+    // everything after `--` is passed to the target
+    let cond = "-n 'not contains -- -- (commandline -opc)'";
+    for spec in SPECS {
+        let mut line = format!("complete -c {} {} -l {}", name, cond, spec.long);
+        if let Some(short) = spec.short {
+            let _ = write!(line, " -s {}", short);
+        }
+        match value(spec) {
+            Value::None if spec.value => line.push_str(" -x"),
+            Value::None => {}
+            Value::File => line.push_str(" -r -F"),
+            Value::Target => {
+                let _ = write!(line, " -x -a '(__run_suid_{}_targets)'", func);
+            }
+            Value::Choices(choices) if spec.optional => {
+                let _ = write!(line, " -a '{}'", choices);
+            }
+            Value::Choices(choices) => {
+                let _ = write!(line, " -x -a '{}'", choices);
+            }
+        }
+        let _ = writeln!(s, "{}", line);
+    }
+    s
+}
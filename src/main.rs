@@ -1,79 +1,188 @@
-use std::{
-    collections::VecDeque,
-    ffi::OsStr,
-    io::ErrorKind,
-    process::{Command, ExitCode, Stdio},
-};
+#![forbid(unsafe_code)]
 
-mod env;
-use env::EnvTrait;
-use smallvec::SmallVec;
-
-#[cfg(unix)]
-mod nix;
+use std::process::ExitCode;
 #[cfg(unix)]
-type Env = nix::Nix;
+use std::{ffi::OsStr, path::Path};
 
-#[cfg(not(unix))]
-compile_error!("Unsupported platform");
-
-const RET_GENERIC_ERROR: u8 = 32 | 1;
-const RET_ENV_ERROR: u8 = 32 | 2;
-const RET_NO_TARGET: u8 = 32 | 3;
-const RET_OWNER_EXEC: u8 = 32 | 8 | 0;
-const RET_PERM_EXEC: u8 = 32 | 8 | 1;
-const RET_OWNER_PARENT: u8 = 32 | 8 | 2;
-const RET_PERM_PARENT: u8 = 32 | 8 | 3;
-const RET_OWNER_TARGET: u8 = 32 | 6;
-const RET_PERM_TARGET: u8 = 32 | 6;
+#[cfg(unix)]
+use run_suid_core::{doctor, exit::RET_GENERIC_ERROR, install};
+use run_suid_core::{error, exit::print_explained};
 
-struct Opts {
-    verbose: bool,
-    dry_run: bool,
-    uid: u32,
-    gid: u32,
-}
+mod args;
+#[cfg(unix)]
+mod completions;
 
 fn main() -> ExitCode {
-    let mut args = std::env::args().collect::<VecDeque<_>>();
-    let fname = args.pop_front().unwrap_or_default();
-    let mut args_l = Vec::with_capacity(args.len());
-    while let Some(f) = args.pop_front() {
-        if f != "--" {
-            args_l.push(f);
-            continue;
-        }
-        break;
+    let mut args = std::env::args();
+    let fname = args.next().unwrap_or_default();
+    let (options, args) = args::split(args);
+    // the subcommands inspect and change the modes of unix files
+    #[cfg(unix)]
+    let subcommand = match options.first().map(String::as_str) {
+        Some(name @ ("doctor" | "install" | "completions")) => Some(name),
+        _ => None,
+    };
+    #[cfg(unix)]
+    if let Some(subcommand) = subcommand {
+        // only the privileges of the invoker are used, so that nothing is revealed or
+        // changed that the invoker could not have done without the wrapper
+        let done = if !run_suid_core::drop_to_invoker() {
+            eprintln!("Unable to drop the privileges of the wrapper.");
+            false
+        } else if subcommand == "install" {
+            install::run(&options[1..])
+        } else if subcommand == "completions" {
+            let call_name = Path::new(&fname).file_name().and_then(OsStr::to_str);
+            let shell = options.get(1).map(String::as_str).unwrap_or_default();
+            match completions::script(shell, call_name.unwrap_or_default()) {
+                Ok(script) if options.len() == 2 => {
+                    print!("{}", script);
+                    true
+                }
+                Ok(_) => {
+                    eprintln!("Usage: {} completions bash|zsh|fish", fname);
+                    false
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    false
+                }
+            }
+        } else {
+            let call_name = Path::new(&fname).file_name().and_then(OsStr::to_str);
+            let path = options.get(1).map(String::as_str);
+            options.len() <= 2 && doctor::run(path, call_name.unwrap_or_default())
+        };
+        return if done {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(RET_GENERIC_ERROR)
+        };
     }
-    let args_l = args_l
-        .iter()
-        .map(String::as_str)
-        .collect::<SmallVec<[_; 8]>>();
-    let args = args
-        .iter()
-        .map(String::as_str)
-        .collect::<SmallVec<[_; 8]>>();
-    let gid = unsafe { Env::getegid() };
-
-    if args_l.contains(&"--help") || args_l.contains(&"-h") {
+    let parsed = args::parse(&options);
+
+    // the description and version are only chatter when quiet
+    let help = parsed.as_ref().ok().and_then(|cli| match cli.help {
+        Some(_) if cli.options.quiet => Some(args::Help::Short),
+        help => help,
+    });
+    if help.is_some() {
         println!("Usage: {} [OPTIONS] [-- EXE_ARGS..]", fname);
         println!("  OPTIONS: ");
         println!("    -h    --help          Display this help text.");
         println!("    -v    --verbose       Display verbose runtime information.");
+        println!("    -q    --quiet         Only print a diagnostic when failing.");
         println!("          --version       Display version information.");
         println!("          --dry-run       Don't actually run the target executable,");
         println!("                          only check that it would have run.");
+        println!("          --show-stats    Print how often the target was run and failed, and");
+        println!("                          when and by whom it was last run.");
+        println!("          --output=FORMAT Print the dry run and verbose information as text");
+        println!("                          (default) or as json.");
+        println!("          --error-format=FORMAT");
+        println!("                          Print why the target was not run as text (default)");
+        println!("                          or as a json line.");
+        println!("          --exec          Replace this process with the target (default).");
+        println!("          --proxy         Keep this process as a parent that forwards signals");
+        println!("                          to the target and waits for it to exit.");
+        println!("          --kill-after=SECONDS");
+        println!("                          Kill the target if it is still running this long");
+        println!("                          after SIGTERM was forwarded to it, implies --proxy.");
+        println!("          --timeout=SECONDS");
+        println!("                          Signal the target if it is still running after");
+        println!("                          this long, implies --proxy.");
+        println!("          --no-init-groups");
+        println!("                          Don't give the target the supplementary groups");
+        println!("                          of its owner.");
+        println!("          --target=NAME   Run the sibling target NAME.run-suid instead of the");
+        println!("                          one named after the executable.");
+        println!("          --arg0=NAME     Give the target NAME as its argv[0], if the");
+        println!("                          configuration allows it.");
+        println!("          --caps=NAMES    Drop every capability except the comma separated");
+        println!("                          ones, such as cap_net_bind_service.");
+        println!("          --preserve-env=NAMES");
+        println!("                          Copy the comma separated environment variables");
+        println!("                          from the caller's environment.");
+        println!("          --env=NAME=VALUE");
+        println!("                          Set an environment variable of the target, if the");
+        println!("                          configuration allows it.");
+        println!("          --env-file=PATH");
+        println!("                          Set the NAME=VALUE lines of a file owned by root as");
+        println!("                          environment variables, if the configuration allows.");
+        println!("          --umask=MODE    Create files of the target with this octal mask,");
+        println!("                          combined with the one of the configuration.");
+        println!("          --nice=N        Run the target with a niceness of at least N,");
+        println!("                          from 0 to 19.");
+        println!("          --cpus=LIST     Only run the target on the CPUs of a list such as");
+        println!("                          0-3,6, within those the configuration allows.");
+        println!("          --oom-score-adj=N");
+        println!("                          Make the target at least this much more likely to");
+        println!("                          be killed when out of memory, from 0 to 1000.");
+        println!("          --private-tmp   Give the target its own empty /tmp and /var/tmp.");
+        println!("          --scope         Run the target in a transient systemd scope of its");
+        println!("                          own, unless systemd is not running.");
+        println!("          --no-network[=try]");
+        println!("                          Run the target without network access, only with");
+        println!("                          loopback, and unless tried fail if unsupported.");
+        println!("          --allow-new-privs");
+        println!("                          Let a target that is not root gain privileges by");
+        println!("                          executing setuid programs, unless configured not to.");
+        println!("          --pass-fd=N[:M]");
+        println!("                          Hand the open descriptor N to the target as M,");
+        println!("                          by default as N, which must be above 2.");
+        println!("          --stdout-file=PATH, --stderr-file=PATH");
+        println!("                          Write the output of the target to a file the");
+        println!("                          invoker may write, as the target user.");
+        println!("          --append        Append to the output files instead of truncating.");
+        println!("          --confirm       Show what is about to run and ask to type yes on");
+        println!("                          the terminal before running it.");
+        println!("          --wait-lock[=SECONDS]");
+        println!("                          Wait for another instance of the target to exit");
+        println!("                          if it may only run so many times at once.");
+        println!("          --pty           Run the target on its own pseudo-terminal, as the");
+        println!("                          leader of a new session, implies --proxy.");
+        println!("          --daemon        Detach from the terminal and session and exit once");
+        println!("                          the target is started, implies --proxy.");
+        println!("          --stats         Print the time, CPU, and memory the target used once");
+        println!("                          it exits, implies --proxy.");
+        println!("          --pidfile=PATH  Write the pid of the target to a file in a directory");
+        println!("                          the invoker may write, implies --proxy.");
+        println!("          --record=PATH   Run the target on a pseudo-terminal and record the");
+        println!("                          session as an asciicast, implies --proxy.");
+        println!("          --explain-exit-code=N");
+        println!("                          Print what the exit code N of the wrapper means and");
+        println!("                          how to resolve it, then exit.");
+        println!("          --require-landlock");
+        println!("                          Refuse to run if the configured Landlock ruleset");
+        println!("                          is not supported by the kernel.");
+        println!("    Values are given as --OPTION=VALUE or --OPTION VALUE.");
         println!("  EXE_ARGS:");
         println!("    if specified, each argument will be passed to the executed subprocess.");
-        if !args_l.contains(&"--help") {
+        println!("Usage: {} doctor [PATH]", fname);
+        println!("    Check the ownership and modes of the installation of this wrapper, or of");
+        println!("    the one at PATH, and print the commands that fix what is wrong.");
+        println!("Usage: {} install --target=PATH --as=PATH [--link]", fname);
+        println!("    Install a copy of this wrapper, or a symbolic link to it with --link, as");
+        println!("    the --as path, and move the --target executable to where it is run from,");
+        println!("    owned by the invoker with the required modes.");
+        println!("Usage: {} completions bash|zsh|fish", fname);
+        println!("    Print a script that completes the options of this wrapper in a shell.");
+        if help == Some(args::Help::Short) {
             return ExitCode::SUCCESS;
         }
-        println!("");
+        println!();
         println!(concat!(env!("CARGO_PKG_DESCRIPTION")));
-        println!("");
+        println!();
+    }
+
+    if let Ok(cli) = &parsed {
+        if let Some(code) = cli.explain_exit_code {
+            print_explained(code, cli.options.json);
+            return ExitCode::SUCCESS;
+        }
     }
 
-    if args_l.contains(&"--help") || args_l.contains(&"--version") {
+    if help.is_some() || parsed.as_ref().map_or(false, |cli| cli.version) {
         println!(concat!(
             env!("CARGO_PKG_NAME"),
             " ",
@@ -86,153 +195,16 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS;
     }
 
-    for arg in args_l.iter() {
-        if !["-v", "--verbose", "--dry-run"].contains(arg) {
-            eprintln!("Unexpected argument: {:?}", arg);
-            return RET_GENERIC_ERROR.into();
-        }
-    }
-
-    let cwd = match std::env::current_dir().and_then(|f| std::fs::canonicalize(f)) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Unable to get the current directory: {}", e);
-            return RET_GENERIC_ERROR.into();
-        }
-    };
-
-    let exe = match std::env::current_exe().and_then(|f| std::fs::canonicalize(f)) {
-        Ok(path) => path,
-        Err(err) => {
-            eprintln!("Unable to find the name of the executable: {}", err);
-            return RET_ENV_ERROR.into();
-        }
-    };
-    let exe_uid = match Env::file_owner(&exe) {
-        Ok((exe_uid, meta, true)) if meta.is_file() => exe_uid,
-        Ok((_, _, true)) => {
-            eprintln!("The executable must be a ... file: {:?}", exe);
-            return RET_ENV_ERROR.into();
-        }
-        Ok((_, _, false)) => {
-            eprintln!("The executable permissions must include the SUID bit as well as be writable by only the owning user: {:?}", exe);
-            return RET_PERM_EXEC.into();
-        }
-        Err(err) => {
-            eprintln!("Unable to find the owner of the executable: {}", err);
-            return RET_ENV_ERROR.into();
-        }
-    };
-    let exe_name = match exe.file_name().map(OsStr::to_str) {
-        Some(Some(fname)) => fname,
-        Some(None) => {
-            eprintln!("Unable to read the name of the executable: {:?}", exe);
-            return RET_ENV_ERROR.into();
-        }
-        None => {
-            eprintln!("Unable to find the name of the executable: {:?}", exe);
-            return RET_ENV_ERROR.into();
+    // asked for even if the other options are invalid
+    let json_errors = match &parsed {
+        Ok(cli) => cli.options.json_errors,
+        Err(_) => {
+            options.windows(2).any(|o| o == ["--error-format", "json"])
+                || options.iter().any(|o| o == "--error-format=json")
         }
     };
-
-    let euid = unsafe { Env::geteuid() };
-
-    if euid != exe_uid {
-        eprintln!("You are not the owner of this executable.");
-        return RET_OWNER_EXEC.into();
+    if json_errors {
+        error::use_json();
     }
-
-    let parent = match exe.parent() {
-        Some(a) => a,
-        None => {
-            eprintln!(
-                "Unable to find the parent directory of the executable: {}",
-                exe.display()
-            );
-            return RET_ENV_ERROR.into();
-        }
-    };
-    let par_uid = match Env::file_owner(parent) {
-        Ok((exe_uid, m, true)) if m.is_dir() => exe_uid,
-        Ok((_, _, true)) => {
-            eprintln!("The parent directory must be a ... directory: {:?}", parent);
-            return RET_ENV_ERROR.into();
-        }
-        Ok((_, _, false)) => {
-            eprintln!(
-                "The parent directory permissions must be writable by only the owning user: {:?}",
-                parent
-            );
-            return RET_PERM_PARENT.into();
-        }
-        Err(err) => {
-            eprintln!("Unable to find the owner of the parent directory: {}", err);
-            return RET_ENV_ERROR.into();
-        }
-    };
-    if euid != par_uid {
-        eprintln!("The the owner of the parent directory is not the same as the executable.");
-        return RET_OWNER_PARENT.into();
-    }
-
-    let target = Env::sibling_target(parent, exe_name);
-    let tar_uid = match Env::file_owner(&target) {
-        Ok((exe_uid, m, true)) if m.is_file() => exe_uid,
-        Ok((_, _, true)) => {
-            eprintln!("The target executable must be a file: {:?}", target);
-            return RET_ENV_ERROR.into();
-        }
-        Ok((_, _, false)) => {
-            eprintln!("The target executable permissions must include the SUID bit as well as be writable by only the owning user: {:?}", target);
-            return RET_PERM_TARGET.into();
-        }
-        Err(err) if err.kind() == ErrorKind::NotFound => {
-            eprintln!(
-                "Unable to find the owner of the target executable {:?}: {}",
-                target, err
-            );
-            return RET_NO_TARGET.into();
-        }
-        Err(err) => {
-            eprintln!(
-                "Unable to find the owner of the target executable {:?}: {}",
-                target, err
-            );
-            return RET_ENV_ERROR.into();
-        }
-    };
-    if euid != 0 && euid != tar_uid {
-        eprintln!("The the owner of the target executable is not the same as the executable.");
-        return RET_OWNER_TARGET.into();
-    }
-
-    let opts = Opts {
-        verbose: args_l.contains(&"--verbose") || args_l.contains(&"-v"),
-        dry_run: args_l.contains(&"--dry-run"),
-        uid: tar_uid,
-        gid,
-    };
-
-    if opts.dry_run {
-        use std::fmt::Write;
-        let mut out = String::new();
-        out.push_str("Dry run: would have succeeded in starting the process: ");
-        write!(out, "{:?}", target).unwrap();
-        for a in args {
-            write!(out, " {:?}", a).unwrap();
-        }
-        println!("{}", out);
-        return ExitCode::SUCCESS;
-    }
-
-    let mut command = Command::new(target);
-    command
-        .current_dir(cwd)
-        .stdin(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .env_clear();
-    Env::prepare_command(&mut command, args, &opts);
-
-    Env::wait_for(command, opts)
+    run_suid_core::run(&fname, parsed.map(|cli| cli.options), args)
 }